@@ -18,6 +18,7 @@ pub enum CompanyKind {
     // Buyers get their goods delivered to them
     Factory { n_trucks: u32 },
     // Buyers get their goods instantly delivered, useful for things like electricity/water/..
+    // The buildings linked to it by power lines or pipes are supplied too
     Network,
 }
 
@@ -51,6 +52,37 @@ pub struct GoodsCompanyDescriptionJSON {
     pub zone: Option<Box<ZoneDescription>>,
 }
 
+/// Where a desire is satisfied once its item is bought
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DesireTarget {
+    /// At the building of the seller, like a meal at a restaurant
+    Seller,
+    /// At home, the item is picked up at the seller then brought home
+    Home,
+    /// At the nearest building of the named company, like a visit to a clinic
+    Building(String),
+}
+
+debug_inspect_impl!(DesireTarget);
+
+#[derive(Serialize, Deserialize)]
+pub struct DesireDescriptionJSON {
+    pub name: String,
+    /// The item bought from the market to satisfy the desire
+    pub item: String,
+    #[serde(default = "default_desire_qty")]
+    pub qty: u32,
+    /// Points of (hours since the desire was last satisfied, score), linearly interpolated.
+    /// Food has a score of -1 right after eating and of 0 after a day.
+    pub curve: Vec<(f32, f32)>,
+    pub target: DesireTarget,
+}
+
+fn default_desire_qty() -> u32 {
+    1
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ZoneDescription {
     pub floor: String,
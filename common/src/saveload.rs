@@ -1,6 +1,6 @@
 //! This module contains the `Encoder` trait, which is used to serialize and deserialize data.
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io;
 use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
@@ -195,3 +195,270 @@ pub fn load_string(
 ) -> std::result::Result<String, Box<dyn std::error::Error + 'static>> {
     std::fs::read_to_string(p).map_err(|e| Box::new(e) as Box<dyn std::error::Error>)
 }
+
+/// Written at the start of every save so that save browsers can show it without decoding the whole world
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SaveMeta {
+    /// Version of the game that wrote the save
+    pub version: String,
+    /// When the save was written, in seconds since the unix epoch
+    pub saved_at: u64,
+    pub tick: u32,
+    /// In-game time, in seconds
+    pub game_time: f64,
+    pub population: u32,
+    pub money_cents: i64,
+    /// Short human readable description of the save
+    pub summary: String,
+}
+
+/// A save slot found on disk
+#[derive(Debug, Clone)]
+pub struct SaveSlot {
+    pub name: String,
+    pub meta: SaveMeta,
+}
+
+const SAVE_MAGIC: &[u8; 4] = b"EGSV";
+/// How many autosave slots are rotated through
+pub const AUTOSAVE_SLOTS: usize = 3;
+const SAVE_EXTENSION: &str = "sav";
+/// The metadata header is small, a longer one means the file is not a save
+const MAX_HEADER_LEN: u32 = 1 << 16;
+
+/// Manages named save slots in a root directory.
+/// Each slot is a single `{root}/{name}.sav` file made of a small JSON metadata header
+/// followed by the save itself. Writes go to a temporary file that is renamed once complete,
+/// so a crash while saving never corrupts the previous save.
+#[derive(Debug, Clone)]
+pub struct SaveStore {
+    root: PathBuf,
+}
+
+impl Default for SaveStore {
+    fn default() -> Self {
+        Self::new("world")
+    }
+}
+
+impl SaveStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of a file in the root directory, for files saved alongside the slots like replays
+    pub fn file_path(&self, file: &str) -> PathBuf {
+        self.root.join(file)
+    }
+
+    /// Fails if the slot name is invalid, see [`check_slot_name`]
+    pub fn slot_path(&self, slot: &str) -> Result<PathBuf> {
+        check_slot_name(slot)?;
+        Ok(self.file_path(&format!("{slot}.{SAVE_EXTENSION}")))
+    }
+
+    pub fn exists(&self, slot: &str) -> bool {
+        self.slot_path(slot).is_ok_and(|path| path.is_file())
+    }
+
+    /// Writes the file by writing a temporary file first then renaming it
+    pub fn write_atomic(&self, file: &str, data: &[u8]) -> Result<()> {
+        std::fs::create_dir_all(&self.root)?;
+        let path = self.file_path(file);
+        let tmp = self.file_path(&format!("{file}.tmp"));
+
+        let mut f = File::create(&tmp)?;
+        f.write_all(data)?;
+        f.sync_all()?;
+        drop(f);
+
+        std::fs::rename(&tmp, &path)
+    }
+
+    pub fn write(&self, slot: &str, meta: &SaveMeta, body: &[u8]) -> Result<()> {
+        check_slot_name(slot)?;
+        let header = serde_json::to_vec(meta)?;
+        let mut data = Vec::with_capacity(8 + header.len() + body.len());
+        data.extend_from_slice(SAVE_MAGIC);
+        data.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        data.extend_from_slice(&header);
+        data.extend_from_slice(body);
+        self.write_atomic(&format!("{slot}.{SAVE_EXTENSION}"), &data)
+    }
+
+    /// Only reads the metadata header of the slot
+    pub fn read_meta(&self, slot: &str) -> Result<SaveMeta> {
+        let mut r = BufReader::new(File::open(self.slot_path(slot)?)?);
+        read_header(&mut r)
+    }
+
+    /// Reads the metadata and the save itself
+    pub fn read(&self, slot: &str) -> Result<(SaveMeta, Vec<u8>)> {
+        let mut r = BufReader::new(File::open(self.slot_path(slot)?)?);
+        let meta = read_header(&mut r)?;
+        let mut body = vec![];
+        r.read_to_end(&mut body)?;
+        Ok((meta, body))
+    }
+
+    pub fn remove(&self, slot: &str) -> Result<()> {
+        std::fs::remove_file(self.slot_path(slot)?)
+    }
+
+    /// All the readable slots, most recent first
+    pub fn list(&self) -> Vec<SaveSlot> {
+        let Ok(entries) = std::fs::read_dir(&self.root) else {
+            return vec![];
+        };
+        let mut slots: Vec<SaveSlot> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != SAVE_EXTENSION {
+                    return None;
+                }
+                let name = path.file_stem()?.to_str()?.to_string();
+                let meta = self.read_meta(&name).ok()?;
+                Some(SaveSlot { name, meta })
+            })
+            .collect();
+        slots.sort_by(|a, b| {
+            b.meta
+                .saved_at
+                .cmp(&a.meta.saved_at)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        slots
+    }
+
+    /// The slot to write the next autosave to, rotating between `count` autosave slots:
+    /// the first free one or else the oldest one
+    pub fn autosave_slot(&self, count: usize) -> String {
+        autosave_slots(count)
+            .min_by_key(|slot| self.read_meta(slot).map(|m| m.saved_at).unwrap_or(0))
+            .unwrap()
+    }
+
+    /// The most recently written of the given slots, if any of them exists
+    pub fn latest_slot(&self, slots: impl IntoIterator<Item = String>) -> Option<String> {
+        slots
+            .into_iter()
+            .filter_map(|slot| Some((self.read_meta(&slot).ok()?.saved_at, slot)))
+            .max_by_key(|(saved_at, _)| *saved_at)
+            .map(|(_, slot)| slot)
+    }
+}
+
+/// The names of the `count` autosave slots
+pub fn autosave_slots(count: usize) -> impl Iterator<Item = String> {
+    (0..count.max(1)).map(|i| format!("autosave_{i}"))
+}
+
+/// Slots are files in the root directory of the store, so their name cannot be empty,
+/// contain path separators or `..`
+pub fn check_slot_name(slot: &str) -> Result<()> {
+    let valid = !slot.is_empty()
+        && !slot.contains("..")
+        && !slot
+            .chars()
+            .any(|c| matches!(c, '/' | '\\' | ':' | '\0') || std::path::is_separator(c));
+    if !valid {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "invalid save slot name {slot:?}, it cannot be empty or contain /, \\, : or .."
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn read_header(r: &mut impl Read) -> Result<SaveMeta> {
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != SAVE_MAGIC {
+        return Err(io::Error::new(ErrorKind::InvalidData, "not a save file"));
+    }
+    let mut len = [0; 4];
+    r.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len);
+    if len > MAX_HEADER_LEN {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("save header is too long ({len} bytes)"),
+        ));
+    }
+    let mut header = vec![0; len as usize];
+    r.read_exact(&mut header)?;
+    serde_json::from_slice(&header).map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_slot_name, SaveMeta, SaveStore};
+    use std::io::ErrorKind;
+
+    #[test]
+    fn test_save_slots() {
+        let root = std::env::temp_dir().join(format!("egregoria_saves_{}", std::process::id()));
+        let store = SaveStore::new(&root);
+
+        let meta = |saved_at| SaveMeta {
+            saved_at,
+            population: 42,
+            ..Default::default()
+        };
+
+        store.write("a", &meta(10), &[1, 2, 3]).unwrap();
+        store.write("b", &meta(20), &[4]).unwrap();
+
+        assert_eq!(store.read_meta("a").unwrap().population, 42);
+        assert_eq!(store.read("a").unwrap().1, vec![1, 2, 3]);
+        assert!(!store.file_path("a.sav.tmp").exists());
+
+        let names: Vec<_> = store.list().into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["b", "a"]);
+
+        store.write("autosave_0", &meta(30), &[]).unwrap();
+        assert_eq!(store.autosave_slot(2), "autosave_1");
+        store.write("autosave_1", &meta(40), &[]).unwrap();
+        assert_eq!(store.autosave_slot(2), "autosave_0");
+        assert_eq!(
+            store.latest_slot(["a".to_string(), "autosave_0".to_string()]),
+            Some("autosave_0".to_string())
+        );
+        assert_eq!(
+            store.latest_slot(super::autosave_slots(2).chain(["c".to_string()])),
+            Some("autosave_1".to_string())
+        );
+        assert_eq!(store.latest_slot(["c".to_string()]), None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_slots() {
+        let root = std::env::temp_dir().join(format!("egregoria_bad_saves_{}", std::process::id()));
+        let store = SaveStore::new(root.join("saves"));
+
+        for slot in ["", "..", "../x", "a/b", "a\\b", "c:x"] {
+            assert!(check_slot_name(slot).is_err(), "{slot}");
+            let err = store.write(slot, &SaveMeta::default(), &[]).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput);
+            assert!(!store.exists(slot));
+        }
+        assert!(!root.join("x.sav").exists());
+        assert!(check_slot_name("city_1").is_ok());
+
+        // a header length that does not fit is refused before reading it
+        std::fs::create_dir_all(root.join("saves")).unwrap();
+        std::fs::write(store.file_path("big.sav"), b"EGSV\xff\xff\xff\xff").unwrap();
+        let err = store.read_meta("big").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
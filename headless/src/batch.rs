@@ -0,0 +1,557 @@
+use common::saveload::SaveStore;
+use serde::Serialize;
+use simulation::economy::{EcoStats, Government, ItemHistories, Money, HISTORY_SIZE};
+use simulation::scenario::{Scenario, ScenarioState};
+use simulation::transportation::traffic_stats::{CountHistory, TrafficStats, TRIP_ENDS};
+use simulation::transportation::VehicleKind;
+use simulation::utils::scheduler::SeqSchedule;
+use simulation::{Simulation, SimulationReplayLoader};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+
+#[derive(Debug, Copy, Clone)]
+pub enum MetricsFormat {
+    Csv,
+    JsonLines,
+}
+
+impl FromStr for MetricsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(MetricsFormat::Csv),
+            "jsonl" => Ok(MetricsFormat::JsonLines),
+            _ => Err(format!("unknown metrics format {s}, expected csv or jsonl")),
+        }
+    }
+}
+
+impl MetricsFormat {
+    /// Guesses the format from the file extension, defaulting to csv
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("jsonl" | "json") => MetricsFormat::JsonLines,
+            _ => MetricsFormat::Csv,
+        }
+    }
+}
+
+pub struct BatchOptions {
+    pub store: SaveStore,
+    /// Save slot to load, a new world is generated if neither a save nor a replay is given
+    pub load: Option<String>,
+    /// Save slot whose replay to run, the replay commands are applied at their recorded tick
+    pub replay: Option<String>,
+    /// Scenario file to start a new world from, the run stops when it is won or lost
+    pub scenario: Option<PathBuf>,
+    pub ticks: u32,
+    pub metrics: Option<PathBuf>,
+    pub metrics_format: Option<MetricsFormat>,
+    pub metrics_every: u32,
+    /// File to write the traffic report to at the end of the run
+    pub traffic: Option<PathBuf>,
+}
+
+/// A snapshot of the simulation state, written every few ticks
+#[derive(Serialize)]
+struct Metrics {
+    tick: u32,
+    population: usize,
+    government_money: f64,
+    cars: usize,
+    trucks: usize,
+    buses: usize,
+    bikes: usize,
+    trains: usize,
+    /// Totals of the last complete 10 minutes bin of the economy stats
+    exports_qty: i64,
+    exports_money: f64,
+    imports_qty: i64,
+    imports_money: f64,
+    internal_trade_qty: i64,
+    internal_trade_money: f64,
+    /// Vehicles and pedestrians entering lanes during the last complete 10 minutes bin
+    lane_vehicles: u64,
+    lane_pedestrians: u64,
+    /// Average waiting time per vehicle at the intersections during the same bin, in seconds
+    intersection_delay: f32,
+    /// Average time spent in each system, in milliseconds
+    systems: BTreeMap<String, f32>,
+}
+
+impl Metrics {
+    fn new(sim: &Simulation, schedule: &SeqSchedule) -> Self {
+        let world = sim.world();
+
+        let mut counts = [0; 4];
+        for v in world.vehicles.values() {
+            counts[match v.vehicle.kind {
+                VehicleKind::Car => 0,
+                VehicleKind::Truck => 1,
+                VehicleKind::Bus => 2,
+                VehicleKind::Bike => 3,
+            }] += 1;
+        }
+
+        let ecostats = sim.read::<EcoStats>();
+        let (exports_qty, exports_money) = last_bin_total(&ecostats.exports);
+        let (imports_qty, imports_money) = last_bin_total(&ecostats.imports);
+        let (internal_trade_qty, internal_trade_money) = last_bin_total(&ecostats.internal_trade);
+
+        let traffic = sim.read::<TrafficStats>();
+        let mut lane_vehicles = 0;
+        let mut lane_pedestrians = 0;
+        for (_, t) in traffic.lanes() {
+            lane_vehicles += traffic.last_complete(0, &t.vehicles) as u64;
+            lane_pedestrians += traffic.last_complete(0, &t.pedestrians) as u64;
+        }
+        let mut through = 0;
+        let mut delay = 0;
+        for (_, t) in traffic.intersections() {
+            through += traffic.last_complete(0, &t.vehicles) as u64;
+            delay += traffic.last_complete(0, &t.delay) as u64;
+        }
+
+        Self {
+            tick: sim.get_tick(),
+            population: world.humans.len(),
+            government_money: to_bucks(sim.read::<Government>().money),
+            cars: counts[0],
+            trucks: counts[1],
+            buses: counts[2],
+            bikes: counts[3],
+            trains: world.trains.len(),
+            exports_qty,
+            exports_money,
+            imports_qty,
+            imports_money,
+            internal_trade_qty,
+            internal_trade_money,
+            lane_vehicles,
+            lane_pedestrians,
+            intersection_delay: delay as f32 / through.max(1) as f32,
+            systems: schedule.times().into_iter().collect(),
+        }
+    }
+
+    fn csv_header(&self) -> String {
+        let mut header = "tick,population,government_money,cars,trucks,buses,bikes,trains,\
+                          exports_qty,exports_money,imports_qty,imports_money,\
+                          internal_trade_qty,internal_trade_money,\
+                          lane_vehicles,lane_pedestrians,intersection_delay"
+            .to_string();
+        for name in self.systems.keys() {
+            header.push_str(",time_");
+            header.push_str(name);
+        }
+        header
+    }
+
+    fn csv_row(&self) -> String {
+        let mut row = format!(
+            "{},{},{:.2},{},{},{},{},{},{},{:.2},{},{:.2},{},{:.2},{},{},{:.2}",
+            self.tick,
+            self.population,
+            self.government_money,
+            self.cars,
+            self.trucks,
+            self.buses,
+            self.bikes,
+            self.trains,
+            self.exports_qty,
+            self.exports_money,
+            self.imports_qty,
+            self.imports_money,
+            self.internal_trade_qty,
+            self.internal_trade_money,
+            self.lane_vehicles,
+            self.lane_pedestrians,
+            self.intersection_delay,
+        );
+        for t in self.systems.values() {
+            row.push_str(&format!(",{t:.4}"));
+        }
+        row
+    }
+}
+
+fn to_bucks(m: Money) -> f64 {
+    m.cents() as f64 / 100.0
+}
+
+/// Sums the last complete bin of the shortest level over all items
+fn last_bin_total(h: &ItemHistories) -> (i64, f64) {
+    let cursor = (h.cursors()[0] + HISTORY_SIZE - 1) % HISTORY_SIZE;
+    let mut qty = 0;
+    let mut money = Money::ZERO;
+    for (_, level) in h.iter_histories(0) {
+        qty += level.past_ring_items[cursor];
+        money += level.past_ring_money[cursor];
+    }
+    (qty, to_bucks(money))
+}
+
+/// Traffic counts per lane, turn and intersection and the sampled trips,
+/// summed over the histories of the shortest level
+#[derive(Serialize)]
+struct TrafficReport {
+    tick: u32,
+    lanes: Vec<LaneReport>,
+    turns: Vec<TurnReport>,
+    intersections: Vec<IntersectionReport>,
+    trips: Vec<TripReport>,
+}
+
+#[derive(Serialize)]
+struct LaneReport {
+    id: String,
+    from: [f32; 2],
+    to: [f32; 2],
+    vehicles: u64,
+    pedestrians: u64,
+    /// Smoothed speed of the vehicles, in m/s
+    avg_speed: f32,
+}
+
+#[derive(Serialize)]
+struct TurnReport {
+    intersection: String,
+    src: String,
+    dst: String,
+    vehicles: u64,
+}
+
+#[derive(Serialize)]
+struct IntersectionReport {
+    id: String,
+    pos: [f32; 2],
+    vehicles: u64,
+    /// Average waiting time per vehicle, in seconds
+    avg_delay: f32,
+}
+
+#[derive(Serialize)]
+struct TripReport {
+    from: &'static str,
+    to: &'static str,
+    trips: u64,
+}
+
+fn history_total(h: &CountHistory) -> u64 {
+    h.level(0).past_ring.iter().map(|&x| x as u64).sum()
+}
+
+impl TrafficReport {
+    fn new(sim: &Simulation) -> Self {
+        let traffic = sim.read::<TrafficStats>();
+        let map = sim.map();
+
+        let lanes = traffic
+            .lanes()
+            .filter_map(|(id, t)| {
+                let lane = map.lanes().get(id)?;
+                Some(LaneReport {
+                    id: format!("{id:?}"),
+                    from: lane.points.first().xy().into(),
+                    to: lane.points.last().xy().into(),
+                    vehicles: history_total(&t.vehicles),
+                    pedestrians: history_total(&t.pedestrians),
+                    avg_speed: t.avg_speed,
+                })
+            })
+            .collect();
+
+        let turns = traffic
+            .turns()
+            .map(|(id, h)| TurnReport {
+                intersection: format!("{:?}", id.parent),
+                src: format!("{:?}", id.src),
+                dst: format!("{:?}", id.dst),
+                vehicles: history_total(h),
+            })
+            .collect();
+
+        let intersections = traffic
+            .intersections()
+            .filter_map(|(id, t)| {
+                let inter = map.intersections().get(id)?;
+                let vehicles = history_total(&t.vehicles);
+                Some(IntersectionReport {
+                    id: format!("{id:?}"),
+                    pos: inter.pos.xy().into(),
+                    vehicles,
+                    avg_delay: history_total(&t.delay) as f32 / vehicles.max(1) as f32,
+                })
+            })
+            .collect();
+
+        let mut trips = vec![];
+        for from in TRIP_ENDS {
+            for to in TRIP_ENDS {
+                let Some(h) = traffic.trip_history(from, to) else {
+                    continue;
+                };
+                trips.push(TripReport {
+                    from: from.name(),
+                    to: to.name(),
+                    trips: history_total(h),
+                });
+            }
+        }
+
+        Self {
+            tick: sim.get_tick(),
+            lanes,
+            turns,
+            intersections,
+            trips,
+        }
+    }
+
+    fn write(&self, path: &Path) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut out, self)?;
+        out.flush()
+    }
+}
+
+struct MetricsWriter {
+    out: BufWriter<File>,
+    format: MetricsFormat,
+    wrote_header: bool,
+}
+
+impl MetricsWriter {
+    fn new(path: &Path, format: MetricsFormat) -> std::io::Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+            format,
+            wrote_header: false,
+        })
+    }
+
+    fn write(&mut self, m: &Metrics) -> std::io::Result<()> {
+        match self.format {
+            MetricsFormat::Csv => {
+                if !self.wrote_header {
+                    writeln!(self.out, "{}", m.csv_header())?;
+                    self.wrote_header = true;
+                }
+                writeln!(self.out, "{}", m.csv_row())
+            }
+            MetricsFormat::JsonLines => {
+                serde_json::to_writer(&mut self.out, m)?;
+                writeln!(self.out)
+            }
+        }
+    }
+}
+
+/// Runs the simulation for the given number of ticks as fast as possible, without networking.
+/// Returns false if something went wrong, including a replay not playing back as recorded.
+pub fn run_batch(opt: BatchOptions) -> bool {
+    let mut loader: Option<SimulationReplayLoader> = None;
+
+    let mut sim = if let Some(ref name) = opt.replay {
+        let Some(replay) = Simulation::load_replay_from_slot(&opt.store, name) else {
+            log::error!("could not load replay {}", name);
+            return false;
+        };
+        let (sim, mut l) = Simulation::from_replay(replay);
+        l.speed = 1;
+        loader = Some(l);
+        sim
+    } else if let Some(ref name) = opt.load {
+        let Some(sim) = Simulation::load_from_slot(&opt.store, name) else {
+            log::error!("could not load save {}", name);
+            return false;
+        };
+        sim
+    } else if let Some(ref path) = opt.scenario {
+        let Some(scenario) = Scenario::load(path) else {
+            return false;
+        };
+        Simulation::new_with_scenario(scenario)
+    } else {
+        log::info!("no save or replay given, generating a new world");
+        Simulation::new(true)
+    };
+
+    let mut writer = match opt.metrics {
+        Some(ref path) => {
+            let format = opt
+                .metrics_format
+                .unwrap_or_else(|| MetricsFormat::from_path(path));
+            match MetricsWriter::new(path, format) {
+                Ok(w) => Some(w),
+                Err(e) => {
+                    log::error!("could not create metrics file {:?}: {}", path, e);
+                    return false;
+                }
+            }
+        }
+        None => None,
+    };
+
+    let mut schedule = Simulation::schedule();
+
+    let start_tick = sim.get_tick();
+    let end_tick = start_tick + opt.ticks;
+    let every = opt.metrics_every.max(1);
+    let start = Instant::now();
+    let mut replay_done = false;
+
+    log::info!("running {} ticks from tick {}", opt.ticks, start_tick);
+
+    while sim.get_tick() < end_tick {
+        let before = sim.get_tick();
+        if let Some(ref mut l) = loader {
+            if !replay_done && l.advance_tick(&mut sim, &mut schedule) {
+                log::info!("replay finished at tick {}", sim.get_tick());
+                replay_done = true;
+            }
+        }
+        if sim.get_tick() == before {
+            sim.tick(&mut schedule, &[]);
+        }
+
+        let outcome = sim.read::<ScenarioState>().outcome;
+        let tick = sim.get_tick();
+        if (tick - start_tick) % every != 0 && tick != end_tick && outcome.is_none() {
+            continue;
+        }
+
+        let m = Metrics::new(&sim, &schedule);
+        log::info!(
+            "tick {}: population {}, money {:.2}",
+            m.tick,
+            m.population,
+            m.government_money
+        );
+        if let Some(ref mut w) = writer {
+            if let Err(e) = w.write(&m) {
+                log::error!("could not write metrics: {}", e);
+                return false;
+            }
+        }
+
+        if let Some(outcome) = outcome {
+            log::info!("scenario finished at tick {}: {:?}", tick, outcome);
+            break;
+        }
+    }
+
+    if let Some(ref mut w) = writer {
+        if let Err(e) = w.out.flush() {
+            log::error!("could not write metrics: {}", e);
+        }
+    }
+
+    if let Some(ref path) = opt.traffic {
+        if let Err(e) = TrafficReport::new(&sim).write(path) {
+            log::error!("could not write traffic report {:?}: {}", path, e);
+            return false;
+        }
+    }
+
+    let elapsed = start.elapsed().as_secs_f32();
+    let ran = sim.get_tick() - start_tick;
+    log::info!(
+        "ran {} ticks in {:.1}s ({:.0} ticks/s)",
+        ran,
+        elapsed,
+        ran as f32 / elapsed.max(f32::EPSILON)
+    );
+
+    if let Some(d) = loader.and_then(|l| l.divergence) {
+        log::error!(
+            "replay diverged at tick {} starting with {}",
+            d.tick.0,
+            d.resource
+        );
+        return false;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Metrics, MetricsFormat, MetricsWriter};
+    use std::path::PathBuf;
+
+    fn metrics(tick: u32) -> Metrics {
+        Metrics {
+            tick,
+            population: 12,
+            government_money: 1234.5,
+            cars: 3,
+            trucks: 1,
+            buses: 0,
+            bikes: 2,
+            trains: 0,
+            exports_qty: 4,
+            exports_money: 10.0,
+            imports_qty: 0,
+            imports_money: 0.0,
+            internal_trade_qty: 7,
+            internal_trade_money: 3.25,
+            lane_vehicles: 20,
+            lane_pedestrians: 5,
+            intersection_delay: 1.5,
+            systems: [("market_update".to_string(), 0.25)].into_iter().collect(),
+        }
+    }
+
+    /// Writes two snapshots in the given format and returns the lines of the file
+    fn write_and_read(format: MetricsFormat, ext: &str) -> Vec<String> {
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("metrics_test_{}.{ext}", std::process::id()));
+        let mut w = MetricsWriter::new(&path, format).unwrap();
+        w.write(&metrics(10)).unwrap();
+        w.write(&metrics(20)).unwrap();
+        drop(w);
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        content.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let lines = write_and_read(MetricsFormat::Csv, "csv");
+        assert_eq!(lines.len(), 3);
+
+        let header: Vec<&str> = lines[0].split(',').collect();
+        assert_eq!(header[0], "tick");
+        assert_eq!(header.last(), Some(&"time_market_update"));
+        for (row, tick) in lines[1..].iter().zip(["10", "20"]) {
+            let row: Vec<&str> = row.split(',').collect();
+            assert_eq!(row.len(), header.len());
+            assert_eq!(row[0], tick);
+            let money = header
+                .iter()
+                .position(|&h| h == "government_money")
+                .unwrap();
+            assert_eq!(row[money], "1234.50");
+            assert_eq!(row.last(), Some(&"0.2500"));
+        }
+    }
+
+    #[test]
+    fn test_jsonl_export() {
+        let lines = write_and_read(MetricsFormat::JsonLines, "jsonl");
+        assert_eq!(lines.len(), 2);
+
+        for (line, tick) in lines.iter().zip([10, 20]) {
+            let v: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(v["tick"], tick);
+            assert_eq!(v["population"], 12);
+            assert_eq!(v["systems"]["market_update"], 0.25);
+        }
+    }
+}
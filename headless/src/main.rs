@@ -1,11 +1,17 @@
 use common::logger::MyLog;
+use common::saveload::{autosave_slots, SaveStore, AUTOSAVE_SLOTS};
 use common::unwrap_or;
 use networking::{Frame, Server, ServerConfiguration, ServerPollResult};
 use simulation::engine_interaction::WorldCommands;
-use simulation::Simulation;
+use simulation::multiplayer::{PlayerID, Players};
+use simulation::scenario::Scenario;
+use simulation::{Replay, Simulation};
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 use structopt::StructOpt;
 
+mod batch;
+
 const VERSION: &str = include_str!("../../VERSION");
 
 #[derive(StructOpt, Debug)]
@@ -19,14 +25,67 @@ struct Opt {
     #[structopt(long, default_value = "300")]
     autosave: u64,
 
+    /// Directory containing the save slots
+    #[structopt(long, default_value = "world", parse(from_os_str))]
+    save_dir: PathBuf,
+
+    /// Save slot the server loads, it resumes from the latest autosave instead if it is more recent.
+    /// Autosaves rotate through the same slots as the game's
+    #[structopt(long, default_value = "world")]
+    slot: String,
+
+    /// Prevent players from bulldozing the assets built by other players.
+    /// There is no host client on a dedicated server to toggle it in game
+    #[structopt(long)]
+    protect_assets: bool,
+
     /// Always continue running even when everyone is disconnected
     #[structopt(long)]
     always_run: bool,
 
+    /// Record hashes of the state in the replay every this many ticks,
+    /// to find where clients desync when playing it back. 0 to disable
+    #[structopt(long)]
+    replay_checkpoints: Option<u32>,
+
     /// Timestep in millisecond.
     /// i.e. 20ms = 50FPS
     #[structopt(long, default_value = "20")]
     timestep: u64,
+
+    /// Batch mode: run this many ticks as fast as possible without networking, then exit
+    #[structopt(long)]
+    ticks: Option<u32>,
+
+    /// Batch mode: save slot to load
+    #[structopt(long)]
+    load: Option<String>,
+
+    /// Batch mode: save slot whose replay to run
+    #[structopt(long, conflicts_with = "load")]
+    replay: Option<String>,
+
+    /// Scenario file to start a new game from.
+    /// In server mode it is only started when there is no save to load.
+    /// In batch mode the run stops once the scenario is won or lost
+    #[structopt(long, parse(from_os_str), conflicts_with_all = &["load", "replay"])]
+    scenario: Option<PathBuf>,
+
+    /// Batch mode: file to write the metrics to
+    #[structopt(long, parse(from_os_str))]
+    metrics: Option<PathBuf>,
+
+    /// Batch mode: csv or jsonl, guessed from the metrics file extension by default
+    #[structopt(long)]
+    metrics_format: Option<batch::MetricsFormat>,
+
+    /// Batch mode: write the metrics every this many ticks
+    #[structopt(long, default_value = "250")]
+    metrics_every: u32,
+
+    /// Batch mode: file to write the traffic counts, delays and trips to at the end, as json
+    #[structopt(long, parse(from_os_str))]
+    traffic: Option<PathBuf>,
 }
 
 fn main() {
@@ -34,13 +93,62 @@ fn main() {
     MyLog::init();
     simulation::init::init();
 
+    if let Some(ticks) = opt.ticks {
+        let ok = batch::run_batch(batch::BatchOptions {
+            store: SaveStore::new(opt.save_dir),
+            load: opt.load,
+            replay: opt.replay,
+            scenario: opt.scenario,
+            ticks,
+            metrics: opt.metrics,
+            metrics_format: opt.metrics_format,
+            metrics_every: opt.metrics_every,
+            traffic: opt.traffic,
+        });
+        if !ok {
+            std::process::exit(1);
+        }
+        return;
+    }
+
     log::info!("starting server with version: {}", VERSION);
 
-    let mut w = unwrap_or!(Simulation::load_from_disk("world"), {
-        log::info!("savegame not found defaulting to empty");
-        Simulation::new(true)
+    let store = SaveStore::new(opt.save_dir);
+
+    let slot = store
+        .latest_slot(std::iter::once(opt.slot.clone()).chain(autosave_slots(AUTOSAVE_SLOTS)))
+        .unwrap_or_else(|| opt.slot.clone());
+    log::info!("loading slot {}", slot);
+
+    let loaded = Simulation::load_from_slot(&store, &slot);
+    if let (Some(_), Some(scenario)) = (&loaded, &opt.scenario) {
+        log::warn!(
+            "scenario {} is ignored because the save {} was loaded instead",
+            scenario.display(),
+            slot
+        );
+    }
+
+    let mut w = unwrap_or!(loaded, {
+        match opt.scenario.as_ref().and_then(Scenario::load) {
+            Some(scenario) => {
+                log::info!("savegame not found starting scenario {}", scenario.name);
+                Simulation::new_with_scenario(scenario)
+            }
+            None => {
+                log::info!("savegame not found defaulting to empty");
+                Simulation::new(true)
+            }
+        }
     });
 
+    if let Some(every) = opt.replay_checkpoints {
+        w.write::<Replay>().checkpoint_every = every;
+    }
+    if opt.protect_assets {
+        w.write::<Players>().protect_assets = true;
+    }
+
     let mut sched = Simulation::schedule();
 
     let mut server: Server<Simulation, WorldCommands> = match Server::start(ServerConfiguration {
@@ -65,13 +173,17 @@ fn main() {
         if let ServerPollResult::Input(inputs) = server.poll(&w, Frame(w.get_tick()), None) {
             for frame in inputs {
                 assert_eq!(frame.frame.0, w.get_tick() + 1);
-                let merged: WorldCommands = frame.inputs.into_iter().map(|x| x.inp).collect();
+                let merged: WorldCommands = frame
+                    .inputs
+                    .into_iter()
+                    .map(|x| x.inp.sent_by(PlayerID(x.sent_by)))
+                    .collect();
                 w.tick(&mut sched, merged.as_ref());
             }
         }
 
         if last_saved.elapsed().as_secs() > opt.autosave {
-            w.save_to_disk("world");
+            w.save_to_slot(&store, &store.autosave_slot(AUTOSAVE_SLOTS));
             last_saved = Instant::now();
         }
 
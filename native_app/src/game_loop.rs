@@ -13,12 +13,13 @@ use simulation::Simulation;
 
 use crate::audio::GameAudio;
 use crate::gui::windows::debug::DebugObjs;
+use crate::gui::windows::load::city_slot;
 use crate::gui::windows::settings::{manage_settings, Settings};
 use crate::gui::{ExitState, FollowEntity, Gui, Tool, UiTextures};
 use crate::inputmap::{Bindings, InputAction, InputMap};
 use crate::rendering::{InstancedRender, MapRenderOptions, MapRenderer, OrbitCamera};
 use crate::uiworld::{SaveLoadState, UiWorld};
-use common::saveload::Encoder;
+use common::saveload::{Encoder, SaveStore};
 use simulation::utils::scheduler::SeqSchedule;
 
 pub const VERSION: &str = include_str!("../../VERSION");
@@ -44,10 +45,17 @@ impl engine::framework::State for State {
         Gui::set_style(&ctx.egui.egui);
         log::info!("loaded egui_render");
 
+        let store = SaveStore::default();
+        let latest = store
+            .list()
+            .first()
+            .map(|s| s.name.clone())
+            .unwrap_or_else(|| "world".to_string());
         let sim: Simulation =
-            Simulation::load_from_disk("world").unwrap_or_else(|| Simulation::new(true));
+            Simulation::load_from_slot(&store, &latest).unwrap_or_else(|| Simulation::new(true));
         let game_schedule = Simulation::schedule();
         let mut uiworld = UiWorld::init();
+        uiworld.write::<SaveLoadState>().slot = city_slot(&store, &latest);
 
         let mut bindings = uiworld.write::<Bindings>();
         let default_bindings = Bindings::default();
@@ -93,14 +101,21 @@ impl engine::framework::State for State {
         profiling::scope!("game_loop::update");
 
         let mut slstate = self.uiw.write::<SaveLoadState>();
-        if slstate.please_save && !slstate.saving_status.load(Ordering::SeqCst) {
-            slstate.please_save = false;
+        if slstate.please_save.is_some() && !slstate.saving_status.load(Ordering::SeqCst) {
+            let slot = slstate.please_save.take().unwrap();
             let cpy = self.sim.clone();
             slstate.saving_status.store(true, Ordering::SeqCst);
             let status = slstate.saving_status.clone();
             std::thread::spawn(move || {
                 profiling::scope!("game_loop::update::save");
-                cpy.read().unwrap().save_to_disk("world");
+                if cpy
+                    .read()
+                    .unwrap()
+                    .save_to_slot(&SaveStore::default(), &slot)
+                    .is_none()
+                {
+                    log::error!("could not save to slot {}", slot);
+                }
                 status.store(false, Ordering::SeqCst);
             });
         }
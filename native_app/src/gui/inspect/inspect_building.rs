@@ -1,16 +1,18 @@
 use crate::uiworld::UiWorld;
 use egui::{Context, Ui, Widget};
-use simulation::economy::{ItemRegistry, Market};
+use simulation::economy::{ItemRegistry, Market, MarketMode, Wallets};
 use simulation::engine_interaction::WorldCommand;
 use simulation::{Simulation, SoulID};
 
 use crate::gui::inspect::entity_link;
 use crate::gui::item_icon;
 use egui_inspect::{Inspect, InspectArgs, InspectVec2Rotation};
-use simulation::map::{Building, BuildingID, BuildingKind, Zone, MAX_ZONE_AREA};
-use simulation::map_dynamic::BuildingInfos;
+use simulation::map::{Building, BuildingID, BuildingKind, UtilityKind, Zone, MAX_ZONE_AREA};
+use simulation::map_dynamic::{BuildingInfos, UtilityGrid};
+use simulation::multiplayer::Players;
 use simulation::souls::freight_station::FreightTrainState;
 use simulation::souls::goods_company::{GoodsCompanyRegistry, Recipe};
+use simulation::souls::satisfaction::workers_productivity;
 
 /// Inspect a specific building, showing useful information about it
 pub fn inspect_building(uiworld: &mut UiWorld, sim: &Simulation, ui: &Context, id: BuildingID) {
@@ -36,6 +38,10 @@ pub fn inspect_building(uiworld: &mut UiWorld, sim: &Simulation, ui: &Context, i
                 ui.label(format!("{:?}", building.id));
             }
 
+            if let Some(owner) = sim.read::<Players>().owners.building(id) {
+                ui.label(format!("Built by player {}", owner.0));
+            }
+
             match building.kind {
                 BuildingKind::House => render_house(ui, uiworld, sim, building),
                 BuildingKind::GoodsCompany(_) => {
@@ -48,6 +54,8 @@ pub fn inspect_building(uiworld: &mut UiWorld, sim: &Simulation, ui: &Context, i
                 BuildingKind::ExternalTrading => {}
             };
 
+            render_utilities(ui, sim, building);
+
             if let Some(ref zone) = building.zone {
                 let mut cpy = zone.filldir;
                 if InspectVec2Rotation::render_mut(
@@ -86,6 +94,18 @@ fn render_house(ui: &mut Ui, uiworld: &mut UiWorld, sim: &Simulation, b: &Buildi
         entity_link(uiworld, sim, ui, owner);
     });
 
+    ui.label(format!(
+        "Residents ({}/{}):",
+        info.residents.len(),
+        b.capacity()
+    ));
+    for &soul in info.residents.iter() {
+        let SoulID::Human(soul) = soul else {
+            continue;
+        };
+        entity_link(uiworld, sim, ui, soul);
+    }
+
     ui.label("Currently in the house:");
     for &soul in info.inside.iter() {
         let SoulID::Human(soul) = soul else {
@@ -95,6 +115,34 @@ fn render_house(ui: &mut Ui, uiworld: &mut UiWorld, sim: &Simulation, b: &Buildi
     }
 }
 
+fn render_utilities(ui: &mut Ui, sim: &Simulation, b: &Building) {
+    let grid = sim.read::<UtilityGrid>();
+    let map = sim.map();
+    for kind in UtilityKind::ALL {
+        let n_links = map
+            .utility_links()
+            .values()
+            .filter(|l| l.kind == kind && (l.src == b.id || l.dst == b.id))
+            .count();
+        let served = grid.served(b.id, kind);
+        if served < 1.0 {
+            egui::ProgressBar::new(served)
+                .text(format!("{}: {:.0}% served", kind.name(), served * 100.0))
+                .desired_width(200.0)
+                .ui(ui);
+        }
+        if let Some(c) = grid.component_of(kind, b.id) {
+            ui.label(format!(
+                "{}: {} links, grid supplies {:.1}/{:.1} per second",
+                kind.name(),
+                n_links,
+                c.supply,
+                c.demand
+            ));
+        }
+    }
+}
+
 fn render_freightstation(ui: &mut Ui, uiworld: &mut UiWorld, sim: &Simulation, b: &Building) {
     let Some(SoulID::FreightStation(owner)) = sim.read::<BuildingInfos>().owner(b.id) else {
         return;
@@ -145,13 +193,30 @@ fn render_goodscompany(ui: &mut Ui, uiworld: &mut UiWorld, sim: &Simulation, b:
         .text(format!("workers: {}/{}", workers.0.len(), max_workers))
         .desired_width(200.0)
         .ui(ui);
-    if let Some(driver) = goods.driver {
+    for &driver in &goods.drivers {
         ui.horizontal(|ui| {
             ui.label("Driver is");
             entity_link(uiworld, sim, ui, driver);
         });
     }
-    let productivity = goods.productivity(workers.0.len(), b.zone.as_ref());
+    if !goods.trucks.is_empty() {
+        let waiting: i32 = c.sold.0.iter().map(|t| t.qty).sum();
+        ui.label(format!(
+            "{} trucks, {} goods waiting for pickup",
+            goods.trucks.len(),
+            waiting
+        ));
+    }
+    if market.mode == MarketMode::DynamicPrices {
+        let balance = sim.read::<Wallets>().balance(SoulID::GoodsCompany(c_id));
+        ui.label(format!("Money: {balance}"));
+    }
+    let productivity = goods.productivity(
+        workers.0.len(),
+        b.zone.as_ref(),
+        sim.read::<UtilityGrid>().productivity_factor(b.id)
+            * workers_productivity(&sim.world().humans, &workers.0),
+    );
     let productivity = (productivity * 100.0).round();
     if productivity < 100.0 {
         egui::ProgressBar::new(productivity)
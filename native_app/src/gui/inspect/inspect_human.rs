@@ -1,10 +1,10 @@
 use egui::{Context, Widget};
 
-use simulation::economy::{ItemRegistry, Market};
+use simulation::economy::{ItemRegistry, Market, MarketMode, Wallets};
 use simulation::map_dynamic::Destination;
-use simulation::souls::desire::WorkKind;
+use simulation::souls::desire::{DesireRegistry, WorkKind};
 use simulation::transportation::Location;
-use simulation::{HumanID, Simulation};
+use simulation::{HumanID, Simulation, SoulID};
 
 use crate::gui::inspect::{building_link, follow_button};
 use crate::gui::item_icon;
@@ -33,6 +33,9 @@ pub fn inspect_human(uiworld: &mut UiWorld, sim: &Simulation, ui: &Context, id:
                 Location::Vehicle(_) => {
                     ui.label("In a vehicle");
                 }
+                Location::Train(_) => {
+                    ui.label("In a train");
+                }
                 Location::Building(x) => {
                     ui.horizontal(|ui| {
                         ui.label("In a building:");
@@ -62,6 +65,11 @@ pub fn inspect_human(uiworld: &mut UiWorld, sim: &Simulation, ui: &Context, id:
 
             ui.label(format!("Last ate: {}", human.food.last_ate));
 
+            if sim.read::<Market>().mode == MarketMode::DynamicPrices {
+                let balance = sim.read::<Wallets>().balance(SoulID::Human(id));
+                ui.label(format!("Money: {balance}"));
+            }
+
             if let Some(ref x) = human.work {
                 ui.horizontal(|ui| {
                     ui.label("Working at");
@@ -94,6 +102,17 @@ pub fn inspect_human(uiworld: &mut UiWorld, sim: &Simulation, ui: &Context, id:
                 egui::DragValue::new(&mut score).ui(ui);
                 ui.label("Work");
             });
+            let desires_registry = sim.read::<DesireRegistry>();
+            for desire in &human.desires {
+                let Some(descr) = desires_registry.descriptions.get(desire.desire) else {
+                    continue;
+                };
+                ui.horizontal(|ui| {
+                    let mut score = desire.last_score;
+                    egui::DragValue::new(&mut score).ui(ui);
+                    ui.label(&descr.name);
+                });
+            }
 
             let market = sim.read::<Market>();
             let itemregistry = sim.read::<ItemRegistry>();
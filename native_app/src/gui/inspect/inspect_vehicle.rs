@@ -42,7 +42,7 @@ pub fn inspect_vehicle(
             }
 
             for (human_id, human) in &sim.world().humans {
-                if human.router.personal_car == Some(id) {
+                if human.router.personal_car == Some(id) || human.router.personal_bike == Some(id) {
                     ui.horizontal(|ui| {
                         ui.label("Owned by");
                         entity_link(uiworld, sim, ui, human_id);
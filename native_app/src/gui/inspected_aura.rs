@@ -22,6 +22,7 @@ pub fn inspected_aura(sim: &Simulation, uiworld: &mut UiWorld) {
             match *loc {
                 Location::Outside => {}
                 Location::Vehicle(v) => pos = sim.pos(v),
+                Location::Train(t) => pos = sim.pos(t),
                 Location::Building(b) => pos = map.buildings().get(b).map(|b| b.door_pos),
             }
         }
@@ -13,7 +13,7 @@ pub struct LotBrushResource {
 }
 
 /// Lot brush tool
-/// Allows to build houses on lots
+/// Allows to zone lots, zoned lots grow buildings on their own
 pub fn lotbrush(sim: &Simulation, uiworld: &mut UiWorld) {
     profiling::scope!("gui::lotbrush");
     let res = uiworld.read::<LotBrushResource>();
@@ -32,6 +32,8 @@ pub fn lotbrush(sim: &Simulation, uiworld: &mut UiWorld) {
     let mut col = match kind {
         LotKind::Unassigned => simulation::config().lot_unassigned_col,
         LotKind::Residential => simulation::config().lot_residential_col,
+        LotKind::Commercial => simulation::config().lot_commercial_col,
+        LotKind::Industrial => simulation::config().lot_industrial_col,
     };
 
     col.a = 0.2;
@@ -40,13 +42,17 @@ pub fn lotbrush(sim: &Simulation, uiworld: &mut UiWorld) {
     draw.circle(mpos.up(0.8), res.radius).color(col);
 
     if inp.act.contains(&InputAction::Select) {
-        for v in map
+        let lots: Vec<_> = map
             .spatial_map()
             .query_around(mpos.xy(), res.radius, ProjectFilter::LOT)
-        {
-            if let ProjectKind::Lot(id) = v {
-                commands.map_build_house(id);
-            }
+            .filter_map(|v| match v {
+                ProjectKind::Lot(id) => Some(id),
+                _ => None,
+            })
+            .filter(|&id| map.lots().get(id).is_some_and(|lot| lot.kind != kind))
+            .collect();
+        if !lots.is_empty() {
+            commands.map_set_lot_kind(lots, kind);
         }
     }
 }
@@ -23,7 +23,9 @@ pub mod roadbuild;
 pub mod roadeditor;
 pub mod selectable;
 pub mod specialbuilding;
+pub mod terraforming;
 pub mod topgui;
+pub mod utilities;
 pub mod windows;
 pub mod zoneedit;
 
@@ -39,6 +41,8 @@ pub fn run_ui_systems(sim: &Simulation, uiworld: &mut UiWorld) {
     roadeditor::roadeditor(sim, uiworld);
     specialbuilding::specialbuilding(sim, uiworld);
     addtrain::addtrain(sim, uiworld);
+    terraforming::terraforming(sim, uiworld);
+    utilities::utilities(sim, uiworld);
     zoneedit::zoneedit(sim, uiworld);
 
     // run last so other systems can have the chance to cancel select
@@ -114,6 +118,8 @@ pub enum Tool {
     LotBrush,
     SpecialBuilding,
     Train,
+    Terraforming,
+    Utilities,
 }
 
 impl Tool {
@@ -1,4 +1,4 @@
-use crate::gui::{PotentialCommands, Tool};
+use crate::gui::{ErrorTooltip, PotentialCommands, Tool};
 use crate::inputmap::{InputAction, InputMap};
 use crate::rendering::immediate::{ImmediateDraw, ImmediateSound};
 use crate::uiworld::UiWorld;
@@ -8,8 +8,10 @@ use geom::{PolyLine3, Spline3, Vec2, Vec3};
 use simulation::engine_interaction::{WorldCommand, WorldCommands};
 use simulation::map::{
     Intersection, LanePatternBuilder, Map, MapProject, ProjectFilter, ProjectKind, PylonPosition,
+    VerticalProfile, MAX_ELEVATION,
 };
 use simulation::Simulation;
+use std::borrow::Cow;
 use BuildState::{Hover, Interpolation, Start};
 use ProjectKind::{Building, Ground, Inter, Road};
 
@@ -26,7 +28,10 @@ pub struct RoadBuildResource {
     pub build_state: BuildState,
     pub pattern_builder: LanePatternBuilder,
     pub snap_to_grid: bool,
+    /// Negative offsets build tunnels
     pub height_offset: f32,
+    /// None uses the default grade of the lane pattern
+    pub max_grade: Option<f32>,
 }
 
 /// Road building tool
@@ -94,7 +99,9 @@ pub fn roadbuild(sim: &Simulation, uiworld: &mut UiWorld) {
 
     // If a road was placed recently (as it is async with networking) prepare the next road
     for command in uiworld.received_commands().iter() {
-        if let WorldCommand::MapMakeConnection { to, .. } = command {
+        if let WorldCommand::MapMakeConnection { to, .. }
+        | WorldCommand::MapMakeProfiledConnection { to, .. } = command
+        {
             if let proj @ MapProject { kind: Inter(_), .. } =
                 map.project(to.pos, 0.0, ProjectFilter::ALL)
             {
@@ -110,12 +117,12 @@ pub fn roadbuild(sim: &Simulation, uiworld: &mut UiWorld) {
 
     if inp.just_act.contains(&InputAction::UpElevation) {
         state.height_offset += 5.0;
-        state.height_offset = state.height_offset.min(100.0);
+        state.height_offset = state.height_offset.min(MAX_ELEVATION);
     }
 
     if inp.just_act.contains(&InputAction::DownElevation) {
         state.height_offset -= 5.0;
-        state.height_offset = state.height_offset.max(0.0);
+        state.height_offset = state.height_offset.max(-MAX_ELEVATION);
     }
 
     let mut cur_proj = map.project(
@@ -200,24 +207,35 @@ pub fn roadbuild(sim: &Simulation, uiworld: &mut UiWorld) {
         _ => true,
     };
 
-    state.update_drawing(map, immdraw, cur_proj, patwidth, tool, is_valid);
-    potential_command.0.clear();
-    match state.build_state {
-        Hover => {}
-        Start(selected_proj) => potential_command.set(WorldCommand::MapMakeConnection {
-            from: selected_proj,
-            to: cur_proj,
-            inter: None,
-            pat: state.pattern_builder.build(),
-        }),
+    let command = match state.build_state {
+        Hover => None,
+        Start(selected_proj) => Some(state.connection_command(map, selected_proj, cur_proj, None)),
         Interpolation(interpoint, selected_proj) => {
-            potential_command.set(WorldCommand::MapMakeConnection {
-                from: selected_proj,
-                to: cur_proj,
-                inter: Some(interpoint),
-                pat: state.pattern_builder.build(),
-            })
+            Some(state.connection_command(map, selected_proj, cur_proj, Some(interpoint)))
         }
+    };
+
+    let mut is_valid = is_valid;
+    if let Some(WorldCommand::MapMakeProfiledConnection {
+        from,
+        to,
+        inter,
+        ref pat,
+        ref profile,
+    }) = command
+    {
+        if let Err(e) = profile.check(from.pos, to.pos, inter, pat, &map.terrain) {
+            is_valid = false;
+            let mut tooltip = uiworld.write::<ErrorTooltip>();
+            tooltip.msg = Some(Cow::Owned(format!("Cannot build: {e}")));
+            tooltip.isworld = true;
+        }
+    }
+
+    state.update_drawing(map, immdraw, cur_proj, patwidth, tool, is_valid);
+    potential_command.0.clear();
+    if let Some(command) = command {
+        potential_command.set(command);
     }
 
     if is_valid && inp.just_act.contains(&InputAction::Select) {
@@ -350,6 +368,49 @@ fn check_intersect(
 }
 
 impl RoadBuildResource {
+    pub fn max_grade(&self) -> f32 {
+        self.max_grade
+            .unwrap_or_else(|| VerticalProfile::default_max_grade(&self.pattern_builder.build()))
+    }
+
+    /// Connections with an end above or below the ground carry a vertical profile to be checked
+    fn connection_command(
+        &self,
+        map: &Map,
+        from: MapProject,
+        to: MapProject,
+        inter: Option<Vec2>,
+    ) -> WorldCommand {
+        let elevation = |proj: MapProject| match proj.kind {
+            Ground => map
+                .terrain
+                .height(proj.pos.xy())
+                .map_or(0.0, |h| proj.pos.z - h - 0.3),
+            _ => 0.0,
+        };
+        let pat = self.pattern_builder.build();
+        let profile = VerticalProfile {
+            from_elevation: elevation(from),
+            to_elevation: elevation(to),
+            max_grade: self.max_grade(),
+        };
+        if profile.from_elevation.abs() < 0.5 && profile.to_elevation.abs() < 0.5 {
+            return WorldCommand::MapMakeConnection {
+                from,
+                to,
+                inter,
+                pat,
+            };
+        }
+        WorldCommand::MapMakeProfiledConnection {
+            from,
+            to,
+            inter,
+            pat,
+            profile,
+        }
+    }
+
     pub fn update_drawing(
         &self,
         map: &Map,
@@ -3,7 +3,9 @@ use crate::inputmap::{InputAction, InputMap};
 use crate::rendering::immediate::ImmediateDraw;
 use crate::uiworld::UiWorld;
 use geom::Color;
-use simulation::map::{IntersectionID, LightPolicy, TurnPolicy};
+use simulation::map::{
+    IntersectionID, LaneDirection, LightPolicy, RoadID, RoadRestrictions, TurnPolicy,
+};
 use simulation::map::{ProjectFilter, ProjectKind};
 use simulation::Simulation;
 
@@ -14,14 +16,40 @@ pub struct IntersectionComponent {
     pub light_policy: LightPolicy,
 }
 
-#[derive(Default)]
+#[derive(Clone)]
+pub struct RoadComponent {
+    pub id: RoadID,
+    pub restrictions: RoadRestrictions,
+    pub one_way: Option<LaneDirection>,
+}
+
 pub struct RoadEditorResource {
     pub inspect: Option<IntersectionComponent>,
+    pub road: Option<RoadComponent>,
     pub dirty: bool,
+    pub road_dirty: bool,
+    /// Intersections picked for a green wave, in driving order
+    pub corridor: Vec<IntersectionID>,
+    /// Speed in m/s of the vehicles riding the green wave
+    pub wave_speed: f32,
+}
+
+impl Default for RoadEditorResource {
+    fn default() -> Self {
+        Self {
+            inspect: None,
+            road: None,
+            dirty: false,
+            road_dirty: false,
+            corridor: vec![],
+            wave_speed: 13.0,
+        }
+    }
 }
 
 /// RoadEditor tool
-/// Allows to edit intersections properties like turns and signals
+/// Allows to edit intersections properties like turns and signals,
+/// and roads properties like speed limits and restrictions
 pub fn roadeditor(sim: &Simulation, uiworld: &mut UiWorld) {
     profiling::scope!("gui::roadeditor");
     let tool = uiworld.read::<Tool>();
@@ -33,9 +61,24 @@ pub fn roadeditor(sim: &Simulation, uiworld: &mut UiWorld) {
 
     if !matches!(*tool, Tool::RoadEditor) {
         state.inspect = None;
+        state.road = None;
         return;
     }
 
+    if let Some(id) = state.road.as_ref().map(|x| x.id) {
+        if let Some(road) = map.roads().get(id) {
+            imm_draw
+                .polyline(
+                    road.points().iter().map(|p| p.up(0.1)).collect::<Vec<_>>(),
+                    road.width,
+                    false,
+                )
+                .color(simulation::config().gui_primary.a(0.3));
+        } else {
+            state.road = None;
+        }
+    }
+
     if let Some(id) = state.inspect.as_ref().map(|x| x.id) {
         if let Some(inter) = map.intersections().get(id) {
             let lanes = map.lanes();
@@ -59,31 +102,63 @@ pub fn roadeditor(sim: &Simulation, uiworld: &mut UiWorld) {
         }
     }
 
+    let corridor: Vec<_> = state
+        .corridor
+        .iter()
+        .filter_map(|&id| Some(map.intersections().get(id)?.pos.up(0.5)))
+        .collect();
+    if corridor.len() >= 2 {
+        imm_draw
+            .polyline(corridor, 3.0, false)
+            .color(simulation::config().gui_success.a(0.5));
+    }
+
     let mut proj_pos = unwrap_ret!(inp.unprojected);
-    let cur_proj = map.project(proj_pos, 10.0, ProjectFilter::INTER);
+    let cur_proj = map.project(proj_pos, 10.0, ProjectFilter::INTER | ProjectFilter::ROAD);
 
     let mut proj_col;
 
-    if let ProjectKind::Inter(id) = cur_proj.kind {
-        if Some(id) != state.inspect.as_ref().map(|x| x.id) {
+    match cur_proj.kind {
+        ProjectKind::Inter(id) => {
+            if Some(id) != state.inspect.as_ref().map(|x| x.id) {
+                proj_pos = cur_proj.pos;
+            }
+            proj_col = simulation::config().gui_primary;
+        }
+        ProjectKind::Road(_) => {
             proj_pos = cur_proj.pos;
+            proj_col = simulation::config().gui_primary;
+        }
+        _ => {
+            proj_col = simulation::config().gui_disabled;
         }
-        proj_col = simulation::config().gui_primary;
-    } else {
-        proj_col = simulation::config().gui_disabled;
     }
 
     if inp.act.contains(&InputAction::Select) {
-        if let ProjectKind::Inter(id) = cur_proj.kind {
-            proj_col = simulation::config().gui_success;
-            proj_pos = cur_proj.pos;
-            let inter = &map.intersections()[id];
-            state.inspect = Some(IntersectionComponent {
-                id,
-                turn_policy: inter.turn_policy,
-                light_policy: inter.light_policy,
-            });
-            state.dirty = false;
+        match cur_proj.kind {
+            ProjectKind::Inter(id) => {
+                proj_col = simulation::config().gui_success;
+                proj_pos = cur_proj.pos;
+                let inter = &map.intersections()[id];
+                state.inspect = Some(IntersectionComponent {
+                    id,
+                    turn_policy: inter.turn_policy,
+                    light_policy: inter.light_policy,
+                });
+                state.road = None;
+                state.dirty = false;
+            }
+            ProjectKind::Road(id) => {
+                proj_col = simulation::config().gui_success;
+                state.road = Some(RoadComponent {
+                    id,
+                    restrictions: map.road_restrictions(id),
+                    one_way: map.roads()[id].one_way_direction(),
+                });
+                state.inspect = None;
+                state.road_dirty = false;
+            }
+            _ => {}
         }
     }
 
@@ -99,4 +174,11 @@ pub fn roadeditor(sim: &Simulation, uiworld: &mut UiWorld) {
         }
         state.dirty = false;
     }
+
+    if state.road_dirty {
+        if let Some(roadc) = &state.road {
+            commands.map_set_road_restrictions(roadc.id, roadc.restrictions);
+        }
+        state.road_dirty = false;
+    }
 }
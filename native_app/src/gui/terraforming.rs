@@ -0,0 +1,100 @@
+use super::Tool;
+use crate::inputmap::{InputAction, InputMap};
+use crate::rendering::immediate::ImmediateDraw;
+use crate::uiworld::UiWorld;
+use geom::Color;
+use serde::{Deserialize, Serialize};
+use simulation::engine_interaction::WorldCommand;
+use simulation::map::{TerrainBrush, TerrainBrushKind};
+use simulation::Simulation;
+use std::time::{Duration, Instant};
+
+/// How often the brush is applied while the mouse is held
+const BRUSH_PERIOD: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TerraformKind {
+    Raise,
+    Lower,
+    Flatten,
+    Smooth,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TerraformingResource {
+    pub kind: TerraformKind,
+    pub radius: f32,
+    /// Meters per application when raising or lowering, between 0 and 1 otherwise
+    pub strength: f32,
+    /// Height to flatten to, picked when the mouse is pressed
+    #[serde(skip)]
+    level: Option<f32>,
+    #[serde(skip)]
+    last_applied: Option<Instant>,
+}
+
+/// Terraforming tool
+/// Allows to raise, lower, flatten and smooth the terrain
+pub fn terraforming(sim: &Simulation, uiworld: &mut UiWorld) {
+    profiling::scope!("gui::terraforming");
+    let mut res = uiworld.write::<TerraformingResource>();
+    let tool = *uiworld.read::<Tool>();
+    let inp = uiworld.read::<InputMap>();
+    let mut draw = uiworld.write::<ImmediateDraw>();
+    let map = sim.map();
+    let commands = &mut *uiworld.commands();
+
+    if !matches!(tool, Tool::Terraforming) {
+        return;
+    }
+
+    let mpos = unwrap_ret!(inp.unprojected);
+    let mut col = match res.kind {
+        TerraformKind::Raise => Color::GREEN,
+        TerraformKind::Lower => Color::RED,
+        TerraformKind::Flatten => Color::BLUE,
+        TerraformKind::Smooth => Color::CYAN,
+    };
+    col.a = 0.2;
+    draw.circle(mpos.up(0.8), res.radius).color(col);
+
+    if inp.just_act.contains(&InputAction::Select) {
+        res.level = map.terrain.height(mpos.xy());
+        res.last_applied = None;
+    }
+    if !inp.act.contains(&InputAction::Select) {
+        return;
+    }
+    if res
+        .last_applied
+        .is_some_and(|last| last.elapsed() < BRUSH_PERIOD)
+    {
+        return;
+    }
+    res.last_applied = Some(Instant::now());
+
+    let kind = match res.kind {
+        TerraformKind::Raise => TerrainBrushKind::Raise,
+        TerraformKind::Lower => TerrainBrushKind::Lower,
+        TerraformKind::Flatten => TerrainBrushKind::Flatten(unwrap_ret!(res.level)),
+        TerraformKind::Smooth => TerrainBrushKind::Smooth,
+    };
+    commands.push(WorldCommand::MapTerrainBrush(TerrainBrush {
+        kind,
+        center: mpos.xy(),
+        radius: res.radius,
+        strength: res.strength,
+    }));
+}
+
+impl Default for TerraformingResource {
+    fn default() -> Self {
+        Self {
+            kind: TerraformKind::Raise,
+            radius: 100.0,
+            strength: 1.0,
+            level: None,
+            last_applied: None,
+        }
+    }
+}
@@ -4,13 +4,16 @@ use crate::gui::inspect::inspector;
 use crate::gui::lotbrush::LotBrushResource;
 use crate::gui::roadeditor::RoadEditorResource;
 use crate::gui::specialbuilding::{SpecialBuildKind, SpecialBuildingResource};
+use crate::gui::terraforming::{TerraformKind, TerraformingResource};
+use crate::gui::utilities::UtilitiesResource;
 use crate::gui::windows::settings::Settings;
 use crate::gui::windows::GUIWindows;
 use crate::gui::{ErrorTooltip, PotentialCommands, RoadBuildResource, Tool, UiTextures};
 use crate::inputmap::{InputAction, InputMap};
+use crate::network::NetworkState;
 use crate::uiworld::{SaveLoadState, UiWorld};
 use common::descriptions::BuildingGen;
-use common::saveload::Encoder;
+use common::saveload::{Encoder, SaveStore, AUTOSAVE_SLOTS};
 use egui::load::SizedTexture;
 use egui::{
     Align2, Color32, Context, Frame, Id, LayerId, Response, RichText, Rounding, Stroke, Style, Ui,
@@ -19,11 +22,14 @@ use egui::{
 use egui_inspect::{Inspect, InspectArgs};
 use geom::{Polygon, Vec2};
 use serde::{Deserialize, Serialize};
-use simulation::economy::{Government, Item, ItemRegistry, Money};
+use simulation::economy::{Government, Item, ItemRegistry, Money, ZoneDemand};
 use simulation::engine_interaction::WorldCommand;
 use simulation::map::{
-    BuildingKind, LanePatternBuilder, LightPolicy, MapProject, TurnPolicy, Zone,
+    BuildingKind, LaneDirection, LanePatternBuilder, LightPolicy, LotKind, MapProject, TurnPolicy,
+    UtilityKind, Zone, MAX_ELEVATION, MAX_SPEED_LIMIT, MAX_UTILITY_LINK_LENGTH, MIN_SPEED_LIMIT,
 };
+use simulation::multiplayer::Players;
+use simulation::scenario::{ScenarioOutcome, ScenarioState};
 use simulation::souls::goods_company::GoodsCompanyRegistry;
 use simulation::utils::time::{GameTime, SECONDS_PER_HOUR};
 use simulation::Simulation;
@@ -73,6 +79,7 @@ impl Gui {
     pub fn render(&mut self, ui: &Context, uiworld: &mut UiWorld, sim: &Simulation) {
         profiling::scope!("topgui::render");
         self.auto_save(uiworld);
+        Self::undo_redo(uiworld);
 
         if self.hidden {
             return;
@@ -121,8 +128,12 @@ impl Gui {
             return;
         }
 
+        let player = uiworld.read::<NetworkState>().player();
+        let money = sim
+            .read::<Players>()
+            .money(player, &sim.read::<Government>());
         egui::show_tooltip(ui, Id::new("tooltip_command_cost"), |ui| {
-            if cost > sim.read::<Government>().money {
+            if cost > money {
                 ui.colored_label(Color32::RED, format!("{cost} too expensive"));
             } else {
                 ui.label(cost.to_string());
@@ -134,7 +145,8 @@ impl Gui {
         let every = uiworld.read::<Settings>().auto_save_every.into();
         if let Some(every) = every {
             if self.last_save.elapsed() > every {
-                uiworld.write::<SaveLoadState>().please_save = true;
+                uiworld.write::<SaveLoadState>().please_save =
+                    Some(SaveStore::default().autosave_slot(AUTOSAVE_SLOTS));
                 uiworld.save_to_disk();
                 self.last_save = Instant::now();
             }
@@ -162,6 +174,8 @@ impl Gui {
             Roadbuilding,
             Bulldozer,
             Train,
+            Terraforming,
+            Utilities,
         }
         uiworld.check_present(|| Tab::Hand);
 
@@ -190,6 +204,8 @@ impl Gui {
             ("buildings", Tab::Roadbuilding, Tool::SpecialBuilding),
             ("bulldozer", Tab::Bulldozer, Tool::Bulldozer),
             ("traintool", Tab::Train, Tool::Train),
+            ("terraforming", Tab::Terraforming, Tool::Terraforming),
+            ("utilities", Tab::Utilities, Tool::Utilities),
         ];
 
         Window::new("Toolbox")
@@ -206,14 +222,20 @@ impl Gui {
                 let cur_tab = *uiworld.read::<Tab>();
 
                 for (name, tab, default_tool) in &tools {
-                    if egui::ImageButton::new(SizedTexture::new(
-                        uiworld.read::<UiTextures>().get(name),
-                        [toolbox_w, 30.0],
-                    ))
-                    .selected(std::mem::discriminant(tab) == std::mem::discriminant(&cur_tab))
-                    .ui(ui)
-                    .clicked()
-                    {
+                    let selected = std::mem::discriminant(tab) == std::mem::discriminant(&cur_tab);
+                    // tools without an icon yet get a text button
+                    let button = match uiworld.read::<UiTextures>().try_get(name) {
+                        Some(tex) => {
+                            egui::ImageButton::new(SizedTexture::new(tex, [toolbox_w, 30.0]))
+                                .selected(selected)
+                                .ui(ui)
+                        }
+                        None => ui.add_sized(
+                            [toolbox_w, 30.0],
+                            egui::SelectableLabel::new(selected, *name),
+                        ),
+                    };
+                    if button.clicked() {
                         uiworld.insert::<Tool>(*default_tool);
                         uiworld.insert(*tab);
                     }
@@ -224,6 +246,8 @@ impl Gui {
             let state = &mut *uiworld.write::<RoadEditorResource>();
             if let Some(ref mut v) = state.inspect {
                 let dirty = &mut state.dirty;
+                let corridor = &mut state.corridor;
+                let wave_speed = &mut state.wave_speed;
                 Window::new("Editor")
                     .fixed_size([150.0, 200.0])
                     .fixed_pos([w - 150.0 - toolbox_w, h * 0.5 - 30.0])
@@ -259,6 +283,82 @@ impl Gui {
                         if !had_roundabout && v.turn_policy.roundabout.is_some() {
                             v.light_policy = LightPolicy::StopSigns;
                         }
+
+                        ui.add_space(10.0);
+                        ui.label("Green wave");
+                        if ui
+                            .button(format!("add to corridor ({})", corridor.len()))
+                            .clicked()
+                            && corridor.last() != Some(&v.id)
+                        {
+                            corridor.push(v.id);
+                        }
+                        ui.horizontal(|ui| {
+                            egui::DragValue::new(wave_speed)
+                                .clamp_range(1.0..=40.0f32)
+                                .speed(0.5)
+                                .ui(ui);
+                            ui.label("speed (m/s)");
+                        });
+                        if corridor.len() >= 2 && ui.button("apply green wave").clicked() {
+                            let timings = match v.light_policy {
+                                LightPolicy::CustomLights(timings) => timings,
+                                _ => Default::default(),
+                            };
+                            uiworld.commands().map_green_wave(
+                                std::mem::take(corridor),
+                                timings,
+                                *wave_speed,
+                            );
+                        }
+                        if !corridor.is_empty() && ui.button("clear corridor").clicked() {
+                            corridor.clear();
+                        }
+                    });
+            }
+            if let Some(ref mut v) = state.road {
+                let dirty = &mut state.road_dirty;
+                Window::new("Road")
+                    .fixed_size([150.0, 200.0])
+                    .fixed_pos([w - 150.0 - toolbox_w, h * 0.5 - 30.0])
+                    .vscroll(false)
+                    .title_bar(true)
+                    .collapsible(false)
+                    .resizable(false)
+                    .show(ui, |ui| {
+                        let r = &mut v.restrictions;
+                        let mut custom_speed = r.speed_limit.is_some();
+                        if ui.checkbox(&mut custom_speed, "speed limit").changed() {
+                            r.speed_limit = custom_speed.then_some(13.0);
+                            *dirty = true;
+                        }
+                        if let Some(ref mut speed) = r.speed_limit {
+                            ui.horizontal(|ui| {
+                                *dirty |= egui::DragValue::new(speed)
+                                    .clamp_range(MIN_SPEED_LIMIT..=MAX_SPEED_LIMIT)
+                                    .speed(0.5)
+                                    .ui(ui)
+                                    .changed();
+                                ui.label("m/s");
+                            });
+                        }
+                        *dirty |= ui.checkbox(&mut r.no_trucks, "no trucks").changed();
+                        *dirty |= ui.checkbox(&mut r.bus_only, "bus lanes").changed();
+
+                        ui.add_space(10.0);
+                        ui.label("Direction");
+                        for (dir, name) in [
+                            (None, "two way"),
+                            (Some(LaneDirection::Forward), "one way forward"),
+                            (Some(LaneDirection::Backward), "one way backward"),
+                        ] {
+                            if ui.selectable_label(v.one_way == dir, name).clicked()
+                                && v.one_way != dir
+                            {
+                                v.one_way = dir;
+                                uiworld.commands().map_set_one_way(v.id, dir);
+                            }
+                        }
                     });
             }
         }
@@ -366,11 +466,26 @@ impl Gui {
                             roadbuild.height_offset = 0.0;
                         }
                         egui::DragValue::new(&mut roadbuild.height_offset)
-                            .clamp_range(0.0..=100.0f32)
+                            .clamp_range(-MAX_ELEVATION..=MAX_ELEVATION)
                             .speed(1.0)
                             .ui(ui);
                         ui.label("height off");
                     });
+                    ui.horizontal(|ui| {
+                        if ui.button("auto").clicked() {
+                            roadbuild.max_grade = None;
+                        }
+                        let mut grade = roadbuild.max_grade() * 100.0;
+                        if egui::DragValue::new(&mut grade)
+                            .clamp_range(1.0..=30.0f32)
+                            .speed(0.1)
+                            .ui(ui)
+                            .changed()
+                        {
+                            roadbuild.max_grade = Some(grade / 100.0);
+                        }
+                        ui.label("max grade %");
+                    });
                     let pat = &mut roadbuild.pattern_builder;
 
                     static BUILDERS: &[(&str, LanePatternBuilder)] = &[
@@ -381,6 +496,10 @@ impl Gui {
                         ),
                         ("Street", LanePatternBuilder::new()),
                         ("Street one-way", LanePatternBuilder::new().one_way(true)),
+                        (
+                            "Street with bike lanes",
+                            LanePatternBuilder::new().bike_lanes(true),
+                        ),
                         (
                             "Avenue",
                             LanePatternBuilder::new().n_lanes(2).speed_limit(13.0),
@@ -467,8 +586,9 @@ impl Gui {
 
         if matches!(*uiworld.read::<Tab>(), Tab::Housebrush) {
             let lbw = 120.0;
-            Window::new("House Brush")
-                .fixed_size([lbw, 50.0])
+            Window::new("Zoning")
+                .min_width(lbw)
+                .auto_sized()
                 .fixed_pos([w - toolbox_w - lbw - 10.0, h * 0.5 - 30.0])
                 .hscroll(false)
                 .title_bar(true)
@@ -476,6 +596,25 @@ impl Gui {
                 .resizable(false)
                 .show(ui, |ui| {
                     let mut cur_brush = uiworld.write::<LotBrushResource>();
+                    let demand = sim.read::<ZoneDemand>();
+                    for (kind, name, demand) in [
+                        (LotKind::Unassigned, "Unassigned", None),
+                        (
+                            LotKind::Residential,
+                            "Residential",
+                            Some(demand.residential),
+                        ),
+                        (LotKind::Commercial, "Commercial", Some(demand.commercial)),
+                        (LotKind::Industrial, "Industrial", Some(demand.industrial)),
+                    ] {
+                        ui.horizontal(|ui| {
+                            ui.radio_value(&mut cur_brush.kind, kind, name);
+                            if let Some(demand) = demand {
+                                ui.label(format!("demand: {demand}"))
+                                    .on_hover_text("Zoned lots grow buildings to meet it");
+                            }
+                        });
+                    }
 
                     ui.horizontal(|ui| {
                         egui::DragValue::new(&mut cur_brush.radius)
@@ -486,6 +625,76 @@ impl Gui {
                 });
         }
 
+        if matches!(*uiworld.read::<Tab>(), Tab::Terraforming) {
+            let lbw = 120.0;
+            Window::new("Terraforming")
+                .min_width(lbw)
+                .auto_sized()
+                .fixed_pos([w - toolbox_w - lbw - 10.0, h * 0.5 - 30.0])
+                .hscroll(false)
+                .title_bar(true)
+                .collapsible(false)
+                .resizable(false)
+                .show(ui, |ui| {
+                    let mut res = uiworld.write::<TerraformingResource>();
+                    for (kind, name) in [
+                        (TerraformKind::Raise, "Raise"),
+                        (TerraformKind::Lower, "Lower"),
+                        (TerraformKind::Flatten, "Flatten"),
+                        (TerraformKind::Smooth, "Smooth"),
+                    ] {
+                        ui.radio_value(&mut res.kind, kind, name);
+                    }
+                    ui.horizontal(|ui| {
+                        egui::DragValue::new(&mut res.radius)
+                            .clamp_range(10.0..=500.0f32)
+                            .ui(ui);
+                        ui.label("radius");
+                    });
+                    let max_strength = match res.kind {
+                        TerraformKind::Raise | TerraformKind::Lower => 10.0,
+                        TerraformKind::Flatten | TerraformKind::Smooth => 1.0,
+                    };
+                    res.strength = res.strength.min(max_strength);
+                    ui.horizontal(|ui| {
+                        egui::DragValue::new(&mut res.strength)
+                            .clamp_range(0.05..=max_strength)
+                            .speed(0.05)
+                            .ui(ui);
+                        ui.label("strength");
+                    });
+                });
+        }
+
+        if matches!(*uiworld.read::<Tab>(), Tab::Utilities) {
+            let lbw = 120.0;
+            Window::new("Utilities")
+                .min_width(lbw)
+                .auto_sized()
+                .fixed_pos([w - toolbox_w - lbw - 10.0, h * 0.5 - 30.0])
+                .hscroll(false)
+                .title_bar(true)
+                .collapsible(false)
+                .resizable(false)
+                .show(ui, |ui| {
+                    let mut res = uiworld.write::<UtilitiesResource>();
+                    for kind in UtilityKind::ALL {
+                        if ui.radio(res.kind == kind, kind.name()).clicked() && res.kind != kind {
+                            res.kind = kind;
+                            res.capacity = kind.default_capacity();
+                            res.from = None;
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        egui::DragValue::new(&mut res.capacity)
+                            .clamp_range(1.0..=200.0f32)
+                            .ui(ui);
+                        ui.label("capacity");
+                    });
+                    ui.label(format!("max length: {MAX_UTILITY_LINK_LENGTH}m"));
+                });
+        }
+
         if matches!(*uiworld.read::<Tab>(), Tab::Bulldozer) {
             let lbw = 120.0;
             Window::new("Bulldozer")
@@ -608,6 +817,22 @@ impl Gui {
         }
     }
 
+    /// Undoes or redoes the last map edit of the player
+    pub fn undo_redo(uiworld: &mut UiWorld) {
+        let inp = uiworld.read::<InputMap>();
+        let undo = inp.just_act.contains(&InputAction::Undo);
+        let redo = inp.just_act.contains(&InputAction::Redo);
+        drop(inp);
+
+        let mut commands = uiworld.commands();
+        if undo {
+            commands.undo();
+        }
+        if redo {
+            commands.redo();
+        }
+    }
+
     pub fn time_controls(&mut self, ui: &Context, uiworld: &mut UiWorld, sim: &Simulation) {
         profiling::scope!("topgui::time_controls");
         let time = sim.read::<GameTime>().daytime;
@@ -703,12 +928,29 @@ impl Gui {
                 }
 
                 if ui.add_enabled(enabled, egui::Button::new(name)).clicked() {
-                    slstate.please_save = true;
+                    slstate.please_save = Some(slstate.slot.clone());
                     self.last_save = Instant::now();
                     uiworld.save_to_disk();
                 }
 
-                ui.label(format!("Money: {}", sim.read::<Government>().money));
+                let player = uiworld.read::<NetworkState>().player();
+                let money = sim
+                    .read::<Players>()
+                    .money(player, &sim.read::<Government>());
+                ui.label(format!("Money: {money}"));
+
+                let scenario = sim.read::<ScenarioState>();
+                if let Some(ref s) = scenario.scenario {
+                    match scenario.outcome {
+                        None => ui.label(format!("Scenario: {}", s.name)),
+                        Some(ScenarioOutcome::Won) => {
+                            ui.colored_label(Color32::GREEN, format!("{}: won!", s.name))
+                        }
+                        Some(ScenarioOutcome::Lost) => {
+                            ui.colored_label(Color32::RED, format!("{}: lost", s.name))
+                        }
+                    };
+                }
 
                 let mut estate = uiworld.write::<ExitState>();
 
@@ -726,7 +968,7 @@ impl Gui {
                                 //let _tok = ui.push_style_var(StyleVar::ItemSpacing([2.0, 5.0]));
                                 if let ExitState::Saving = *estate {
                                     ui.label("Saving...");
-                                    if !slstate.please_save
+                                    if slstate.please_save.is_none()
                                         && !slstate.saving_status.load(Ordering::SeqCst)
                                     {
                                         std::process::exit(0);
@@ -735,7 +977,7 @@ impl Gui {
                                 }
                                 if ui.button("Save and exit").clicked() {
                                     if let ExitState::ExitAsk = *estate {
-                                        slstate.please_save = true;
+                                        slstate.please_save = Some(slstate.slot.clone());
                                         *estate = ExitState::Saving;
                                     }
                                 }
@@ -766,7 +1008,7 @@ impl Gui {
                     ExitState::ExitAsk => {
                         if ui.button("Save and exit").clicked() {
                             if let ExitState::ExitAsk = *estate {
-                                slstate.please_save = true;
+                                slstate.please_save = Some(slstate.slot.clone());
                                 *estate = ExitState::Saving;
                             }
                         }
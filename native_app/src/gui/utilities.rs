@@ -0,0 +1,93 @@
+use super::Tool;
+use crate::inputmap::{InputAction, InputMap};
+use crate::rendering::immediate::ImmediateDraw;
+use crate::uiworld::UiWorld;
+use geom::Color;
+use simulation::map::{BuildingID, Map, ProjectFilter, ProjectKind, UtilityKind};
+use simulation::map_dynamic::UtilityGrid;
+use simulation::Simulation;
+
+pub struct UtilitiesResource {
+    pub kind: UtilityKind,
+    /// Units per second carried by the new links
+    pub capacity: f32,
+    /// Building the next link starts from
+    pub from: Option<BuildingID>,
+}
+
+impl Default for UtilitiesResource {
+    fn default() -> Self {
+        Self {
+            kind: UtilityKind::Electricity,
+            capacity: UtilityKind::Electricity.default_capacity(),
+            from: None,
+        }
+    }
+}
+
+/// Utilities tool
+/// Allows to build power lines and pipes between buildings, clicking an existing one removes it
+pub fn utilities(sim: &Simulation, uiworld: &mut UiWorld) {
+    profiling::scope!("gui::utilities");
+    let tool = *uiworld.read::<Tool>();
+    let mut res = uiworld.write::<UtilitiesResource>();
+
+    if !matches!(tool, Tool::Utilities) {
+        res.from = None;
+        return;
+    }
+
+    let inp = uiworld.read::<InputMap>();
+    let mut draw = uiworld.write::<ImmediateDraw>();
+    let map: &Map = &sim.map();
+    let grid = sim.read::<UtilityGrid>();
+    let commands = &mut *uiworld.commands();
+
+    // links are red when their grid cannot meet its demand
+    for link in map.utility_links().values() {
+        let (Some(src), Some(dst)) = (map.buildings().get(link.src), map.buildings().get(link.dst))
+        else {
+            continue;
+        };
+        let served = grid
+            .component_of(link.kind, link.src)
+            .map_or(1.0, |c| c.served());
+        let alpha = if link.kind == res.kind { 0.9 } else { 0.3 };
+        draw.line(src.door_pos.up(3.0), dst.door_pos.up(3.0), 0.5)
+            .color(Color::new(1.0 - served, served, 0.2, alpha));
+    }
+
+    let Some(unproj) = inp.unprojected else {
+        return;
+    };
+    let hovered = match map.project(unproj, 0.0, ProjectFilter::BUILDING).kind {
+        ProjectKind::Building(id) => Some(id),
+        _ => None,
+    };
+
+    if let Some(b) = res.from.and_then(|id| map.buildings().get(id)) {
+        draw.line(b.door_pos.up(3.0), unproj.up(3.0), 0.5)
+            .color(simulation::config().gui_primary);
+    }
+
+    if !inp.just_act.contains(&InputAction::Select) {
+        return;
+    }
+    let Some(hovered) = hovered else {
+        res.from = None;
+        return;
+    };
+    match res.from.take() {
+        None => res.from = Some(hovered),
+        Some(from) if from == hovered => {}
+        Some(from) => {
+            if map.find_utility_link(res.kind, from, hovered).is_some() {
+                commands.map_remove_utility_link(res.kind, from, hovered);
+            } else {
+                commands.map_make_utility_link(res.kind, from, hovered, res.capacity);
+            }
+            // chain the links
+            res.from = Some(hovered);
+        }
+    }
+}
@@ -13,6 +13,7 @@ use egui::Widget;
 use engine::Tesselator;
 use geom::{Camera, Color, LinearColor, Spline3, Vec2};
 use simulation::engine_interaction::WorldCommand;
+use simulation::map::procgen::osm::{OsmBounds, OsmData};
 use simulation::map::{
     IntersectionID, Map, MapSubscriber, RoadSegmentKind, TraverseKind, UpdateType,
 };
@@ -63,6 +64,30 @@ impl Default for TestFieldProperties {
     }
 }
 
+/// Where to import an OpenStreetMap extract from, see [`OsmData`]
+pub struct OsmImportState {
+    path: String,
+    custom_bounds: bool,
+    bounds: OsmBounds,
+    error: String,
+}
+
+impl Default for OsmImportState {
+    fn default() -> Self {
+        Self {
+            path: "city.osm".to_string(),
+            custom_bounds: false,
+            bounds: OsmBounds {
+                min_lat: 48.85,
+                min_lon: 2.3,
+                max_lat: 48.86,
+                max_lon: 2.32,
+            },
+            error: String::new(),
+        }
+    }
+}
+
 /// debug window for various debug options
 pub fn debug(
     window: egui::Window<'_>,
@@ -171,6 +196,51 @@ pub fn debug(
             );
         }
 
+        drop(state);
+        ui.separator();
+        let mut osm = uiworld.write::<OsmImportState>();
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut osm.path);
+            ui.label("OSM XML file");
+        });
+        ui.checkbox(&mut osm.custom_bounds, "custom bounds")
+            .on_hover_text("Otherwise the bounds written in the file are used");
+        if osm.custom_bounds {
+            let b = &mut osm.bounds;
+            for (v, name) in [
+                (&mut b.min_lat, "min lat"),
+                (&mut b.min_lon, "min lon"),
+                (&mut b.max_lat, "max lat"),
+                (&mut b.max_lon, "max lon"),
+            ] {
+                ui.horizontal(|ui| {
+                    egui::DragValue::new(v).speed(0.001).ui(ui);
+                    ui.label(name);
+                });
+            }
+        }
+        if ui
+            .small_button("import OSM")
+            .on_hover_text("Import the roads and railways around the camera")
+            .clicked()
+        {
+            let bounds = osm.custom_bounds.then_some(osm.bounds);
+            osm.error = match OsmData::load(&osm.path) {
+                Ok(data) => match data.import_command(bounds, cam.xy(), &sim.map().terrain) {
+                    Some(cmd) => {
+                        uiworld.commands().push(cmd);
+                        String::new()
+                    }
+                    None => "the file has no bounds, set custom bounds".to_string(),
+                },
+                Err(e) => e.to_string(),
+            };
+        }
+        if !osm.error.is_empty() {
+            ui.colored_label(egui::Color32::RED, &osm.error);
+        }
+        drop(osm);
+
         ui.label(format!("{} pedestrians", sim.world().humans.len()));
         ui.label(format!("{} vehicles", sim.world().vehicles.len()));
 
@@ -4,7 +4,8 @@ use egui::{Align2, Color32, Ui};
 use egui_plot::{Line, PlotPoints};
 use geom::Color;
 use simulation::economy::{
-    EcoStats, ItemHistories, ItemRegistry, Market, HISTORY_SIZE, LEVEL_FREQS, LEVEL_NAMES,
+    BudgetCategory, EcoStats, Government, ItemHistories, ItemRegistry, Market, MarketMode, Money,
+    TaxKind, HISTORY_SIZE, LEVEL_FREQS, LEVEL_NAMES,
 };
 use simulation::Simulation;
 use slotmapd::Key;
@@ -15,6 +16,7 @@ enum EconomyTab {
     ImportExports,
     InternalTrade,
     MarketPrices,
+    Budget,
 }
 
 #[derive(Copy, Clone, Default)]
@@ -39,6 +41,7 @@ pub fn economy(window: egui::Window<'_>, ui: &egui::Context, uiw: &mut UiWorld,
         hist_type: Default::default(),
     });
     let mut state = uiw.write::<EconomyState>();
+    let uiw = &*uiw;
     let ecostats = sim.read::<EcoStats>();
     let registry = sim.read::<ItemRegistry>();
 
@@ -74,6 +77,12 @@ pub fn economy(window: egui::Window<'_>, ui: &egui::Context, uiw: &mut UiWorld,
                 {
                     state.tab = EconomyTab::MarketPrices;
                 }
+                if ui
+                    .selectable_label(matches!(state.tab, EconomyTab::Budget), "Budget")
+                    .clicked()
+                {
+                    state.tab = EconomyTab::Budget;
+                }
             });
 
             ui.horizontal(|ui| {
@@ -282,7 +291,12 @@ pub fn economy(window: egui::Window<'_>, ui: &egui::Context, uiw: &mut UiWorld,
                 }
                 EconomyTab::MarketPrices => {
                     ui.push_id(3, |ui| {
-                        render_market_prices(sim, ui);
+                        render_market_prices(sim, uiw, ui);
+                    });
+                }
+                EconomyTab::Budget => {
+                    ui.push_id(4, |ui| {
+                        render_budget(sim, uiw, ui, curlevel);
                     });
                 }
             }
@@ -290,14 +304,103 @@ pub fn economy(window: egui::Window<'_>, ui: &egui::Context, uiw: &mut UiWorld,
         });
 }
 
-fn render_market_prices(sim: &Simulation, ui: &mut Ui) {
+fn render_market_prices(sim: &Simulation, uiw: &UiWorld, ui: &mut Ui) {
     let registry = sim.read::<ItemRegistry>();
     let market = sim.read::<Market>();
-    egui::Grid::new("marketprices").show(ui, |ui| {
-        for (id, market) in market.iter() {
-            ui.label(&registry[*id].name);
-            ui.label(market.ext_value.to_string());
+
+    let mut dynamic = market.mode == MarketMode::DynamicPrices;
+    if ui
+        .checkbox(&mut dynamic, "Dynamic prices")
+        .on_hover_text(
+            "Prices follow supply and demand, souls pay for what they buy and companies pay wages",
+        )
+        .changed()
+    {
+        uiw.commands().set_market_mode(if dynamic {
+            MarketMode::DynamicPrices
+        } else {
+            MarketMode::FixedPrices
+        });
+    }
+
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        egui::Grid::new("marketprices")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Item");
+                ui.label("External value");
+                if dynamic {
+                    ui.label("Price");
+                    ui.label("Demand");
+                    ui.label("Supply");
+                }
+                ui.end_row();
+
+                for (id, market) in market.iter() {
+                    ui.label(&registry[*id].name);
+                    ui.label(market.ext_value.to_string());
+                    if dynamic {
+                        let col = if market.demand > market.supply {
+                            Color32::RED
+                        } else if market.supply > market.demand {
+                            Color32::GREEN
+                        } else {
+                            ui.visuals().text_color()
+                        };
+                        ui.colored_label(col, market.price.to_string());
+                        ui.label(market.demand.to_string());
+                        ui.label(market.supply.to_string());
+                    }
+                    ui.end_row();
+                }
+            });
+    });
+}
+
+fn render_budget(sim: &Simulation, uiw: &UiWorld, ui: &mut Ui, curlevel: usize) {
+    let gvt = sim.read::<Government>();
+
+    ui.label("Taxes");
+    egui::Grid::new("taxes").show(ui, |ui| {
+        for (kind, name) in [
+            (TaxKind::Income, "Income tax"),
+            (TaxKind::Sales, "Sales tax"),
+            (TaxKind::Property, "Property tax"),
+        ] {
+            let mut percent = gvt.taxes.get(kind) * 100.0;
+            ui.label(name);
+            if ui
+                .add(
+                    egui::DragValue::new(&mut percent)
+                        .clamp_range(0.0..=100.0)
+                        .speed(0.5)
+                        .suffix("%"),
+                )
+                .changed()
+            {
+                uiw.commands().set_tax(kind, percent / 100.0);
+            }
+            ui.end_row();
+        }
+    });
+
+    ui.separator();
+    ui.label(format!("Budget over the last {}", LEVEL_NAMES[curlevel]));
+    let mut balance = Money::ZERO;
+    egui::Grid::new("budget").striped(true).show(ui, |ui| {
+        for cat in BudgetCategory::ALL {
+            let total = gvt.budget.total(cat, curlevel);
+            balance += total;
+            ui.label(cat.name());
+            let col = if cat.is_income() {
+                Color32::GREEN
+            } else {
+                Color32::RED
+            };
+            ui.colored_label(col, total.to_string());
             ui.end_row();
         }
     });
+    ui.separator();
+    ui.label(format!("Balance: {balance}"));
 }
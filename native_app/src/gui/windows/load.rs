@@ -1,50 +1,140 @@
-#![allow(unused)]
 use crate::uiworld::{SaveLoadState, UiWorld};
-use egui::{Color32, DroppedFile, Widget};
+use common::saveload::{check_slot_name, SaveSlot, SaveStore};
+use egui::{Color32, Widget};
+use simulation::scenario::{Scenario, SCENARIOS_DIR};
 use simulation::Simulation;
-use std::path::PathBuf;
 
 #[derive(Default)]
 pub struct LoadState {
-    curpath: Option<PathBuf>,
+    /// Cached list of the slots, refreshed when the window is opened or on demand
+    slots: Option<Vec<SaveSlot>>,
+    /// Cached list of the scenarios, refreshed with the slots
+    scenarios: Option<Vec<Scenario>>,
+    save_as: String,
     load_fail: String,
 }
 
+/// The slot manual saves of a loaded city go to, autosaves are loaded as a new city
+pub fn city_slot(store: &SaveStore, loaded: &str) -> String {
+    if loaded.starts_with("autosave_") {
+        return new_city_slot(store);
+    }
+    loaded.to_string()
+}
+
+/// A free slot for a new city
+pub fn new_city_slot(store: &SaveStore) -> String {
+    (1..)
+        .map(|i| format!("city_{i}"))
+        .find(|slot| !store.exists(slot))
+        .unwrap()
+}
+
 /// Load window
-/// Allows to load a replay from disk and play it
+/// Allows to browse the save slots, load a save or play its replay, and save to a new slot
 pub fn load(window: egui::Window<'_>, ui: &egui::Context, uiw: &mut UiWorld, _: &Simulation) {
     window.show(ui, |ui| {
+        let store = SaveStore::default();
         let mut lstate = uiw.write::<LoadState>();
+        let mut slstate = uiw.write::<SaveLoadState>();
 
-        let has_save = ui.data_mut(|d| {
-            *d.get_persisted_mut_or_insert_with(ui.make_persistent_id("has_save"), || {
-                std::fs::metadata("world/world_replay.json").is_ok()
-            })
+        if ui.button("New Game").clicked() {
+            slstate.please_load_sim = Some(Simulation::new(true));
+            slstate.slot = new_city_slot(&store);
+        }
+
+        ui.label(format!("Current city: {}", slstate.slot));
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut lstate.save_as);
+            let name = lstate.save_as.trim().to_string();
+            if ui
+                .add_enabled(check_slot_name(&name).is_ok(), egui::Button::new("Save as"))
+                .on_disabled_hover_text("The name cannot be empty or contain /, \\, : or ..")
+                .clicked()
+            {
+                slstate.please_save = Some(name.clone());
+                slstate.slot = name;
+                lstate.slots = None;
+            }
         });
 
-        if ui.button("New Game").clicked() {
-            uiw.write::<SaveLoadState>().please_load_sim = Some(Simulation::new(true));
+        ui.separator();
+        if ui.button("Refresh").clicked() {
+            lstate.slots = None;
+            lstate.scenarios = None;
         }
 
-        if has_save {
-            if ui.button("Load world/world_replay.json").clicked() {
-                let replay = Simulation::load_replay_from_disk("world");
+        let scenarios = lstate
+            .scenarios
+            .get_or_insert_with(|| Scenario::list().iter().filter_map(Scenario::load).collect())
+            .clone();
+        egui::CollapsingHeader::new("Scenarios").show(ui, |ui| {
+            if scenarios.is_empty() {
+                ui.label(format!("No scenarios found in {SCENARIOS_DIR}"));
+            }
+            egui::Grid::new("scenarios").striped(true).show(ui, |ui| {
+                for scenario in &scenarios {
+                    ui.label(&scenario.name);
+                    ui.label(&scenario.description);
+                    if ui.button("Play").clicked() {
+                        slstate.please_load_sim =
+                            Some(Simulation::new_with_scenario(scenario.clone()));
+                        slstate.slot = new_city_slot(&store);
+                    }
+                    ui.end_row();
+                }
+            });
+        });
 
-                if let Some(replay) = replay {
-                    let (sim, loader) = Simulation::from_replay(replay);
-                    uiw.write::<SaveLoadState>().please_load = Some(loader);
-                    uiw.write::<SaveLoadState>().please_load_sim = Some(sim);
-                } else {
-                    lstate.load_fail = "Failed to load replay".to_string();
+        let slots = lstate.slots.get_or_insert_with(|| store.list()).clone();
+        if slots.is_empty() {
+            ui.label(format!("No saves found in {}", store.root().display()));
+        }
+
+        egui::Grid::new("save_slots").striped(true).show(ui, |ui| {
+            for slot in &slots {
+                ui.label(&slot.name);
+                ui.label(&slot.meta.summary);
+                if ui.button("Load").clicked() {
+                    match Simulation::load_from_slot(&store, &slot.name) {
+                        Some(sim) => {
+                            slstate.please_load_sim = Some(sim);
+                            slstate.slot = city_slot(&store, &slot.name);
+                        }
+                        None => lstate.load_fail = format!("Failed to load {}", slot.name),
+                    }
+                }
+                if ui
+                    .button("Replay")
+                    .on_hover_text("Rebuild the city by playing back everything that happened")
+                    .clicked()
+                {
+                    match Simulation::load_replay_from_slot(&store, &slot.name) {
+                        Some(replay) => {
+                            let (sim, loader) = Simulation::from_replay(replay);
+                            slstate.please_load = Some(loader);
+                            slstate.please_load_sim = Some(sim);
+                            slstate.slot = city_slot(&store, &slot.name);
+                        }
+                        None => {
+                            lstate.load_fail = format!("Failed to load replay of {}", slot.name)
+                        }
+                    }
                 }
+                ui.end_row();
             }
-        } else {
-            ui.label("No replay found in world/world_replay.json");
-        }
+        });
 
-        if let Some(ref mut loading) = uiw.write::<SaveLoadState>().please_load {
+        if let Some(ref mut loading) = slstate.please_load {
             let ticks_done = loading.pastt.0;
-            let ticks_total = loading.replay.commands.last().map(|c| c.0 .0).unwrap_or(0);
+            // the replay keeps playing after the last command to verify the last checkpoints
+            let ticks_total = loading
+                .replay
+                .commands
+                .last()
+                .map(|c| c.0 .0)
+                .max(loading.replay.checkpoints.last().map(|c| c.tick.0))
+                .unwrap_or(0);
             egui::ProgressBar::new((ticks_done as f32) / (ticks_total as f32))
                 .text(format!("Loading replay: {ticks_done}/{ticks_total}"))
                 .ui(ui);
@@ -11,7 +11,9 @@ mod economy;
 pub mod load;
 #[cfg(feature = "multiplayer")]
 pub mod network;
+mod population;
 pub mod settings;
+mod traffic;
 
 pub trait GUIWindow: Send + Sync {
     fn render_window(
@@ -64,6 +66,8 @@ impl Default for GUIWindows {
         #[cfg(feature = "multiplayer")]
         s.insert("Network", network::network, false);
         s.insert("Load", load::load, false);
+        s.insert("Traffic", traffic::traffic, false);
+        s.insert("Population", population::population, false);
         s
     }
 }
@@ -3,6 +3,8 @@ use crate::uiworld::UiWorld;
 use common::saveload::Encoder;
 use egui::{Context, RichText, Ui};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use simulation::engine_interaction::WorldCommands;
+use simulation::multiplayer::Players;
 use simulation::Simulation;
 use std::collections::BTreeMap;
 
@@ -65,6 +67,15 @@ pub fn network(window: egui::Window<'_>, ui: &Context, uiworld: &mut UiWorld, si
             NetworkState::Server(ref server) => {
                 ui.label("Running server");
                 ui.label(server.lock().unwrap().describe());
+                let mut protect = sim.read::<Players>().protect_assets;
+                if ui
+                    .checkbox(&mut protect, "Players cannot bulldoze others' assets")
+                    .changed()
+                {
+                    uiworld
+                        .write::<WorldCommands>()
+                        .set_asset_protection(protect);
+                }
                 show_hashes(ui, sim, &mut info);
             }
         }
@@ -0,0 +1,83 @@
+use crate::uiworld::UiWorld;
+use egui::{Align2, Color32};
+use egui_plot::{Line, PlotPoints};
+use simulation::souls::population::{Population, PopulationDay};
+use simulation::souls::satisfaction::SatisfactionStats;
+use simulation::Simulation;
+
+/// Population window
+/// Shows how many humans live in the city by age, how it changed over the last days
+/// and how satisfied they are
+pub fn population(
+    window: egui::Window<'_>,
+    ui: &egui::Context,
+    _uiw: &mut UiWorld,
+    sim: &Simulation,
+) {
+    let pop = sim.read::<Population>();
+    let stats = sim.read::<SatisfactionStats>().clone();
+
+    window
+        .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+        .default_size([500.0, 400.0])
+        .show(ui, move |ui| {
+            let Some(last) = pop.last_day() else {
+                ui.label("The first day has not ended yet");
+                return;
+            };
+            ui.label(format!(
+                "{} inhabitants: {} children, {} workers, {} retirees",
+                last.total(),
+                last.children,
+                last.workers,
+                last.retirees
+            ));
+            ui.label(format!(
+                "Yesterday: {} births, {} deaths, {} moved in, {} left",
+                last.births, last.deaths, last.immigrants, last.emigrants
+            ));
+
+            let pct = |v: f32| (v * 100.0).round();
+            ui.label(format!(
+                "Satisfaction: {:.0}%, {} unhappy",
+                pct(stats.satisfaction),
+                stats.unhappy
+            ));
+            ui.label(format!(
+                "Commute {:.0}% | Food {:.0}% | Employment {:.0}% | Quiet {:.0}% | Clean air {:.0}% | Utilities {:.0}%",
+                pct(stats.commute),
+                pct(stats.food),
+                pct(stats.employment),
+                pct(stats.noise),
+                pct(stats.pollution),
+                pct(stats.utilities)
+            ));
+
+            let line = |f: fn(&PopulationDay) -> u32, name: &str, color: Color32| {
+                Line::new(
+                    pop.history()
+                        .enumerate()
+                        .map(|(i, d)| [i as f64, f(d) as f64])
+                        .collect::<PlotPoints>(),
+                )
+                .color(color)
+                .name(name)
+            };
+
+            egui_plot::Plot::new("populationplot")
+                .height(200.0)
+                .allow_boxed_zoom(false)
+                .include_y(0.0)
+                .include_x(0.0)
+                .allow_drag(false)
+                .allow_scroll(false)
+                .allow_zoom(false)
+                .legend(egui_plot::Legend::default())
+                .show(ui, |ui| {
+                    ui.line(line(PopulationDay::total, "Total", Color32::WHITE));
+                    ui.line(line(|d| d.children, "Children", Color32::LIGHT_GREEN));
+                    ui.line(line(|d| d.workers, "Workers", Color32::LIGHT_BLUE));
+                    ui.line(line(|d| d.retirees, "Retirees", Color32::LIGHT_RED));
+                });
+        });
+}
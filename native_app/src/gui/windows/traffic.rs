@@ -0,0 +1,163 @@
+use crate::rendering::immediate::ImmediateDraw;
+use crate::uiworld::UiWorld;
+use egui::Align2;
+use geom::Color;
+use simulation::economy::LEVEL_NAMES;
+use simulation::map::{IntersectionID, LaneID};
+use simulation::transportation::traffic_stats::{TrafficStats, TRIP_ENDS, TRIP_SAMPLING};
+use simulation::Simulation;
+use std::cmp::Reverse;
+
+/// How many of the busiest lanes and intersections are listed
+const N_BUSIEST: usize = 10;
+
+#[derive(Default)]
+struct TrafficState {
+    curlevel: usize,
+    show_on_map: bool,
+}
+
+/// Traffic window
+/// Shows how many vehicles and pedestrians use the roads, where they wait and where they go
+pub fn traffic(window: egui::Window<'_>, ui: &egui::Context, uiw: &mut UiWorld, sim: &Simulation) {
+    uiw.check_present(TrafficState::default);
+    let mut state = uiw.write::<TrafficState>();
+    let stats = sim.read::<TrafficStats>();
+    let map = sim.map();
+    let level = state.curlevel;
+
+    let mut lanes: Vec<(LaneID, u32, u32, f32)> = stats
+        .lanes()
+        .map(|(id, t)| {
+            (
+                id,
+                stats.last_complete(level, &t.vehicles),
+                stats.last_complete(level, &t.pedestrians),
+                t.avg_speed,
+            )
+        })
+        .filter(|&(_, v, p, _)| v + p > 0)
+        .collect();
+    lanes.sort_by_key(|&(_, v, p, _)| Reverse(v + p));
+
+    let mut inters: Vec<(IntersectionID, u32, Option<f32>)> = stats
+        .intersections()
+        .map(|(id, t)| {
+            (
+                id,
+                stats.last_complete(level, &t.vehicles),
+                stats.avg_delay(level, id),
+            )
+        })
+        .filter(|&(_, v, _)| v > 0)
+        .collect();
+    inters.sort_by_key(|&(_, v, _)| Reverse(v));
+
+    if state.show_on_map {
+        let mut draw = uiw.write::<ImmediateDraw>();
+        let max = lanes
+            .iter()
+            .map(|&(_, v, _, _)| v)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        for &(id, v, _, _) in &lanes {
+            let Some(lane) = map.lanes().get(id) else {
+                continue;
+            };
+            let t = v as f32 / max as f32;
+            let points: Vec<_> = lane.points.iter().map(|p| p.up(0.3)).collect();
+            draw.polyline(points, 0.5 + 3.0 * t, false)
+                .color(Color::new(t, 1.0 - t, 0.0, 0.7));
+        }
+        for &(id, _, delay) in &inters {
+            let (Some(inter), Some(delay)) = (map.intersections().get(id), delay) else {
+                continue;
+            };
+            draw.circle(inter.pos.up(0.4), (delay * 2.0).min(30.0))
+                .color(Color::RED.a(0.3));
+        }
+    }
+
+    window
+        .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+        .default_size([500.0, 500.0])
+        .show(ui, move |ui| {
+            ui.horizontal(|ui| {
+                for (i, name) in LEVEL_NAMES.iter().enumerate() {
+                    if ui.selectable_label(i == state.curlevel, *name).clicked() {
+                        state.curlevel = i;
+                    }
+                }
+                ui.separator();
+                ui.checkbox(&mut state.show_on_map, "Show on map");
+            });
+
+            let vehicles: u32 = lanes.iter().map(|&(_, v, _, _)| v).sum();
+            let pedestrians: u32 = lanes.iter().map(|&(_, _, p, _)| p).sum();
+            ui.label(format!(
+                "During the last {}: {} vehicles and {} pedestrians entered a lane",
+                LEVEL_NAMES[level], vehicles, pedestrians
+            ));
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.heading("Busiest intersections");
+                egui::Grid::new("busiest_inters")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("Position");
+                        ui.label("Vehicles");
+                        ui.label("Average delay");
+                        ui.end_row();
+                        for &(id, v, delay) in inters.iter().take(N_BUSIEST) {
+                            let Some(inter) = map.intersections().get(id) else {
+                                continue;
+                            };
+                            ui.label(format!("{:.0} {:.0}", inter.pos.x, inter.pos.y));
+                            ui.label(v.to_string());
+                            ui.label(delay.map_or("-".to_string(), |d| format!("{d:.1}s")));
+                            ui.end_row();
+                        }
+                    });
+
+                ui.heading("Busiest lanes");
+                egui::Grid::new("busiest_lanes")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.label("From");
+                        ui.label("Vehicles");
+                        ui.label("Pedestrians");
+                        ui.label("Speed");
+                        ui.end_row();
+                        for &(id, v, p, speed) in lanes.iter().take(N_BUSIEST) {
+                            let Some(lane) = map.lanes().get(id) else {
+                                continue;
+                            };
+                            let from = lane.points.first();
+                            ui.label(format!("{:.0} {:.0}", from.x, from.y));
+                            ui.label(v.to_string());
+                            ui.label(p.to_string());
+                            ui.label(format!("{:.0} km/h", speed * 3.6));
+                            ui.end_row();
+                        }
+                    });
+
+                ui.heading("Trips")
+                    .on_hover_text(format!("One trip out of {TRIP_SAMPLING} is counted"));
+                egui::Grid::new("trips").striped(true).show(ui, |ui| {
+                    ui.label("from \\ to");
+                    for to in TRIP_ENDS {
+                        ui.label(to.name());
+                    }
+                    ui.end_row();
+                    for from in TRIP_ENDS {
+                        ui.label(from.name());
+                        for to in TRIP_ENDS {
+                            ui.label(stats.trips(level, from, to).to_string());
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+}
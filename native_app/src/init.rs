@@ -5,7 +5,9 @@ use crate::gui::lotbrush::LotBrushResource;
 use crate::gui::roadbuild::RoadBuildResource;
 use crate::gui::roadeditor::RoadEditorResource;
 use crate::gui::specialbuilding::SpecialBuildingResource;
-use crate::gui::windows::debug::{DebugObjs, DebugState, TestFieldProperties};
+use crate::gui::terraforming::TerraformingResource;
+use crate::gui::utilities::UtilitiesResource;
+use crate::gui::windows::debug::{DebugObjs, DebugState, OsmImportState, TestFieldProperties};
 use crate::gui::windows::settings::Settings;
 use crate::gui::zoneedit::ZoneEditState;
 use crate::gui::{
@@ -29,6 +31,7 @@ pub fn init() {
     #[cfg(feature = "multiplayer")]
     register_resource::<crate::gui::windows::network::NetworkConnectionInfo>("netinfo");
     register_resource::<LotBrushResource>("lot_brush");
+    register_resource::<TerraformingResource>("terraforming");
     register_resource::<Bindings>("bindings");
 
     register_resource_noserialize::<BulldozerState>();
@@ -47,12 +50,14 @@ pub fn init() {
     register_resource_noserialize::<PotentialCommands>();
     register_resource_noserialize::<ZoneEditState>();
     register_resource_noserialize::<TestFieldProperties>();
+    register_resource_noserialize::<OsmImportState>();
     register_resource_noserialize::<ReceivedCommands>();
     register_resource_noserialize::<RoadBuildResource>();
     register_resource_noserialize::<RoadEditorResource>();
     register_resource_noserialize::<SpecialBuildingResource>();
     register_resource_noserialize::<Timings>();
     register_resource_noserialize::<Tool>();
+    register_resource_noserialize::<UtilitiesResource>();
     register_resource_noserialize::<WorldCommands>();
     register_resource_noserialize::<crate::gui::windows::load::LoadState>();
     register_resource_noserialize::<crate::uiworld::SaveLoadState>();
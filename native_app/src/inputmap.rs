@@ -39,6 +39,8 @@ pub enum InputAction {
     OpenEconomyMenu,
     PausePlay,
     OpenChat,
+    Undo,
+    Redo,
 }
 
 // All unit inputs need to match
@@ -90,6 +92,8 @@ const DEFAULT_BINDINGS: &[(InputAction, &[&[UnitInput]])] = &[
     (OpenEconomyMenu, &[&[Key(K::E)]]),
     (PausePlay,       &[&[Key(K::Space)]]),
     (OpenChat, &[&[Key(K::T)]]),
+    (Undo,            &[&[Key(K::LControl), Key(K::Z)]]),
+    (Redo,            &[&[Key(K::LControl), Key(K::Y)]]),
 ];
 
 impl Default for Bindings {
@@ -323,6 +327,8 @@ impl Display for InputAction {
                 OpenEconomyMenu => "Economy Menu",
                 PausePlay => "Pause/Play",
                 OpenChat => "Interact with Chat",
+                Undo => "Undo",
+                Redo => "Redo",
             }
         )
     }
@@ -25,6 +25,13 @@ mod inner {
     pub fn sim_update(state: &mut State) {
         super::handle_singleplayer(state);
     }
+
+    impl NetworkState {
+        /// The player the commands of this game are attributed to
+        pub fn player(&self) -> simulation::multiplayer::PlayerID {
+            simulation::multiplayer::PlayerID::HOST
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -109,6 +116,7 @@ mod inner {
         ConnectConf, Frame, PollResult, ServerConfiguration, ServerPollResult, VirtualClientConf,
     };
     use simulation::engine_interaction::WorldCommands;
+    use simulation::multiplayer::PlayerID;
     use simulation::Simulation;
     use std::net::ToSocketAddrs;
     use std::sync::Mutex;
@@ -123,6 +131,18 @@ mod inner {
         Server(Server),
     }
 
+    impl NetworkState {
+        /// The player the commands of this game are attributed to
+        pub fn player(&self) -> PlayerID {
+            match self {
+                NetworkState::Singleplayer(_) | NetworkState::Server(_) => PlayerID::HOST,
+                NetworkState::Client(client) => {
+                    client.lock().unwrap().id().map_or(PlayerID::HOST, PlayerID)
+                }
+            }
+        }
+    }
+
     pub fn sim_update(state: &mut State) {
         if matches!(
             *state.uiw.read::<NetworkState>(),
@@ -198,7 +218,7 @@ mod inner {
                 let commands: WorldCommands = frame_commands
                     .inputs
                     .iter()
-                    .map(|x| x.inp.clone())
+                    .map(|x| x.inp.clone().sent_by(PlayerID(x.sent_by)))
                     .collect();
                 let t = sim.tick(&mut state.game_schedule, commands.as_ref());
                 state
@@ -50,8 +50,13 @@ impl InstancedRender {
 
             match v.vehicle.kind {
                 VehicleKind::Car => self.cars.instances.push(instance),
-                VehicleKind::Truck => self.trucks.instances.push(instance),
-                _ => {}
+                // no bus model yet, buses are told apart by their tint
+                VehicleKind::Truck | VehicleKind::Bus => self.trucks.instances.push(instance),
+                // no bike model yet either, draw the rider
+                VehicleKind::Bike => self.pedestrians.instances.push(MeshInstance {
+                    pos: trans.position.up(0.5),
+                    ..instance
+                }),
             }
         }
 
@@ -733,6 +733,8 @@ impl MapBuilders {
             let col = match lot.kind {
                 LotKind::Unassigned => simulation::config().lot_unassigned_col,
                 LotKind::Residential => simulation::config().lot_residential_col,
+                LotKind::Commercial => simulation::config().lot_commercial_col,
+                LotKind::Industrial => simulation::config().lot_industrial_col,
             };
             self.tess_lots.set_color(col);
             self.tess_lots
@@ -16,7 +16,10 @@ pub struct SaveLoadState {
     pub please_load: Option<SimulationReplayLoader>,
     pub please_load_sim: Option<Simulation>,
     pub render_reset: bool,
-    pub please_save: bool,
+    /// Slot to save the simulation to
+    pub please_save: Option<String>,
+    /// Slot of the city being played, where manual saves go
+    pub slot: String,
     pub saving_status: Arc<AtomicBool>,
 }
 
@@ -28,6 +28,8 @@ pub struct FrameInputs<I> {
 #[derive(Debug)]
 pub struct ServerInput<I> {
     pub sent_by_me: bool,
+    /// Id given by the server to the player who sent this input, the server's own player is 0
+    pub sent_by: u32,
     pub inp: I,
 }
 
@@ -101,6 +103,16 @@ impl<W: DeserializeOwned, I: Serialize + DeserializeOwned + Default> Client<W, I
         })
     }
 
+    /// Id given by the server to this client once it is authenticated
+    pub fn id(&self) -> Option<u32> {
+        match self.state {
+            ClientState::Downloading { id, .. }
+            | ClientState::CatchingUp { id, .. }
+            | ClientState::Playing { id, .. } => Some(id.0),
+            ClientState::Connecting | ClientState::Disconnected { .. } => None,
+        }
+    }
+
     #[allow(clippy::collapsible_if)]
     pub fn poll(&mut self, input: I) -> PollResult<W, I> {
         //log::info!("{:?}", &self.state);
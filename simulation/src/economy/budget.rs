@@ -0,0 +1,143 @@
+use crate::economy::{Money, HISTORY_SIZE, LEVEL_FREQS};
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
+use std::collections::BTreeMap;
+
+/// Where the money of the government comes from or goes to
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum BudgetCategory {
+    IncomeTax,
+    SalesTax,
+    PropertyTax,
+    Exports,
+    Imports,
+    Construction,
+    RoadMaintenance,
+    BuildingMaintenance,
+    Welfare,
+    /// Money given by a scenario
+    Grants,
+}
+
+impl BudgetCategory {
+    pub const ALL: [BudgetCategory; 10] = [
+        BudgetCategory::IncomeTax,
+        BudgetCategory::SalesTax,
+        BudgetCategory::PropertyTax,
+        BudgetCategory::Exports,
+        BudgetCategory::Imports,
+        BudgetCategory::Construction,
+        BudgetCategory::RoadMaintenance,
+        BudgetCategory::BuildingMaintenance,
+        BudgetCategory::Welfare,
+        BudgetCategory::Grants,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BudgetCategory::IncomeTax => "Income tax",
+            BudgetCategory::SalesTax => "Sales tax",
+            BudgetCategory::PropertyTax => "Property tax",
+            BudgetCategory::Exports => "Exports",
+            BudgetCategory::Imports => "Imports",
+            BudgetCategory::Construction => "Construction",
+            BudgetCategory::RoadMaintenance => "Road maintenance",
+            BudgetCategory::BuildingMaintenance => "Building maintenance",
+            BudgetCategory::Welfare => "Welfare",
+            BudgetCategory::Grants => "Grants",
+        }
+    }
+
+    pub fn is_income(self) -> bool {
+        matches!(
+            self,
+            BudgetCategory::IncomeTax
+                | BudgetCategory::SalesTax
+                | BudgetCategory::PropertyTax
+                | BudgetCategory::Exports
+                | BudgetCategory::Grants
+        )
+    }
+}
+
+/// One history of one category at one frequency level
+/// Amounts are from the government point of view, expenses are negative
+#[derive(Serialize, Deserialize)]
+pub struct BudgetHistoryLevel {
+    #[serde(with = "BigArray")]
+    pub past_ring: [Money; HISTORY_SIZE],
+}
+
+impl Default for BudgetHistoryLevel {
+    fn default() -> Self {
+        Self {
+            past_ring: [Money::ZERO; HISTORY_SIZE],
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct BudgetHistory {
+    levels: [BudgetHistoryLevel; LEVEL_FREQS.len()],
+}
+
+/// The ledger of the government, kept at the same frequencies as the [`crate::economy::EcoStats`]
+#[derive(Serialize, Deserialize)]
+pub struct Budget {
+    m: BTreeMap<BudgetCategory, BudgetHistory>,
+    cursors: [usize; LEVEL_FREQS.len()],
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self {
+            m: BudgetCategory::ALL
+                .iter()
+                .map(|&cat| (cat, BudgetHistory::default()))
+                .collect(),
+            cursors: [0; LEVEL_FREQS.len()],
+        }
+    }
+}
+
+impl Budget {
+    pub fn cursors(&self) -> &[usize] {
+        &self.cursors
+    }
+
+    pub fn iter_histories(
+        &self,
+        level: usize,
+    ) -> impl Iterator<Item = (BudgetCategory, &BudgetHistoryLevel)> {
+        self.m
+            .iter()
+            .filter_map(move |(cat, history)| Some((*cat, history.levels.get(level)?)))
+    }
+
+    /// Sum of the whole history of a category at the given level
+    pub fn total(&self, cat: BudgetCategory, level: usize) -> Money {
+        self.m
+            .get(&cat)
+            .and_then(|h| h.levels.get(level))
+            .map(|l| l.past_ring.iter().copied().sum())
+            .unwrap_or(Money::ZERO)
+    }
+
+    pub fn record(&mut self, cat: BudgetCategory, amount: Money) {
+        let h = self.m.entry(cat).or_default();
+        for (level, cursor) in h.levels.iter_mut().zip(&self.cursors) {
+            level.past_ring[*cursor] += amount;
+        }
+    }
+
+    pub fn advance(&mut self, tick: u32) {
+        for (c_i, (c, freq)) in self.cursors.iter_mut().zip(&LEVEL_FREQS).enumerate() {
+            if tick.is_multiple_of(*freq) {
+                *c = (*c + 1) % HISTORY_SIZE;
+                self.m.values_mut().for_each(|h| {
+                    h.levels[c_i].past_ring[*c] = Money::ZERO;
+                });
+            }
+        }
+    }
+}
@@ -70,6 +70,15 @@ impl ItemHistories {
             .filter_map(move |(id, history)| Some((*id, history.levels.get(level)?)))
     }
 
+    /// The quantity traded during the last complete bin of the level
+    pub fn last_complete(&self, level: usize, item: ItemID) -> i64 {
+        let Some(h) = self.m.get(&item) else {
+            return 0;
+        };
+        let cursor = (self.cursors[level] + HISTORY_SIZE - 1) % HISTORY_SIZE;
+        h.levels[level].past_ring_items[cursor]
+    }
+
     pub fn handle_trade(&mut self, trade: &Trade) {
         if trade.qty <= 0 {
             return;
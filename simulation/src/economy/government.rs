@@ -1,28 +1,142 @@
-use crate::economy::Money;
+use crate::economy::{Budget, BudgetCategory, Market, MarketMode, Money, Wallets};
 use crate::engine_interaction::WorldCommand;
-use crate::map::{LanePattern, MapProject, MAX_ZONE_AREA};
-use crate::{BuildingKind, GoodsCompanyRegistry, Simulation};
+use crate::map::{BuildingID, LanePattern, Map, MapProject, VerticalProfile, MAX_ZONE_AREA};
+use crate::utils::resources::Resources;
+use crate::utils::time::{Tick, TICKS_PER_SECOND};
+use crate::world::HumanID;
+use crate::{BuildingKind, GoodsCompanyRegistry, Simulation, SoulID, World};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Wage earned by every worker each second, the income tax is taken from it
+pub const WORKER_WAGE_PER_SECOND: Money = Money::new_cents(2);
+/// Rent value of a house each second, the property tax is taken from it
+pub const HOUSE_RENT_PER_SECOND: Money = Money::new_cents(1);
+pub const ROAD_MAINTENANCE_PER_KM_PER_SECOND: Money = Money::new_cents(10);
+/// Cubic meters of ground moved by terrain brushes for one buck
+pub const TERRAIN_VOLUME_PER_BUCK: f32 = 100.0;
+/// Extra cost per meter and per lane of the parts of a connection in a tunnel or on a bridge
+pub const TUNNEL_BUCKS_PER_METER: f32 = 0.3;
+pub const BRIDGE_BUCKS_PER_METER: f32 = 0.1;
+/// Cost per meter of a power line or a pipe, for each unit per second of capacity
+pub const UTILITY_LINK_BUCKS_PER_METER: f32 = 0.01;
 
 /// The government represents the player.
 #[derive(Serialize, Deserialize)]
 pub struct Government {
     pub money: Money,
+    pub taxes: TaxRates,
+    pub budget: Budget,
+}
+
+/// The government before taxes and budgets were added
+#[derive(Deserialize)]
+pub(crate) struct GovernmentV0 {
+    money: Money,
+}
+
+impl From<GovernmentV0> for Government {
+    fn from(old: GovernmentV0) -> Self {
+        Self {
+            money: old.money,
+            ..Default::default()
+        }
+    }
 }
 
 impl Default for Government {
     fn default() -> Self {
         Self {
             money: Money::new_bucks(150_000),
+            taxes: TaxRates::default(),
+            budget: Budget::default(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaxKind {
+    Income,
+    Sales,
+    Property,
+}
+
+/// Tax rates as a fraction between 0 and 1 of what is taxed
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct TaxRates {
+    /// Of the wages of the workers
+    pub income: f32,
+    /// Of the value of the goods traded inside the city
+    pub sales: f32,
+    /// Of the rent value of the houses
+    pub property: f32,
+}
+
+impl Default for TaxRates {
+    fn default() -> Self {
+        Self {
+            income: 0.1,
+            sales: 0.05,
+            property: 0.2,
+        }
+    }
+}
+
+impl TaxRates {
+    pub fn get(&self, kind: TaxKind) -> f32 {
+        match kind {
+            TaxKind::Income => self.income,
+            TaxKind::Sales => self.sales,
+            TaxKind::Property => self.property,
+        }
+    }
+
+    pub fn set(&mut self, kind: TaxKind, rate: f32) {
+        let rate = rate.clamp(0.0, 1.0);
+        match kind {
+            TaxKind::Income => self.income = rate,
+            TaxKind::Sales => self.sales = rate,
+            TaxKind::Property => self.property = rate,
         }
     }
 }
 
+/// Multiplies an amount by a rate or a factor, rounding towards zero
+pub(crate) fn scale_money(amount: Money, rate: f32) -> Money {
+    Money::new_inner((amount.inner() as f64 * rate as f64) as i64)
+}
+
 impl Government {
+    /// Changes the money of the government and records it in the budget.
+    /// The amount is positive for income and negative for expenses.
+    pub fn transaction(&mut self, cat: BudgetCategory, amount: Money) {
+        if amount == Money::ZERO {
+            return;
+        }
+        self.money += amount;
+        self.budget.record(cat, amount);
+    }
+
+    pub fn building_maintenance(kind: BuildingKind) -> Money {
+        match kind {
+            // half a cent
+            BuildingKind::House => Money::new_cents(1) / 2,
+            BuildingKind::GoodsCompany(_) => Money::new_cents(1),
+            BuildingKind::RailFreightStation | BuildingKind::TrainStation => Money::new_cents(5),
+            BuildingKind::ExternalTrading => Money::ZERO,
+        }
+    }
+
     pub fn action_cost(action: &WorldCommand, sim: &Simulation) -> Money {
         Money::new_bucks(match action {
             WorldCommand::MapBuildHouse(_) => 100,
             WorldCommand::AddTrain { n_wagons, .. } => 1000 + 100 * (*n_wagons as i64),
+            WorldCommand::MakeTransitLine { stops } => 100 + 20 * stops.len() as i64,
+            WorldCommand::UpdateTransitLine { stops, .. } => 20 * stops.len() as i64,
+            WorldCommand::AddBus { .. } => 500,
+            WorldCommand::MakePassengerLine { stations, .. } => 200 * stations.len() as i64,
+            WorldCommand::UpdatePassengerLine { stations, .. } => 50 * stations.len() as i64,
+            WorldCommand::AddPassengerTrain { n_wagons, .. } => 1000 + 100 * (*n_wagons as i64),
             WorldCommand::MapMakeConnection { from, to, pat, .. } => {
                 Self::connection_cost(from, to, pat)
             }
@@ -53,6 +167,36 @@ impl Government {
                 }
                 total
             }
+            WorldCommand::MapMakeProfiledConnection {
+                from,
+                to,
+                inter,
+                pat,
+                profile,
+            } => {
+                let map = sim.map();
+                let (from, to) = profile.apply(*from, *to, &map.terrain);
+                let (tunnel, bridge) =
+                    VerticalProfile::structure_lengths(from.pos, to.pos, *inter, pat, &map.terrain);
+                let n_lanes = (pat.lanes_forward.len() + pat.lanes_backward.len()) as f32;
+                Self::connection_cost(&from, &to, pat)
+                    + ((tunnel * TUNNEL_BUCKS_PER_METER + bridge * BRIDGE_BUCKS_PER_METER)
+                        * n_lanes) as i64
+            }
+            WorldCommand::MapMakeUtilityLink {
+                src, dst, capacity, ..
+            } => {
+                let map = sim.map();
+                let (Some(src), Some(dst)) = (map.buildings.get(*src), map.buildings.get(*dst))
+                else {
+                    return Money::ZERO;
+                };
+                20 + (src.door_pos.distance(dst.door_pos) * capacity * UTILITY_LINK_BUCKS_PER_METER)
+                    as i64
+            }
+            WorldCommand::MapTerrainBrush(ref brush) => {
+                (sim.map().terrain.brush_volume(brush) / TERRAIN_VOLUME_PER_BUCK) as i64
+            }
             WorldCommand::MapBuildSpecialBuilding { kind: x, .. } => match x {
                 BuildingKind::GoodsCompany(x) => {
                     let descr = &sim.read::<GoodsCompanyRegistry>().descriptions[*x];
@@ -80,3 +224,256 @@ impl Government {
             * (pat.lanes_forward.len() + pat.lanes_backward.len()) as i64
     }
 }
+
+/// Collects the taxes and pays the maintenance every second.
+/// Sales taxes are collected when the trades are made, see [`crate::economy::market_update`]
+pub fn fiscal_system(world: &mut World, resources: &mut Resources) {
+    profiling::scope!("economy::fiscal_system");
+    let tick = resources.read::<Tick>().0;
+    let mut gvt = resources.write::<Government>();
+    gvt.budget.advance(tick);
+
+    if !tick.is_multiple_of(TICKS_PER_SECOND) {
+        return;
+    }
+
+    let income_tax_per_worker = scale_money(WORKER_WAGE_PER_SECOND, gvt.taxes.income);
+    let dynamic = resources.read::<Market>().mode == MarketMode::DynamicPrices;
+    let mut wallets = resources.write::<Wallets>();
+    let mut n_workers: i64 = 0;
+    for (id, c) in world.companies.iter() {
+        for &worker in &c.workers.0 {
+            if dynamic {
+                // companies pay the wages they can afford, the income tax is taken from them
+                let company = SoulID::GoodsCompany(id);
+                if !wallets.can_afford(company, WORKER_WAGE_PER_SECOND) {
+                    continue;
+                }
+                wallets.pay(company, SoulID::Human(worker), WORKER_WAGE_PER_SECOND);
+                wallets.add(SoulID::Human(worker), -income_tax_per_worker);
+            }
+            n_workers += 1;
+        }
+    }
+    gvt.transaction(BudgetCategory::IncomeTax, n_workers * income_tax_per_worker);
+
+    let map = resources.read::<Map>();
+
+    let mut building_maintenance = Money::ZERO;
+    for b in map.buildings().values() {
+        building_maintenance += Government::building_maintenance(b.kind);
+    }
+
+    // the property tax is paid by the residents, so empty houses are not taxed
+    let mut residents: BTreeMap<BuildingID, Vec<HumanID>> = BTreeMap::new();
+    for (id, h) in world.humans.iter() {
+        residents.entry(h.home.house).or_default().push(id);
+    }
+    residents.retain(|&house, _| {
+        map.buildings()
+            .get(house)
+            .is_some_and(|b| b.kind == BuildingKind::House)
+    });
+    let property_tax = if dynamic {
+        // the residents of each house share its property tax, as far as they can pay it
+        let per_house = scale_money(HOUSE_RENT_PER_SECOND, gvt.taxes.property);
+        let mut collected = Money::ZERO;
+        for humans in residents.values() {
+            let share = per_house / humans.len() as i64;
+            for &id in humans {
+                let soul = SoulID::Human(id);
+                let paid = share.min(wallets.balance(soul).max(Money::ZERO));
+                wallets.add(soul, -paid);
+                collected += paid;
+            }
+        }
+        collected
+    } else {
+        scale_money(
+            residents.len() as i64 * HOUSE_RENT_PER_SECOND,
+            gvt.taxes.property,
+        )
+    };
+    gvt.transaction(BudgetCategory::PropertyTax, property_tax);
+    gvt.transaction(BudgetCategory::BuildingMaintenance, -building_maintenance);
+
+    let road_km: f32 = map.roads().values().map(|r| r.length()).sum::<f32>() / 1000.0;
+    gvt.transaction(
+        BudgetCategory::RoadMaintenance,
+        -scale_money(ROAD_MAINTENANCE_PER_KM_PER_SECOND, road_km),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fiscal_system, scale_money, HOUSE_RENT_PER_SECOND};
+    use crate::economy::{
+        market_update, BudgetCategory, Government, ItemRegistry, Market, MarketMode, Money,
+        TaxKind, Wallets,
+    };
+    use crate::souls::population::spawn_household;
+    use crate::tests::TestCtx;
+    use crate::utils::time::{Tick, TICKS_PER_SECOND};
+    use crate::{SoulID, WorldCommand};
+    use geom::{vec2, vec3};
+
+    #[test]
+    fn test_taxes_and_maintenance() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(500.0, 0.0, 0.0)]);
+        let house = test.build_house_near(vec2(250.0, 20.0));
+        spawn_household(&mut test.g, house, u32::MAX);
+
+        test.apply(&[WorldCommand::SetTax {
+            kind: TaxKind::Property,
+            rate: 2.0,
+        }]);
+        assert_eq!(test.g.read::<Government>().taxes.property, 1.0);
+
+        for _ in 0..100 {
+            test.tick();
+        }
+
+        let gvt = test.g.read::<Government>();
+        assert!(gvt.budget.total(BudgetCategory::PropertyTax, 0) > Money::ZERO);
+        assert!(gvt.budget.total(BudgetCategory::RoadMaintenance, 0) < Money::ZERO);
+        assert!(gvt.budget.total(BudgetCategory::BuildingMaintenance, 0) < Money::ZERO);
+        assert_eq!(gvt.budget.total(BudgetCategory::SalesTax, 0), Money::ZERO);
+    }
+
+    #[test]
+    fn test_property_tax_paid_by_residents() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(500.0, 0.0, 0.0)]);
+        let house = test.build_house_near(vec2(250.0, 20.0));
+        spawn_household(&mut test.g, house, u32::MAX);
+        test.apply(&[WorldCommand::SetMarketMode(MarketMode::DynamicPrices)]);
+        test.tick();
+        assert!(!test.g.world().humans.is_empty());
+
+        let humans_money = |test: &TestCtx| -> Money {
+            test.g
+                .read::<Wallets>()
+                .iter()
+                .filter(|(soul, _)| matches!(soul, SoulID::Human(_)))
+                .map(|(_, &m)| m)
+                .sum()
+        };
+        let property_tax = |test: &TestCtx| {
+            test.g
+                .read::<Government>()
+                .budget
+                .total(BudgetCategory::PropertyTax, 0)
+        };
+        let before = humans_money(&test);
+        let gvt_before = test.g.read::<Government>().money;
+        let tax_before = property_tax(&test);
+
+        test.g.write::<Tick>().0 = TICKS_PER_SECOND * 10;
+        fiscal_system(&mut test.g.world, &mut test.g.resources);
+
+        let collected = property_tax(&test) - tax_before;
+        assert!(collected > Money::ZERO);
+        // nobody works yet, so the residents only paid the property tax
+        assert_eq!(humans_money(&test), before - collected);
+        assert!(test.g.read::<Government>().money < gvt_before + collected);
+    }
+
+    #[test]
+    fn test_property_tax_of_empty_houses_and_poor_residents() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(500.0, 0.0, 0.0)]);
+        let house = test.build_house_near(vec2(250.0, 20.0));
+        spawn_household(&mut test.g, house, u32::MAX);
+        test.build_house_near(vec2(100.0, 20.0));
+        test.tick();
+        assert!(!test.g.world().humans.is_empty());
+
+        let property_tax = |test: &mut TestCtx, second: u32| {
+            let before = test
+                .g
+                .read::<Government>()
+                .budget
+                .total(BudgetCategory::PropertyTax, 0);
+            test.g.write::<Tick>().0 = TICKS_PER_SECOND * second;
+            fiscal_system(&mut test.g.world, &mut test.g.resources);
+            test.g
+                .read::<Government>()
+                .budget
+                .total(BudgetCategory::PropertyTax, 0)
+                - before
+        };
+
+        // only the occupied house is taxed in both modes
+        let rate = test.g.read::<Government>().taxes.property;
+        let fixed = property_tax(&mut test, 10);
+        assert_eq!(fixed, scale_money(HOUSE_RENT_PER_SECOND, rate));
+
+        test.apply(&[WorldCommand::SetMarketMode(MarketMode::DynamicPrices)]);
+        let humans: Vec<_> = test.g.world().humans.keys().map(SoulID::Human).collect();
+        for &soul in &humans {
+            test.g.write::<Wallets>().add(soul, Money::new_bucks(1));
+        }
+        let dynamic = property_tax(&mut test, 20);
+        assert!(dynamic > Money::ZERO && dynamic <= fixed);
+
+        // residents without money cannot pay more than they have
+        let mut wallets = test.g.write::<Wallets>();
+        for &soul in &humans {
+            let balance = wallets.balance(soul);
+            wallets.add(soul, -balance);
+        }
+        drop(wallets);
+        assert_eq!(property_tax(&mut test, 30), Money::ZERO);
+        assert!(test
+            .g
+            .read::<Wallets>()
+            .iter()
+            .all(|(_, &m)| m >= Money::ZERO));
+    }
+
+    #[test]
+    fn test_welfare_and_purchases_in_dynamic_mode() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(500.0, 0.0, 0.0)]);
+        let house = test.build_house_near(vec2(250.0, 20.0));
+        spawn_household(&mut test.g, house, u32::MAX);
+        test.apply(&[WorldCommand::SetMarketMode(MarketMode::DynamicPrices)]);
+        test.tick();
+
+        let humans: Vec<_> = test.g.world().humans.keys().collect();
+        assert!(!humans.is_empty());
+        let bread = test.g.read::<ItemRegistry>().id("bread");
+        let bread_cost = test.g.read::<Market>().cost(bread, 1);
+        assert!(bread_cost > Money::ZERO);
+
+        let mut wallets = test.g.write::<Wallets>();
+        let soul = SoulID::Human(humans[0]);
+        let balance = wallets.balance(soul);
+        wallets.add(soul, -balance);
+        assert!(!wallets.can_afford(soul, bread_cost));
+        wallets.add(soul, bread_cost);
+        assert!(wallets.can_afford(soul, bread_cost));
+        drop(wallets);
+
+        // the unemployed get the welfare once, paid by the government
+        let before = test.g.read::<Wallets>().balance(soul);
+        let welfare_before = test
+            .g
+            .read::<Government>()
+            .budget
+            .total(BudgetCategory::Welfare, 0);
+        test.g.write::<Tick>().0 = TICKS_PER_SECOND * 10;
+        market_update(&mut test.g.world, &mut test.g.resources);
+
+        let gained = test.g.read::<Wallets>().balance(soul) - before;
+        assert!(gained > Money::ZERO);
+        let welfare = test
+            .g
+            .read::<Government>()
+            .budget
+            .total(BudgetCategory::Welfare, 0)
+            - welfare_before;
+        assert_eq!(welfare, -(gained * humans.len() as i64));
+    }
+}
@@ -1,4 +1,6 @@
-use crate::economy::{Item, ItemID, ItemRegistry, Money, WORKER_CONSUMPTION_PER_SECOND};
+use crate::economy::{
+    scale_money, Item, ItemID, ItemRegistry, Money, WORKER_CONSUMPTION_PER_SECOND,
+};
 use crate::map::BuildingID;
 use crate::map_dynamic::BuildingInfos;
 use crate::souls::goods_company::GoodsCompanyID;
@@ -23,6 +25,11 @@ pub struct BuyOrder {
     pub qty: u32,
 }
 
+/// Clearing prices can't go further than this factor away from the external value
+const MAX_PRICE_FACTOR: i64 = 5;
+/// How much the clearing price moves each update when only buyers or only sellers are present
+const PRICE_ADJUSTMENT: f32 = 0.05;
+
 #[derive(Serialize, Deserialize)]
 pub struct SingleMarket {
     // todo: change i32 to Quantity
@@ -31,6 +38,52 @@ pub struct SingleMarket {
     sell_orders: BTreeMap<SoulID, SellOrder>,
     pub ext_value: Money,
     optout_exttrade: bool,
+    /// Price internal trades are made at when prices are dynamic
+    pub price: Money,
+    /// Quantity asked by the buy orders on the last trading round
+    pub demand: u32,
+    /// Quantity offered by the sell orders on the last trading round
+    pub supply: u32,
+}
+
+/// The market before dynamic prices were added
+#[derive(Deserialize)]
+pub(crate) struct SingleMarketV0 {
+    capital: BTreeMap<SoulID, i32>,
+    buy_orders: BTreeMap<SoulID, BuyOrder>,
+    sell_orders: BTreeMap<SoulID, SellOrder>,
+    ext_value: Money,
+    optout_exttrade: bool,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct MarketV0 {
+    markets: BTreeMap<ItemID, SingleMarketV0>,
+}
+
+impl From<MarketV0> for Market {
+    fn from(old: MarketV0) -> Self {
+        Self {
+            markets: old
+                .markets
+                .into_iter()
+                .map(|(id, m)| {
+                    (
+                        id,
+                        SingleMarket {
+                            capital: m.capital,
+                            buy_orders: m.buy_orders,
+                            sell_orders: m.sell_orders,
+                            ..SingleMarket::new(m.ext_value, m.optout_exttrade)
+                        },
+                    )
+                })
+                .collect(),
+            mode: MarketMode::default(),
+            all_trades: Default::default(),
+            potential: Default::default(),
+        }
+    }
 }
 
 impl SingleMarket {
@@ -41,6 +94,9 @@ impl SingleMarket {
             sell_orders: Default::default(),
             ext_value,
             optout_exttrade,
+            price: ext_value,
+            demand: 0,
+            supply: 0,
         }
     }
 
@@ -59,12 +115,26 @@ impl SingleMarket {
     }
 }
 
+/// How money is involved when goods are exchanged
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MarketMode {
+    /// Goods are exchanged between souls for free,
+    /// the government pays for imports and earns from exports
+    #[default]
+    FixedPrices,
+    /// Each item has a clearing price following supply and demand.
+    /// Souls have a [`crate::economy::Wallets`] balance, buy and sell goods at the clearing price
+    /// and settle external trades themselves, companies pay the wages of their workers.
+    DynamicPrices,
+}
+
 /// Market handles good exchanging between souls themselves and the external market.
-/// When goods are exchanges between souls, money is not involved.
+/// When goods are exchanges between souls, money is only involved with dynamic prices.
 /// When goods are exchanged with the external market, money is involved.
 #[derive(Serialize, Deserialize)]
 pub struct Market {
     markets: BTreeMap<ItemID, SingleMarket>,
+    pub mode: MarketMode,
     // reuse the trade vec to avoid allocations
     #[serde(skip)]
     all_trades: Vec<Trade>,
@@ -128,6 +198,7 @@ impl Market {
                 .iter()
                 .map(|v| (v.id, SingleMarket::new(prices[&v.id], v.optout_exttrade)))
                 .collect(),
+            mode: MarketMode::default(),
             all_trades: Default::default(),
             potential: Default::default(),
         }
@@ -183,6 +254,21 @@ impl Market {
             .insert(soul, BuyOrder { pos: near, qty });
     }
 
+    /// The most buying the quantity of an item can cost, nothing with fixed prices
+    pub fn cost(&self, kind: ItemID, qty: u32) -> Money {
+        if self.mode != MarketMode::DynamicPrices {
+            return Money::ZERO;
+        }
+        self.markets
+            .get(&kind)
+            .map_or(Money::ZERO, |m| m.price.max(m.ext_value) * qty as i64)
+    }
+
+    /// Withdraws the buy order of the agent, if any
+    pub fn cancel_buy(&mut self, soul: SoulID, kind: ItemID) {
+        self.m(kind).buy_orders.remove(&soul);
+    }
+
     pub fn buy_until(&mut self, soul: SoulID, near: Vec2, kind: ItemID, qty: u32) {
         let c = self.capital(soul, kind);
         if c >= qty as i32 {
@@ -219,6 +305,13 @@ impl Market {
         self.all_trades.clear();
 
         for (&kind, market) in &mut self.markets {
+            market.demand = market.buy_orders.values().map(|o| o.qty).sum();
+            market.supply = market
+                .sell_orders
+                .iter()
+                .map(|(soul, o)| o.qty.min(market.capital(*soul).unwrap_or(0).max(0) as u32))
+                .sum();
+
             // Naive O(n²) alg
             // We don't immediatly apply the trades, because we want to find the nearest-positioned trades
             for (&seller, sorder) in &market.sell_orders {
@@ -355,6 +448,24 @@ impl Market {
         &self.all_trades
     }
 
+    /// Moves the clearing prices towards the side of the market that is lacking:
+    /// up when there is more demand than supply, down otherwise
+    pub fn update_prices(&mut self) {
+        for market in self.markets.values_mut() {
+            let demand = market.demand as f32;
+            let supply = market.supply as f32;
+            if demand + supply == 0.0 {
+                continue;
+            }
+            let pressure = (demand - supply) / (demand + supply);
+            let price = scale_money(market.price, 1.0 + PRICE_ADJUSTMENT * pressure);
+            market.price = price.clamp(
+                market.ext_value / MAX_PRICE_FACTOR,
+                market.ext_value * MAX_PRICE_FACTOR,
+            );
+        }
+    }
+
     pub fn inner(&self) -> &BTreeMap<ItemID, SingleMarket> {
         &self.markets
     }
@@ -437,8 +548,8 @@ fn calculate_prices(
 
 #[cfg(test)]
 mod tests {
-    use super::Market;
-    use crate::economy::{ItemRegistry, WORKER_CONSUMPTION_PER_SECOND};
+    use super::{Market, MAX_PRICE_FACTOR};
+    use crate::economy::{ItemRegistry, Money, WORKER_CONSUMPTION_PER_SECOND};
     use crate::souls::goods_company::{GoodsCompanyDescription, Recipe};
     use crate::world::CompanyID;
     use crate::{GoodsCompanyRegistry, SoulID};
@@ -492,6 +603,47 @@ mod tests {
         assert_eq!(t0.qty, 2);
     }
 
+    #[test]
+    fn test_clearing_price() {
+        let seller = SoulID::GoodsCompany(mk_ent((1 << 32) | 1));
+        let buyer = SoulID::GoodsCompany(mk_ent((1 << 32) | 2));
+
+        let mut registry = ItemRegistry::default();
+        registry.load_item_definitions(
+            r#"
+          [{
+            "name": "cereal",
+            "label": "Cereal"
+          }]
+        "#,
+        );
+        let cereal = registry.id("cereal");
+
+        let mut m = Market::new(&registry, &GoodsCompanyRegistry::default());
+        let ext_value = Money::new_bucks(10);
+        m.m(cereal).ext_value = ext_value;
+        m.m(cereal).price = ext_value;
+
+        // shortage: only buyers
+        for _ in 0..100 {
+            m.buy(buyer, Vec2::ZERO, cereal, 5);
+            m.make_trades();
+            m.update_prices();
+        }
+        assert_eq!(m.inner()[&cereal].demand, 5);
+        assert_eq!(m.inner()[&cereal].price, ext_value * MAX_PRICE_FACTOR);
+
+        // glut: only sellers keeping their stock
+        m.produce(seller, cereal, 10);
+        m.sell(seller, Vec2::X, cereal, 10, 10);
+        for _ in 0..200 {
+            m.make_trades();
+            m.update_prices();
+        }
+        assert_eq!(m.inner()[&cereal].supply, 10);
+        assert_eq!(m.inner()[&cereal].price, ext_value / MAX_PRICE_FACTOR);
+    }
+
     #[test]
     fn calculate_prices() {
         let mut registry = ItemRegistry::default();
@@ -5,7 +5,8 @@
 //! The economy is divided in 2 parts:
 //!
 //! - The market, which is the place where goods are exchanged.
-//! - The government, which is the entity representing the player
+//! - The government, which is the entity representing the player, it collects taxes and keeps
+//!   a budget of its income and expenses
 //!
 use crate::utils::resources::Resources;
 use crate::World;
@@ -17,17 +18,23 @@ use std::fmt::{Debug, Display, Formatter};
 use std::iter::Sum;
 use std::ops::{Add, AddAssign, Div, Mul, Neg, SubAssign};
 
+mod budget;
 mod ecostats;
 mod government;
 mod item;
 mod market;
+mod wallets;
+mod zoning;
 
 use crate::utils::time::{Tick, TICKS_PER_SECOND};
 use crate::world::HumanID;
+pub use budget::*;
 pub use ecostats::*;
 pub use government::*;
 pub use item::*;
 pub use market::*;
+pub use wallets::*;
+pub use zoning::*;
 
 const WORKER_CONSUMPTION_PER_SECOND: Money = Money::new_cents(1);
 
@@ -199,15 +206,37 @@ pub fn market_update(world: &mut World, resources: &mut Resources) {
     let mut m = resources.write::<Market>();
     let job_opening = resources.read::<ItemRegistry>().id("job-opening");
     let mut gvt = resources.write::<Government>();
+    let mut wallets = resources.write::<Wallets>();
     let tick = resources.read::<Tick>().0;
+    let dynamic = m.mode == MarketMode::DynamicPrices;
 
     if tick % TICKS_PER_SECOND == 0 {
-        gvt.money -= n_workers as i64 * WORKER_CONSUMPTION_PER_SECOND;
+        if dynamic {
+            // workers live off their wages, the others off the welfare paid to their wallet
+            let mut n_paid = 0;
+            for (id, h) in world.humans.iter() {
+                if h.work.is_none() {
+                    wallets.add(SoulID::Human(id), WORKER_CONSUMPTION_PER_SECOND);
+                    n_paid += 1;
+                }
+            }
+            gvt.transaction(
+                BudgetCategory::Welfare,
+                -(n_paid * WORKER_CONSUMPTION_PER_SECOND),
+            );
+            m.update_prices();
+        } else {
+            gvt.transaction(
+                BudgetCategory::Welfare,
+                -(n_workers as i64 * WORKER_CONSUMPTION_PER_SECOND),
+            );
+        }
     }
 
-    let trades = m.make_trades();
+    // copied so that the prices can still be read while settling the trades
+    let trades = m.make_trades().to_vec();
 
-    resources.write::<EcoStats>().advance(tick, trades);
+    resources.write::<EcoStats>().advance(tick, &trades);
 
     for &trade in trades.iter() {
         log::debug!("A trade was made! {:?}", trade);
@@ -218,7 +247,36 @@ pub fn market_update(world: &mut World, resources: &mut Resources) {
                 comp.workers.0.push(trade.buyer.soul().try_into().unwrap())
             }
         }
-        gvt.money += trade.money_delta;
+        match (trade.buyer, trade.seller) {
+            // with dynamic prices, souls settle external trades themselves
+            (TradeTarget::ExternalTrade, TradeTarget::Soul(seller)) if dynamic => {
+                wallets.add(seller, trade.money_delta)
+            }
+            (TradeTarget::Soul(buyer), TradeTarget::ExternalTrade) if dynamic => {
+                wallets.add(buyer, trade.money_delta)
+            }
+            (TradeTarget::ExternalTrade, _) => {
+                gvt.transaction(BudgetCategory::Exports, trade.money_delta)
+            }
+            (_, TradeTarget::ExternalTrade) => {
+                gvt.transaction(BudgetCategory::Imports, trade.money_delta)
+            }
+            (TradeTarget::Soul(buyer), TradeTarget::Soul(seller)) if trade.kind != job_opening => {
+                let market = &m.inner()[&trade.kind];
+                if dynamic {
+                    let value = market.price * trade.qty as i64;
+                    let tax = scale_money(value, gvt.taxes.sales);
+                    wallets.pay(buyer, seller, value);
+                    wallets.add(seller, -tax);
+                    gvt.transaction(BudgetCategory::SalesTax, tax)
+                } else {
+                    let value = market.ext_value * trade.qty as i64;
+                    let tax = scale_money(value, gvt.taxes.sales);
+                    gvt.transaction(BudgetCategory::SalesTax, tax)
+                }
+            }
+            _ => {}
+        }
 
         match trade.seller {
             TradeTarget::Soul(id) => {
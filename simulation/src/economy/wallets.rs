@@ -0,0 +1,48 @@
+use crate::economy::Money;
+use crate::SoulID;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// How far in debt a company can go to buy its inputs and pay its workers
+pub const COMPANY_CREDIT: Money = Money::new_bucks(1000);
+
+/// Money balances of the souls, only used when the market has dynamic prices.
+/// Humans only buy what they can afford, companies can borrow up to [`COMPANY_CREDIT`].
+#[derive(Default, Serialize, Deserialize)]
+pub struct Wallets {
+    m: BTreeMap<SoulID, Money>,
+}
+
+impl Wallets {
+    pub fn balance(&self, soul: SoulID) -> Money {
+        self.m.get(&soul).copied().unwrap_or(Money::ZERO)
+    }
+
+    /// Whether the soul has enough money, or credit, to spend the amount
+    pub fn can_afford(&self, soul: SoulID, amount: Money) -> bool {
+        let credit = match soul {
+            SoulID::GoodsCompany(_) => COMPANY_CREDIT,
+            _ => Money::ZERO,
+        };
+        self.balance(soul) + credit >= amount
+    }
+
+    /// Adds money to a soul, or removes it if the amount is negative
+    pub fn add(&mut self, soul: SoulID, amount: Money) {
+        *self.m.entry(soul).or_default() += amount;
+    }
+
+    pub fn pay(&mut self, from: SoulID, to: SoulID, amount: Money) {
+        self.add(from, -amount);
+        self.add(to, amount);
+    }
+
+    /// A soul was removed from the world, its money disappears with it
+    pub fn remove(&mut self, soul: SoulID) {
+        self.m.remove(&soul);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&SoulID, &Money)> {
+        self.m.iter()
+    }
+}
@@ -0,0 +1,364 @@
+use crate::economy::{EcoStats, ItemID, ItemRegistry, Market, LEVEL_FREQS};
+use crate::map::{BuildingKind, LotID, LotKind, Map, ProjectFilter, ProjectKind};
+use crate::map_dynamic::BuildingInfos;
+use crate::souls::goods_company::GoodsCompanyRegistry;
+use crate::utils::time::Tick;
+use crate::{Simulation, SoulID};
+use common::descriptions::CompanyKind;
+use geom::{Intersect, Vec2, OBB};
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Every how many ticks the zoned lots can grow buildings, the imports are measured over the same period
+pub const ZONE_GROWTH_PERIOD: u32 = LEVEL_FREQS[0];
+/// Most buildings grown in each kind of zone per growth period
+pub const MAX_GROWTH_PER_PERIOD: usize = 3;
+/// Ticks to wait before growing another company making the same item,
+/// so that the last one has time to hire and produce
+pub const ITEM_GROWTH_COOLDOWN: u32 = LEVEL_FREQS[2];
+/// How many of the lots closest to where a building is needed are tried
+const MAX_LOTS_TRIED: usize = 20;
+
+/// What the city lacks, which the zoned lots grow buildings to answer.
+/// Updated every [`ZONE_GROWTH_PERIOD`] by [`zone_growth_system`]
+#[derive(Default, Serialize, Deserialize)]
+pub struct ZoneDemand {
+    /// Job openings that no one looks for, each new house brings a worker
+    pub residential: u32,
+    /// Goods sold by stores that were imported during the last period
+    pub commercial: u32,
+    /// Goods made by factories that were imported during the last period
+    pub industrial: u32,
+    /// When a company making each item was last grown
+    grown: BTreeMap<ItemID, Tick>,
+}
+
+/// A need of the city, located around where it is felt
+struct Need {
+    qty: u32,
+    pos: Vec2,
+}
+
+/// Averages the positions weighted by the quantities
+fn need(it: impl Iterator<Item = (u32, Vec2)>) -> Option<Need> {
+    let (qty, sum) = it.fold((0, Vec2::ZERO), |(qty, sum), (q, pos)| {
+        (qty + q, sum + pos * q as f32)
+    });
+    (qty > 0).then(|| Need {
+        qty,
+        pos: sum / qty as f32,
+    })
+}
+
+/// The footprint of a building of the given size on a lot, facing the same road.
+/// Returns None if it would cover something else than ground or lots of the same zone
+fn footprint(map: &Map, lot: LotID, size: f32) -> Option<OBB> {
+    let lot = map.lots().get(lot)?;
+    // the second axis of a lot points away from its road
+    let [_, depth] = lot.shape.axis();
+    let axis = depth.normalize();
+    let front = lot.shape.center() - depth * 0.5;
+    let obb = OBB::new(front + axis * size * 0.5, axis, size, size);
+
+    // lots next to each other touch, so only what is well inside counts
+    let inner = obb.expand(-1.0);
+    let blocked = map
+        .spatial_map()
+        .query(inner, ProjectFilter::ALL)
+        .any(|k| match k {
+            ProjectKind::Lot(id) => map.lots().get(id).is_none_or(|l| l.kind != lot.kind),
+            _ => true,
+        });
+    (!blocked).then_some(obb)
+}
+
+/// Lots of the zone, closest to the position first
+fn closest_lots(map: &Map, kind: LotKind, pos: Vec2) -> Vec<LotID> {
+    let mut lots: Vec<_> = map
+        .lots()
+        .values()
+        .filter(|l| l.kind == kind)
+        .map(|l| (l.id, l.shape.center().distance2(pos)))
+        .collect();
+    lots.sort_by_key(|&(_, d)| OrderedFloat(d));
+    lots.into_iter()
+        .take(MAX_LOTS_TRIED)
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Grows houses, stores and factories on the zoned lots depending on what the city lacks.
+/// The buildings are owned by no one, the companies get their souls from
+/// [`crate::souls::add_souls_to_empty_buildings`] and the houses their inhabitants from
+/// [`crate::souls::population::population_system`]
+pub fn zone_growth_system(sim: &mut Simulation) {
+    profiling::scope!("economy::zone_growth_system");
+    let tick = *sim.read::<Tick>();
+    if !tick.0.is_multiple_of(ZONE_GROWTH_PERIOD) {
+        return;
+    }
+
+    let map = sim.map();
+    let market = sim.read::<Market>();
+    let registry = sim.read::<ItemRegistry>();
+    let companies = sim.read::<GoodsCompanyRegistry>();
+    let imports = &sim.read::<EcoStats>().imports;
+    let world = sim.world();
+    let door = |b| map.buildings().get(b).map(|b| b.door_pos.xy());
+
+    // unfilled jobs, where the companies are
+    let job_opening = registry.id("job-opening");
+    let seekers = market.inner().get(&job_opening).map_or(0, |m| m.demand);
+    let jobs = need(world.companies.iter().filter_map(|(id, c)| {
+        let open = market.capital(SoulID::GoodsCompany(id), job_opening);
+        (open > 0).then_some((open as u32, door(c.comp.building)?))
+    }));
+    let mut residential = jobs.map(|n| Need {
+        qty: n.qty.saturating_sub(seekers),
+        pos: n.pos,
+    });
+    // a new city attracts its first inhabitants
+    if world.humans.is_empty() && residential.as_ref().is_none_or(|n| n.qty == 0) {
+        residential = map
+            .lots()
+            .values()
+            .find(|l| l.kind == LotKind::Residential)
+            .map(|l| Need {
+                qty: 1,
+                pos: l.shape.center(),
+            });
+    }
+
+    // imported goods, near the consumers
+    let consumers = need(
+        world
+            .humans
+            .values()
+            .filter_map(|h| Some((1, door(h.home.house)?))),
+    );
+    let mut commercial = vec![];
+    let mut industrial = vec![];
+    let mut zone = sim.write::<ZoneDemand>();
+    for item in registry.iter() {
+        let qty = imports.last_complete(0, item.id).max(0) as u32;
+        if qty == 0 {
+            continue;
+        }
+        let grown = zone.grown.get(&item.id);
+        if grown.is_some_and(|t| tick.0 < t.0 + ITEM_GROWTH_COOLDOWN) {
+            continue;
+        }
+        let Some(descr) = companies.descriptions.values().find(|d| {
+            d.zone.is_none()
+                && d.recipe.production.iter().any(|&(i, _)| i == item.id)
+                && matches!(d.kind, CompanyKind::Store | CompanyKind::Factory { .. })
+        }) else {
+            continue;
+        };
+        match descr.kind {
+            CompanyKind::Store => {
+                let Some(ref c) = consumers else {
+                    continue;
+                };
+                commercial.push((qty, item.id, descr.id, c.pos));
+            }
+            _ => {
+                // near the companies using it
+                let Some(users) = need(world.companies.values().filter_map(|c| {
+                    c.comp
+                        .recipe
+                        .consumption
+                        .iter()
+                        .any(|&(i, _)| i == item.id)
+                        .then_some((1, door(c.comp.building)?))
+                })) else {
+                    continue;
+                };
+                industrial.push((qty, item.id, descr.id, users.pos));
+            }
+        }
+    }
+    // the most imported first
+    commercial.sort_by_key(|&(qty, ..)| std::cmp::Reverse(qty));
+    industrial.sort_by_key(|&(qty, ..)| std::cmp::Reverse(qty));
+
+    zone.residential = residential.as_ref().map_or(0, |n| n.qty);
+    zone.commercial = commercial.iter().map(|&(qty, ..)| qty).sum();
+    zone.industrial = industrial.iter().map(|&(qty, ..)| qty).sum();
+
+    let mut houses = vec![];
+    if let Some(ref n) = residential {
+        houses = closest_lots(&map, LotKind::Residential, n.pos);
+        houses.truncate((n.qty as usize).min(MAX_GROWTH_PER_PERIOD));
+    }
+
+    let mut to_build = vec![];
+    for (kind, needs) in [
+        (LotKind::Commercial, &commercial),
+        (LotKind::Industrial, &industrial),
+    ] {
+        for &(_, item, company, pos) in needs.iter().take(MAX_GROWTH_PER_PERIOD) {
+            let descr = &companies.descriptions[company];
+            let Some(obb) = closest_lots(&map, kind, pos)
+                .into_iter()
+                .find_map(|lot| footprint(&map, lot, descr.size))
+            else {
+                continue;
+            };
+            // do not grow two buildings on the same lots
+            if to_build
+                .iter()
+                .any(|(o, ..): &(OBB, _, _)| o.expand(-1.0).intersects(&obb))
+            {
+                continue;
+            }
+            zone.grown.insert(item, tick);
+            to_build.push((obb, company, descr.bgen));
+        }
+    }
+    zone.grown
+        .retain(|_, t| tick.0 < t.0 + ITEM_GROWTH_COOLDOWN);
+
+    drop((map, market, registry, companies, zone));
+
+    for lot in houses {
+        if let Some(b) = sim.map_mut().build_house(lot) {
+            sim.write::<BuildingInfos>().insert(b);
+        }
+    }
+    for (obb, company, gen) in to_build {
+        let b = sim.map_mut().build_special_building(
+            &obb,
+            BuildingKind::GoodsCompany(company),
+            gen,
+            None,
+        );
+        if let Some(b) = b {
+            sim.write::<BuildingInfos>().insert(b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{zone_growth_system, ZoneDemand, ZONE_GROWTH_PERIOD};
+    use crate::economy::{EcoStats, ItemRegistry, Money, Trade, TradeTarget};
+    use crate::map::{BuildingKind, LotID, LotKind};
+    use crate::souls::human::spawn_human;
+    use crate::tests::TestCtx;
+    use crate::utils::time::{GameTime, SECONDS_PER_DAY};
+    use crate::{SoulID, WorldCommand};
+    use geom::{vec2, vec3, Vec2};
+
+    fn lots_near(test: &TestCtx, p: Vec2, radius: f32) -> Vec<LotID> {
+        test.g
+            .map()
+            .lots()
+            .values()
+            .filter(|l| l.shape.center().distance(p) < radius)
+            .map(|l| l.id)
+            .collect()
+    }
+
+    fn count(test: &TestCtx, kind: LotKind) -> usize {
+        let map = test.g.map();
+        map.lots().values().filter(|l| l.kind == kind).count()
+    }
+
+    #[test]
+    fn test_zoning_grows_houses() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0., 0., 0.), vec3(300., 0., 0.)]);
+        let lots = lots_near(&test, vec2(150.0, 0.0), 100.0);
+        assert!(lots.len() > 2);
+
+        test.apply(&[WorldCommand::MapSetLotKind {
+            lots: lots.clone(),
+            kind: LotKind::Residential,
+        }]);
+        assert_eq!(count(&test, LotKind::Residential), lots.len());
+
+        test.apply(&[WorldCommand::Undo]);
+        assert_eq!(count(&test, LotKind::Residential), 0);
+
+        test.apply(&[WorldCommand::MapSetLotKind {
+            lots: lots.clone(),
+            kind: LotKind::Residential,
+        }]);
+        // the first tick is a growth tick, an empty city gets its first house
+        test.tick();
+        let houses = test
+            .g
+            .map()
+            .buildings()
+            .values()
+            .filter(|b| b.kind == BuildingKind::House)
+            .count();
+        assert_eq!(houses, 1);
+        assert_eq!(count(&test, LotKind::Residential), lots.len() - 1);
+
+        // the first inhabitant moves in the next day and does not need more houses
+        let mut time = test.g.write::<GameTime>();
+        *time = GameTime::new(0.0, time.timestamp + SECONDS_PER_DAY as f64);
+        drop(time);
+        for _ in 0..ZONE_GROWTH_PERIOD {
+            test.tick();
+        }
+        assert_eq!(test.g.world().humans.len(), 1);
+        assert_eq!(test.g.read::<ZoneDemand>().residential, 0);
+        assert_eq!(count(&test, LotKind::Residential), lots.len() - 1);
+    }
+
+    #[test]
+    fn test_zoning_grows_stores_for_imports() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0., 0., 0.), vec3(300., 0., 0.)]);
+        let house = test.build_house_near(vec2(20.0, 20.0));
+        spawn_human(&mut test.g, house).unwrap();
+
+        let lots = lots_near(&test, vec2(250.0, 0.0), 50.0);
+        test.apply(&[WorldCommand::MapSetLotKind {
+            lots,
+            kind: LotKind::Commercial,
+        }]);
+
+        // bread was imported during the last period
+        let bread = test.g.read::<ItemRegistry>().id("bread");
+        {
+            let mut stats = test.g.write::<EcoStats>();
+            stats.imports.handle_trade(&Trade {
+                buyer: TradeTarget::Soul(SoulID::Human(
+                    test.g.world().humans.keys().next().unwrap(),
+                )),
+                seller: TradeTarget::ExternalTrade,
+                qty: 5,
+                kind: bread,
+                money_delta: Money::ZERO,
+            });
+            stats.imports.advance(ZONE_GROWTH_PERIOD);
+        }
+
+        zone_growth_system(&mut test.g);
+        assert_eq!(test.g.read::<ZoneDemand>().commercial, 5);
+        let map = test.g.map();
+        let store = map
+            .buildings()
+            .values()
+            .find(|b| matches!(b.kind, BuildingKind::GoodsCompany(_)))
+            .unwrap();
+        assert!(store.obb.center().distance(vec2(250.0, 0.0)) < 60.0);
+        drop(map);
+
+        // the same item is not grown again right away
+        zone_growth_system(&mut test.g);
+        let companies = test
+            .g
+            .map()
+            .buildings()
+            .values()
+            .filter(|b| matches!(b.kind, BuildingKind::GoodsCompany(_)))
+            .count();
+        assert_eq!(companies, 1);
+    }
+}
@@ -7,21 +7,30 @@ use serde::{Deserialize, Serialize};
 use geom::{vec3, Vec2, OBB};
 use WorldCommand::*;
 
-use crate::economy::Government;
+use crate::economy::{Government, Market, MarketMode, TaxKind};
 use crate::map::procgen::{load_parismap, load_testfield};
 use crate::map::{
-    BuildingID, BuildingKind, IntersectionID, LaneID, LanePattern, LanePatternBuilder, LightPolicy,
-    LotID, Map, MapProject, ProjectKind, RoadID, Terrain, TurnPolicy, Zone,
+    BuildingID, BuildingKind, IntersectionID, LaneDirection, LaneID, LanePattern,
+    LanePatternBuilder, LightPolicy, LightTimings, LotID, LotKind, Map, MapProject, ProjectKind,
+    RoadID, RoadRestrictions, Terrain, TerrainBrush, TurnPolicy, UtilityKind, VerticalProfile,
+    Zone,
 };
 use crate::map_dynamic::{BuildingInfos, ParkingManagement};
 use crate::multiplayer::chat::Message;
-use crate::multiplayer::MultiplayerState;
+use crate::multiplayer::{MultiplayerState, PlayerID, Players};
+use crate::scenario::Scenario;
+use crate::transportation::passenger_rail::{
+    spawn_passenger_train, PassengerLineID, PassengerLines,
+};
 use crate::transportation::testing_vehicles::RandomVehicles;
 use crate::transportation::train::{spawn_train, RailWagonKind};
+use crate::transportation::transit::{spawn_bus, TransitLineID, TransitLines, TransitStop};
 use crate::transportation::{spawn_parked_vehicle_with_spot, unpark, VehicleKind};
 use crate::utils::rand_provider::RandProvider;
 use crate::utils::time::{GameTime, Tick};
-use crate::{Replay, Simulation, SimulationOptions};
+use crate::utils::undo::{inverse_before, UndoCommand, UndoHistory};
+use crate::world::{TrainEnt, VehicleEnt, WagonEnt};
+use crate::{GoodsCompanyRegistry, ParCommandBuffer, Replay, Simulation, SimulationOptions};
 
 #[derive(Clone, Default)]
 pub struct WorldCommands {
@@ -66,6 +75,7 @@ pub enum WorldCommand {
     MapBuildSpecialBuilding {
         pos: OBB,
         kind: BuildingKind,
+        #[serde(with = "building_gen_serde")]
         gen: BuildingGen,
         #[serde(default)]
         zone: Option<Zone>,
@@ -81,6 +91,91 @@ pub enum WorldCommand {
         zone: Zone,
     },
     SetGameTime(GameTime),
+    MakeTransitLine {
+        stops: Vec<TransitStop>,
+    },
+    UpdateTransitLine {
+        line: TransitLineID,
+        stops: Vec<TransitStop>,
+    },
+    RemoveTransitLine(TransitLineID),
+    AddBus {
+        line: TransitLineID,
+    },
+    MakePassengerLine {
+        stations: Vec<BuildingID>,
+        headway: f64,
+    },
+    UpdatePassengerLine {
+        line: PassengerLineID,
+        stations: Vec<BuildingID>,
+        headway: f64,
+    },
+    RemovePassengerLine(PassengerLineID),
+    AddPassengerTrain {
+        line: PassengerLineID,
+        n_wagons: u32,
+    },
+    SetTax {
+        kind: TaxKind,
+        rate: f32,
+    },
+    SetMarketMode(MarketMode),
+    /// The following commands were issued by this player, see [`WorldCommands::sent_by`]
+    ActAs(PlayerID),
+    SetAssetProtection(bool),
+    /// Reverts the last map edit of the acting player, see [`UndoHistory`]
+    Undo,
+    Redo,
+    /// Sets up a scenario, the simulation options are applied beforehand with [`Init`]
+    StartScenario(Box<Scenario>),
+    MapTerrainBrush(TerrainBrush),
+    /// Same as [`MapMakeConnection`] with a chosen elevation at each end, for bridges and tunnels
+    MapMakeProfiledConnection {
+        from: MapProject,
+        to: MapProject,
+        inter: Option<Vec2>,
+        pat: LanePattern,
+        profile: VerticalProfile,
+    },
+    /// Sets custom lights along a path of intersections, see [`Map::green_wave`]
+    MapGreenWave {
+        corridor: Vec<IntersectionID>,
+        timings: LightTimings,
+        speed: f32,
+    },
+    /// Sets the speed limit and the restrictions of a road, see [`Map::set_road_restrictions`]
+    MapSetRoadRestrictions {
+        road: RoadID,
+        restrictions: RoadRestrictions,
+    },
+    /// Makes a road one way, or two way if the direction is None, see [`Map::set_road_direction`]
+    MapSetOneWay {
+        road: RoadID,
+        direction: Option<LaneDirection>,
+    },
+    /// Rebuilds the lanes of a road from a pattern, see [`Map::set_road_pattern`]
+    MapSetRoadPattern {
+        road: RoadID,
+        pat: LanePattern,
+    },
+    /// Builds a power line or a pipe between two buildings, see [`Map::make_utility_link`]
+    MapMakeUtilityLink {
+        kind: UtilityKind,
+        src: BuildingID,
+        dst: BuildingID,
+        capacity: f32,
+    },
+    MapRemoveUtilityLink {
+        kind: UtilityKind,
+        src: BuildingID,
+        dst: BuildingID,
+    },
+    /// Zones the lots, zoned lots grow buildings on their own, see [`crate::economy::zone_growth_system`]
+    MapSetLotKind {
+        lots: Vec<LotID>,
+        kind: LotKind,
+    },
 }
 
 impl AsRef<[WorldCommand]> for WorldCommands {
@@ -110,6 +205,16 @@ impl WorldCommands {
         self.commands.is_empty()
     }
 
+    /// Attributes the commands to the player who sent them.
+    /// Only the server knows who sent what, so attributions made by the sender are dropped.
+    pub fn sent_by(mut self, player: PlayerID) -> Self {
+        self.commands.retain(|cmd| !matches!(cmd, ActAs(_)));
+        if !self.commands.is_empty() {
+            self.commands.insert(0, ActAs(player));
+        }
+        self
+    }
+
     pub fn map_load_paris(&mut self) {
         self.commands.push(MapLoadParis)
     }
@@ -176,6 +281,67 @@ impl WorldCommands {
         })
     }
 
+    pub fn make_transit_line(&mut self, stops: Vec<TransitStop>) {
+        self.commands.push(MakeTransitLine { stops })
+    }
+
+    pub fn update_transit_line(&mut self, line: TransitLineID, stops: Vec<TransitStop>) {
+        self.commands.push(UpdateTransitLine { line, stops })
+    }
+
+    pub fn remove_transit_line(&mut self, line: TransitLineID) {
+        self.commands.push(RemoveTransitLine(line))
+    }
+
+    pub fn add_bus(&mut self, line: TransitLineID) {
+        self.commands.push(AddBus { line })
+    }
+
+    pub fn make_passenger_line(&mut self, stations: Vec<BuildingID>, headway: f64) {
+        self.commands.push(MakePassengerLine { stations, headway })
+    }
+
+    pub fn update_passenger_line(
+        &mut self,
+        line: PassengerLineID,
+        stations: Vec<BuildingID>,
+        headway: f64,
+    ) {
+        self.commands.push(UpdatePassengerLine {
+            line,
+            stations,
+            headway,
+        })
+    }
+
+    pub fn remove_passenger_line(&mut self, line: PassengerLineID) {
+        self.commands.push(RemovePassengerLine(line))
+    }
+
+    pub fn add_passenger_train(&mut self, line: PassengerLineID, n_wagons: u32) {
+        self.commands.push(AddPassengerTrain { line, n_wagons })
+    }
+
+    pub fn set_tax(&mut self, kind: TaxKind, rate: f32) {
+        self.commands.push(SetTax { kind, rate })
+    }
+
+    pub fn set_market_mode(&mut self, mode: MarketMode) {
+        self.commands.push(SetMarketMode(mode))
+    }
+
+    pub fn set_asset_protection(&mut self, protect: bool) {
+        self.commands.push(SetAssetProtection(protect))
+    }
+
+    pub fn undo(&mut self) {
+        self.commands.push(Undo)
+    }
+
+    pub fn redo(&mut self) {
+        self.commands.push(Redo)
+    }
+
     pub fn map_update_intersection_policy(
         &mut self,
         id: IntersectionID,
@@ -188,6 +354,51 @@ impl WorldCommands {
             light: lp,
         })
     }
+
+    pub fn map_green_wave(
+        &mut self,
+        corridor: Vec<IntersectionID>,
+        timings: LightTimings,
+        speed: f32,
+    ) {
+        self.commands.push(MapGreenWave {
+            corridor,
+            timings,
+            speed,
+        })
+    }
+
+    pub fn map_set_road_restrictions(&mut self, road: RoadID, restrictions: RoadRestrictions) {
+        self.commands
+            .push(MapSetRoadRestrictions { road, restrictions })
+    }
+
+    pub fn map_set_one_way(&mut self, road: RoadID, direction: Option<LaneDirection>) {
+        self.commands.push(MapSetOneWay { road, direction })
+    }
+
+    pub fn map_make_utility_link(
+        &mut self,
+        kind: UtilityKind,
+        src: BuildingID,
+        dst: BuildingID,
+        capacity: f32,
+    ) {
+        self.commands.push(MapMakeUtilityLink {
+            kind,
+            src,
+            dst,
+            capacity,
+        })
+    }
+
+    pub fn map_remove_utility_link(&mut self, kind: UtilityKind, src: BuildingID, dst: BuildingID) {
+        self.commands.push(MapRemoveUtilityLink { kind, src, dst })
+    }
+
+    pub fn map_set_lot_kind(&mut self, lots: Vec<LotID>, kind: LotKind) {
+        self.commands.push(MapSetLotKind { lots, kind })
+    }
 }
 
 impl WorldCommand {
@@ -197,15 +408,27 @@ impl WorldCommand {
             self,
             MapBuildHouse(_)
                 | MapUpdateIntersectionPolicy { .. }
+                | MapGreenWave { .. }
+                | MapSetRoadRestrictions { .. }
+                | MapSetOneWay { .. }
+                | MapSetRoadPattern { .. }
+                | MapMakeUtilityLink { .. }
+                | MapRemoveUtilityLink { .. }
+                | MapSetLotKind { .. }
                 | UpdateZone { .. }
                 | SetGameTime(_)
+                | MakeTransitLine { .. }
+                | UpdateTransitLine { .. }
+                | MakePassengerLine { .. }
+                | UpdatePassengerLine { .. }
+                | SetTax { .. }
+                | SetMarketMode(_)
+                | ActAs(_)
+                | SetAssetProtection(_)
         )
     }
 
     pub fn apply(&self, sim: &mut Simulation) {
-        let cost = Government::action_cost(self, sim);
-        sim.write::<Government>().money -= cost;
-
         let mut rep = sim.resources.write::<Replay>();
         if rep.enabled {
             let tick = sim.read::<Tick>();
@@ -213,14 +436,90 @@ impl WorldCommand {
         }
         drop(rep);
 
+        if !sim.read::<Players>().is_allowed(self, &sim.map()) {
+            log::info!(
+                "{:?} is not allowed to apply {:?}",
+                sim.read::<Players>().acting(),
+                self
+            );
+            return;
+        }
+
+        if !self.is_valid(sim) {
+            log::info!("{:?} is invalid", self);
+            return;
+        }
+
+        let cost = Government::action_cost(self, sim);
+        if !sim
+            .read::<Players>()
+            .can_afford(&sim.read::<Government>(), cost)
+        {
+            log::info!(
+                "{:?} cannot afford {:?} for {}",
+                sim.read::<Players>().acting(),
+                self,
+                cost
+            );
+            return;
+        }
+        sim.write::<Players>()
+            .charge(&mut sim.write::<Government>(), cost);
+
+        if let Some(inverse) = self.execute(sim) {
+            let player = sim.read::<Players>().acting();
+            sim.write::<UndoHistory>().record(player, inverse, cost);
+        }
+    }
+
+    /// Whether the command refers to things that exist and can be used that way.
+    /// Invalid commands are refused before being charged.
+    pub fn is_valid(&self, sim: &Simulation) -> bool {
+        match *self {
+            MakeTransitLine { ref stops } => {
+                let map = sim.map();
+                stops.iter().all(|s| s.is_valid(&map))
+            }
+            UpdateTransitLine { line, ref stops } => {
+                let map = sim.map();
+                sim.read::<TransitLines>().get(line).is_some()
+                    && stops.iter().all(|s| s.is_valid(&map))
+            }
+            _ => true,
+        }
+    }
+
+    /// Applies the command without recording nor charging it.
+    /// Returns the commands reverting it if it is a map edit that can be undone.
+    pub(crate) fn execute(&self, sim: &mut Simulation) -> Option<Vec<UndoCommand>> {
+        let player = sim.read::<Players>().acting();
+        let mut inverse = inverse_before(
+            self,
+            &sim.map(),
+            &sim.read::<GoodsCompanyRegistry>(),
+            &sim.read::<Players>().owners,
+        );
+        let mut created = vec![];
+
         match *self {
-            MapRemoveIntersection(id) => sim.map_mut().remove_intersection(id),
-            MapRemoveRoad(id) => drop(sim.map_mut().remove_road(id)),
-            MapRemoveBuilding(id) => drop(sim.map_mut().remove_building(id)),
+            MapRemoveIntersection(id) => {
+                sim.map_mut().remove_intersection(id);
+                sim.write::<Players>().owners.clean(&sim.map());
+            }
+            MapRemoveRoad(id) => {
+                drop(sim.map_mut().remove_road(id));
+                sim.write::<Players>().owners.clean(&sim.map());
+            }
+            MapRemoveBuilding(id) => {
+                drop(sim.map_mut().remove_building(id));
+                sim.write::<Players>().owners.clean(&sim.map());
+            }
             MapBuildHouse(id) => {
                 if let Some(build) = sim.map_mut().build_house(id) {
                     let mut infos = sim.write::<BuildingInfos>();
                     infos.insert(build);
+                    sim.write::<Players>().owners.set_building(build, player);
+                    created.push(MapRemoveBuilding(build));
                 }
             }
             MapMakeConnection {
@@ -229,7 +528,13 @@ impl WorldCommand {
                 inter,
                 ref pat,
             } => {
-                sim.write::<Map>().make_connection(from, to, inter, pat);
+                let mut map = sim.write::<Map>();
+                if let Some((_, r)) = map.make_connection(from, to, inter, pat) {
+                    sim.write::<Players>()
+                        .owners
+                        .set_connection(&map, &from, &to, r, player);
+                    created.push(MapRemoveRoad(r));
+                }
             }
             MapMakeMultipleConnections(ref projects, ref links) => {
                 let mut map = sim.map_mut();
@@ -246,6 +551,10 @@ impl WorldCommand {
                     }
 
                     if let Some((_, r)) = map.make_connection(fromproj, toproj, *interpoint, pat) {
+                        sim.write::<Players>()
+                            .owners
+                            .set_connection(&map, &fromproj, &toproj, r, player);
+                        created.push(MapRemoveRoad(r));
                         if fromproj.kind.is_ground() {
                             inters.insert(*from, map.roads[r].src);
                         }
@@ -274,6 +583,8 @@ impl WorldCommand {
                         .build_special_building(&obb, kind, gen, zone.clone())
                 {
                     sim.write::<BuildingInfos>().insert(id);
+                    sim.write::<Players>().owners.set_building(id, player);
+                    created.push(MapRemoveBuilding(id));
                 }
             }
             SetGameTime(gt) => *sim.write::<GameTime>() = gt,
@@ -292,6 +603,7 @@ impl WorldCommand {
                 if opts.save_replay {
                     let mut rep = sim.resources.write::<Replay>();
                     rep.enabled = true;
+                    rep.checkpoint_every = opts.replay_checkpoints;
                     let tick = sim.read::<Tick>();
                     rep.commands.push((*tick, Init(opts.clone())));
                 }
@@ -330,12 +642,128 @@ impl WorldCommand {
                     sim.write::<RandomVehicles>().vehicles.insert(v_id);
                 }
             }
+            MakeTransitLine { ref stops } => {
+                sim.write::<TransitLines>().make_line(stops.clone());
+            }
+            UpdateTransitLine { line, ref stops } => {
+                sim.write::<TransitLines>().update_line(line, stops.clone());
+            }
+            RemoveTransitLine(line) => {
+                let buses = sim.write::<TransitLines>().remove_line(line);
+                sim.read::<ParCommandBuffer<VehicleEnt>>().kill_all(&buses);
+            }
+            AddBus { line } => {
+                spawn_bus(sim, line);
+            }
+            MakePassengerLine {
+                ref stations,
+                headway,
+            } => {
+                sim.write::<PassengerLines>()
+                    .make_line(stations.clone(), headway);
+            }
+            UpdatePassengerLine {
+                line,
+                ref stations,
+                headway,
+            } => {
+                sim.write::<PassengerLines>()
+                    .update_line(line, stations.clone(), headway);
+            }
+            RemovePassengerLine(line) => {
+                let trains = sim.write::<PassengerLines>().remove_line(line);
+                let wagons: Vec<_> = sim
+                    .world
+                    .wagons
+                    .iter()
+                    .filter(|(_, w)| trains.contains(&w.itfollower.leader))
+                    .map(|(id, _)| id)
+                    .collect();
+                sim.read::<ParCommandBuffer<WagonEnt>>().kill_all(&wagons);
+                sim.read::<ParCommandBuffer<TrainEnt>>().kill_all(&trains);
+            }
+            AddPassengerTrain { line, n_wagons } => {
+                spawn_passenger_train(sim, line, n_wagons);
+            }
+            SetTax { kind, rate } => {
+                sim.write::<Government>().taxes.set(kind, rate);
+            }
+            SetMarketMode(mode) => {
+                sim.write::<Market>().mode = mode;
+            }
+            ActAs(player) => {
+                sim.write::<Players>().set_acting(player);
+            }
+            SetAssetProtection(protect) => {
+                sim.write::<Players>().protect_assets = protect;
+            }
+            Undo => UndoHistory::undo(sim),
+            Redo => UndoHistory::redo(sim),
+            StartScenario(ref scenario) => scenario.start(sim),
+            MapTerrainBrush(ref brush) => sim.map_mut().terrain_brush(brush),
+            MapGreenWave {
+                ref corridor,
+                timings,
+                speed,
+            } => sim.map_mut().green_wave(corridor, timings, speed),
+            MapSetRoadRestrictions { road, restrictions } => {
+                sim.map_mut().set_road_restrictions(road, restrictions)
+            }
+            MapSetOneWay { road, direction } => sim.map_mut().set_road_direction(road, direction),
+            MapSetRoadPattern { road, ref pat } => sim.map_mut().set_road_pattern(road, pat),
+            MapMakeUtilityLink {
+                kind,
+                src,
+                dst,
+                capacity,
+            } => {
+                if sim
+                    .map_mut()
+                    .make_utility_link(kind, src, dst, capacity)
+                    .is_some()
+                {
+                    created.push(MapRemoveUtilityLink { kind, src, dst });
+                }
+            }
+            MapRemoveUtilityLink { kind, src, dst } => {
+                let mut map = sim.map_mut();
+                if let Some(link) = map.find_utility_link(kind, src, dst) {
+                    map.remove_utility_link(link);
+                }
+            }
+            MapSetLotKind { ref lots, kind } => {
+                let mut map = sim.map_mut();
+                for &lot in lots {
+                    map.set_lot_kind(lot, kind);
+                }
+            }
+            MapMakeProfiledConnection {
+                from,
+                to,
+                inter,
+                ref pat,
+                ref profile,
+            } => {
+                let mut map = sim.write::<Map>();
+                if let Some((_, r)) = map.make_profiled_connection(from, to, inter, pat, profile) {
+                    sim.write::<Players>()
+                        .owners
+                        .set_connection(&map, &from, &to, r, player);
+                    created.push(MapRemoveRoad(r));
+                }
+            }
             SendMessage { ref message } => {
                 sim.write::<MultiplayerState>()
                     .chat
                     .add_message(message.clone());
             }
         }
+
+        // objects created last are removed first
+        if let Some(ref mut inverse) = inverse {
+            inverse.extend(created.into_iter().rev().map(UndoCommand::from));
+        }
+        inverse
     }
 }
 
@@ -395,3 +823,44 @@ impl From<&WorldCommands> for Vec<WorldCommand> {
         x.commands.clone()
     }
 }
+
+/// [`BuildingGen`] is internally tagged to be read from the json descriptions,
+/// which bincode cannot deserialize, so it is sent and saved as a plain enum
+mod building_gen_serde {
+    use common::descriptions::BuildingGen;
+    use geom::Vec2;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    enum Repr {
+        House,
+        Farm,
+        CenteredDoor { vertical_factor: f32 },
+        NoWalkway { door_pos: Vec2 },
+    }
+
+    pub fn serialize<S: Serializer>(gen: &BuildingGen, s: S) -> Result<S::Ok, S::Error> {
+        if s.is_human_readable() {
+            return gen.serialize(s);
+        }
+        match *gen {
+            BuildingGen::House => Repr::House,
+            BuildingGen::Farm => Repr::Farm,
+            BuildingGen::CenteredDoor { vertical_factor } => Repr::CenteredDoor { vertical_factor },
+            BuildingGen::NoWalkway { door_pos } => Repr::NoWalkway { door_pos },
+        }
+        .serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<BuildingGen, D::Error> {
+        if d.is_human_readable() {
+            return BuildingGen::deserialize(d);
+        }
+        Ok(match Repr::deserialize(d)? {
+            Repr::House => BuildingGen::House,
+            Repr::Farm => BuildingGen::Farm,
+            Repr::CenteredDoor { vertical_factor } => BuildingGen::CenteredDoor { vertical_factor },
+            Repr::NoWalkway { door_pos } => BuildingGen::NoWalkway { door_pos },
+        })
+    }
+}
@@ -1,54 +1,88 @@
-use crate::economy::{init_market, market_update, EcoStats, Government, ItemRegistry, Market};
-use crate::map::Map;
+use crate::economy::{
+    fiscal_system, init_market, market_update, zone_growth_system, EcoStats, Government,
+    GovernmentV0, ItemRegistry, Market, MarketV0, Wallets, ZoneDemand,
+};
+use crate::map::{Map, SerializedMap, SerializedMapV0, SerializedMapV1, TravelTimes};
 use crate::map_dynamic::{
     dispatch_system, itinerary_update, routing_changed_system, routing_update_system,
-    BuildingInfos, Dispatcher, ParkingManagement,
+    utility_grid_system, BuildingInfos, BuildingInfosV0, Dispatcher, ParkingManagement,
+    UtilityGrid,
 };
-use crate::multiplayer::MultiplayerState;
+use crate::multiplayer::{MultiplayerState, Players};
 use crate::physics::coworld_synchronize;
+use crate::scenario::{scenario_system, ScenarioState};
+use crate::souls::desire::{init_desires, DesireRegistry};
 use crate::souls::freight_station::freight_station_system;
 use crate::souls::goods_company::{company_system, GoodsCompanyRegistry};
 use crate::souls::human::update_decision_system;
+use crate::souls::population::{population_system, Population};
+use crate::souls::satisfaction::{satisfaction_system, SatisfactionStats};
+use crate::transportation::passenger_rail::{passenger_rail_system, PassengerLines};
 use crate::transportation::pedestrian_decision_system;
-use crate::transportation::road::{vehicle_decision_system, vehicle_state_update_system};
+use crate::transportation::road::{
+    travel_times_system, vehicle_decision_system, vehicle_state_update_system,
+};
 use crate::transportation::testing_vehicles::{random_vehicles_update, RandomVehicles};
+use crate::transportation::traffic_stats::{traffic_stats_system, TrafficStats};
 use crate::transportation::train::{
     locomotive_system, train_reservations_update, TrainReservations,
 };
+use crate::transportation::transit::{transit_system, TransitLines};
 use crate::utils::resources::Resources;
 use crate::utils::time::Tick;
-use crate::world::{CompanyEnt, FreightStationEnt, HumanEnt, TrainEnt, VehicleEnt, WagonEnt};
+use crate::utils::undo::UndoHistory;
+use crate::world::{
+    CompanyEnt, FreightStationEnt, HumanEnt, TrainEnt, VehicleEnt, WagonEnt, WorldV0, WorldV1,
+};
 use crate::World;
 use crate::{
     add_souls_to_empty_buildings, utils, CollisionWorld, GameTime, ParCommandBuffer, RandProvider,
-    Replay, RunnableSystem, Simulation, SimulationOptions, RNG_SEED, SECONDS_PER_DAY,
-    SECONDS_PER_HOUR,
+    Replay, ReplayV0, RunnableSystem, Simulation, SimulationOptions, SimulationOptionsV0, RNG_SEED,
+    SECONDS_PER_DAY, SECONDS_PER_HOUR, WORLD_KEY,
 };
 use common::saveload::{Bincode, Encoder};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use std::sync::{Mutex, Once};
 
+/// Registers the systems and resources, only the first call does anything
 pub fn init() {
+    static INIT: Once = Once::new();
+    INIT.call_once(init_inner);
+}
+
+fn init_inner() {
     register_system("dispatch_system", dispatch_system);
     register_system("update_decision_system", update_decision_system);
+    register_system("utility_grid_system", utility_grid_system);
+    register_system("satisfaction_system", satisfaction_system);
     register_system("company_system", company_system);
     register_system("pedestrian_decision_system", pedestrian_decision_system);
     register_system("coworld_synchronize", coworld_synchronize);
     register_system("locomotive_system", locomotive_system);
     register_system("vehicle_decision_system", vehicle_decision_system);
     register_system("vehicle_state_update_system", vehicle_state_update_system);
+    register_system("travel_times_system", travel_times_system);
+    register_system("traffic_stats_system", traffic_stats_system);
     register_system("routing_changed_system", routing_changed_system);
     register_system("routing_update_system", routing_update_system);
     register_system("itinerary_update", itinerary_update);
     register_system("market_update", market_update);
+    register_system("fiscal_system", fiscal_system);
     register_system("train_reservations_update", train_reservations_update);
     register_system("freight_station", freight_station_system);
     register_system("random_vehicles", random_vehicles_update);
+    register_system("transit_system", transit_system);
+    register_system("passenger_rail_system", passenger_rail_system);
 
+    register_system_sim("zone_growth_system", zone_growth_system);
+    register_system_sim("population_system", population_system);
     register_system_sim("add_souls_to_empty_buildings", add_souls_to_empty_buildings);
+    register_system_sim("scenario_system", scenario_system);
 
     register_resource_noserialize::<GoodsCompanyRegistry>();
     register_resource_noserialize::<ItemRegistry>();
+    register_resource_noserialize::<DesireRegistry>();
     register_resource_noserialize::<ParCommandBuffer<VehicleEnt>>();
     register_resource_noserialize::<ParCommandBuffer<TrainEnt>>();
     register_resource_noserialize::<ParCommandBuffer<HumanEnt>>();
@@ -60,13 +94,22 @@ pub fn init() {
     register_resource_noinit::<SimulationOptions, Bincode>("simoptions");
 
     register_init(init_market);
+    register_init(init_desires);
 
     register_resource_default::<MultiplayerState, Bincode>("multiplayer_state");
+    register_resource_default::<Players, Bincode>("players");
+    register_resource_default::<UndoHistory, Bincode>("undo_history");
+    register_resource_default::<ScenarioState, Bincode>("scenario");
     register_resource_default::<RandomVehicles, Bincode>("random_vehicles");
     register_resource_default::<Tick, Bincode>("tick");
     register_resource_default::<Map, Bincode>("map");
+    register_resource_default::<TravelTimes, Bincode>("travel_times");
+    register_resource_default::<TrafficStats, Bincode>("traffic_stats");
     register_resource_default::<TrainReservations, Bincode>("train_reservations");
+    register_resource_default::<TransitLines, Bincode>("transit_lines");
+    register_resource_default::<PassengerLines, Bincode>("passenger_lines");
     register_resource_default::<Government, Bincode>("government");
+    register_resource_default::<Wallets, Bincode>("wallets");
     register_resource_default::<ParkingManagement, Bincode>("pmanagement");
     register_resource_default::<BuildingInfos, Bincode>("binfos");
     register_resource::<GameTime, Bincode>("game_time", || {
@@ -75,7 +118,28 @@ pub fn init() {
     register_resource::<CollisionWorld, Bincode>("coworld", || CollisionWorld::new(100));
     register_resource::<RandProvider, Bincode>("randprovider", || RandProvider::new(RNG_SEED));
     register_resource_default::<Dispatcher, Bincode>("dispatcher");
+    register_resource_default::<UtilityGrid, Bincode>("utility_grid");
+    register_resource_default::<ZoneDemand, Bincode>("zone_demand");
+    register_resource_default::<Population, Bincode>("population");
+    register_resource_default::<SatisfactionStats, Bincode>("satisfaction_stats");
     register_resource_default::<Replay, Bincode>("replay");
+
+    register_migration::<ReplayV0, Replay, Bincode>("replay", 0, Replay::from);
+    register_migration::<MarketV0, Market, Bincode>("market", 0, Market::from);
+    register_migration::<GovernmentV0, Government, Bincode>("government", 0, Government::from);
+    register_migration::<SimulationOptionsV0, SimulationOptions, Bincode>(
+        "simoptions",
+        0,
+        SimulationOptions::from,
+    );
+    register_migration::<SerializedMapV0, SerializedMapV1, Bincode>(
+        "map",
+        0,
+        SerializedMapV1::from,
+    );
+    register_migration::<SerializedMapV1, SerializedMap, Bincode>("map", 1, SerializedMap::from);
+    register_migration::<BuildingInfosV0, BuildingInfos, Bincode>("binfos", 0, BuildingInfos::from);
+    register_migration::<WorldV0, WorldV1, Bincode>(WORLD_KEY, 0, WorldV1::from);
 }
 
 pub struct InitFunc {
@@ -88,6 +152,13 @@ pub(crate) struct SaveLoadFunc {
     pub load: Box<dyn Fn(&mut Simulation, Vec<u8>) + 'static>,
 }
 
+/// Upgrades the saved blob of a resource from schema version `from` to `from + 1`
+pub(crate) struct Migration {
+    pub resource: &'static str,
+    pub from: u32,
+    pub f: Box<dyn Fn(Vec<u8>) -> Result<Vec<u8>, String> + Send + 'static>,
+}
+
 pub(crate) struct GSystem {
     pub(crate) s: Box<dyn Fn() -> Box<dyn RunnableSystem>>,
 }
@@ -95,6 +166,44 @@ pub(crate) struct GSystem {
 pub(crate) static mut INIT_FUNCS: Vec<InitFunc> = Vec::new();
 pub(crate) static mut SAVELOAD_FUNCS: Vec<SaveLoadFunc> = Vec::new();
 pub(crate) static mut GSYSTEMS: Vec<GSystem> = Vec::new();
+static MIGRATIONS: Mutex<Vec<Migration>> = Mutex::new(Vec::new());
+
+/// The schema version of a resource is the number of migrations registered for it.
+/// To change how a resource is saved, keep a copy of the old type and register a migration from it.
+/// The world is versioned the same way under the name [`crate::WORLD_KEY`].
+pub(crate) fn schema_version(resource: &str) -> u32 {
+    let migrations = MIGRATIONS.lock().unwrap();
+    migrations.iter().filter(|m| m.resource == resource).count() as u32
+}
+
+/// Upgrades the blob of a resource saved with the given schema version to the current one
+pub(crate) fn migrate(
+    resource: &str,
+    mut version: u32,
+    mut data: Vec<u8>,
+) -> Result<Vec<u8>, String> {
+    let current = schema_version(resource);
+    if version > current {
+        return Err(format!(
+            "{resource} was saved with schema version {version} but this game only knows up to version {current}, is the save from a newer game?"
+        ));
+    }
+
+    let migrations = MIGRATIONS.lock().unwrap();
+    while version < current {
+        let m = migrations
+            .iter()
+            .find(|m| m.resource == resource && m.from == version)
+            .ok_or_else(|| format!("no migration for {resource} from schema version {version}"))?;
+        data = (m.f)(data).map_err(|e| {
+            format!("could not migrate {resource} from schema version {version}: {e}")
+        })?;
+        log::info!("migrated {} from schema version {}", resource, version);
+        version += 1;
+    }
+
+    Ok(data)
+}
 
 fn register_init(s: fn(&mut World, &mut Resources)) {
     unsafe {
@@ -154,6 +263,28 @@ fn register_resource<T: 'static + Send + Sync + Serialize + DeserializeOwned, E:
     }
 }
 
+/// Registers the upgrade of a resource from schema version `from` to the next one,
+/// migrations must be registered in order
+fn register_migration<Old: DeserializeOwned, New: Serialize, E: Encoder>(
+    resource: &'static str,
+    from: u32,
+    f: impl Fn(Old) -> New + Send + 'static,
+) {
+    assert_eq!(
+        from,
+        schema_version(resource),
+        "migrations of {resource} must be registered in order"
+    );
+    MIGRATIONS.lock().unwrap().push(Migration {
+        resource,
+        from,
+        f: Box::new(move |data| {
+            let old = E::decode::<Old>(&data).map_err(|e| e.to_string())?;
+            E::encode(&f(old)).map_err(|e| e.to_string())
+        }),
+    });
+}
+
 fn register_resource_noinit<T: 'static + Send + Sync + Serialize + DeserializeOwned, E: Encoder>(
     name: &'static str,
 ) {
@@ -161,11 +292,141 @@ fn register_resource_noinit<T: 'static + Send + Sync + Serialize + DeserializeOw
         SAVELOAD_FUNCS.push(SaveLoadFunc {
             name,
             save: Box::new(move |uiworld| E::encode(&*uiworld.read::<T>()).unwrap()),
-            load: Box::new(move |uiworld, data| {
-                if let Ok(res) = E::decode::<T>(&data) {
-                    uiworld.insert(res);
-                }
+            load: Box::new(move |uiworld, data| match E::decode::<T>(&data) {
+                Ok(res) => uiworld.insert(res),
+                Err(e) => log::error!("could not load {}: {}", name, e),
             }),
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{init, migrate, schema_version};
+    use crate::engine_interaction::WorldCommands;
+    use crate::souls::desire::{Delivery, DesireRegistry, WorkKind};
+    use crate::souls::human::HumanDecisionKind;
+    use crate::utils::time::Tick;
+    use crate::{Replay, Simulation, WORLD_KEY};
+    use common::descriptions::CompanyKind;
+    use common::saveload::{Bincode, CompressedBincode, Encoder};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_migrate_replay_v0() {
+        init();
+
+        #[derive(Serialize)]
+        struct OldReplay {
+            enabled: bool,
+            commands: Vec<(Tick, crate::WorldCommand)>,
+        }
+
+        let old = Bincode::encode(&OldReplay {
+            enabled: true,
+            commands: vec![],
+        })
+        .unwrap();
+
+        let new = migrate("replay", 0, old).unwrap();
+        let replay: Replay = Bincode::decode(&new).unwrap();
+        assert!(replay.enabled);
+        assert!(replay.checkpoints.is_empty());
+    }
+
+    #[test]
+    fn test_migrate_from_newer_version_fails() {
+        init();
+
+        let current = schema_version("replay");
+        assert!(migrate("replay", current, vec![1, 2, 3]).is_ok());
+        assert!(migrate("replay", current + 1, vec![]).is_err());
+    }
+
+    #[test]
+    fn test_world_schema_version() {
+        init();
+
+        /// How the simulation is saved
+        #[derive(Serialize, Deserialize)]
+        struct Save {
+            inline_world: Vec<u8>,
+            world: Vec<u8>,
+            version: String,
+            res: BTreeMap<String, Vec<u8>>,
+        }
+
+        let sim = Simulation::new(false);
+        let mut save: Save = Bincode::decode(&Bincode::encode(&sim).unwrap()).unwrap();
+        let mut versions: BTreeMap<String, u32> =
+            Bincode::decode(&save.res["schema_versions"]).unwrap();
+        assert_eq!(versions[WORLD_KEY], schema_version(WORLD_KEY));
+        assert!(Bincode::decode::<Simulation>(&Bincode::encode(&save).unwrap()).is_ok());
+
+        // a world from a newer game cannot be read
+        versions.insert(WORLD_KEY.to_string(), schema_version(WORLD_KEY) + 1);
+        save.res.insert(
+            "schema_versions".to_string(),
+            Bincode::encode(&versions).unwrap(),
+        );
+        let err = Bincode::decode::<Simulation>(&Bincode::encode(&save).unwrap())
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("newer game"), "{err}");
+    }
+
+    #[test]
+    fn test_load_legacy_save() {
+        init();
+
+        // saved before the world had a schema version, with a flour factory whose driver is
+        // on their way to deliver at a freight station
+        let sim: Simulation =
+            CompressedBincode::decode(include_bytes!("tests/legacy_save.zip")).unwrap();
+        let world = sim.world();
+        assert_eq!(world.humans.len(), 2);
+        assert_eq!(world.companies.len(), 1);
+
+        let station = world.freight_stations.values().next().unwrap().f.building;
+        let comp = &world.companies.values().next().unwrap().comp;
+        assert!(matches!(comp.kind, CompanyKind::Factory { n_trucks: 1 }));
+        assert_eq!(comp.drivers.len(), 1);
+
+        let driver = &world.humans[comp.drivers[0]];
+        assert!(matches!(
+            driver.work.as_ref().unwrap().kind,
+            WorkKind::Driver {
+                deliver_order: Some(Delivery {
+                    to,
+                    trades: 1,
+                    delivered: false,
+                    ..
+                }),
+                ..
+            } if to == station
+        ));
+        let HumanDecisionKind::MultiStack(ref stack) = driver.decision.kind else {
+            panic!("{:?}", driver.decision.kind);
+        };
+        assert!(matches!(
+            stack[0],
+            HumanDecisionKind::DeliverAtBuilding(b, 1) if b == station
+        ));
+
+        let n_desires = sim.read::<DesireRegistry>().descriptions.len();
+        assert!(n_desires > 0);
+        for h in world.humans.values() {
+            assert_eq!(h.desires.len(), n_desires);
+            assert!(h.router.personal_bike.is_none());
+        }
+
+        // the upgraded world is saved at the current version and keeps running
+        let mut sim: Simulation = Bincode::decode(&Bincode::encode(&sim).unwrap()).unwrap();
+        assert_eq!(sim.world().humans.len(), 2);
+        let mut sched = Simulation::schedule();
+        for _ in 0..100 {
+            sim.tick(&mut sched, WorldCommands::default().as_ref());
+        }
+    }
+}
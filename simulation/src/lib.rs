@@ -1,23 +1,30 @@
 #![allow(clippy::too_many_arguments)]
 #![allow(clippy::type_complexity)]
 
+use crate::economy::Government;
 use crate::engine_interaction::WorldCommand;
 use crate::map::{BuildingKind, Map};
 use crate::map_dynamic::{Itinerary, ItineraryLeader};
+use crate::multiplayer::{PlayerID, Players};
 use crate::physics::CollisionWorld;
 use crate::physics::Speed;
 use crate::souls::add_souls_to_empty_buildings;
 use crate::souls::goods_company::GoodsCompanyRegistry;
 use crate::utils::resources::{Ref, RefMut, Resources};
-use common::saveload::Encoder;
+use common::saveload::{Encoder, SaveMeta, SaveStore};
 use derive_more::{From, TryInto};
 use geom::Vec3;
+use serde::de::value::SeqAccessDeserializer;
+use serde::de::{Error as _, Expected, SeqAccess, Visitor};
+use serde::ser::Error as _;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use slotmapd::HopSlotMap;
 use std::any::Any;
 use std::collections::BTreeMap;
 use std::fmt::{Display, Formatter};
 use std::hash::Hash;
-use std::time::{Duration, Instant};
+use std::io::ErrorKind;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use utils::rand_provider::RandProvider;
 use utils::scheduler::SeqSchedule;
 use utils::time::{GameTime, SECONDS_PER_DAY, SECONDS_PER_HOUR};
@@ -42,6 +49,7 @@ pub mod map;
 pub mod map_dynamic;
 pub mod multiplayer;
 pub mod physics;
+pub mod scenario;
 pub mod souls;
 #[cfg(test)]
 mod tests;
@@ -52,7 +60,7 @@ mod world;
 pub use world::*;
 
 use crate::engine_interaction::WorldCommand::Init;
-use crate::init::{GSYSTEMS, INIT_FUNCS, SAVELOAD_FUNCS};
+use crate::init::{migrate, schema_version, GSYSTEMS, INIT_FUNCS, SAVELOAD_FUNCS};
 use crate::utils::scheduler::RunnableSystem;
 use crate::utils::time::{Tick, SECONDS_PER_REALTIME_SECOND};
 use common::FastMap;
@@ -116,6 +124,27 @@ const VERSION: &str = include_str!("../../VERSION");
 pub struct SimulationOptions {
     pub terrain_size: u32,
     pub save_replay: bool,
+    /// Every how many ticks the replay records hashes of the state to check playback, 0 to disable.
+    /// Defaults for the scenario files, saves are upgraded by the "simoptions" migration
+    #[serde(default)]
+    pub replay_checkpoints: u32,
+}
+
+/// The options before replay checkpoints were added
+#[derive(Deserialize)]
+pub(crate) struct SimulationOptionsV0 {
+    terrain_size: u32,
+    save_replay: bool,
+}
+
+impl From<SimulationOptionsV0> for SimulationOptions {
+    fn from(old: SimulationOptionsV0) -> Self {
+        Self {
+            terrain_size: old.terrain_size,
+            save_replay: old.save_replay,
+            replay_checkpoints: 0,
+        }
+    }
 }
 
 impl Default for SimulationOptions {
@@ -123,6 +152,7 @@ impl Default for SimulationOptions {
         SimulationOptions {
             terrain_size: 50,
             save_replay: true,
+            replay_checkpoints: 0,
         }
     }
 }
@@ -146,7 +176,8 @@ impl Simulation {
         })
     }
 
-    pub fn from_replay(replay: Replay) -> (Simulation, SimulationReplayLoader) {
+    /// A simulation with every resource initialized but no commands applied
+    fn new_empty() -> Simulation {
         let mut sim = Simulation {
             world: Default::default(),
             resources: Default::default(),
@@ -160,45 +191,48 @@ impl Simulation {
             }
         }
 
+        sim
+    }
+
+    pub fn from_replay(replay: Replay) -> (Simulation, SimulationReplayLoader) {
         (
-            sim,
+            Self::new_empty(),
             SimulationReplayLoader {
                 replay,
                 pastt: Tick::default(),
                 idx: 0,
                 speed: 1,
                 advance_n_ticks: 0,
+                checkpoint_idx: 0,
+                divergence: None,
             },
         )
     }
 
     pub fn new_with_options(opts: SimulationOptions) -> Simulation {
-        let mut sim = Simulation {
-            world: Default::default(),
-            resources: Default::default(),
-        };
-
-        info!("Seed is {}", RNG_SEED);
+        let mut sim = Self::new_empty();
         info!("{:?}", opts);
 
-        unsafe {
-            for s in &INIT_FUNCS {
-                (s.f)(&mut sim);
-            }
-        }
-
         Init(Box::new(opts)).apply(&mut sim);
 
-        let start_commands: Vec<(u32, WorldCommand)> =
-            common::saveload::JSON::decode(START_COMMANDS.as_bytes()).unwrap();
-
-        for (_, command) in start_commands {
+        for command in start_commands() {
             command.apply(&mut sim);
         }
 
         sim
     }
 
+    /// Starts a new game from a scenario, see [`scenario::Scenario`]
+    pub fn new_with_scenario(scenario: scenario::Scenario) -> Simulation {
+        let mut sim = Self::new_empty();
+        info!("Starting scenario {}", scenario.name);
+
+        Init(Box::new(scenario.options)).apply(&mut sim);
+        WorldCommand::StartScenario(Box::new(scenario)).apply(&mut sim);
+
+        sim
+    }
+
     pub fn world_res(&mut self) -> (&mut World, &mut Resources) {
         (&mut self.world, &mut self.resources)
     }
@@ -244,6 +278,7 @@ impl Simulation {
         // so that instant commands work on single player but the game is still deterministic
         {
             profiling::scope!("applying commands");
+            self.write::<Players>().set_acting(PlayerID::HOST);
             for command in commands {
                 command.apply(self);
             }
@@ -261,6 +296,8 @@ impl Simulation {
         game_schedule.execute(self);
         self.write::<Tick>().0 += 1;
 
+        Replay::record_checkpoint(self);
+
         t.elapsed()
     }
 
@@ -269,37 +306,110 @@ impl Simulation {
     }
 
     pub fn hashes(&self) -> BTreeMap<String, u64> {
-        let mut hashes = BTreeMap::new();
+        self.ordered_hashes()
+            .into_iter()
+            .map(|(name, hash)| (name.to_string(), hash))
+            .collect()
+    }
+
+    /// Hashes of the world then of the resources, in the order they are saved
+    pub fn ordered_hashes(&self) -> Vec<(&'static str, u64)> {
+        let mut hashes = Vec::new();
         let ser = common::saveload::Bincode::encode(&self.world).unwrap();
-        hashes.insert("world".to_string(), common::hash_u64(&*ser));
+        hashes.push(("world", common::hash_u64(&*ser)));
 
         unsafe {
             for l in &SAVELOAD_FUNCS {
                 let v = (l.save)(self);
-                hashes.insert(l.name.to_string(), common::hash_u64(&*v));
+                hashes.push((l.name, common::hash_u64(&*v)));
             }
         }
 
         hashes
     }
 
-    pub fn load_replay_from_disk(save_name: &str) -> Option<Replay> {
-        let path = format!("{save_name}_replay");
-        let replay: Replay = common::saveload::JSON::load(&path).ok()?;
+    /// The metadata shown in save browsers
+    pub fn save_meta(&self) -> SaveMeta {
+        let time = self.read::<GameTime>();
+        let population = self.world.humans.len() as u32;
+        let money = self.read::<Government>().money;
+        SaveMeta {
+            version: VERSION.to_string(),
+            saved_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            tick: self.get_tick(),
+            game_time: time.timestamp,
+            population,
+            money_cents: money.cents(),
+            summary: format!("{} - {} people - {} money", time.daytime, population, money),
+        }
+    }
+
+    pub fn load_replay_from_slot(store: &SaveStore, slot: &str) -> Option<Replay> {
+        common::saveload::check_slot_name(slot)
+            .map_err(|e| log::error!("could not load replay: {}", e))
+            .ok()?;
+        let data = std::fs::read(store.file_path(&format!("{slot}_replay.json"))).ok()?;
+        let replay: Replay = common::saveload::JSON::decode(&data)
+            .map_err(|e| log::error!("could not load replay {}: {}", slot, e))
+            .ok()?;
         Some(replay)
     }
 
-    pub fn load_from_disk(save_name: &str) -> Option<Self> {
-        let sim: Simulation = common::saveload::CompressedBincode::load(save_name).ok()?;
+    pub fn load_from_slot(store: &SaveStore, slot: &str) -> Option<Self> {
+        let (meta, body) = match store.read(slot) {
+            Ok((meta, body)) => (Some(meta), body),
+            // saves from before slots only contain the compressed simulation
+            Err(e) if e.kind() == ErrorKind::NotFound => (
+                None,
+                std::fs::read(store.file_path(&format!("{slot}.zip"))).ok()?,
+            ),
+            Err(e) => {
+                log::error!("could not load {}: {}", slot, e);
+                return None;
+            }
+        };
+        let sim: Simulation = common::saveload::CompressedBincode::decode(&body)
+            .map_err(|e| match meta {
+                Some(meta) if meta.version != VERSION => log::error!(
+                    "could not load {} saved by version {} of the game, this is version {}: {}",
+                    slot,
+                    meta.version,
+                    VERSION,
+                    e
+                ),
+                _ => log::error!("could not load {}: {}", slot, e),
+            })
+            .ok()?;
+        log::info!("successfully loaded {}", slot);
         Some(sim)
     }
 
-    pub fn save_to_disk(&self, save_name: &str) {
-        common::saveload::CompressedBincode::save(&self, save_name);
+    /// Saves the simulation to the slot, along with the replay if it is being recorded
+    pub fn save_to_slot(&self, store: &SaveStore, slot: &str) -> Option<()> {
+        let body = common::saveload::CompressedBincode::encode(self)
+            .map_err(|e| log::error!("failed serializing: {}", e))
+            .ok()?;
+        store
+            .write(slot, &self.save_meta(), &body)
+            .map_err(|e| log::error!("could not save {}: {}", slot, e))
+            .ok()?;
+
         let rep = self.resources.read::<Replay>();
         if rep.enabled {
-            common::saveload::JSONPretty::save(&*rep, &format!("{save_name}_replay"));
+            let data = common::saveload::JSONPretty::encode(&*rep)
+                .map_err(|e| log::error!("failed serializing replay: {}", e))
+                .ok()?;
+            store
+                .write_atomic(&format!("{slot}_replay.json"), &data)
+                .map_err(|e| log::error!("could not save replay of {}: {}", slot, e))
+                .ok()?;
         }
+
+        log::info!("successfully saved {}", slot);
+        Some(())
     }
 
     pub fn pos<E: WorldTransform>(&self, id: E) -> Option<Vec3> {
@@ -356,17 +466,29 @@ impl Serialize for Simulation {
         let t = Instant::now();
         let mut m: FastMap<String, Vec<u8>> = FastMap::default();
 
+        let mut versions: BTreeMap<String, u32> = BTreeMap::new();
+
         unsafe {
             for l in &SAVELOAD_FUNCS {
                 let v: Vec<u8> = (l.save)(self);
                 m.insert(l.name.to_string(), v);
+                versions.insert(l.name.to_string(), schema_version(l.name));
             }
         }
 
+        versions.insert(WORLD_KEY.to_string(), schema_version(WORLD_KEY));
+        m.insert(
+            SCHEMA_VERSIONS_KEY.to_string(),
+            common::saveload::Bincode::encode(&versions).map_err(S::Error::custom)?,
+        );
+
         log::info!("took {}s to serialize resources", t.elapsed().as_secs_f32());
 
+        let world = common::saveload::Bincode::encode(&self.world).map_err(S::Error::custom)?;
+
         let v = SimulationSer {
-            world: &self.world,
+            inline_world: &[],
+            world,
             version: VERSION.to_string(),
             res: m,
         }
@@ -376,20 +498,150 @@ impl Serialize for Simulation {
     }
 }
 
+/// Stored alongside the resources so that saves made before resources had schema versions
+/// can still be read, their resources are all at version 0
+const SCHEMA_VERSIONS_KEY: &str = "schema_versions";
+
+/// The world is saved as a blob with its own schema version under this name,
+/// its migrations are registered like the ones of the resources
+pub(crate) const WORLD_KEY: &str = "world";
+
 #[derive(Serialize)]
-struct SimulationSer<'a> {
-    world: &'a World,
+struct SimulationSer {
+    /// Always empty, see [`SavedWorld`]
+    inline_world: &'static [u8],
+    world: Vec<u8>,
     version: String,
     res: FastMap<String, Vec<u8>>,
 }
 
-#[derive(Deserialize)]
 struct SimulationDeser {
-    world: World,
+    world: SavedWorld,
     version: String,
     res: FastMap<String, Vec<u8>>,
 }
 
+/// Saves from before the world had a schema version kept it inline, at version 0.
+/// Such a world starts with the slots of the vehicles, which always hold the sentinel slot,
+/// so newer saves start with an empty sequence instead, followed by the world blob.
+/// Telling them apart relies on sequences knowing their length, as they do in bincode.
+enum SavedWorld {
+    Inline(Box<WorldV0>),
+    Blob(Vec<u8>),
+}
+
+/// Reads the inline world of old saves, or the empty sequence of newer ones
+struct InlineWorld(Option<Box<WorldV0>>);
+
+/// The vehicles of an inline world, or nothing for the empty sequence of newer saves
+struct InlineVehicles(Option<HopSlotMap<VehicleID, VehicleEnt>>);
+
+fn next_field<'de, T: Deserialize<'de>, A: SeqAccess<'de>>(
+    seq: &mut A,
+    i: usize,
+    expected: &dyn Expected,
+) -> Result<T, A::Error> {
+    seq.next_element()?
+        .ok_or_else(|| A::Error::invalid_length(i, expected))
+}
+
+impl<'de> Deserialize<'de> for InlineVehicles {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct VehiclesVisitor;
+
+        impl<'de> Visitor<'de> for VehiclesVisitor {
+            type Value = InlineVehicles;
+
+            fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+                f.write_str("the slots of the vehicles")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                if seq.size_hint() == Some(0) {
+                    return Ok(InlineVehicles(None));
+                }
+                HopSlotMap::deserialize(SeqAccessDeserializer::new(seq))
+                    .map(|v| InlineVehicles(Some(v)))
+            }
+        }
+
+        d.deserialize_seq(VehiclesVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for InlineWorld {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct WorldVisitor;
+
+        impl<'de> Visitor<'de> for WorldVisitor {
+            type Value = InlineWorld;
+
+            fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+                f.write_str("an inline world")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let InlineVehicles(Some(vehicles)) = next_field(&mut seq, 0, &self)? else {
+                    return Ok(InlineWorld(None));
+                };
+                Ok(InlineWorld(Some(Box::new(WorldV0 {
+                    vehicles,
+                    humans: next_field(&mut seq, 1, &self)?,
+                    trains: next_field(&mut seq, 2, &self)?,
+                    wagons: next_field(&mut seq, 3, &self)?,
+                    freight_stations: next_field(&mut seq, 4, &self)?,
+                    companies: next_field(&mut seq, 5, &self)?,
+                }))))
+            }
+        }
+
+        d.deserialize_struct(
+            "World",
+            &[
+                "vehicles",
+                "humans",
+                "trains",
+                "wagons",
+                "freight_stations",
+                "companies",
+            ],
+            WorldVisitor,
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for SimulationDeser {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        struct SimulationVisitor;
+
+        impl<'de> Visitor<'de> for SimulationVisitor {
+            type Value = SimulationDeser;
+
+            fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+                f.write_str("a saved simulation")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let world = match next_field(&mut seq, 0, &self)? {
+                    InlineWorld(Some(world)) => SavedWorld::Inline(world),
+                    InlineWorld(None) => SavedWorld::Blob(next_field(&mut seq, 1, &self)?),
+                };
+                Ok(SimulationDeser {
+                    world,
+                    version: next_field(&mut seq, 2, &self)?,
+                    res: next_field(&mut seq, 3, &self)?,
+                })
+            }
+        }
+
+        d.deserialize_struct(
+            "SimulationDeser",
+            &["inline_world", "world", "version", "res"],
+            SimulationVisitor,
+        )
+    }
+}
+
 impl<'de> Deserialize<'de> for Simulation {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -398,7 +650,8 @@ impl<'de> Deserialize<'de> for Simulation {
         log::info!("deserializing sim state");
         let t = Instant::now();
 
-        let mut simdeser = <SimulationDeser as Deserialize>::deserialize(deserializer)?;
+        let mut simdeser = <SimulationDeser as Deserialize>::deserialize(deserializer)
+            .map_err(|e| D::Error::custom(format!("the save is corrupted: {e}")))?;
 
         log::info!(
             "took {}s to deserialize base deser",
@@ -429,16 +682,39 @@ impl<'de> Deserialize<'de> for Simulation {
             }
         }
 
-        sim.world = simdeser.world;
+        let versions: BTreeMap<String, u32> = match simdeser.res.remove(SCHEMA_VERSIONS_KEY) {
+            Some(data) => common::saveload::Bincode::decode(&data).map_err(D::Error::custom)?,
+            None => BTreeMap::new(),
+        };
+
+        let (world_version, world) = match simdeser.world {
+            SavedWorld::Inline(world) => (
+                0,
+                common::saveload::Bincode::encode(&world).map_err(D::Error::custom)?,
+            ),
+            SavedWorld::Blob(world) => (versions.get(WORLD_KEY).copied().unwrap_or(0), world),
+        };
+        let world = migrate(WORLD_KEY, world_version, world).map_err(D::Error::custom)?;
+        sim.world = common::saveload::Bincode::decode(&world).map_err(|e| {
+            D::Error::custom(format!(
+                "could not decode the world saved with schema version {world_version}: {e}"
+            ))
+        })?;
 
         unsafe {
             for l in &SAVELOAD_FUNCS {
                 if let Some(data) = simdeser.res.remove(l.name) {
+                    let version = versions.get(l.name).copied().unwrap_or(0);
+                    let data = migrate(l.name, version, data).map_err(D::Error::custom)?;
                     (l.load)(&mut sim, data);
                 }
             }
         }
 
+        if world_version == 0 {
+            souls::desire::add_missing_desires(&mut sim.world, &sim.resources);
+        }
+
         log::info!(
             "took {}s to deserialize in total",
             t.elapsed().as_secs_f32()
@@ -448,6 +724,13 @@ impl<'de> Deserialize<'de> for Simulation {
     }
 }
 
+/// The commands building the starting layout of a new game
+pub(crate) fn start_commands() -> Vec<WorldCommand> {
+    let start_commands: Vec<(u32, WorldCommand)> =
+        common::saveload::JSON::decode(START_COMMANDS.as_bytes()).unwrap();
+    start_commands.into_iter().map(|(_, c)| c).collect()
+}
+
 const START_COMMANDS: &str = r#"
 [
      [
@@ -1,7 +1,11 @@
-use crate::map::{Intersection, LaneID, Lanes, Roads, TrafficControl, TrafficLightSchedule};
+use crate::map::{
+    ActuatedLight, Intersection, LaneID, Lanes, Map, RoadID, Roads, TrafficBehavior,
+    TrafficControl, TrafficLightSchedule,
+};
 use crate::utils::time::SECONDS_PER_REALTIME_SECOND;
 use egui_inspect::{egui, egui::Ui, Inspect, InspectArgs};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LightPolicy {
@@ -10,26 +14,107 @@ pub enum LightPolicy {
     Lights,
     #[default]
     Auto,
+    /// Lights with chosen durations and offset
+    CustomLights(LightTimings),
+    /// Lights giving the green to the approaches where vehicles are waiting
+    Actuated(ActuatedTimings),
+}
+
+/// Durations of a fixed light cycle, in game seconds.
+/// Each phase is green, then orange, then red for everyone during `all_red`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightTimings {
+    pub green: u16,
+    pub orange: u16,
+    pub all_red: u16,
+    /// Delay of the start of the cycle, to make green waves along a corridor
+    pub offset: u16,
+}
+
+/// Bounds of the phases of actuated lights, in game seconds
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActuatedTimings {
+    /// A phase stays green at least this long
+    pub min_green: u16,
+    /// A phase gives way after this long if vehicles are waiting elsewhere
+    pub max_green: u16,
+    pub orange: u16,
+}
+
+impl Default for LightTimings {
+    fn default() -> Self {
+        Self {
+            green: 10 * SECONDS_PER_REALTIME_SECOND as u16,
+            orange: 4 * SECONDS_PER_REALTIME_SECOND as u16,
+            all_red: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl LightTimings {
+    /// Length of one phase, the whole cycle lasts a phase per pair of approaches
+    pub fn phase_length(&self) -> u16 {
+        self.green + self.orange + self.all_red
+    }
+
+    pub fn clamped(self) -> Self {
+        Self {
+            green: self.green.clamp(1, 600),
+            orange: self.orange.min(120),
+            all_red: self.all_red.min(120),
+            offset: self.offset,
+        }
+    }
+}
+
+impl Default for ActuatedTimings {
+    fn default() -> Self {
+        Self {
+            min_green: 5 * SECONDS_PER_REALTIME_SECOND as u16,
+            max_green: 30 * SECONDS_PER_REALTIME_SECOND as u16,
+            orange: 4 * SECONDS_PER_REALTIME_SECOND as u16,
+        }
+    }
 }
 
 impl LightPolicy {
-    pub fn apply(self, inter: &Intersection, lanes: &mut Lanes, roads: &Roads) {
-        let in_road_lanes: Vec<Vec<LaneID>> = inter
+    /// Lanes needing a light coming into the intersection, grouped by road
+    pub fn incoming_light_lanes(inter: &Intersection, roads: &Roads) -> Vec<(RoadID, Vec<LaneID>)> {
+        inter
             .roads
             .iter()
             .map(|&x| {
-                roads
-                    .get(x)
-                    .into_iter()
-                    .flat_map(|r| {
-                        r.incoming_lanes_to(inter.id)
-                            .iter()
-                            .filter(|(_, kind)| kind.needs_light())
-                            .map(|&(id, _)| id)
-                    })
-                    .collect::<Vec<_>>()
+                (
+                    x,
+                    roads
+                        .get(x)
+                        .into_iter()
+                        .flat_map(|r| {
+                            r.incoming_lanes_to(inter.id)
+                                .iter()
+                                .filter(|(_, kind)| kind.needs_light())
+                                .map(|&(id, _)| id)
+                        })
+                        .collect::<Vec<_>>(),
+                )
             })
-            .filter(|v| !v.is_empty())
+            .filter(|(_, v)| !v.is_empty())
+            .collect()
+    }
+
+    /// The phase during which the lights of the lanes coming from the road are green
+    pub fn phase_of(inter: &Intersection, road: RoadID, roads: &Roads) -> Option<u16> {
+        let in_road_lanes = Self::incoming_light_lanes(inter, roads);
+        let n_phases = in_road_lanes.len().div_ceil(2);
+        let i = in_road_lanes.iter().position(|(r, _)| *r == road)?;
+        Some((i % n_phases) as u16)
+    }
+
+    pub fn apply(self, inter: &Intersection, lanes: &mut Lanes, roads: &Roads) {
+        let in_road_lanes: Vec<Vec<LaneID>> = Self::incoming_light_lanes(inter, roads)
+            .into_iter()
+            .map(|(_, v)| v)
             .collect();
 
         for incoming_lanes in &in_road_lanes {
@@ -46,6 +131,12 @@ impl LightPolicy {
             LightPolicy::Lights => {
                 Self::lights(in_road_lanes, inter, lanes);
             }
+            LightPolicy::CustomLights(timings) => {
+                Self::custom_lights(in_road_lanes, timings, lanes);
+            }
+            LightPolicy::Actuated(_) => {
+                Self::actuated(in_road_lanes, lanes);
+            }
             LightPolicy::Auto => {
                 if in_road_lanes.len() <= 2 {
                     return;
@@ -100,6 +191,137 @@ impl LightPolicy {
             }
         }
     }
+
+    fn custom_lights(in_road_lanes: Vec<Vec<LaneID>>, timings: LightTimings, lanes: &mut Lanes) {
+        if in_road_lanes.is_empty() {
+            return;
+        }
+        let timings = timings.clamped();
+        let n_cycles = in_road_lanes.len().div_ceil(2) as u16;
+        let cycle_size = timings.phase_length();
+        let total_length = cycle_size * n_cycles;
+
+        // the schedule offset advances the cycle, so delaying it means going back
+        let delay = total_length - timings.offset % total_length;
+
+        for (i, incoming_lanes) in in_road_lanes.into_iter().enumerate() {
+            let i = i as u16;
+            let light = TrafficControl::Light(TrafficLightSchedule::from_basic(
+                timings.green,
+                timings.orange,
+                total_length - timings.green - timings.orange,
+                (cycle_size * (i % n_cycles) + delay) % total_length,
+            ));
+
+            for lane in incoming_lanes {
+                unwrap_cont!(lanes.get_mut(lane)).control = light;
+            }
+        }
+    }
+
+    /// Every light starts red, [`update_actuated_lights`] gives the green to the first phase
+    fn actuated(in_road_lanes: Vec<Vec<LaneID>>, lanes: &mut Lanes) {
+        let n_phases = in_road_lanes.len().div_ceil(2);
+        for (i, incoming_lanes) in in_road_lanes.into_iter().enumerate() {
+            let light = TrafficControl::Actuated(ActuatedLight {
+                phase: (i % n_phases) as u8,
+                behavior: TrafficBehavior::RED,
+                since: 0,
+            });
+            for lane in incoming_lanes {
+                unwrap_cont!(lanes.get_mut(lane)).control = light;
+            }
+        }
+    }
+}
+
+/// Switches the phases of the actuated lights depending on the number of vehicles
+/// waiting on each incoming lane.
+/// The green stays on its phase for at least `min_green`, and goes to the phase with the
+/// longest queue when nobody is waiting on it anymore or after `max_green`.
+pub fn update_actuated_lights(map: &mut Map, seconds: u32, waiting: &BTreeMap<LaneID, u32>) {
+    for inter in map.intersections.values() {
+        let LightPolicy::Actuated(timings) = inter.light_policy else {
+            continue;
+        };
+
+        let lights: Vec<(LaneID, ActuatedLight)> = inter
+            .roads
+            .iter()
+            .filter_map(|&r| map.roads.get(r))
+            .flat_map(|r| r.incoming_lanes_to(inter.id).iter())
+            .filter_map(|&(id, _)| match map.lanes.get(id)?.control {
+                TrafficControl::Actuated(light) => Some((id, light)),
+                _ => None,
+            })
+            .collect();
+        let Some(n_phases) = lights.iter().map(|(_, l)| l.phase as usize + 1).max() else {
+            continue;
+        };
+
+        let mut queues = vec![0; n_phases];
+        for (id, light) in &lights {
+            queues[light.phase as usize] += waiting.get(id).copied().unwrap_or(0);
+        }
+
+        let current = lights
+            .iter()
+            .map(|(_, l)| l)
+            .find(|l| !l.behavior.is_red())
+            .copied();
+
+        let mut set_phase = |phase: u8, behavior: TrafficBehavior| {
+            for (id, light) in &lights {
+                if light.phase != phase {
+                    continue;
+                }
+                if let Some(lane) = map.lanes.get_mut(*id) {
+                    lane.control = TrafficControl::Actuated(ActuatedLight {
+                        behavior,
+                        since: seconds,
+                        ..*light
+                    });
+                }
+            }
+        };
+
+        let Some(current) = current else {
+            set_phase(0, TrafficBehavior::GREEN);
+            continue;
+        };
+
+        let elapsed = seconds.saturating_sub(current.since);
+        let cur = current.phase as usize;
+        let others_waiting = queues
+            .iter()
+            .enumerate()
+            .any(|(phase, &q)| phase != cur && q > 0);
+
+        match current.behavior {
+            TrafficBehavior::GREEN
+                if others_waiting
+                    && (elapsed >= timings.max_green as u32
+                        || (elapsed >= timings.min_green as u32 && queues[cur] == 0)) =>
+            {
+                set_phase(current.phase, TrafficBehavior::ORANGE);
+            }
+            TrafficBehavior::ORANGE if elapsed >= timings.orange as u32 => {
+                // the longest queue gets the green, ties go to the next phases first
+                let next = (1..n_phases)
+                    .map(|i| (cur + i) % n_phases)
+                    .max_by_key(|&phase| {
+                        (
+                            queues[phase],
+                            std::cmp::Reverse((phase + n_phases - cur) % n_phases),
+                        )
+                    })
+                    .unwrap_or(cur);
+                set_phase(current.phase, TrafficBehavior::RED);
+                set_phase(next as u8, TrafficBehavior::GREEN);
+            }
+            _ => {}
+        }
+    }
 }
 
 impl Inspect<LightPolicy> for LightPolicy {
@@ -119,6 +341,8 @@ impl Inspect<LightPolicy> for LightPolicy {
             LightPolicy::StopSigns => 1,
             LightPolicy::Lights => 2,
             LightPolicy::Auto => 3,
+            LightPolicy::CustomLights(_) => 4,
+            LightPolicy::Actuated(_) => 5,
         };
 
         let tostr = |x: LightPolicy| match x {
@@ -126,6 +350,8 @@ impl Inspect<LightPolicy> for LightPolicy {
             LightPolicy::StopSigns => "Stop signs",
             LightPolicy::Lights => "Lights",
             LightPolicy::Auto => "Auto",
+            LightPolicy::CustomLights(_) => "Custom lights",
+            LightPolicy::Actuated(_) => "Actuated lights",
         };
 
         let get = |i| match i {
@@ -133,16 +359,202 @@ impl Inspect<LightPolicy> for LightPolicy {
             1 => LightPolicy::StopSigns,
             2 => LightPolicy::Lights,
             3 => LightPolicy::Auto,
+            4 => LightPolicy::CustomLights(Default::default()),
+            5 => LightPolicy::Actuated(Default::default()),
             _ => unreachable!(),
         };
 
-        let changed = egui::ComboBox::from_label(label)
-            .show_index(ui, &mut id, 4, |i| tostr(get(i)).to_string())
+        let mut changed = egui::ComboBox::from_label(label)
+            .show_index(ui, &mut id, 6, |i| tostr(get(i)).to_string())
             .changed();
         if changed {
             *p = get(id);
         }
 
+        let mut duration = |ui: &mut Ui, v: &mut u16, name: &str| {
+            ui.horizontal(|ui| {
+                changed |= ui
+                    .add(egui::DragValue::new(v).clamp_range(0..=600))
+                    .changed();
+                ui.label(name);
+            });
+        };
+        match p {
+            LightPolicy::CustomLights(ref mut timings) => {
+                duration(ui, &mut timings.green, "green (s)");
+                duration(ui, &mut timings.orange, "orange (s)");
+                duration(ui, &mut timings.all_red, "all red (s)");
+                duration(ui, &mut timings.offset, "offset (s)");
+            }
+            LightPolicy::Actuated(ref mut timings) => {
+                duration(ui, &mut timings.min_green, "min green (s)");
+                duration(ui, &mut timings.max_green, "max green (s)");
+                duration(ui, &mut timings.orange, "orange (s)");
+            }
+            _ => {}
+        }
+
         changed
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{update_actuated_lights, ActuatedTimings, LightPolicy, LightTimings};
+    use crate::map::{IntersectionID, LaneID, Map, TrafficBehavior};
+    use crate::tests::TestCtx;
+    use geom::{vec2, vec3, Vec2};
+    use std::collections::BTreeMap;
+
+    fn inter_at(map: &Map, p: Vec2) -> IntersectionID {
+        map.intersections()
+            .values()
+            .find(|i| i.pos.xy().distance(p) < 1.0)
+            .unwrap()
+            .id
+    }
+
+    /// A light controlled lane going from `from` into `to`
+    fn lane_between(map: &Map, from: IntersectionID, to: IntersectionID) -> LaneID {
+        let inter = &map.intersections()[to];
+        let road = inter
+            .roads
+            .iter()
+            .map(|&r| &map.roads()[r])
+            .find(|r| r.other_end(to) == Some(from))
+            .unwrap();
+        road.incoming_lanes_to(to)
+            .iter()
+            .find(|(_, kind)| kind.needs_light())
+            .unwrap()
+            .0
+    }
+
+    fn behavior(map: &Map, lane: LaneID, seconds: u32) -> TrafficBehavior {
+        map.lanes()[lane].control.get_behavior(seconds)
+    }
+
+    #[test]
+    fn test_custom_lights_and_green_wave() {
+        let test = TestCtx::new();
+        test.build_roads(&[
+            vec3(100.0, 300.0, 0.0),
+            vec3(300.0, 300.0, 0.0),
+            vec3(700.0, 300.0, 0.0),
+            vec3(900.0, 300.0, 0.0),
+        ]);
+        for x in [300.0, 700.0] {
+            test.build_roads(&[vec3(x, 100.0, 0.0), vec3(x, 300.0, 0.0)]);
+            test.build_roads(&[vec3(x, 300.0, 0.0), vec3(x, 500.0, 0.0)]);
+        }
+
+        let mut map = test.g.map_mut();
+        let west = inter_at(&map, vec2(100.0, 300.0));
+        let a = inter_at(&map, vec2(300.0, 300.0));
+        let b = inter_at(&map, vec2(700.0, 300.0));
+        let south = inter_at(&map, vec2(300.0, 100.0));
+
+        let timings = LightTimings {
+            green: 30,
+            orange: 10,
+            all_red: 5,
+            offset: 0,
+        };
+        map.update_intersection(a, |i| i.light_policy = LightPolicy::CustomLights(timings));
+
+        // two phases of 45 seconds
+        let from_west = lane_between(&map, west, a);
+        let from_south = lane_between(&map, south, a);
+        let road_west = map.lanes()[from_west].parent;
+        let phase = LightPolicy::phase_of(&map.intersections()[a], road_west, map.roads()).unwrap();
+        let (first, second) = if phase == 0 {
+            (from_west, from_south)
+        } else {
+            (from_south, from_west)
+        };
+        assert_eq!(behavior(&map, first, 0), TrafficBehavior::GREEN);
+        assert_eq!(behavior(&map, first, 35), TrafficBehavior::ORANGE);
+        assert_eq!(behavior(&map, first, 42), TrafficBehavior::RED);
+        assert_eq!(behavior(&map, first, 90), TrafficBehavior::GREEN);
+        assert_eq!(behavior(&map, second, 20), TrafficBehavior::RED);
+        assert_eq!(behavior(&map, second, 50), TrafficBehavior::GREEN);
+
+        // the offset delays the cycle
+        map.update_intersection(a, |i| {
+            i.light_policy = LightPolicy::CustomLights(LightTimings {
+                offset: 20,
+                ..timings
+            })
+        });
+        assert_eq!(behavior(&map, first, 10), TrafficBehavior::RED);
+        assert_eq!(behavior(&map, first, 25), TrafficBehavior::GREEN);
+
+        map.green_wave(&[a, b], timings, 10.0);
+        let road_ab = map.lanes()[lane_between(&map, a, b)].parent;
+        let travel = (map.roads()[road_ab].length() / 10.0 * 15.0) as u32;
+        let from_west = lane_between(&map, west, a);
+        let from_a = lane_between(&map, a, b);
+        assert_eq!(behavior(&map, from_west, 1), TrafficBehavior::GREEN);
+        assert_eq!(behavior(&map, from_a, travel + 1), TrafficBehavior::GREEN);
+        assert_eq!(behavior(&map, from_a, travel + 50), TrafficBehavior::RED);
+
+        // no cycle to schedule without incoming lanes
+        LightPolicy::custom_lights(vec![], timings, &mut map.lanes);
+    }
+
+    #[test]
+    fn test_actuated_lights() {
+        let test = TestCtx::new();
+        test.build_roads(&[
+            vec3(100.0, 300.0, 0.0),
+            vec3(300.0, 300.0, 0.0),
+            vec3(500.0, 300.0, 0.0),
+        ]);
+        test.build_roads(&[vec3(300.0, 100.0, 0.0), vec3(300.0, 300.0, 0.0)]);
+        test.build_roads(&[vec3(300.0, 300.0, 0.0), vec3(300.0, 500.0, 0.0)]);
+
+        let mut map = test.g.map_mut();
+        let center = inter_at(&map, vec2(300.0, 300.0));
+        let west = inter_at(&map, vec2(100.0, 300.0));
+        let south = inter_at(&map, vec2(300.0, 100.0));
+        map.update_intersection(center, |i| {
+            i.light_policy = LightPolicy::Actuated(ActuatedTimings {
+                min_green: 10,
+                max_green: 60,
+                orange: 5,
+            })
+        });
+
+        let from_west = lane_between(&map, west, center);
+        let from_south = lane_between(&map, south, center);
+        let mut waiting = BTreeMap::new();
+
+        update_actuated_lights(&mut map, 0, &waiting);
+        let (green, red) = if behavior(&map, from_west, 0) == TrafficBehavior::GREEN {
+            (from_west, from_south)
+        } else {
+            (from_south, from_west)
+        };
+        assert_eq!(behavior(&map, red, 0), TrafficBehavior::RED);
+
+        // nobody waits elsewhere, the green stays
+        update_actuated_lights(&mut map, 100, &waiting);
+        assert_eq!(behavior(&map, green, 100), TrafficBehavior::GREEN);
+
+        waiting.insert(red, 3);
+        update_actuated_lights(&mut map, 101, &waiting);
+        assert_eq!(behavior(&map, green, 101), TrafficBehavior::ORANGE);
+        update_actuated_lights(&mut map, 104, &waiting);
+        assert_eq!(behavior(&map, green, 104), TrafficBehavior::ORANGE);
+        update_actuated_lights(&mut map, 106, &waiting);
+        assert_eq!(behavior(&map, green, 106), TrafficBehavior::RED);
+        assert_eq!(behavior(&map, red, 106), TrafficBehavior::GREEN);
+
+        // the queue holds the green until the maximum when others wait
+        waiting.insert(green, 1);
+        update_actuated_lights(&mut map, 150, &waiting);
+        assert_eq!(behavior(&map, red, 150), TrafficBehavior::GREEN);
+        update_actuated_lights(&mut map, 166, &waiting);
+        assert_eq!(behavior(&map, red, 166), TrafficBehavior::ORANGE);
+    }
+}
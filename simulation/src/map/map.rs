@@ -1,23 +1,26 @@
 use crate::map::serializing::SerializedMap;
 use crate::map::{
-    Building, BuildingID, BuildingKind, Intersection, IntersectionID, Lane, LaneID, LaneKind,
-    LanePattern, Lot, LotID, LotKind, MapSubscriber, MapSubscribers, ParkingSpotID, ParkingSpots,
-    ProjectFilter, ProjectKind, Road, RoadID, RoadSegmentKind, SpatialMap, Terrain, UpdateType,
-    Zone,
+    Building, BuildingID, BuildingKind, Intersection, IntersectionID, Lane, LaneDirection, LaneID,
+    LaneKind, LanePattern, LightPolicy, LightTimings, Lot, LotID, LotKind, MapSubscriber,
+    MapSubscribers, ParkingSpotID, ParkingSpots, ProjectFilter, ProjectKind, Road, RoadID,
+    RoadRestrictions, RoadSegmentKind, SpatialMap, Terrain, TerrainBrush, UpdateType, UtilityKind,
+    UtilityLink, UtilityLinkID, VerticalProfile, Zone, MAX_UTILITY_LINK_LENGTH, TUNNEL_DEPTH,
 };
+use crate::utils::time::SECONDS_PER_REALTIME_SECOND;
 use common::descriptions::BuildingGen;
-use geom::OBB;
+use geom::{BoldLine, PolyLine, OBB};
 use geom::{Spline3, Vec2, Vec3};
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
 use slotmapd::HopSlotMap;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 pub type Roads = HopSlotMap<RoadID, Road>;
 pub type Lanes = HopSlotMap<LaneID, Lane>;
 pub type Intersections = HopSlotMap<IntersectionID, Intersection>;
 pub type Buildings = HopSlotMap<BuildingID, Building>;
 pub type Lots = HopSlotMap<LotID, Lot>;
+pub type UtilityLinks = HopSlotMap<UtilityLinkID, UtilityLink>;
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct MapProject {
@@ -33,6 +36,8 @@ pub struct Map {
     pub(crate) lots: Lots,
     pub(crate) spatial_map: SpatialMap,
     pub(crate) bkinds: BTreeMap<BuildingKind, Vec<BuildingID>>,
+    pub(crate) restrictions: BTreeMap<RoadID, RoadRestrictions>,
+    pub(crate) utility_links: UtilityLinks,
     pub terrain: Terrain,
     pub parking: ParkingSpots,
     pub subscribers: MapSubscribers,
@@ -59,6 +64,8 @@ impl Map {
             terrain: Terrain::default(),
             spatial_map: SpatialMap::default(),
             bkinds: Default::default(),
+            restrictions: Default::default(),
+            utility_links: UtilityLinks::default(),
             subscribers: Default::default(),
         }
     }
@@ -75,6 +82,58 @@ impl Map {
         self.check_invariants()
     }
 
+    /// Gives custom lights to the intersections of the corridor, offset so that vehicles
+    /// leaving the first intersection at `speed` (in m/s) find the green at the next ones.
+    /// Intersections with a different number of phases have a different cycle and drift apart.
+    pub fn green_wave(&mut self, corridor: &[IntersectionID], timings: LightTimings, speed: f32) {
+        info!("green_wave {:?} {:?} {}", corridor, timings, speed);
+        let timings = timings.clamped();
+        let speed = speed.max(1.0);
+
+        let road_between = |map: &Map, a: IntersectionID, b: IntersectionID| {
+            let inter = map.intersections.get(a)?;
+            inter
+                .roads
+                .iter()
+                .copied()
+                .find(|&r| map.roads.get(r).is_some_and(|r| r.other_end(a) == Some(b)))
+        };
+
+        let mut travel = 0.0;
+        for (k, &id) in corridor.iter().enumerate() {
+            // the phase of the approach coming from the previous intersection,
+            // or of the road towards the next one for the first intersection
+            let road = if k == 0 {
+                corridor
+                    .get(1)
+                    .and_then(|&next| road_between(self, id, next))
+            } else {
+                let Some(road) = road_between(self, corridor[k - 1], id) else {
+                    return;
+                };
+                travel += self.roads[road].length() / speed * SECONDS_PER_REALTIME_SECOND as f32;
+                Some(road)
+            };
+
+            let Some(inter) = self.intersections.get(id) else {
+                return;
+            };
+            let n_phases = LightPolicy::incoming_light_lanes(inter, &self.roads)
+                .len()
+                .div_ceil(2) as u16;
+            let total_length = (timings.phase_length() * n_phases).max(1);
+            let phase = road
+                .and_then(|r| LightPolicy::phase_of(inter, r, &self.roads))
+                .unwrap_or(0);
+
+            let offset = ((travel as u32 + (timings.phase_length() * phase) as u32)
+                % total_length as u32) as u16;
+            self.update_intersection(id, move |i| {
+                i.light_policy = LightPolicy::CustomLights(LightTimings { offset, ..timings });
+            });
+        }
+    }
+
     pub fn remove_intersection(&mut self, src: IntersectionID) {
         info!("remove_intersection {:?}", src);
         self.remove_intersection_inner(src);
@@ -106,6 +165,8 @@ impl Map {
 
         let b = self.buildings.remove(b)?;
         self.spatial_map.remove(b.id);
+        self.utility_links
+            .retain(|_, link| link.src != b.id && link.dst != b.id);
         self.subscribers.dispatch(UpdateType::Building, &b);
 
         if b.kind.is_cached_in_bkinds() {
@@ -157,6 +218,24 @@ impl Map {
         Some((to, r))
     }
 
+    /// Same as [`Map::make_connection`], but the ends built on the ground are moved to the
+    /// elevations of the profile first, and the connection is refused if it doesn't respect it.
+    pub fn make_profiled_connection(
+        &mut self,
+        from: MapProject,
+        to: MapProject,
+        interpoint: Option<Vec2>,
+        pattern: &LanePattern,
+        profile: &VerticalProfile,
+    ) -> Option<(IntersectionID, RoadID)> {
+        let (from, to) = profile.apply(from, to, &self.terrain);
+        if let Err(e) = profile.check(from.pos, to.pos, interpoint, pattern, &self.terrain) {
+            info!("refused connection {:?} {:?}: {}", from, to, e);
+            return None;
+        }
+        self.make_connection(from, to, interpoint, pattern)
+    }
+
     pub fn update_zone(&mut self, id: BuildingID, f: impl FnOnce(&mut Zone)) {
         let Some(b) = self.buildings.get_mut(id) else {
             return;
@@ -183,6 +262,130 @@ impl Map {
         self.check_invariants()
     }
 
+    /// Edits the terrain heights.
+    /// Intersections, roads, buildings and lots sitting on the edited ground follow it,
+    /// bridges and tunnels stay where they are.
+    pub fn terrain_brush(&mut self, brush: &TerrainBrush) {
+        info!("terrain brush {:?}", brush);
+
+        let deltas = self.terrain.brush_deltas(brush);
+        if deltas.is_empty() {
+            return;
+        }
+
+        let on_ground: Vec<ProjectKind> = self
+            .spatial_map
+            .query_around(
+                brush.center,
+                brush.radius,
+                ProjectFilter::INTER
+                    | ProjectFilter::ROAD
+                    | ProjectFilter::BUILDING
+                    | ProjectFilter::LOT,
+            )
+            .filter(|&kind| match kind {
+                // the ends of bridges are not on the ground
+                ProjectKind::Inter(id) => self.intersections.get(id).is_some_and(|i| {
+                    self.terrain
+                        .height(i.pos.xy())
+                        .is_some_and(|h| (i.pos.z - 0.3 - h).abs() < 1.0)
+                }),
+                ProjectKind::Road(id) => self
+                    .roads
+                    .get(id)
+                    .is_some_and(|r| r.is_on_ground(&self.terrain, brush.center, brush.radius)),
+                _ => true,
+            })
+            .collect();
+
+        // the roads of the moved intersections that lie on the ground under the brush are
+        // draped again, the others (bridges and tunnels) go straight between their ends
+        let draped: BTreeSet<RoadID> = on_ground
+            .iter()
+            .filter_map(|&kind| match kind {
+                ProjectKind::Inter(id) => self.intersections.get(id),
+                _ => None,
+            })
+            .flat_map(|i| i.roads.iter().copied())
+            .filter(|&r| {
+                self.roads.get(r).is_some_and(|road| {
+                    road.is_on_ground(&self.terrain, brush.center, brush.radius)
+                })
+            })
+            .collect();
+
+        self.terrain.apply_deltas(&deltas, |c| {
+            self.subscribers.dispatch_chunk(UpdateType::Terrain, c)
+        });
+
+        let mut roads = vec![];
+        for kind in on_ground {
+            match kind {
+                ProjectKind::Inter(id) => {
+                    let inter = unwrap_cont!(self.intersections.get_mut(id));
+                    let h = unwrap_cont!(self.terrain.height(inter.pos.xy()));
+                    inter.pos.z = h + 0.3;
+                    for r in inter.roads.clone() {
+                        let road = unwrap_cont!(self.roads.get_mut(r));
+                        let (Some(src), Some(dst)) = (
+                            self.intersections.get(road.src),
+                            self.intersections.get(road.dst),
+                        ) else {
+                            continue;
+                        };
+                        if draped.contains(&r) {
+                            // keeps the points a previous brush draped outside of this one
+                            road.set_end_heights(src, dst);
+                            roads.push(r);
+                        } else {
+                            road.update_points(src, dst);
+                        }
+                        self.spatial_map.update(r, road.boldline());
+                    }
+                    self.invalidate(id);
+                }
+                ProjectKind::Building(id) => {
+                    let b = unwrap_cont!(self.buildings.get_mut(id));
+                    let h = unwrap_cont!(self.terrain.height(b.obb.center()));
+                    let dz = h - b.height;
+                    for (poly, _) in &mut b.mesh.faces {
+                        for v in poly {
+                            v.z += dz;
+                        }
+                    }
+                    b.door_pos.z += dz;
+                    b.height = h;
+                    self.subscribers.dispatch(UpdateType::Building, b);
+                }
+                ProjectKind::Lot(id) => {
+                    let lot = unwrap_cont!(self.lots.get_mut(id));
+                    lot.height = unwrap_cont!(self.terrain.height(lot.shape.center()));
+                    self.subscribers.dispatch(UpdateType::Road, lot);
+                }
+                ProjectKind::Road(id) => roads.push(id),
+                ProjectKind::Ground => {}
+            }
+        }
+
+        // after the intersections moved, as it regenerates the points of their roads
+        roads.sort_unstable();
+        roads.dedup();
+        let mut to_invalidate = vec![];
+        for id in roads {
+            let road = unwrap_cont!(self.roads.get_mut(id));
+            road.drape(&self.terrain, brush.center, brush.radius);
+            self.spatial_map.update(id, road.boldline());
+            to_invalidate.extend([road.src, road.dst]);
+        }
+        to_invalidate.sort_unstable();
+        to_invalidate.dedup();
+        for id in to_invalidate {
+            self.invalidate(id);
+        }
+
+        self.check_invariants();
+    }
+
     pub fn build_special_building(
         &mut self,
         obb: &OBB,
@@ -305,6 +508,114 @@ impl Map {
         Some(road)
     }
 
+    pub fn set_road_restrictions(&mut self, road: RoadID, restrictions: RoadRestrictions) {
+        info!("set_road_restrictions {:?} {:?}", road, restrictions);
+        let r = unwrap_ret!(self.roads.get(road));
+        let (src, dst) = (r.src, r.dst);
+
+        let mut restrictions = restrictions.clamped();
+        let old = self.road_restrictions(road);
+        let is_road = |kind: &LaneKind| matches!(kind, LaneKind::Driving | LaneKind::Bus);
+        // the lanes only have the limit of the pattern while no speed limit is set
+        restrictions.pattern_speed_limit = if old.speed_limit.is_some() {
+            old.pattern_speed_limit
+        } else {
+            r.lanes_iter()
+                .filter(|(_, kind)| is_road(kind))
+                .filter_map(|(id, _)| Some(self.lanes.get(id)?.speed_limit))
+                .reduce(f32::max)
+        };
+
+        if let (None, Some(_), Some(limit)) = (
+            restrictions.speed_limit,
+            old.speed_limit,
+            restrictions.pattern_speed_limit,
+        ) {
+            for (id, kind) in r.lanes_iter() {
+                if is_road(&kind) {
+                    unwrap_cont!(self.lanes.get_mut(id)).speed_limit = limit;
+                }
+            }
+        }
+
+        self.restrictions.insert(road, restrictions);
+        self.invalidate(src);
+        self.invalidate(dst);
+
+        // the pattern speed limit was restored
+        let unrestricted = RoadRestrictions {
+            pattern_speed_limit: restrictions.pattern_speed_limit,
+            ..Default::default()
+        };
+        if restrictions == unrestricted {
+            self.restrictions.remove(&road);
+        }
+        self.check_invariants();
+    }
+
+    /// Makes the road one way in the given direction, or two way if None.
+    /// The road keeps its id
+    pub fn set_road_direction(&mut self, road: RoadID, direction: Option<LaneDirection>) {
+        info!("set_road_direction {:?} {:?}", road, direction);
+        let r = unwrap_ret!(self.roads.get(road));
+        let pattern = r.pattern(&self.lanes).with_direction(direction);
+        self.set_road_pattern(road, &pattern);
+    }
+
+    /// Rebuilds the lanes of the road from the pattern, the road keeps its id
+    pub fn set_road_pattern(&mut self, road: RoadID, pattern: &LanePattern) {
+        info!("set_road_pattern {:?} {:?}", road, pattern);
+        let r = unwrap_ret!(self.roads.get_mut(road));
+
+        r.rebuild_lanes(pattern, &mut self.lanes, &mut self.parking);
+        self.subscribers.dispatch(UpdateType::Road, &*r);
+        self.spatial_map.update(road, r.boldline());
+        let (src, dst) = (r.src, r.dst);
+
+        self.invalidate(src);
+        self.invalidate(dst);
+        log::info!(
+            "{} parking spots reused when changing the lanes",
+            self.parking.clean_reuse()
+        );
+
+        Lot::remove_intersecting_lots(self, road);
+        self.check_invariants();
+    }
+
+    /// Builds a power line or a pipe between two buildings.
+    /// Returns None if there is already one of this kind between them or if they are too far apart
+    pub fn make_utility_link(
+        &mut self,
+        kind: UtilityKind,
+        src: BuildingID,
+        dst: BuildingID,
+        capacity: f32,
+    ) -> Option<UtilityLinkID> {
+        info!("make_utility_link {:?} {:?} {:?}", kind, src, dst);
+        let (a, b) = (self.buildings.get(src)?, self.buildings.get(dst)?);
+        if src == dst
+            || capacity <= 0.0
+            || a.door_pos.distance(b.door_pos) > MAX_UTILITY_LINK_LENGTH
+            || self.find_utility_link(kind, src, dst).is_some()
+        {
+            return None;
+        }
+
+        Some(self.utility_links.insert_with_key(|id| UtilityLink {
+            id,
+            kind,
+            src,
+            dst,
+            capacity,
+        }))
+    }
+
+    pub fn remove_utility_link(&mut self, id: UtilityLinkID) -> Option<UtilityLink> {
+        info!("remove_utility_link {:?}", id);
+        self.utility_links.remove(id)
+    }
+
     pub fn set_lot_kind(&mut self, lot: LotID, kind: LotKind) {
         match self.lots.get_mut(lot) {
             Some(lot) => {
@@ -361,8 +672,9 @@ impl Map {
             );
             other_end.update_interface_radius(&mut self.roads);
 
+            let restrictions = self.restrictions.get(&x).copied().unwrap_or_default();
             #[allow(clippy::indexing_slicing)] // borrowed before
-            self.roads[x].update_lanes(&mut self.lanes, &mut self.parking);
+            self.roads[x].update_lanes(&mut self.lanes, &mut self.parking, &restrictions);
         }
 
         #[allow(clippy::indexing_slicing)] // borrowed before
@@ -380,6 +692,7 @@ impl Map {
         let road = self.roads.remove(road_id)?;
 
         self.spatial_map.remove(road_id);
+        self.restrictions.remove(&road_id);
 
         for (id, _) in road.lanes_iter() {
             self.lanes.remove(id);
@@ -400,6 +713,7 @@ impl Map {
         info!("split_road {:?} {:?}", r_id, pos);
 
         let pat = self.roads.get(r_id)?.pattern(&self.lanes);
+        let restrictions = self.restrictions.get(&r_id).copied();
 
         let r = unwrap_or!(self.remove_raw_road(r_id), {
             log::error!("Trying to split unexisting road");
@@ -459,6 +773,14 @@ impl Map {
             self.parking.clean_reuse()
         );
 
+        if let Some(restrictions) = restrictions {
+            self.restrictions.insert(r1, restrictions);
+            self.restrictions.insert(r2, restrictions);
+            self.invalidate(src_id);
+            self.invalidate(id);
+            self.invalidate(r.dst);
+        }
+
         let r1 = self.roads.get(r1)?;
         let r2 = self.roads.get(r2)?;
 
@@ -531,15 +853,41 @@ impl Map {
 
         #[allow(clippy::indexing_slicing)]
         let r = &self.roads[id];
-        let mut b = r.boldline();
-        b.expand(40.0);
-        self.terrain.remove_near(&b, |c| {
-            self.subscribers.dispatch_chunk(UpdateType::Terrain, c)
-        });
+        for mut b in self.surface_boldlines(r) {
+            b.expand(40.0);
+            self.terrain.remove_near(&b, |c| {
+                self.subscribers.dispatch_chunk(UpdateType::Terrain, c)
+            });
+        }
 
         Some(id)
     }
 
+    /// The parts of the road that are not in a tunnel, trees above tunnels are kept
+    fn surface_boldlines(&self, r: &Road) -> Vec<BoldLine> {
+        let in_tunnel = |p: Vec3| {
+            self.terrain
+                .height(p.xy())
+                .is_some_and(|h| h - p.z >= TUNNEL_DEPTH)
+        };
+        if !r.points().iter().any(|&p| in_tunnel(p)) {
+            return vec![r.boldline()];
+        }
+
+        let mut runs = vec![vec![]];
+        for (p, _) in r.points().equipoints_dir(10.0, false) {
+            if !in_tunnel(p) {
+                runs.last_mut().unwrap().push(p.xy());
+            } else if !runs.last().unwrap().is_empty() {
+                runs.push(vec![]);
+            }
+        }
+        runs.into_iter()
+            .filter(|run| run.len() >= 2)
+            .map(|run| BoldLine::new(PolyLine::new(run), r.width * 0.5))
+            .collect()
+    }
+
     // Public helpers
     pub fn project(&self, pos: Vec3, tolerance: f32, filter: ProjectFilter) -> MapProject {
         let mk_proj = move |kind| MapProject { pos, kind };
@@ -605,6 +953,26 @@ impl Map {
     pub fn lots(&self) -> &Lots {
         &self.lots
     }
+    pub fn utility_links(&self) -> &UtilityLinks {
+        &self.utility_links
+    }
+
+    pub fn find_utility_link(
+        &self,
+        kind: UtilityKind,
+        a: BuildingID,
+        b: BuildingID,
+    ) -> Option<UtilityLinkID> {
+        self.utility_links
+            .values()
+            .find(|link| link.kind == kind && link.connects(a, b))
+            .map(|link| link.id)
+    }
+
+    pub fn road_restrictions(&self, road: RoadID) -> RoadRestrictions {
+        self.restrictions.get(&road).copied().unwrap_or_default()
+    }
+
     pub fn spatial_map(&self) -> &SpatialMap {
         &self.spatial_map
     }
@@ -6,6 +6,7 @@ mod objects {
     mod parking;
     mod road;
     mod turn;
+    mod utility_link;
 
     pub use building::*;
     pub use intersection::*;
@@ -14,6 +15,7 @@ mod objects {
     pub use parking::*;
     pub use road::*;
     pub use turn::*;
+    pub use utility_link::*;
 }
 
 pub use objects::*;
@@ -21,6 +23,7 @@ pub use objects::*;
 pub mod procgen {
     mod building;
     pub mod heightmap;
+    pub mod osm;
     mod presets;
 
     pub use building::*;
@@ -32,23 +35,30 @@ mod light_policy;
 #[allow(clippy::module_inception)]
 mod map;
 mod pathfinding;
+mod road_restrictions;
 mod serializing;
 mod spatial_map;
 mod terrain;
 mod traffic_control;
+mod travel_times;
 mod traversable;
 mod turn_policy;
+mod vertical_profile;
 
 // Use self or else it would be ambiguous with "pathfinding" crate
 pub use self::pathfinding::*;
 pub use change_detection::*;
 pub use light_policy::*;
 pub use map::*;
+pub use road_restrictions::*;
+pub(crate) use serializing::{SerializedMap, SerializedMapV0, SerializedMapV1};
 pub use spatial_map::*;
 pub use terrain::*;
 pub use traffic_control::*;
+pub use travel_times::*;
 pub use traversable::*;
 pub use turn_policy::*;
+pub use vertical_profile::*;
 
 pub use ::pathfinding as pathfinding_crate;
 
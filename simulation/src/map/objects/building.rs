@@ -47,6 +47,11 @@ pub struct StraightRoadGen {
 
 pub const MAX_ZONE_AREA: f32 = 50000.0; // in m²
 
+/// Ground area of a house needed by each of its residents, in m²
+pub const HOUSE_AREA_PER_RESIDENT: f32 = 200.0;
+/// Most residents a house can have, however big it is
+pub const MAX_HOUSE_CAPACITY: u32 = 8;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Zone {
     pub poly: Polygon,
@@ -144,4 +149,14 @@ impl Building {
             }
         }))
     }
+
+    /// How many humans can live in the building, only houses have residents
+    pub fn capacity(&self) -> u32 {
+        if self.kind != BuildingKind::House {
+            return 0;
+        }
+        let [a, b] = self.obb.axis();
+        let area = a.mag() * b.mag();
+        ((area / HOUSE_AREA_PER_RESIDENT) as u32).clamp(1, MAX_HOUSE_CAPACITY)
+    }
 }
@@ -101,6 +101,33 @@ impl LanePattern {
     pub fn width(&self) -> f32 {
         self.lanes().map(|(kind, _, _)| kind.width()).sum()
     }
+
+    /// The same pattern with the traffic going only in the given direction, or both ways if None.
+    /// Sidewalks are kept on both sides.
+    pub fn with_direction(mut self, direction: Option<LaneDirection>) -> LanePattern {
+        fn split(lanes: &[(LaneKind, f32)]) -> (Vec<(LaneKind, f32)>, Vec<(LaneKind, f32)>) {
+            lanes
+                .iter()
+                .partition(|(kind, _)| *kind != LaneKind::Walking)
+        }
+
+        let (traffic_f, walking_f) = split(&self.lanes_forward);
+        let (traffic_b, walking_b) = split(&self.lanes_backward);
+
+        let (traffic_f, traffic_b) = match direction {
+            Some(LaneDirection::Forward) if traffic_f.is_empty() => (traffic_b, vec![]),
+            Some(LaneDirection::Forward) => (traffic_f, vec![]),
+            Some(LaneDirection::Backward) if traffic_b.is_empty() => (vec![], traffic_f),
+            Some(LaneDirection::Backward) => (vec![], traffic_b),
+            None if traffic_f.is_empty() => (traffic_b.clone(), traffic_b),
+            None if traffic_b.is_empty() => (traffic_f.clone(), traffic_f),
+            None => (traffic_f, traffic_b),
+        };
+
+        self.lanes_forward = traffic_f.into_iter().chain(walking_f).collect();
+        self.lanes_backward = traffic_b.into_iter().chain(walking_b).collect();
+        self
+    }
 }
 
 #[derive(PartialEq, Copy, Clone, Inspect)]
@@ -112,6 +139,7 @@ pub struct LanePatternBuilder {
     pub parking: bool,
     pub one_way: bool,
     pub rail: bool,
+    pub bike_lanes: bool,
 }
 impl Eq for LanePatternBuilder {}
 
@@ -130,6 +158,7 @@ impl LanePatternBuilder {
             parking: true,
             one_way: false,
             rail: false,
+            bike_lanes: false,
         }
     }
 
@@ -163,6 +192,11 @@ impl LanePatternBuilder {
         self
     }
 
+    pub const fn bike_lanes(mut self, bike_lanes: bool) -> Self {
+        self.bike_lanes = bike_lanes;
+        self
+    }
+
     pub fn width(self) -> f32 {
         if self.rail {
             let wayf = if self.one_way { 1.0 } else { 2.0 };
@@ -177,6 +211,9 @@ impl LanePatternBuilder {
         if self.parking {
             w += LaneKind::Parking.width() * wayf;
         }
+        if self.bike_lanes {
+            w += LaneKind::Biking.width() * wayf;
+        }
         w += self.n_lanes as f32 * wayf * LaneKind::Driving.width();
         w + 0.5
     }
@@ -185,6 +222,7 @@ impl LanePatternBuilder {
         if self.n_lanes == 0 {
             self.parking = false;
             self.sidewalks = true;
+            self.bike_lanes = false;
         }
 
         let mut backward = if self.one_way {
@@ -195,6 +233,13 @@ impl LanePatternBuilder {
 
         let mut forward: Vec<_> = (0..self.n_lanes).map(|_| LaneKind::Driving).collect();
 
+        if self.bike_lanes {
+            if !self.one_way {
+                backward.push(LaneKind::Biking);
+            }
+            forward.push(LaneKind::Biking);
+        }
+
         if self.parking {
             if !self.one_way {
                 backward.push(LaneKind::Parking);
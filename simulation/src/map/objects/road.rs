@@ -1,6 +1,6 @@
 use crate::map::{
     Intersection, IntersectionID, Lane, LaneDirection, LaneID, LaneKind, LanePattern, Lanes,
-    ParkingSpots, Roads, SpatialMap, Terrain,
+    ParkingSpots, RoadRestrictions, Roads, SpatialMap, Terrain,
 };
 use geom::Spline3;
 use geom::{BoldLine, PolyLine3};
@@ -12,6 +12,9 @@ new_key_type! {
     pub struct RoadID;
 }
 
+/// Spacing of the points added when a road is laid on edited ground
+const DRAPE_STEP: f32 = 5.0;
+
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum RoadSegmentKind {
     Straight,
@@ -25,6 +28,14 @@ impl RoadSegmentKind {
             (to - elbow) * std::f32::consts::FRAC_1_SQRT_2,
         ))
     }
+
+    /// The elbow given to [`RoadSegmentKind::from_elbow`], if the segment is curved
+    pub fn elbow(&self, from: Vec2) -> Option<Vec2> {
+        match *self {
+            RoadSegmentKind::Straight => None,
+            RoadSegmentKind::Curved((d, _)) => Some(from + d * std::f32::consts::SQRT_2),
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -73,8 +84,8 @@ impl Road {
     ) -> RoadID {
         let width = lane_pattern.width();
         let points = Self::generate_points(
-            src,
-            dst,
+            src.pos,
+            dst.pos,
             segment,
             lane_pattern.lanes().any(|(a, _, _)| a.is_rail()),
         );
@@ -95,22 +106,57 @@ impl Road {
         #[allow(clippy::indexing_slicing)]
         let road = &mut roads[id];
 
+        road.add_lanes(lane_pattern, lanes);
+        road.update_lanes(lanes, parking, &RoadRestrictions::default());
+
+        spatial.insert(id, road.boldline());
+        road.id
+    }
+
+    fn add_lanes(&mut self, lane_pattern: &LanePattern, lanes: &mut Lanes) {
         let mut dist_from_bottom = 0.0;
         for (lane_k, dir, limit) in lane_pattern.lanes() {
-            let id = Lane::make(road, lanes, lane_k, limit, dir, dist_from_bottom);
+            let id = Lane::make(self, lanes, lane_k, limit, dir, dist_from_bottom);
 
             match dir {
-                LaneDirection::Forward => road.lanes_forward.insert(0, (id, lane_k)),
-                LaneDirection::Backward => road.lanes_backward.push((id, lane_k)),
+                LaneDirection::Forward => self.lanes_forward.insert(0, (id, lane_k)),
+                LaneDirection::Backward => self.lanes_backward.push((id, lane_k)),
             }
 
             dist_from_bottom += lane_k.width();
         }
+    }
 
-        road.update_lanes(lanes, parking);
+    /// Replaces the lanes by the ones of the pattern while keeping the road id,
+    /// call [`Road::update_lanes`] afterwards
+    pub(crate) fn rebuild_lanes(
+        &mut self,
+        lane_pattern: &LanePattern,
+        lanes: &mut Lanes,
+        parking: &mut ParkingSpots,
+    ) {
+        for (id, _) in self.lanes_iter() {
+            parking.remove_to_reuse(id);
+            lanes.remove(id);
+        }
+        self.lanes_forward.clear();
+        self.lanes_backward.clear();
+        self.width = lane_pattern.width();
+        self.add_lanes(lane_pattern, lanes);
+    }
 
-        spatial.insert(id, road.boldline());
-        road.id
+    /// The direction of the traffic if the road is one way, sidewalks don't count
+    pub fn one_way_direction(&self) -> Option<LaneDirection> {
+        let has_traffic =
+            |lanes: &[(LaneID, LaneKind)]| lanes.iter().any(|(_, kind)| kind.vehicles());
+        match (
+            has_traffic(&self.lanes_forward),
+            has_traffic(&self.lanes_backward),
+        ) {
+            (true, false) => Some(LaneDirection::Forward),
+            (false, true) => Some(LaneDirection::Backward),
+            _ => None,
+        }
     }
 
     pub fn is_one_way(&self) -> bool {
@@ -183,10 +229,105 @@ impl Road {
         }
     }
 
-    pub fn update_lanes(&mut self, lanes: &mut Lanes, parking: &mut ParkingSpots) {
+    /// Regenerates the points after one of the ends moved, call [`Road::update_lanes`] afterwards
+    pub(crate) fn update_points(&mut self, src: &Intersection, dst: &Intersection) {
+        let precise = self.lanes_iter().any(|(_, kind)| kind.is_rail());
+        self.points = Self::generate_points(src.pos, dst.pos, self.segment, precise);
+    }
+
+    /// Moves the ends of the road to the heights of its intersections, keeping the points
+    /// in between. Call [`Road::drape`] then [`Road::update_lanes`] afterwards
+    pub(crate) fn set_end_heights(&mut self, src: &Intersection, dst: &Intersection) {
+        let mut points = self.points.as_slice().to_vec();
+        if let Some(first) = points.first_mut() {
+            first.z = src.pos.z;
+        }
+        if let Some(last) = points.last_mut() {
+            last.z = dst.pos.z;
+        }
+        self.points = PolyLine3::new(points);
+    }
+
+    /// Whether the part of the road within `radius` of `center` lies on the ground
+    pub(crate) fn is_on_ground(&self, terrain: &Terrain, center: Vec2, radius: f32) -> bool {
+        self.points
+            .equipoints_dir(DRAPE_STEP, false)
+            .filter(|(pos, _)| pos.xy().distance(center) <= radius)
+            .all(|(pos, _)| {
+                terrain
+                    .height(pos.xy())
+                    .is_none_or(|h| (pos.z - 0.3 - h).abs() < 1.0)
+            })
+    }
+
+    /// Lays the part of the road within `radius` of `center` back on the ground after
+    /// the terrain was edited, call [`Road::update_lanes`] afterwards
+    pub(crate) fn drape(&mut self, terrain: &Terrain, center: Vec2, radius: f32) {
+        let inside = |pos: Vec3| pos.xy().distance(center) <= radius;
+
+        let mut points = vec![self.points.first()];
+        for seg in self.points.segments() {
+            let (a, b) = (seg.src.xy(), seg.dst.xy());
+            let ab = b - a;
+            let t = ((center - a).dot(ab) / ab.mag2().max(0.0001)).clamp(0.0, 1.0);
+            let n = if (a + ab * t).distance(center) <= radius {
+                (ab.mag() / DRAPE_STEP).ceil().max(1.0) as usize
+            } else {
+                1
+            };
+            for i in 1..=n {
+                points.push(seg.src + (seg.dst - seg.src) * (i as f32 / n as f32));
+            }
+        }
+
+        let n = points.len();
+        for (i, pos) in points.iter_mut().enumerate() {
+            // the ends are at the intersections
+            if i == 0 || i + 1 == n || !inside(*pos) {
+                continue;
+            }
+            if let Some(h) = terrain.height(pos.xy()) {
+                pos.z = h + 0.3;
+            }
+        }
+
+        self.points = PolyLine3::new(points);
+    }
+
+    pub fn update_lanes(
+        &mut self,
+        lanes: &mut Lanes,
+        parking: &mut ParkingSpots,
+        restrictions: &RoadRestrictions,
+    ) {
         self.update_interfaced_points();
-        for (id, _) in self.lanes_iter() {
+        for side in [&mut self.lanes_forward, &mut self.lanes_backward] {
+            let is_road = |kind: &LaneKind| matches!(kind, LaneKind::Driving | LaneKind::Bus);
+            // a bus lane is only reserved if cars keep at least one lane
+            let outer = side
+                .iter()
+                .rposition(|(_, kind)| is_road(kind))
+                .filter(|_| side.iter().filter(|(_, kind)| is_road(kind)).count() >= 2);
+            for (i, (_, kind)) in side.iter_mut().enumerate() {
+                if !is_road(kind) {
+                    continue;
+                }
+                *kind = if restrictions.bus_only && Some(i) == outer {
+                    LaneKind::Bus
+                } else {
+                    LaneKind::Driving
+                };
+            }
+        }
+        for (id, kind) in self.lanes_iter() {
             let l = unwrap_contlog!(lanes.get_mut(id), "lane in road does not exist anymore");
+            l.kind = kind;
+            if let Some(limit) = restrictions
+                .speed_limit
+                .filter(|_| matches!(kind, LaneKind::Driving | LaneKind::Bus))
+            {
+                l.speed_limit = limit;
+            }
             l.gen_pos(self);
             if matches!(l.kind, LaneKind::Parking) {
                 parking.generate_spots(l);
@@ -244,7 +385,8 @@ impl Road {
             .equipoints_dir(80.0, true)
             .filter_map(move |(pos, dir)| {
                 let h = terrain.height(pos.xy())?;
-                if (h - pos.z).abs() <= 2.0 {
+                // tunnels don't need pylons
+                if pos.z - h <= 2.0 {
                     return None;
                 }
                 Some(PylonPosition {
@@ -267,39 +409,37 @@ impl Road {
         self.interfaced_points =
             points.cut(self.interface_from(self.src), self.interface_from(self.dst));
 
+        // shift the heights so that the ends are at the intersections' height, keeping
+        // the ups and downs of roads following the ground
         let cpoints = &mut self.interfaced_points;
-        let o_beg = points.first().z;
-        let o_end = points.last().z;
-        let i_beg = cpoints.first().z;
-        let i_end = cpoints.last().z;
-        let i_range = i_end - i_beg;
-        let o_range = o_end - o_beg;
-
-        if i_range.abs() < 0.01 {
-            if cpoints.n_points() == 1 {
-                return;
-            }
+        let d_beg = points.first().z - cpoints.first().z;
+        let d_end = points.last().z - cpoints.last().z;
+        let l = cpoints.length();
 
-            let n = cpoints.n_points() as f32 - 1.0;
-            for (i, v) in cpoints.iter_mut().enumerate() {
-                v.z = o_beg + (i as f32) * o_range / n;
+        if l < 0.01 {
+            for v in cpoints.iter_mut() {
+                v.z += d_beg;
             }
             return;
         }
 
+        let mut last = cpoints.first();
+        let mut d = 0.0;
         for v in cpoints.iter_mut() {
-            v.z = ((v.z - i_beg) * o_range) / i_range + o_beg;
+            d += v.distance(last);
+            last = *v;
+            let t = (d / l).min(1.0);
+            v.z += d_beg + (d_end - d_beg) * t;
         }
     }
 
-    fn generate_points(
-        src: &Intersection,
-        dst: &Intersection,
+    /// The center line of a road going from `from` to `to`, before it is cut at the intersections
+    pub(crate) fn generate_points(
+        from: Vec3,
+        to: Vec3,
         segment: RoadSegmentKind,
         precise: bool,
     ) -> PolyLine3 {
-        let from = src.pos;
-        let to = dst.pos;
         let diff = to - from;
 
         let spline = match segment {
@@ -0,0 +1,80 @@
+use crate::map::BuildingID;
+use egui_inspect::debug_inspect_impl;
+use serde::{Deserialize, Serialize};
+use slotmapd::new_key_type;
+
+new_key_type! {
+    pub struct UtilityLinkID;
+}
+
+debug_inspect_impl!(UtilityLinkID);
+
+/// Longest power line or pipe that can be built between two buildings, in meters
+pub const MAX_UTILITY_LINK_LENGTH: f32 = 500.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum UtilityKind {
+    Electricity,
+    Water,
+}
+
+impl UtilityKind {
+    pub const ALL: [UtilityKind; 2] = [UtilityKind::Electricity, UtilityKind::Water];
+
+    /// The item produced by the network companies supplying the utility
+    pub fn item_name(self) -> &'static str {
+        match self {
+            UtilityKind::Electricity => "electricity",
+            UtilityKind::Water => "water",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            UtilityKind::Electricity => "Electricity",
+            UtilityKind::Water => "Water",
+        }
+    }
+
+    /// Capacity of a new power line or pipe, in units per second
+    pub fn default_capacity(self) -> f32 {
+        match self {
+            UtilityKind::Electricity => 20.0,
+            UtilityKind::Water => 10.0,
+        }
+    }
+
+    /// Units per second needed by a house
+    pub fn house_demand(self) -> f32 {
+        match self {
+            UtilityKind::Electricity => 0.05,
+            UtilityKind::Water => 0.05,
+        }
+    }
+
+    /// Units per second needed by a company for each of its jobs
+    pub fn worker_demand(self) -> f32 {
+        match self {
+            UtilityKind::Electricity => 0.05,
+            UtilityKind::Water => 0.02,
+        }
+    }
+}
+
+/// A power line or a pipe carrying a utility between two buildings.
+/// There is at most one link of each kind between two buildings.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct UtilityLink {
+    pub id: UtilityLinkID,
+    pub kind: UtilityKind,
+    pub src: BuildingID,
+    pub dst: BuildingID,
+    /// Units per second that can go through the link
+    pub capacity: f32,
+}
+
+impl UtilityLink {
+    pub fn connects(&self, a: BuildingID, b: BuildingID) -> bool {
+        (self.src == a && self.dst == b) || (self.src == b && self.dst == a)
+    }
+}
@@ -1,6 +1,8 @@
 use crate::map::{
-    LaneID, LaneKind, LanePatternBuilder, Map, Traversable, TraverseDirection, TraverseKind, TurnID,
+    Lane, LaneID, LaneKind, LanePatternBuilder, Map, TravelTimes, Traversable, TraverseDirection,
+    TraverseKind, TurnID,
 };
+use crate::transportation::VehicleKind;
 use crate::utils::time::Tick;
 use common::hash_u64;
 use geom::{PolyLine3, Vec3};
@@ -12,13 +14,14 @@ pub trait Pathfinder {
     fn path(
         &self,
         map: &Map,
+        times: &TravelTimes,
         tick: Tick,
         start: Traversable,
         end: LaneID,
     ) -> Option<Vec<Traversable>>;
     fn nearest_lane(&self, map: &Map, pos: Vec3) -> Option<LaneID>;
     fn local_route(&self, map: &Map, lane: LaneID, start: Vec3, end: Vec3) -> Option<PolyLine3>;
-    fn authorized_lane(&self, kind: LaneKind) -> bool;
+    fn authorized_lane(&self, map: &Map, lane: &Lane) -> bool;
 }
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
@@ -26,20 +29,27 @@ pub enum PathKind {
     Pedestrian,
     Vehicle,
     Rail,
+    Bike,
+    Truck,
+    Bus,
 }
 
 impl Pathfinder for PathKind {
     fn path(
         &self,
         map: &Map,
+        times: &TravelTimes,
         tick: Tick,
         start: Traversable,
         end: LaneID,
     ) -> Option<Vec<Traversable>> {
         match self {
-            PathKind::Pedestrian => PedestrianPath.path(map, tick, start, end),
-            PathKind::Vehicle => CarPath.path(map, tick, start, end),
-            PathKind::Rail => RailPath.path(map, tick, start, end),
+            PathKind::Pedestrian => PedestrianPath.path(map, times, tick, start, end),
+            PathKind::Vehicle => CarPath.path(map, times, tick, start, end),
+            PathKind::Rail => RailPath.path(map, times, tick, start, end),
+            PathKind::Bike => BikePath.path(map, times, tick, start, end),
+            PathKind::Truck => TruckPath.path(map, times, tick, start, end),
+            PathKind::Bus => BusPath.path(map, times, tick, start, end),
         }
     }
 
@@ -48,6 +58,9 @@ impl Pathfinder for PathKind {
             PathKind::Pedestrian => PedestrianPath.nearest_lane(map, pos),
             PathKind::Vehicle => CarPath.nearest_lane(map, pos),
             PathKind::Rail => RailPath.nearest_lane(map, pos),
+            PathKind::Bike => BikePath.nearest_lane(map, pos),
+            PathKind::Truck => TruckPath.nearest_lane(map, pos),
+            PathKind::Bus => BusPath.nearest_lane(map, pos),
         }
     }
 
@@ -56,14 +69,20 @@ impl Pathfinder for PathKind {
             PathKind::Pedestrian => PedestrianPath.local_route(map, lane, start, end),
             PathKind::Vehicle => CarPath.local_route(map, lane, start, end),
             PathKind::Rail => RailPath.local_route(map, lane, start, end),
+            PathKind::Bike => BikePath.local_route(map, lane, start, end),
+            PathKind::Truck => TruckPath.local_route(map, lane, start, end),
+            PathKind::Bus => BusPath.local_route(map, lane, start, end),
         }
     }
 
-    fn authorized_lane(&self, kind: LaneKind) -> bool {
+    fn authorized_lane(&self, map: &Map, lane: &Lane) -> bool {
         match self {
-            PathKind::Pedestrian => PedestrianPath.authorized_lane(kind),
-            PathKind::Vehicle => CarPath.authorized_lane(kind),
-            PathKind::Rail => RailPath.authorized_lane(kind),
+            PathKind::Pedestrian => PedestrianPath.authorized_lane(map, lane),
+            PathKind::Vehicle => CarPath.authorized_lane(map, lane),
+            PathKind::Rail => RailPath.authorized_lane(map, lane),
+            PathKind::Bike => BikePath.authorized_lane(map, lane),
+            PathKind::Truck => TruckPath.authorized_lane(map, lane),
+            PathKind::Bus => BusPath.authorized_lane(map, lane),
         }
     }
 }
@@ -74,6 +93,7 @@ impl Pathfinder for PedestrianPath {
     fn path(
         &self,
         map: &Map,
+        _times: &TravelTimes,
         _tick: Tick,
         start: Traversable,
         end: LaneID,
@@ -155,8 +175,8 @@ impl Pathfinder for PedestrianPath {
         Some(PolyLine3::new(v))
     }
 
-    fn authorized_lane(&self, kind: LaneKind) -> bool {
-        matches!(kind, LaneKind::Walking)
+    fn authorized_lane(&self, _map: &Map, lane: &Lane) -> bool {
+        matches!(lane.kind, LaneKind::Walking)
     }
 }
 
@@ -166,11 +186,17 @@ impl Pathfinder for RailPath {
     fn path(
         &self,
         map: &Map,
+        _times: &TravelTimes,
         tick: Tick,
         start: Traversable,
         end: LaneID,
     ) -> Option<Vec<Traversable>> {
-        CarPath.path(map, tick, start, end)
+        lane_path(map, tick, start, end, |l| {
+            if !self.authorized_lane(map, l) {
+                return None;
+            }
+            Some(l.points.length() / l.speed_limit)
+        })
     }
 
     fn nearest_lane(&self, map: &Map, pos: Vec3) -> Option<LaneID> {
@@ -181,8 +207,8 @@ impl Pathfinder for RailPath {
         CarPath.local_route(map, lane, start, end)
     }
 
-    fn authorized_lane(&self, kind: LaneKind) -> bool {
-        matches!(kind, LaneKind::Rail)
+    fn authorized_lane(&self, _map: &Map, lane: &Lane) -> bool {
+        matches!(lane.kind, LaneKind::Rail)
     }
 }
 
@@ -192,80 +218,17 @@ impl Pathfinder for CarPath {
     fn path(
         &self,
         map: &Map,
+        times: &TravelTimes,
         tick: Tick,
         start: Traversable,
         end: LaneID,
     ) -> Option<Vec<Traversable>> {
-        let inters = &map.intersections;
-        let lanes = &map.lanes;
-
-        let start_lane = start.destination_lane();
-
-        let end_pos = inters.get(lanes.get(end)?.dst)?.pos;
-
-        let dummy = LaneID::null();
-
-        const HEURISTIC_SPEED: f32 = LanePatternBuilder::new().speed_limit;
-
-        let heuristic = |&p: &LaneID| {
-            let pos = unwrap_ret!(
-                inters.get(unwrap_ret!(lanes.get(p), OrderedFloat(f32::INFINITY)).dst),
-                OrderedFloat(f32::INFINITY)
-            )
-            .pos;
-            OrderedFloat(pos.distance(end_pos) * 1.2 / HEURISTIC_SPEED) // Inexact but (much) faster
-        };
-
-        let base_random = hash_u64((start_lane.data().as_ffi(), tick.0)) as u32;
-
-        let successors = move |&p: &LaneID| {
-            let l;
-            let p = if p == dummy {
-                l = lanes.get(start_lane);
-                start_lane
-            } else {
-                l = lanes.get(p);
-                p
-            };
-            l.and_then(move |x| inters.get(x.dst))
-                .into_iter()
-                .flat_map(move |inter| {
-                    inter.turns_from(p).map(move |(x, _)| {
-                        let mut cost = f32::INFINITY;
-
-                        if let Some(l) = lanes.get(x.dst) {
-                            cost = l.points.length() / l.speed_limit;
-                            cost += common::rand::randu(l.dist_from_bottom.to_bits() ^ base_random);
-                        }
-
-                        (x.dst, OrderedFloat(cost))
-                    })
-                })
-        };
-
-        let (v, _) =
-            pathfinding::directed::astar::astar(&dummy, successors, heuristic, |p| *p == end)?;
-
-        let mut path = Vec::with_capacity(v.len() * 2);
-        path.push(start);
-
-        let mut last_id = start_lane;
-
-        for lane in v.into_iter().skip(1) {
-            let inter_end = &inters.get(lanes.get(lane)?.src)?;
-            let id = TurnID::new(inter_end.id, last_id, lane, false);
-            path.push(Traversable::new(
-                TraverseKind::Turn(id),
-                TraverseDirection::Forward,
-            ));
-            path.push(Traversable::new(
-                TraverseKind::Lane(lane),
-                TraverseDirection::Forward,
-            ));
-
-            last_id = lane;
-        }
-        Some(path)
+        lane_path(map, tick, start, end, |l| {
+            if !self.authorized_lane(map, l) {
+                return None;
+            }
+            Some(times.lane_time(l))
+        })
     }
 
     fn nearest_lane(&self, map: &Map, pos: Vec3) -> Option<LaneID> {
@@ -293,7 +256,256 @@ impl Pathfinder for CarPath {
         Some(PolyLine3::new(v))
     }
 
-    fn authorized_lane(&self, kind: LaneKind) -> bool {
-        matches!(kind, LaneKind::Driving | LaneKind::Bus)
+    fn authorized_lane(&self, _map: &Map, lane: &Lane) -> bool {
+        matches!(lane.kind, LaneKind::Driving)
+    }
+}
+
+/// Like cars, but can also use the bus lanes
+struct BusPath;
+
+impl Pathfinder for BusPath {
+    fn path(
+        &self,
+        map: &Map,
+        times: &TravelTimes,
+        tick: Tick,
+        start: Traversable,
+        end: LaneID,
+    ) -> Option<Vec<Traversable>> {
+        lane_path(map, tick, start, end, |l| {
+            if !self.authorized_lane(map, l) {
+                return None;
+            }
+            Some(times.lane_time(l))
+        })
+    }
+
+    fn nearest_lane(&self, map: &Map, pos: Vec3) -> Option<LaneID> {
+        CarPath.nearest_lane(map, pos)
+    }
+
+    fn local_route(&self, map: &Map, lane: LaneID, start: Vec3, end: Vec3) -> Option<PolyLine3> {
+        CarPath.local_route(map, lane, start, end)
+    }
+
+    fn authorized_lane(&self, _map: &Map, lane: &Lane) -> bool {
+        matches!(lane.kind, LaneKind::Driving | LaneKind::Bus)
+    }
+}
+
+/// Like cars, but avoids the roads where trucks are banned
+/// unless the trip starts or ends on them
+struct TruckPath;
+
+impl Pathfinder for TruckPath {
+    fn path(
+        &self,
+        map: &Map,
+        times: &TravelTimes,
+        tick: Tick,
+        start: Traversable,
+        end: LaneID,
+    ) -> Option<Vec<Traversable>> {
+        let lanes = &map.lanes;
+        let start_road = lanes.get(start.destination_lane()).map(|l| l.parent);
+        let end_road = lanes.get(end).map(|l| l.parent);
+
+        lane_path(map, tick, start, end, |l| {
+            let endpoint = Some(l.parent) == start_road || Some(l.parent) == end_road;
+            let allowed =
+                self.authorized_lane(map, l) || (endpoint && CarPath.authorized_lane(map, l));
+            if !allowed {
+                return None;
+            }
+            Some(times.lane_time(l))
+        })
+    }
+
+    fn nearest_lane(&self, map: &Map, pos: Vec3) -> Option<LaneID> {
+        CarPath.nearest_lane(map, pos)
+    }
+
+    fn local_route(&self, map: &Map, lane: LaneID, start: Vec3, end: Vec3) -> Option<PolyLine3> {
+        CarPath.local_route(map, lane, start, end)
+    }
+
+    fn authorized_lane(&self, map: &Map, lane: &Lane) -> bool {
+        CarPath.authorized_lane(map, lane) && !map.road_restrictions(lane.parent).no_trucks
+    }
+}
+
+/// How much longer a shared road feels compared to a bike lane
+const BIKE_SHARED_ROAD_FACTOR: f32 = 1.6;
+
+/// How far a bike lane can be for the bike to prefer it over a closer road
+const BIKE_LANE_SEARCH_DIST: f32 = 30.0;
+
+struct BikePath;
+
+impl Pathfinder for BikePath {
+    fn path(
+        &self,
+        map: &Map,
+        _times: &TravelTimes,
+        tick: Tick,
+        start: Traversable,
+        end: LaneID,
+    ) -> Option<Vec<Traversable>> {
+        lane_path(map, tick, start, end, |l| {
+            if !self.authorized_lane(map, l) {
+                return None;
+            }
+            let cost = l.points.length() / l.speed_limit.min(VehicleKind::Bike.max_speed());
+            if l.kind == LaneKind::Biking {
+                return Some(cost);
+            }
+            Some(cost * BIKE_SHARED_ROAD_FACTOR)
+        })
+    }
+
+    fn nearest_lane(&self, map: &Map, pos: Vec3) -> Option<LaneID> {
+        map.nearest_lane(pos, LaneKind::Biking, Some(BIKE_LANE_SEARCH_DIST))
+            .or_else(|| map.nearest_lane(pos, LaneKind::Driving, None))
+    }
+
+    fn local_route(&self, map: &Map, lane: LaneID, start: Vec3, end: Vec3) -> Option<PolyLine3> {
+        CarPath.local_route(map, lane, start, end)
+    }
+
+    fn authorized_lane(&self, _map: &Map, lane: &Lane) -> bool {
+        matches!(lane.kind, LaneKind::Driving | LaneKind::Biking)
+    }
+}
+
+/// A* on the lane graph used by the vehicle pathfinders.
+/// `cost` returns the cost of going through a lane, or None if the lane cannot be used.
+fn lane_path(
+    map: &Map,
+    tick: Tick,
+    start: Traversable,
+    end: LaneID,
+    cost: impl Fn(&Lane) -> Option<f32>,
+) -> Option<Vec<Traversable>> {
+    let inters = &map.intersections;
+    let lanes = &map.lanes;
+
+    let start_lane = start.destination_lane();
+
+    let end_pos = inters.get(lanes.get(end)?.dst)?.pos;
+
+    let dummy = LaneID::null();
+
+    const HEURISTIC_SPEED: f32 = LanePatternBuilder::new().speed_limit;
+
+    let heuristic = |&p: &LaneID| {
+        let pos = unwrap_ret!(
+            inters.get(unwrap_ret!(lanes.get(p), OrderedFloat(f32::INFINITY)).dst),
+            OrderedFloat(f32::INFINITY)
+        )
+        .pos;
+        OrderedFloat(pos.distance(end_pos) * 1.2 / HEURISTIC_SPEED) // Inexact but (much) faster
+    };
+
+    let base_random = hash_u64((start_lane.data().as_ffi(), tick.0)) as u32;
+
+    let cost = &cost;
+    let successors = move |&p: &LaneID| {
+        let l;
+        let p = if p == dummy {
+            l = lanes.get(start_lane);
+            start_lane
+        } else {
+            l = lanes.get(p);
+            p
+        };
+        l.and_then(move |x| inters.get(x.dst))
+            .into_iter()
+            .flat_map(move |inter| {
+                inter.turns_from(p).filter_map(move |(x, _)| {
+                    let l = lanes.get(x.dst)?;
+                    let cost =
+                        cost(l)? + common::rand::randu(l.dist_from_bottom.to_bits() ^ base_random);
+
+                    Some((x.dst, OrderedFloat(cost)))
+                })
+            })
+    };
+
+    let (v, _) = pathfinding::directed::astar::astar(&dummy, successors, heuristic, |p| *p == end)?;
+
+    let mut path = Vec::with_capacity(v.len() * 2);
+    path.push(start);
+
+    let mut last_id = start_lane;
+
+    for lane in v.into_iter().skip(1) {
+        let inter_end = &inters.get(lanes.get(lane)?.src)?;
+        let id = TurnID::new(inter_end.id, last_id, lane, false);
+        path.push(Traversable::new(
+            TraverseKind::Turn(id),
+            TraverseDirection::Forward,
+        ));
+        path.push(Traversable::new(
+            TraverseKind::Lane(lane),
+            TraverseDirection::Forward,
+        ));
+
+        last_id = lane;
+    }
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PathKind, Pathfinder};
+    use crate::map::{
+        LaneKind, LanePatternBuilder, ProjectFilter, TravelTimes, Traversable, TraverseDirection,
+        TraverseKind,
+    };
+    use crate::tests::TestCtx;
+    use crate::utils::time::Tick;
+    use geom::vec3;
+
+    #[test]
+    fn test_bike_path_prefers_bike_lanes() {
+        let test = TestCtx::new();
+
+        {
+            let mut m = test.g.map_mut();
+            let pat = LanePatternBuilder::new().bike_lanes(true).build();
+            let pts = [vec3(0., 0., 0.), vec3(150., 0., 0.), vec3(150., 150., 0.)];
+            for w in pts.windows(2) {
+                let a = m.project(w[0], 0.0, ProjectFilter::ALL);
+                let b = m.project(w[1], 0.0, ProjectFilter::ALL);
+                m.make_connection(a, b, None, &pat);
+            }
+        }
+
+        let map = test.g.map();
+        let start = PathKind::Bike
+            .nearest_lane(&map, vec3(20., 0., 0.))
+            .unwrap();
+        let end = PathKind::Bike
+            .nearest_lane(&map, vec3(150., 130., 0.))
+            .unwrap();
+        assert_eq!(map.lanes()[start].kind, LaneKind::Biking);
+        assert_eq!(map.lanes()[end].kind, LaneKind::Biking);
+
+        let path = PathKind::Bike
+            .path(
+                &map,
+                &TravelTimes::default(),
+                Tick(0),
+                Traversable::new(TraverseKind::Lane(start), TraverseDirection::Forward),
+                end,
+            )
+            .unwrap();
+
+        for t in path {
+            if let TraverseKind::Lane(l) = t.kind {
+                assert_eq!(map.lanes()[l].kind, LaneKind::Biking);
+            }
+        }
     }
 }
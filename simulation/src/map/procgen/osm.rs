@@ -0,0 +1,375 @@
+//! Imports road and rail networks from OpenStreetMap XML extracts (`.osm` files).
+//!
+//! The network is turned into a [`WorldCommand::MapMakeMultipleConnections`] so that
+//! it goes through [`Map::make_connection`] like any other edit, and works in multiplayer.
+//! PBF extracts are not supported, convert them first (e.g. `osmium cat city.pbf -o city.osm`).
+
+use crate::engine_interaction::WorldCommand;
+use crate::map::{LanePattern, LanePatternBuilder, MapProject, Terrain};
+use geom::{vec2, Vec2};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::io::Read;
+use std::path::Path;
+use xml::reader::{EventReader, XmlEvent};
+
+/// Intermediate nodes closer than this to the previous kept node are dropped,
+/// as tiny roads make for ugly intersections
+const MIN_ROAD_LENGTH: f32 = 30.0;
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// A latitude/longitude bounding box, in degrees
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OsmBounds {
+    pub min_lat: f64,
+    pub min_lon: f64,
+    pub max_lat: f64,
+    pub max_lon: f64,
+}
+
+impl OsmBounds {
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        (self.min_lat..=self.max_lat).contains(&lat) && (self.min_lon..=self.max_lon).contains(&lon)
+    }
+
+    pub fn center(&self) -> (f64, f64) {
+        (
+            (self.min_lat + self.max_lat) * 0.5,
+            (self.min_lon + self.max_lon) * 0.5,
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OsmWay {
+    pub nodes: Vec<i64>,
+    pub tags: BTreeMap<String, String>,
+}
+
+impl OsmWay {
+    pub fn tag(&self, k: &str) -> Option<&str> {
+        self.tags.get(k).map(String::as_str)
+    }
+}
+
+/// The nodes and ways of an extract, relations are ignored
+#[derive(Debug, Default)]
+pub struct OsmData {
+    /// Latitude and longitude of each node
+    pub nodes: BTreeMap<i64, (f64, f64)>,
+    pub ways: Vec<OsmWay>,
+    /// The bounds written in the file, if any
+    pub bounds: Option<OsmBounds>,
+}
+
+impl OsmData {
+    pub fn load(path: impl AsRef<Path>) -> Result<OsmData, Box<dyn Error>> {
+        let path = path.as_ref();
+        if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("pbf"))
+        {
+            return Err("PBF extracts are not supported, convert them to OSM XML first".into());
+        }
+        Self::parse(std::fs::File::open(path)?)
+    }
+
+    pub fn parse(source: impl Read) -> Result<OsmData, Box<dyn Error>> {
+        let mut data = OsmData::default();
+        let mut way: Option<OsmWay> = None;
+
+        for event in EventReader::new(std::io::BufReader::new(source)) {
+            match event? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    let attr = |k: &str| {
+                        attributes
+                            .iter()
+                            .find(|a| a.name.local_name == k)
+                            .map(|a| a.value.as_str())
+                    };
+                    let num = |k: &str| attr(k).and_then(|v| v.parse::<f64>().ok());
+                    match name.local_name.as_str() {
+                        "bounds" => {
+                            if let (Some(min_lat), Some(min_lon), Some(max_lat), Some(max_lon)) =
+                                (num("minlat"), num("minlon"), num("maxlat"), num("maxlon"))
+                            {
+                                data.bounds = Some(OsmBounds {
+                                    min_lat,
+                                    min_lon,
+                                    max_lat,
+                                    max_lon,
+                                });
+                            }
+                        }
+                        "node" => {
+                            let id = attr("id").and_then(|v| v.parse().ok());
+                            if let (Some(id), Some(lat), Some(lon)) = (id, num("lat"), num("lon")) {
+                                data.nodes.insert(id, (lat, lon));
+                            }
+                        }
+                        "way" => way = Some(OsmWay::default()),
+                        "nd" => {
+                            if let (Some(w), Some(id)) =
+                                (way.as_mut(), attr("ref").and_then(|v| v.parse().ok()))
+                            {
+                                w.nodes.push(id);
+                            }
+                        }
+                        "tag" => {
+                            if let (Some(w), Some(k), Some(v)) =
+                                (way.as_mut(), attr("k"), attr("v"))
+                            {
+                                w.tags.insert(k.to_string(), v.to_string());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                XmlEvent::EndElement { name } if name.local_name == "way" => {
+                    data.ways.extend(way.take());
+                }
+                _ => {}
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Builds the command importing the roads and railways inside the bounds.
+    /// `bounds` defaults to the bounds of the file, their center is placed at `center`.
+    pub fn import_command(
+        &self,
+        bounds: Option<OsmBounds>,
+        center: Vec2,
+        terrain: &Terrain,
+    ) -> Option<WorldCommand> {
+        let bounds = bounds.or(self.bounds)?;
+        let (lat0, lon0) = bounds.center();
+        let lon_scale = METERS_PER_DEGREE * lat0.to_radians().cos();
+        let project = |(lat, lon): (f64, f64)| {
+            center
+                + vec2(
+                    ((lon - lon0) * lon_scale) as f32,
+                    ((lat - lat0) * METERS_PER_DEGREE) as f32,
+                )
+        };
+
+        let ways: Vec<(&OsmWay, LanePattern)> = self
+            .ways
+            .iter()
+            .filter_map(|w| Some((w, way_pattern(w)?)))
+            .collect();
+
+        // nodes shared by several ways are junctions and must be kept
+        let mut uses: BTreeMap<i64, u32> = BTreeMap::new();
+        for (w, _) in &ways {
+            for &n in &w.nodes {
+                *uses.entry(n).or_default() += 1;
+            }
+        }
+
+        let mut projects = vec![];
+        let mut indices: BTreeMap<i64, usize> = BTreeMap::new();
+        let mut links = vec![];
+        let mut done = BTreeSet::new();
+
+        for (w, pat) in ways {
+            // nodes outside of the bounds cut the way
+            let mut nodes: Vec<Option<(i64, Vec2)>> = w
+                .nodes
+                .iter()
+                .map(|n| {
+                    let &(lat, lon) = self.nodes.get(n)?;
+                    bounds.contains(lat, lon).then(|| (*n, project((lat, lon))))
+                })
+                .collect();
+            if w.tag("oneway") == Some("-1") {
+                nodes.reverse();
+            }
+
+            let mut last: Option<(i64, Vec2)> = None;
+            for (i, &node) in nodes.iter().enumerate() {
+                let Some((n, pos)) = node else {
+                    last = None;
+                    continue;
+                };
+                let is_end = i + 1 == nodes.len() || nodes[i + 1].is_none();
+                if let Some((_, lastpos)) = last {
+                    if !is_end && uses[&n] < 2 && pos.distance(lastpos) < MIN_ROAD_LENGTH {
+                        continue;
+                    }
+                }
+
+                let idx = *indices.entry(n).or_insert_with(|| {
+                    projects.push(MapProject::ground(
+                        pos.z(terrain.height(pos).unwrap_or(0.0) + 0.3),
+                    ));
+                    projects.len() - 1
+                });
+
+                if let Some((lastn, _)) = last {
+                    let lastidx = indices[&lastn];
+                    if lastidx != idx && done.insert((lastidx.min(idx), lastidx.max(idx))) {
+                        links.push((lastidx, idx, None, pat.clone()));
+                    }
+                }
+                last = Some((n, pos));
+            }
+        }
+
+        log::info!(
+            "importing {} intersections and {} roads from OSM",
+            projects.len(),
+            links.len()
+        );
+
+        Some(WorldCommand::MapMakeMultipleConnections(projects, links))
+    }
+}
+
+/// The lane pattern of a way, or None if it is not a road nor a railway we can simulate
+pub fn way_pattern(way: &OsmWay) -> Option<LanePattern> {
+    if way.tag("area") == Some("yes") {
+        return None;
+    }
+    if way.tag("railway") == Some("rail") {
+        return Some(LanePatternBuilder::new().rail(true).build());
+    }
+
+    let highway = way.tag("highway")?;
+    let (lanes, speed, sidewalks, parking, implied_one_way) = match highway {
+        "motorway" => (2, 36.0, false, false, true),
+        "motorway_link" => (1, 20.0, false, false, true),
+        "trunk" => (2, 25.0, false, false, false),
+        "trunk_link" => (1, 17.0, false, false, false),
+        "primary" | "primary_link" => (2, 14.0, true, false, false),
+        "secondary" | "secondary_link" => (1, 14.0, true, false, false),
+        "tertiary" | "tertiary_link" => (1, 14.0, true, true, false),
+        "unclassified" | "residential" | "road" => (1, 9.0, true, true, false),
+        "living_street" => (1, 4.0, true, true, false),
+        "service" => (1, 6.0, true, false, false),
+        _ => return None,
+    };
+
+    let one_way = match way.tag("oneway") {
+        Some("yes" | "true" | "1" | "-1") => true,
+        Some("no" | "false" | "0") => false,
+        _ => implied_one_way || way.tag("junction") == Some("roundabout"),
+    };
+
+    // the lanes tag counts both directions
+    let lanes = match way.tag("lanes").and_then(|v| v.parse::<u32>().ok()) {
+        Some(n) if one_way => n,
+        Some(n) => (n / 2).max(1),
+        None => lanes,
+    };
+
+    let speed = way.tag("maxspeed").and_then(parse_speed).unwrap_or(speed);
+
+    let sidewalks = match way.tag("sidewalk") {
+        Some("no" | "none" | "separate") => false,
+        Some(_) => true,
+        None => sidewalks,
+    };
+
+    let bike_lanes = matches!(way.tag("cycleway"), Some("lane" | "track"));
+
+    Some(
+        LanePatternBuilder::new()
+            .n_lanes(lanes.max(1))
+            .speed_limit(speed)
+            .sidewalks(sidewalks)
+            .parking(parking)
+            .one_way(one_way)
+            .bike_lanes(bike_lanes)
+            .build(),
+    )
+}
+
+/// Parses a maxspeed tag such as "50" or "30 mph" into meters per second
+fn parse_speed(v: &str) -> Option<f32> {
+    let (v, factor) = match v.strip_suffix("mph") {
+        Some(v) => (v, 0.447),
+        None => (v.trim_end_matches("km/h"), 1.0 / 3.6),
+    };
+    let kmh = v.trim().parse::<f32>().ok()?;
+    Some(kmh * factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{way_pattern, OsmData};
+    use crate::map::LaneKind;
+    use crate::tests::TestCtx;
+    use geom::vec2;
+    use std::collections::BTreeSet;
+
+    const EXTRACT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <bounds minlat="48.850" minlon="2.300" maxlat="48.860" maxlon="2.320"/>
+  <node id="1" lat="48.852" lon="2.302"/>
+  <node id="2" lat="48.855" lon="2.310"/>
+  <node id="3" lat="48.858" lon="2.318"/>
+  <node id="4" lat="48.852" lon="2.318"/>
+  <node id="5" lat="48.9" lon="2.318"/>
+  <way id="10">
+    <nd ref="1"/><nd ref="2"/><nd ref="3"/>
+    <tag k="highway" v="primary"/>
+    <tag k="lanes" v="4"/>
+    <tag k="maxspeed" v="50"/>
+  </way>
+  <way id="11">
+    <nd ref="2"/><nd ref="4"/><nd ref="5"/>
+    <tag k="highway" v="residential"/>
+    <tag k="oneway" v="yes"/>
+  </way>
+  <way id="12">
+    <nd ref="1"/><nd ref="4"/>
+    <tag k="highway" v="footway"/>
+  </way>
+</osm>"#;
+
+    #[test]
+    fn test_osm_import() {
+        let data = OsmData::parse(EXTRACT.as_bytes()).unwrap();
+        assert_eq!(data.nodes.len(), 5);
+        assert_eq!(data.ways.len(), 3);
+        assert!(way_pattern(&data.ways[2]).is_none());
+
+        let primary = way_pattern(&data.ways[0]).unwrap();
+        assert_eq!(
+            primary
+                .lanes()
+                .filter(|(kind, _, _)| *kind == LaneKind::Driving)
+                .count(),
+            4
+        );
+
+        let mut test = TestCtx::new();
+        let before: BTreeSet<_> = test.g.map().roads().keys().collect();
+        let cmd = data
+            .import_command(None, vec2(2000.0, 2000.0), &test.g.map().terrain)
+            .unwrap();
+        test.apply(&[cmd]);
+
+        // node 5 is out of bounds and the footway is ignored
+        let map = test.g.map();
+        let new: Vec<_> = map
+            .roads()
+            .values()
+            .filter(|r| !before.contains(&r.id))
+            .collect();
+        assert_eq!(new.len(), 3);
+        let one_ways = new
+            .iter()
+            .filter(|r| {
+                r.incoming_lanes_to(r.src)
+                    .iter()
+                    .all(|(_, kind)| *kind != LaneKind::Driving)
+            })
+            .count();
+        assert_eq!(one_ways, 1);
+    }
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+
+/// Bounds of the speed limits that can be set on a road, in m/s
+pub const MIN_SPEED_LIMIT: f32 = 4.0;
+pub const MAX_SPEED_LIMIT: f32 = 40.0;
+
+/// Rules set on a road on top of its lane pattern, applied by [`crate::map::Road::update_lanes`]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoadRestrictions {
+    /// Speed limit of the driving and bus lanes in m/s, the one of the pattern is kept if None
+    pub speed_limit: Option<f32>,
+    /// Trucks only use the road to reach a destination on it
+    pub no_trucks: bool,
+    /// The outer driving lane of each direction with at least two lanes is reserved to buses
+    pub bus_only: bool,
+    /// Speed limit of the pattern, restored when the speed limit is removed
+    pub(crate) pattern_speed_limit: Option<f32>,
+}
+
+impl RoadRestrictions {
+    pub fn clamped(mut self) -> Self {
+        self.speed_limit = self
+            .speed_limit
+            .map(|s| s.clamp(MIN_SPEED_LIMIT, MAX_SPEED_LIMIT));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RoadRestrictions;
+    use crate::map::{
+        LaneDirection, LaneID, LaneKind, LanePatternBuilder, Map, PathKind, ProjectFilter,
+        TravelTimes, TraverseKind,
+    };
+    use crate::map_dynamic::Itinerary;
+    use crate::tests::TestCtx;
+    use crate::utils::time::Tick;
+    use geom::{vec2, vec3, Vec2};
+
+    fn find_road(map: &Map, a: Vec2, b: Vec2) -> crate::map::RoadID {
+        let at = |p: Vec2, id| map.intersections()[id].pos.xy().distance(p) < 1.0;
+        map.roads()
+            .values()
+            .find(|r| (at(a, r.src) && at(b, r.dst)) || (at(b, r.src) && at(a, r.dst)))
+            .unwrap()
+            .id
+    }
+
+    #[test]
+    fn test_restrictions_update_lanes() {
+        let test = TestCtx::new();
+        let road = {
+            let mut m = test.g.map_mut();
+            let a = m.project(vec3(0.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+            let b = m.project(vec3(200.0, 0.0, 0.0), 0.0, ProjectFilter::ALL);
+            let pat = LanePatternBuilder::new()
+                .n_lanes(2)
+                .bike_lanes(true)
+                .speed_limit(13.0);
+            m.make_connection(a, b, None, &pat.build()).unwrap().1
+        };
+        let kinds = |map: &Map| {
+            map.roads()[road]
+                .lanes_iter()
+                .map(|(id, kind)| {
+                    assert_eq!(map.lanes()[id].kind, kind);
+                    (kind, map.lanes()[id].speed_limit)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        test.g.map_mut().set_road_restrictions(
+            road,
+            RoadRestrictions {
+                speed_limit: Some(1.0),
+                bus_only: true,
+                ..Default::default()
+            },
+        );
+        let map = test.g.map();
+        let lanes = kinds(&map);
+        assert_eq!(lanes.iter().filter(|(k, _)| *k == LaneKind::Bus).count(), 2);
+        assert_eq!(
+            lanes
+                .iter()
+                .filter(|(k, _)| *k == LaneKind::Driving)
+                .count(),
+            2
+        );
+        for (kind, speed) in lanes {
+            match kind {
+                LaneKind::Driving | LaneKind::Bus => assert_eq!(speed, super::MIN_SPEED_LIMIT),
+                _ => assert_eq!(speed, 13.0),
+            }
+        }
+        drop(map);
+
+        test.g
+            .map_mut()
+            .set_road_restrictions(road, RoadRestrictions::default());
+        let map = test.g.map();
+        assert!(map.restrictions.is_empty());
+        for (kind, speed) in kinds(&map) {
+            assert_ne!(kind, LaneKind::Bus);
+            assert_eq!(speed, 13.0);
+        }
+        drop(map);
+
+        // banning trucks keeps the limits of the lanes
+        let lane = test.g.map().roads()[road].lanes_iter().next().unwrap().0;
+        test.g.map_mut().lanes.get_mut(lane).unwrap().speed_limit = 20.0;
+        test.g.map_mut().set_road_restrictions(
+            road,
+            RoadRestrictions {
+                no_trucks: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(test.g.map().lanes()[lane].speed_limit, 20.0);
+    }
+
+    #[test]
+    fn test_trucks_avoid_banned_roads() {
+        let test = TestCtx::new();
+        let (w, a, b, c, d, e) = (
+            vec2(-200.0, 0.0),
+            vec2(0.0, 0.0),
+            vec2(300.0, 0.0),
+            vec2(300.0, 300.0),
+            vec2(0.0, 400.0),
+            vec2(500.0, 300.0),
+        );
+        test.build_roads(&[w.z0(), a.z0(), b.z0(), c.z0(), e.z0()]);
+        test.build_roads(&[a.z0(), d.z0(), c.z0()]);
+
+        let banned = [(w, a), (a, b), (c, e)];
+        for (p, q) in banned {
+            let road = find_road(&test.g.map(), p, q);
+            test.g.map_mut().set_road_restrictions(
+                road,
+                RoadRestrictions {
+                    no_trucks: true,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let map = test.g.map();
+        let lanes_of = |p, q| -> Vec<LaneID> {
+            map.roads()[find_road(&map, p, q)]
+                .lanes_iter()
+                .map(|(id, _)| id)
+                .collect()
+        };
+        let uses = |kind: PathKind, lanes: &[LaneID]| {
+            Itinerary::route(
+                Tick(0),
+                vec3(-150.0, 0.0, 0.0),
+                vec3(450.0, 300.0, 0.0),
+                &map,
+                &TravelTimes::default(),
+                kind,
+            )
+            .unwrap()
+            .get_route()
+            .unwrap()
+            .reversed_route
+            .iter()
+            .any(|t| match t.kind {
+                TraverseKind::Lane(id) => lanes.contains(&id),
+                TraverseKind::Turn(_) => false,
+            })
+        };
+
+        assert!(uses(PathKind::Vehicle, &lanes_of(a, b)));
+        // trucks can still start and end on banned roads but take the detour
+        assert!(uses(PathKind::Truck, &lanes_of(c, e)));
+        assert!(!uses(PathKind::Truck, &lanes_of(a, b)));
+        assert!(uses(PathKind::Truck, &lanes_of(a, d)));
+    }
+
+    #[test]
+    fn test_one_way_conversion() {
+        let test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(200.0, 0.0, 0.0)]);
+        let road = find_road(&test.g.map(), vec2(0.0, 0.0), vec2(200.0, 0.0));
+        let width = test.g.map().roads()[road].width;
+
+        test.g
+            .map_mut()
+            .set_road_direction(road, Some(LaneDirection::Backward));
+        {
+            let map = test.g.map();
+            let r = &map.roads()[road];
+            assert_eq!(r.one_way_direction(), Some(LaneDirection::Backward));
+            assert!(r.width < width);
+            assert!(r.sidewalks(r.src).incoming.is_some());
+            assert!(r.sidewalks(r.src).outgoing.is_some());
+            for (id, _) in r.lanes_iter() {
+                assert_eq!(map.lanes()[id].parent, road);
+            }
+        }
+
+        test.g.map_mut().set_road_direction(road, None);
+        let map = test.g.map();
+        let r = &map.roads()[road];
+        assert_eq!(r.one_way_direction(), None);
+        assert_eq!(r.width, width);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::map::{
-    BuildingID, Buildings, Intersections, Lanes, Lots, Map, ParkingSpots, Roads, SpatialMap,
-    Terrain,
+    BuildingID, Buildings, Intersections, Lanes, Lots, Map, ParkingSpots, RoadID, RoadRestrictions,
+    Roads, SpatialMap, Terrain, UtilityLinks,
 };
 use crate::BuildingKind;
 use serde::{Deserialize, Serialize};
@@ -16,6 +16,68 @@ pub(crate) struct SerializedMap {
     pub lots: Lots,
     pub terrain: Terrain,
     pub bkinds: BTreeMap<BuildingKind, Vec<BuildingID>>,
+    pub restrictions: BTreeMap<RoadID, RoadRestrictions>,
+    pub utility_links: UtilityLinks,
+}
+
+/// The map before utility links were added
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SerializedMapV1 {
+    roads: Roads,
+    intersections: Intersections,
+    buildings: Buildings,
+    lanes: Lanes,
+    parking: ParkingSpots,
+    lots: Lots,
+    terrain: Terrain,
+    bkinds: BTreeMap<BuildingKind, Vec<BuildingID>>,
+    restrictions: BTreeMap<RoadID, RoadRestrictions>,
+}
+
+/// The map before road restrictions were added
+#[derive(Deserialize)]
+pub(crate) struct SerializedMapV0 {
+    roads: Roads,
+    intersections: Intersections,
+    buildings: Buildings,
+    lanes: Lanes,
+    parking: ParkingSpots,
+    lots: Lots,
+    terrain: Terrain,
+    bkinds: BTreeMap<BuildingKind, Vec<BuildingID>>,
+}
+
+impl From<SerializedMapV0> for SerializedMapV1 {
+    fn from(old: SerializedMapV0) -> Self {
+        Self {
+            roads: old.roads,
+            intersections: old.intersections,
+            buildings: old.buildings,
+            lanes: old.lanes,
+            parking: old.parking,
+            lots: old.lots,
+            terrain: old.terrain,
+            bkinds: old.bkinds,
+            restrictions: Default::default(),
+        }
+    }
+}
+
+impl From<SerializedMapV1> for SerializedMap {
+    fn from(old: SerializedMapV1) -> Self {
+        Self {
+            roads: old.roads,
+            intersections: old.intersections,
+            buildings: old.buildings,
+            lanes: old.lanes,
+            parking: old.parking,
+            lots: old.lots,
+            terrain: old.terrain,
+            bkinds: old.bkinds,
+            restrictions: old.restrictions,
+            utility_links: Default::default(),
+        }
+    }
 }
 
 impl From<&Map> for SerializedMap {
@@ -29,6 +91,8 @@ impl From<&Map> for SerializedMap {
             lots: m.lots.clone(),
             terrain: m.terrain.clone(),
             bkinds: m.bkinds.clone(),
+            restrictions: m.restrictions.clone(),
+            utility_links: m.utility_links.clone(),
         }
     }
 }
@@ -46,6 +110,8 @@ impl From<SerializedMap> for Map {
             parking: sel.parking,
             terrain: sel.terrain,
             bkinds: sel.bkinds,
+            restrictions: sel.restrictions,
+            utility_links: sel.utility_links,
             subscribers: Default::default(),
         }
     }
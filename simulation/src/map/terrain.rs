@@ -36,6 +36,29 @@ pub struct Tree {
 
 pub type ChunkID = (u32, u32);
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TerrainBrushKind {
+    Raise,
+    Lower,
+    /// Brings the ground towards this height
+    Flatten(f32),
+    /// Brings the ground towards the average of its neighbours
+    Smooth,
+}
+
+/// An edit of the terrain heights over a disc, strongest at the center
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TerrainBrush {
+    pub kind: TerrainBrushKind,
+    pub center: Vec2,
+    pub radius: f32,
+    /// Meters at the center when raising or lowering, between 0 and 1 when flattening or smoothing
+    pub strength: f32,
+}
+
+/// A height change of one cell: chunk, row, column and delta
+pub type HeightDelta = (ChunkID, usize, usize, f32);
+
 pub fn chunk_id(v: Vec2) -> ChunkID {
     if v.x < 0.0 || v.y < 0.0 {
         return (0, 0);
@@ -104,6 +127,73 @@ impl Terrain {
         (ll.1..=ur.1).flat_map(move |y| (ll.0..=ur.0).map(move |x| (x, y)))
     }
 
+    /// The height changes made by the brush, computed from the heights before the edit
+    pub fn brush_deltas(&self, brush: &TerrainBrush) -> Vec<HeightDelta> {
+        let mut deltas = vec![];
+        if brush.radius <= 0.0 {
+            return deltas;
+        }
+        let aabb = AABB::centered(brush.center, Vec2::splat(brush.radius * 2.0));
+        for id in self.chunks_iter(aabb) {
+            let chunk = unwrap_cont!(self.chunks.get(&id));
+            let ll = Chunk::rect(id).ll;
+            for (y, row) in chunk.heights.iter().enumerate() {
+                for (x, &h) in row.iter().enumerate() {
+                    let p = ll + vec2(x as f32, y as f32) * CELL_SIZE;
+                    let d = p.distance(brush.center);
+                    if d >= brush.radius {
+                        continue;
+                    }
+                    // smooth falloff towards the border
+                    let t = 1.0 - d / brush.radius;
+                    let w = t * t * (3.0 - 2.0 * t);
+                    let strength = brush.strength.clamp(0.0, 1.0);
+
+                    let delta = match brush.kind {
+                        TerrainBrushKind::Raise => brush.strength * w,
+                        TerrainBrushKind::Lower => -brush.strength * w,
+                        TerrainBrushKind::Flatten(target) => (target - h) * w * strength,
+                        TerrainBrushKind::Smooth => {
+                            let neighbours = [
+                                Vec2::x(CELL_SIZE),
+                                Vec2::x(-CELL_SIZE),
+                                Vec2::y(CELL_SIZE),
+                                Vec2::y(-CELL_SIZE),
+                            ]
+                            .map(|off| self.height_nearest(p + off).unwrap_or(h));
+                            let avg = neighbours.iter().sum::<f32>() / 4.0;
+                            (avg - h) * w * strength
+                        }
+                    };
+                    if delta.abs() > 0.001 {
+                        deltas.push((id, y, x, delta));
+                    }
+                }
+            }
+        }
+        deltas
+    }
+
+    /// The volume of ground moved by the brush, in cubic meters
+    pub fn brush_volume(&self, brush: &TerrainBrush) -> f32 {
+        self.brush_deltas(brush)
+            .iter()
+            .map(|(_, _, _, d)| d.abs() * CELL_SIZE * CELL_SIZE)
+            .sum()
+    }
+
+    pub fn apply_deltas(&mut self, deltas: &[HeightDelta], mut f: impl FnMut(ChunkID)) {
+        let mut last = None;
+        for &(id, y, x, delta) in deltas {
+            let chunk = unwrap_cont!(self.chunks.get_mut(&id));
+            chunk.heights[y][x] += delta;
+            if last != Some(id) {
+                f(id);
+                last = Some(id);
+            }
+        }
+    }
+
     pub fn height(&self, p: Vec2) -> Option<f32> {
         let exact = self.height_nearest(p);
         if let (Some(a), Some(b), Some(c), Some(d)) = (
@@ -291,3 +381,106 @@ impl From<&Terrain> for SerializedTerrain {
         t
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{TerrainBrush, TerrainBrushKind};
+    use crate::economy::Government;
+    use crate::engine_interaction::WorldCommand;
+    use crate::tests::TestCtx;
+    use geom::{vec2, vec3};
+
+    #[test]
+    fn test_terrain_brush() {
+        let mut test = TestCtx::new();
+        let ground = |test: &TestCtx, x: f32| test.g.map().terrain.height(vec2(x, 500.0)).unwrap();
+        let (h1, h2) = (ground(&test, 500.0), ground(&test, 700.0));
+        test.build_roads(&[vec3(500.0, 500.0, h1 + 0.3), vec3(700.0, 500.0, h2 + 0.3)]);
+        let money = test.g.read::<Government>().money;
+
+        test.apply(&[WorldCommand::MapTerrainBrush(TerrainBrush {
+            kind: TerrainBrushKind::Raise,
+            center: vec2(500.0, 500.0),
+            radius: 100.0,
+            strength: 10.0,
+        })]);
+
+        assert!(ground(&test, 500.0) > h1 + 5.0);
+        assert_eq!(ground(&test, 700.0), h2);
+        assert!(test.g.read::<Government>().money < money);
+
+        let map = test.g.map();
+        let inter = map
+            .intersections()
+            .values()
+            .find(|i| i.pos.xy().distance(vec2(500.0, 500.0)) < 1.0)
+            .unwrap();
+        assert!((inter.pos.z - ground(&test, 500.0) - 0.3).abs() < 0.01);
+        let road = &map.roads()[inter.roads[0]];
+        assert!(road.points().first().z > h1 + 5.0 || road.points().last().z > h1 + 5.0);
+    }
+
+    #[test]
+    fn test_terrain_brush_under_road() {
+        let mut test = TestCtx::new();
+        let ground = |test: &TestCtx, x: f32| test.g.map().terrain.height(vec2(x, 500.0)).unwrap();
+        let (h1, h2) = (ground(&test, 300.0), ground(&test, 700.0));
+        test.build_roads(&[vec3(300.0, 500.0, h1 + 0.3), vec3(700.0, 500.0, h2 + 0.3)]);
+
+        test.apply(&[WorldCommand::MapTerrainBrush(TerrainBrush {
+            kind: TerrainBrushKind::Raise,
+            center: vec2(500.0, 500.0),
+            radius: 100.0,
+            strength: 10.0,
+        })]);
+
+        let h = ground(&test, 500.0);
+        let map = test.g.map();
+        let road = map
+            .roads()
+            .values()
+            .find(|r| r.points().first().xy().distance(vec2(300.0, 500.0)) < 1.0)
+            .unwrap();
+        let on_road = |points: &geom::PolyLine3| {
+            let p = points.project(vec3(500.0, 500.0, h));
+            assert!((p.z - h - 0.3).abs() < 0.5, "{} {}", p.z, h);
+        };
+        on_road(road.points());
+        on_road(road.interfaced_points());
+        for (id, _) in road.lanes_iter() {
+            on_road(&map.lanes()[id].points);
+        }
+        assert!((road.points().first().z - h1 - 0.3).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_terrain_brush_keeps_draped_road() {
+        let mut test = TestCtx::new();
+        let ground = |test: &TestCtx, x: f32| test.g.map().terrain.height(vec2(x, 500.0)).unwrap();
+        let (h1, h2) = (ground(&test, 300.0), ground(&test, 700.0));
+        test.build_roads(&[vec3(300.0, 500.0, h1 + 0.3), vec3(700.0, 500.0, h2 + 0.3)]);
+
+        let brush = |kind, x: f32| {
+            WorldCommand::MapTerrainBrush(TerrainBrush {
+                kind,
+                center: vec2(x, 500.0),
+                radius: 100.0,
+                strength: 10.0,
+            })
+        };
+        test.apply(&[brush(TerrainBrushKind::Raise, 500.0)]);
+        // moves the intersection, the hill drawn by the first brush is out of reach
+        test.apply(&[brush(TerrainBrushKind::Lower, 250.0)]);
+
+        let h = ground(&test, 500.0);
+        let map = test.g.map();
+        let road = map
+            .roads()
+            .values()
+            .find(|r| r.points().first().xy().distance(vec2(300.0, 500.0)) < 1.0)
+            .unwrap();
+        assert!((road.points().first().z - ground(&test, 300.0) - 0.3).abs() < 0.01);
+        let p = road.points().project(vec3(500.0, 500.0, h));
+        assert!((p.z - h - 0.3).abs() < 0.5, "{} {}", p.z, h);
+    }
+}
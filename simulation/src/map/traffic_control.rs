@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TrafficBehavior {
     RED,
     ORANGE,
@@ -35,11 +35,22 @@ impl TrafficLightSchedule {
     }
 }
 
+/// State of a light switched by [`crate::map::update_actuated_lights`] depending on the traffic
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct ActuatedLight {
+    /// Lanes of the same phase are green together
+    pub phase: u8,
+    pub behavior: TrafficBehavior,
+    /// Time in seconds at which the behavior last changed
+    pub since: u32,
+}
+
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum TrafficControl {
     Always,
     Light(TrafficLightSchedule),
     StopSign,
+    Actuated(ActuatedLight),
 }
 
 impl TrafficControl {
@@ -52,7 +63,7 @@ impl TrafficControl {
     }
 
     pub fn is_light(&self) -> bool {
-        matches!(self, TrafficControl::Light(_))
+        matches!(self, TrafficControl::Light(_) | TrafficControl::Actuated(_))
     }
 
     pub fn get_behavior(&self, seconds: u32) -> TrafficBehavior {
@@ -70,6 +81,7 @@ impl TrafficControl {
                 }
             }
             TrafficControl::StopSign => TrafficBehavior::STOP,
+            TrafficControl::Actuated(light) => light.behavior,
         }
     }
 }
@@ -0,0 +1,191 @@
+use crate::map::{Lane, LaneID, Map};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Speed below which the observed vehicles are considered stuck, in m/s
+const MIN_OBSERVED_SPEED: f32 = 1.0;
+/// Time lost by every vehicle waiting in a queue, in seconds
+const QUEUE_DELAY: f32 = 2.0;
+/// How fast the estimates follow the observations, between 0 and 1
+const SMOOTHING: f32 = 0.3;
+
+/// What was seen of the vehicles on a lane during one observation
+#[derive(Default, Debug, Copy, Clone)]
+pub struct LaneObservation {
+    pub vehicles: u32,
+    pub waiting: u32,
+    pub speed_sum: f32,
+}
+
+/// Travel time estimates of the lanes used by vehicles, in seconds,
+/// maintained from the speed of the vehicles seen on them and the length of the queues.
+/// Lanes without estimate are assumed to flow freely.
+#[derive(Default, Serialize, Deserialize)]
+pub struct TravelTimes {
+    times: BTreeMap<LaneID, f32>,
+}
+
+impl TravelTimes {
+    /// Travel time of the lane at the speed limit
+    pub fn free_flow(lane: &Lane) -> f32 {
+        lane.points.length() / lane.speed_limit
+    }
+
+    pub fn lane_time(&self, lane: &Lane) -> f32 {
+        self.times
+            .get(&lane.id)
+            .copied()
+            .unwrap_or_else(|| Self::free_flow(lane))
+    }
+
+    /// Estimated and free flow travel times of a sequence of lanes
+    pub fn path_time(&self, map: &Map, lanes: impl Iterator<Item = LaneID>) -> (f32, f32) {
+        lanes
+            .filter_map(|id| map.lanes.get(id))
+            .fold((0.0, 0.0), |(est, free), lane| {
+                (est + self.lane_time(lane), free + Self::free_flow(lane))
+            })
+    }
+
+    /// Moves the estimates towards the observations.
+    /// Lanes without vehicles go back to free flow.
+    pub fn observe(&mut self, map: &Map, observations: &BTreeMap<LaneID, LaneObservation>) {
+        for (&id, obs) in observations {
+            let Some(lane) = map.lanes.get(id) else {
+                continue;
+            };
+            if obs.vehicles == 0 {
+                continue;
+            }
+            let free = Self::free_flow(lane);
+            let speed = (obs.speed_sum / obs.vehicles as f32).max(MIN_OBSERVED_SPEED);
+            let target =
+                (lane.points.length() / speed).max(free) + obs.waiting as f32 * QUEUE_DELAY;
+
+            let t = self.times.entry(id).or_insert(free);
+            *t += (target - *t) * SMOOTHING;
+        }
+
+        self.times.retain(|id, t| {
+            if observations.get(id).is_some_and(|obs| obs.vehicles > 0) {
+                return true;
+            }
+            let Some(lane) = map.lanes.get(*id) else {
+                return false;
+            };
+            let free = Self::free_flow(lane);
+            *t += (free - *t) * SMOOTHING;
+            *t > free * 1.05
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LaneObservation, TravelTimes};
+    use crate::map::{LaneID, LaneKind, Map, PathKind, TraverseKind};
+    use crate::map_dynamic::Itinerary;
+    use crate::tests::TestCtx;
+    use crate::utils::time::Tick;
+    use geom::{vec2, vec3, Vec2};
+    use std::collections::BTreeMap;
+
+    /// The driving lanes of the road between the two points
+    fn road_lanes(map: &Map, a: Vec2, b: Vec2) -> Vec<LaneID> {
+        let at = |p: Vec2, id| map.intersections()[id].pos.xy().distance(p) < 1.0;
+        let road = map
+            .roads()
+            .values()
+            .find(|r| (at(a, r.src) && at(b, r.dst)) || (at(b, r.src) && at(a, r.dst)))
+            .unwrap();
+        road.lanes_iter()
+            .filter(|(_, kind)| *kind == LaneKind::Driving)
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    fn jam(map: &Map, times: &mut TravelTimes, lanes: &[LaneID]) {
+        let obs: BTreeMap<_, _> = lanes
+            .iter()
+            .map(|&id| {
+                (
+                    id,
+                    LaneObservation {
+                        vehicles: 10,
+                        waiting: 10,
+                        speed_sum: 0.0,
+                    },
+                )
+            })
+            .collect();
+        for _ in 0..10 {
+            times.observe(map, &obs);
+        }
+    }
+
+    #[test]
+    fn test_congestion_aware_routing() {
+        let test = TestCtx::new();
+        let (w, a, b, c, d, e) = (
+            vec2(-200.0, 0.0),
+            vec2(0.0, 0.0),
+            vec2(300.0, 0.0),
+            vec2(300.0, 300.0),
+            vec2(0.0, 300.0),
+            vec2(500.0, 300.0),
+        );
+        test.build_roads(&[w.z0(), a.z0(), b.z0(), c.z0(), e.z0()]);
+        test.build_roads(&[a.z0(), d.z0(), c.z0()]);
+
+        let map = test.g.map();
+        let via_b = road_lanes(&map, a, b);
+        let via_d = road_lanes(&map, a, d);
+        let mut times = TravelTimes::default();
+
+        let route = |times: &TravelTimes| {
+            Itinerary::route(
+                Tick(0),
+                vec3(-150.0, 0.0, 0.0),
+                vec3(450.0, 300.0, 0.0),
+                &map,
+                times,
+                PathKind::Vehicle,
+            )
+            .unwrap()
+        };
+        let uses = |it: &Itinerary, lanes: &[LaneID]| {
+            it.get_route()
+                .unwrap()
+                .reversed_route
+                .iter()
+                .any(|t| match t.kind {
+                    TraverseKind::Lane(id) => lanes.contains(&id),
+                    TraverseKind::Turn(_) => false,
+                })
+        };
+
+        jam(&map, &mut times, &via_b);
+        let free = TravelTimes::free_flow(&map.lanes()[via_b[0]]);
+        assert!(times.lane_time(&map.lanes()[via_b[0]]) > free * 2.0);
+        assert!(uses(&route(&times), &via_d));
+
+        let mut times = TravelTimes::default();
+        jam(&map, &mut times, &via_d);
+        let mut it = route(&times);
+        assert!(uses(&it, &via_b));
+
+        // the jam moves to the chosen road, the driver takes the other one
+        let mut times = TravelTimes::default();
+        jam(&map, &mut times, &via_b);
+        assert!(it.reroute_if_slow(Tick(0), &map, &times));
+        assert!(uses(&it, &via_d));
+        assert!(!uses(&it, &via_b));
+
+        // without traffic the estimates go back to free flow
+        for _ in 0..30 {
+            times.observe(&map, &BTreeMap::new());
+        }
+        assert!(times.times.is_empty());
+        assert!(!it.reroute_if_slow(Tick(0), &map, &times));
+    }
+}
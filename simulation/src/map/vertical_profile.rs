@@ -0,0 +1,335 @@
+use crate::map::{LanePattern, MapProject, ProjectKind, Road, RoadSegmentKind, Terrain};
+use geom::{Vec2, Vec3};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Roads at least this deep below the ground are in a tunnel
+pub const TUNNEL_DEPTH: f32 = 8.0;
+/// Roads at least this high above the ground are on a bridge, matching where pylons are placed
+pub const BRIDGE_HEIGHT: f32 = 2.0;
+/// Maximum distance of an end of a connection above or below the ground
+pub const MAX_ELEVATION: f32 = 100.0;
+
+/// Spacing of the points at which a connection is checked
+const SAMPLE_STEP: f32 = 5.0;
+
+/// How a connection goes up and down between its ends.
+/// Elevations are relative to the ground, negative values make tunnels.
+/// They only apply to ends built on the ground: ends joining an existing
+/// intersection or road keep its height.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VerticalProfile {
+    pub from_elevation: f32,
+    pub to_elevation: f32,
+    /// Maximum rise over run along the connection, 0.05 is a 5% grade
+    pub max_grade: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ProfileError {
+    TooHigh(f32),
+    TooSteep(f32),
+    /// The connection goes through the ground without being deep enough to be a tunnel
+    HitsTerrain(Vec3),
+}
+
+impl Display for ProfileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            ProfileError::TooHigh(elevation) => {
+                write!(f, "elevation of {elevation:.0}m is out of bounds")
+            }
+            ProfileError::TooSteep(grade) => write!(f, "too steep ({:.1}%)", grade * 100.0),
+            ProfileError::HitsTerrain(_) => write!(f, "goes through the ground"),
+        }
+    }
+}
+
+impl VerticalProfile {
+    /// A profile following the ground at both ends, with the usual grade for the pattern
+    pub fn ground(pattern: &LanePattern) -> Self {
+        Self {
+            from_elevation: 0.0,
+            to_elevation: 0.0,
+            max_grade: Self::default_max_grade(pattern),
+        }
+    }
+
+    /// Trains cannot climb as steep slopes as cars
+    pub fn default_max_grade(pattern: &LanePattern) -> f32 {
+        if pattern.lanes().any(|(kind, _, _)| kind.is_rail()) {
+            0.04
+        } else {
+            0.1
+        }
+    }
+
+    /// Moves the ends built on the ground to their elevation
+    pub fn apply(
+        &self,
+        from: MapProject,
+        to: MapProject,
+        terrain: &Terrain,
+    ) -> (MapProject, MapProject) {
+        let elevate = |mut proj: MapProject, elevation: f32| {
+            if matches!(proj.kind, ProjectKind::Ground) {
+                if let Some(h) = terrain.height(proj.pos.xy()) {
+                    proj.pos.z = h + 0.3 + elevation;
+                }
+            }
+            proj
+        };
+        (
+            elevate(from, self.from_elevation),
+            elevate(to, self.to_elevation),
+        )
+    }
+
+    /// Checks the connection between the (already elevated) ends respects the profile
+    pub fn check(
+        &self,
+        from: Vec3,
+        to: Vec3,
+        interpoint: Option<Vec2>,
+        pattern: &LanePattern,
+        terrain: &Terrain,
+    ) -> Result<(), ProfileError> {
+        for elevation in [self.from_elevation, self.to_elevation] {
+            if elevation.abs() > MAX_ELEVATION {
+                return Err(ProfileError::TooHigh(elevation));
+            }
+        }
+
+        let points = Self::sample(from, to, interpoint, pattern);
+
+        for w in points.windows(2) {
+            let (_, a) = w[0];
+            let (_, b) = w[1];
+            let run = a.xy().distance(b.xy());
+            if run < 0.01 {
+                continue;
+            }
+            let grade = (b.z - a.z).abs() / run;
+            if grade > self.max_grade + 0.001 {
+                return Err(ProfileError::TooSteep(grade));
+            }
+        }
+
+        // The connection can only be in the ground without being deep enough on the portals:
+        // stretches going from the surface straight down to a tunnel
+        let depths: Vec<f32> = points
+            .iter()
+            .map(|&(_, pos)| terrain.height(pos.xy()).map_or(0.0, |h| h + 0.3 - pos.z))
+            .collect();
+        let is_surface = |i: usize| depths[i] <= 1.0;
+        let is_tunnel = |i: usize| depths[i] >= TUNNEL_DEPTH;
+
+        let mut i = 0;
+        while i < depths.len() {
+            if is_surface(i) || is_tunnel(i) {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < depths.len() && !is_surface(i) && !is_tunnel(i) {
+                i += 1;
+            }
+            let before = start.checked_sub(1);
+            let after = (i < depths.len()).then_some(i);
+            let is_portal = match (before, after) {
+                (Some(b), Some(a)) => {
+                    (is_surface(b) && is_tunnel(a)) || (is_tunnel(b) && is_surface(a))
+                }
+                _ => false,
+            };
+            if !is_portal {
+                return Err(ProfileError::HitsTerrain(points[start].1));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Length of the connection in a tunnel and on bridges
+    pub fn structure_lengths(
+        from: Vec3,
+        to: Vec3,
+        interpoint: Option<Vec2>,
+        pattern: &LanePattern,
+        terrain: &Terrain,
+    ) -> (f32, f32) {
+        let mut tunnel = 0.0;
+        let mut bridge = 0.0;
+        let points = Self::sample(from, to, interpoint, pattern);
+        for w in points.windows(2) {
+            let (d_a, a) = w[0];
+            let (d_b, _) = w[1];
+            let Some(h) = terrain.height(a.xy()) else {
+                continue;
+            };
+            if h - a.z >= TUNNEL_DEPTH {
+                tunnel += d_b - d_a;
+            } else if a.z - h > BRIDGE_HEIGHT {
+                bridge += d_b - d_a;
+            }
+        }
+        (tunnel, bridge)
+    }
+
+    /// Points along the center line of the connection with their distance from the start
+    fn sample(
+        from: Vec3,
+        to: Vec3,
+        interpoint: Option<Vec2>,
+        pattern: &LanePattern,
+    ) -> Vec<(f32, Vec3)> {
+        let segment = match interpoint {
+            Some(x) => RoadSegmentKind::from_elbow(from.xy(), to.xy(), x),
+            None => RoadSegmentKind::Straight,
+        };
+        let precise = pattern.lanes().any(|(kind, _, _)| kind.is_rail());
+        let points = Road::generate_points(from, to, segment, precise);
+
+        let mut d = 0.0;
+        let mut last = from;
+        points
+            .equipoints_dir(SAMPLE_STEP, false)
+            .map(|(pos, _)| {
+                d += pos.distance(last);
+                last = pos;
+                (d, pos)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VerticalProfile;
+    use crate::economy::Government;
+    use crate::engine_interaction::WorldCommand;
+    use crate::map::{
+        LanePatternBuilder, MapProject, ProjectKind, Road, TerrainBrush, TerrainBrushKind,
+    };
+    use crate::tests::TestCtx;
+    use geom::{vec2, vec3};
+
+    #[test]
+    fn test_profiled_connections() {
+        let mut test = TestCtx::new();
+        let level = test.g.map().terrain.height(vec2(600.0, 500.0)).unwrap();
+        for _ in 0..20 {
+            test.apply(&[WorldCommand::MapTerrainBrush(TerrainBrush {
+                kind: TerrainBrushKind::Flatten(level),
+                center: vec2(600.0, 500.0),
+                radius: 800.0,
+                strength: 1.0,
+            })]);
+        }
+
+        let pat = LanePatternBuilder::new().build();
+        let connection = |x1: f32, x2: f32, from_elevation: f32, to_elevation: f32| {
+            WorldCommand::MapMakeProfiledConnection {
+                from: MapProject::ground(vec3(x1, 500.0, 0.0)),
+                to: MapProject::ground(vec3(x2, 500.0, 0.0)),
+                inter: None,
+                pat: pat.clone(),
+                profile: VerticalProfile {
+                    from_elevation,
+                    to_elevation,
+                    max_grade: 0.1,
+                },
+            }
+        };
+        let n_roads = |test: &TestCtx| test.g.map().roads().len();
+        let before = n_roads(&test);
+
+        // too steep
+        test.apply(&[connection(400.0, 500.0, 0.0, 30.0)]);
+        assert_eq!(n_roads(&test), before);
+
+        // too shallow to be a tunnel
+        test.apply(&[connection(300.0, 700.0, -4.0, -4.0)]);
+        assert_eq!(n_roads(&test), before);
+
+        let tunnel = connection(300.0, 700.0, -20.0, -20.0);
+        let ground = WorldCommand::MapMakeConnection {
+            from: MapProject::ground(vec3(300.0, 500.0, level + 0.3)),
+            to: MapProject::ground(vec3(700.0, 500.0, level + 0.3)),
+            inter: None,
+            pat: pat.clone(),
+        };
+        assert!(
+            Government::action_cost(&tunnel, &test.g) > Government::action_cost(&ground, &test.g)
+        );
+
+        test.apply(&[tunnel]);
+        assert_eq!(n_roads(&test), before + 1);
+        let map = test.g.map();
+        let road = map
+            .roads()
+            .values()
+            .find(|r| r.points().first().xy().distance(vec2(300.0, 500.0)) < 1.0)
+            .unwrap();
+        assert!(road.points().iter().all(|p| p.z < level - 15.0));
+        assert_eq!(
+            Road::pylons_positions(road.interfaced_points(), &map.terrain).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_tunnel_ramp() {
+        let mut test = TestCtx::new();
+        let level = test.g.map().terrain.height(vec2(600.0, 500.0)).unwrap();
+        for _ in 0..20 {
+            test.apply(&[WorldCommand::MapTerrainBrush(TerrainBrush {
+                kind: TerrainBrushKind::Flatten(level),
+                center: vec2(600.0, 500.0),
+                radius: 800.0,
+                strength: 1.0,
+            })]);
+        }
+
+        let pat = LanePatternBuilder::new().build();
+        test.apply(&[WorldCommand::MapMakeConnection {
+            from: MapProject::ground(vec3(200.0, 500.0, level + 0.3)),
+            to: MapProject::ground(vec3(300.0, 500.0, level + 0.3)),
+            inter: None,
+            pat: pat.clone(),
+        }]);
+        let inter = test
+            .g
+            .map()
+            .intersections()
+            .iter()
+            .find(|(_, i)| i.pos.xy().distance(vec2(300.0, 500.0)) < 1.0)
+            .map(|(id, i)| MapProject {
+                pos: i.pos,
+                kind: ProjectKind::Inter(id),
+            })
+            .unwrap();
+        let n_roads = |test: &TestCtx| test.g.map().roads().len();
+        let before = n_roads(&test);
+
+        let ramp = |to_x: f32, to_elevation: f32| WorldCommand::MapMakeProfiledConnection {
+            from: inter,
+            to: MapProject::ground(vec3(to_x, 500.0, 0.0)),
+            inter: None,
+            pat: pat.clone(),
+            profile: VerticalProfile {
+                from_elevation: 0.0,
+                to_elevation,
+                max_grade: 0.1,
+            },
+        };
+
+        // a ramp that stays too shallow is not a portal
+        test.apply(&[ramp(400.0, -4.0)]);
+        assert_eq!(n_roads(&test), before);
+
+        // more than 60m of the ramp is shallow before reaching the tunnel
+        test.apply(&[ramp(600.0, -20.0)]);
+        assert_eq!(n_roads(&test), before + 1);
+    }
+}
@@ -8,6 +8,8 @@ use std::collections::BTreeMap;
 pub struct BuildingInfo {
     pub owner: Option<SoulID>,
     pub inside: Vec<SoulID>,
+    /// The humans living in the building, the owner of a house is one of them
+    pub residents: Vec<SoulID>,
 }
 
 #[derive(Clone, Default, Serialize, Deserialize, Debug)]
@@ -16,6 +18,46 @@ pub struct BuildingInfos {
     owners: BTreeMap<SoulID, BuildingID>,
 }
 
+/// The building infos before houses had several residents
+#[derive(Deserialize)]
+pub(crate) struct BuildingInfoV0 {
+    owner: Option<SoulID>,
+    inside: Vec<SoulID>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct BuildingInfosV0 {
+    assignment: SecondaryMap<BuildingID, BuildingInfoV0>,
+    owners: BTreeMap<SoulID, BuildingID>,
+}
+
+impl From<BuildingInfosV0> for BuildingInfos {
+    fn from(old: BuildingInfosV0) -> Self {
+        Self {
+            assignment: old
+                .assignment
+                .into_iter()
+                .map(|(id, info)| {
+                    // a house was owned by its only resident
+                    let residents = match info.owner {
+                        Some(soul @ SoulID::Human(_)) => vec![soul],
+                        _ => vec![],
+                    };
+                    (
+                        id,
+                        BuildingInfo {
+                            owner: info.owner,
+                            inside: info.inside,
+                            residents,
+                        },
+                    )
+                })
+                .collect(),
+            owners: old.owners,
+        }
+    }
+}
+
 impl BuildingInfos {
     pub fn insert(&mut self, building: BuildingID) {
         self.assignment.insert(building, BuildingInfo::default());
@@ -44,6 +86,33 @@ impl BuildingInfos {
         self.assignment.get(building).and_then(|x| x.owner)
     }
 
+    pub fn residents(&self, building: BuildingID) -> &[SoulID] {
+        self.assignment
+            .get(building)
+            .map_or(&[], |x| x.residents.as_slice())
+    }
+
+    pub fn add_resident(&mut self, building: BuildingID, soul: SoulID) {
+        let b = unwrap_ret!(self.get_mut(building));
+        if !b.residents.contains(&soul) {
+            b.residents.push(soul);
+        }
+    }
+
+    /// The soul moves out, if it owned the building another resident becomes the owner
+    pub fn remove_resident(&mut self, building: BuildingID, soul: SoulID) {
+        let b = unwrap_ret!(self.assignment.get_mut(building));
+        b.residents.retain(|&r| r != soul);
+        if b.owner != Some(soul) {
+            return;
+        }
+        b.owner = b.residents.first().copied();
+        self.owners.remove(&soul);
+        if let Some(owner) = b.owner {
+            self.owners.insert(owner, building);
+        }
+    }
+
     pub fn get_in(&mut self, building: BuildingID, e: SoulID) {
         let b = unwrap_ret!(self.get_mut(building));
         if cfg!(debug_assertions) && b.inside.contains(&e) {
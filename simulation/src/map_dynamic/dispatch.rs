@@ -1,4 +1,5 @@
 use crate::map::{LaneID, LaneKind, TraverseDirection};
+use crate::transportation::VehicleKind;
 use crate::utils::resources::Resources;
 use crate::world::{TrainID, VehicleID};
 use crate::{Map, World};
@@ -91,15 +92,18 @@ impl Dispatcher {
             disp_trains.register(DispatchID::FreightTrain(ent), map, train.trans.position);
         });
 
-        /*
         let disp_trucks = self
             .dispatches
             .entry(DispatchKind::SmallTruck)
             .or_insert_with(|| DispatchOne::new(DispatchKind::SmallTruck.lane_kind()));
 
-        world.vehicles.iter().for_each(|(ent, truck)| {
-            disp_trucks.register(DispatchID::Truck(ent), map, truck.trans.position);
-        })*/
+        world
+            .vehicles
+            .iter()
+            .filter(|(_, v)| matches!(v.vehicle.kind, VehicleKind::Truck))
+            .for_each(|(ent, truck)| {
+                disp_trucks.register(DispatchID::SmallTruck(ent), map, truck.trans.position);
+            });
     }
 
     /// Frees the entity as it is no longer used
@@ -114,6 +118,18 @@ impl Dispatcher {
         disp.reserved_by.remove(&ent);
     }
 
+    /// Reserves the entity so that queries never return it, for example when it is owned by
+    /// something that doesn't go through the dispatcher
+    pub fn reserve(&mut self, ent: impl Into<DispatchID>) {
+        let ent: DispatchID = ent.into();
+        let kind: DispatchKind = ent.into();
+        self.dispatches
+            .entry(kind)
+            .or_insert_with(|| DispatchOne::new(kind.lane_kind()))
+            .reserved_by
+            .insert(ent);
+    }
+
     pub fn unregister(&mut self, id: DispatchID) {
         let kind = id.into();
         let Some(disp) = self.dispatches.get_mut(&kind) else {
@@ -136,6 +152,21 @@ impl Dispatcher {
         disp.reserve(best_ent);
         Some(best_ent)
     }
+
+    /// Same as [`Dispatcher::query`] but only considers the entities accepted by the filter,
+    /// for example the trucks of a company
+    pub fn query_filtered(
+        &mut self,
+        map: &Map,
+        kind: DispatchKind,
+        target: DispatchQueryTarget,
+        filter: impl Fn(DispatchID) -> bool,
+    ) -> Option<DispatchID> {
+        let disp = self.dispatches.get_mut(&kind)?;
+        let best_ent = disp.query_filtered(map, kind, target, filter)?;
+        disp.reserve(best_ent);
+        Some(best_ent)
+    }
 }
 
 impl DispatchOne {
@@ -222,6 +253,16 @@ impl DispatchOne {
         map: &Map,
         kind: DispatchKind,
         target: DispatchQueryTarget,
+    ) -> Option<DispatchID> {
+        self.query_filtered(map, kind, target, |_| true)
+    }
+
+    fn query_filtered(
+        &mut self,
+        map: &Map,
+        kind: DispatchKind,
+        target: DispatchQueryTarget,
+        filter: impl Fn(DispatchID) -> bool,
     ) -> Option<DispatchID> {
         // todo: handle the case where there are few entities in the cache
         // todo: probably some kind of astar on good candidates
@@ -266,7 +307,7 @@ impl DispatchOne {
                 continue;
             };
             for ent in ents {
-                if self.reserved_by.contains(ent) {
+                if self.reserved_by.contains(ent) || !filter(*ent) {
                     continue;
                 }
                 let pos = self.positions.get(ent).unwrap();
@@ -1,4 +1,6 @@
-use crate::map::{Map, PathKind, Pathfinder, Traversable, TraverseDirection, TraverseKind};
+use crate::map::{
+    LaneID, Map, PathKind, Pathfinder, TravelTimes, Traversable, TraverseDirection, TraverseKind,
+};
 use crate::utils::resources::Resources;
 use crate::utils::time::{GameTime, Tick};
 use crate::world::TrainID;
@@ -52,6 +54,11 @@ pub struct Route {
 
 pub const OBJECTIVE_OK_DIST: f32 = 3.0;
 
+/// A route is reconsidered when traffic makes it this many times slower than at free flow
+const REROUTE_SLOWDOWN: f32 = 1.5;
+/// A new route is only taken if it is at least this much faster
+const REROUTE_GAIN: f32 = 0.8;
+
 impl Itinerary {
     pub const NONE: Self = Self {
         kind: ItineraryKind::None,
@@ -97,6 +104,7 @@ impl Itinerary {
         start: Vec3,
         end: Vec3,
         map: &Map,
+        times: &TravelTimes,
         pathkind: PathKind,
     ) -> Option<Itinerary> {
         let start_lane = pathkind.nearest_lane(map, start)?;
@@ -122,7 +130,7 @@ impl Itinerary {
         }
 
         let mut reversed_route: Vec<Traversable> = pathkind
-            .path(map, tick, cur, end_lane)?
+            .path(map, times, tick, cur, end_lane)?
             .into_iter()
             .rev()
             .collect();
@@ -168,6 +176,45 @@ impl Itinerary {
         Some(it)
     }
 
+    /// Replaces the rest of a vehicle route by a faster one when the traffic made it much slower
+    /// than at free flow. The vehicle keeps driving on its current lane.
+    /// Returns true if the route changed.
+    pub fn reroute_if_slow(&mut self, tick: Tick, map: &Map, times: &TravelTimes) -> bool {
+        let ItineraryKind::Route(ref mut r, kind) = self.kind else {
+            return false;
+        };
+        if !matches!(kind, PathKind::Vehicle | PathKind::Truck | PathKind::Bus)
+            || !matches!(r.cur.kind, TraverseKind::Lane(_))
+        {
+            return false;
+        }
+        let Some(&Traversable {
+            kind: TraverseKind::Lane(end),
+            ..
+        }) = r.reversed_route.first()
+        else {
+            return false;
+        };
+
+        let (cur_time, free_time) = times.path_time(map, route_lanes(&r.reversed_route));
+        if cur_time < free_time * REROUTE_SLOWDOWN {
+            return false;
+        }
+
+        let Some(path) = kind.path(map, times, tick, r.cur, end) else {
+            return false;
+        };
+        let mut reversed_route: Vec<Traversable> = path.into_iter().rev().collect();
+        reversed_route.pop(); // Remove current lane
+
+        let (new_time, _) = times.path_time(map, route_lanes(&reversed_route));
+        if new_time > cur_time * REROUTE_GAIN {
+            return false;
+        }
+        r.reversed_route = reversed_route;
+        true
+    }
+
     fn advance(&mut self, map: &Map, position: Vec3) -> Option<Vec3> {
         let v = self.reversed_local_path.pop();
 
@@ -204,6 +251,7 @@ impl Itinerary {
         tick: Tick,
         time: u32,
         map: &Map,
+        times: &TravelTimes,
     ) -> Vec3 {
         while let Some(p) = self.get_point() {
             let dist = position.distance(p);
@@ -245,7 +293,7 @@ impl Itinerary {
                 *wait_ticks -= 1;
                 return position;
             }
-            *self = unwrap_or!(Self::route(tick, position, dest, map, kind), {
+            *self = unwrap_or!(Self::route(tick, position, dest, map, times, kind), {
                 *wait_ticks = 200;
                 return position;
             });
@@ -259,11 +307,12 @@ impl Itinerary {
         position: Vec3,
         tick: Tick,
         map: &Map,
+        times: &TravelTimes,
         pathkind: PathKind,
     ) -> Option<Itinerary> {
         let lanes = &map.lanes;
         let lane = lanes.values().nth(rng as usize % lanes.len())?;
-        if !pathkind.authorized_lane(lane.kind) {
+        if !pathkind.authorized_lane(map, lane) {
             return None;
         }
         Itinerary::route(
@@ -271,6 +320,7 @@ impl Itinerary {
             position,
             lane.points.point_along(lane.points.length() * 0.5),
             map,
+            times,
             pathkind,
         )
     }
@@ -364,6 +414,13 @@ impl Itinerary {
     }
 }
 
+fn route_lanes(route: &[Traversable]) -> impl Iterator<Item = LaneID> + '_ {
+    route.iter().filter_map(|t| match t.kind {
+        TraverseKind::Lane(id) => Some(id),
+        TraverseKind::Turn(_) => None,
+    })
+}
+
 impl Inspect<ItineraryKind> for ItineraryKind {
     fn render(d: &ItineraryKind, label: &'static str, ui: &mut Ui, args: &InspectArgs) {
         match *d {
@@ -416,6 +473,7 @@ pub fn itinerary_update(world: &mut World, resources: &mut Resources) {
     profiling::scope!("map_dynamic::itinerary_update");
     let time = &*resources.read::<GameTime>();
     let map = &*resources.read::<Map>();
+    let times = &*resources.read::<TravelTimes>();
     let tick = *resources.read::<Tick>();
 
     world.query_it_trans_speed().for_each(
@@ -426,6 +484,7 @@ pub fn itinerary_update(world: &mut World, resources: &mut Resources) {
                 tick,
                 time.seconds,
                 map,
+                times,
             );
         },
     );
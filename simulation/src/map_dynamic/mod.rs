@@ -3,9 +3,11 @@ mod dispatch;
 mod itinerary;
 mod parking;
 mod router;
+mod utility_grid;
 
 pub use binfos::*;
 pub use dispatch::*;
 pub use itinerary::*;
 pub use parking::*;
 pub use router::*;
+pub use utility_grid::*;
@@ -1,14 +1,28 @@
-use crate::map::{BuildingID, Map, PathKind};
+use crate::map::{BuildingID, Map, PathKind, TravelTimes};
 use crate::map_dynamic::{Itinerary, ParkingManagement, ParkingReserveError, SpotReservation};
 use crate::physics::CollisionWorld;
+use crate::souls::goods_company::GoodsCompanyRegistry;
+use crate::transportation::passenger_rail::{PassengerLineID, PassengerLines, TrainTrip};
+use crate::transportation::traffic_stats::{TrafficStats, TripEnd};
+use crate::transportation::transit::{BusTrip, TransitLineID, TransitLines, WALK_SPEED_ESTIMATE};
 use crate::transportation::{put_pedestrian_in_coworld, unpark, Location, VehicleState};
 use crate::utils::resources::Resources;
-use crate::world::{HumanEnt, HumanID, VehicleEnt, VehicleID};
+use crate::utils::time::{GameInstant, GameTime, Tick};
+use crate::world::{HumanEnt, HumanID, TrainEnt, TrainID, VehicleEnt, VehicleID};
 use crate::{ParCommandBuffer, World};
 use egui_inspect::Inspect;
 use geom::{Spline3, Transform, Vec3};
 use serde::{Deserialize, Serialize};
-use slotmapd::HopSlotMap;
+use slotmapd::{HopSlotMap, Key};
+
+/// Trips shorter than this are walked rather than biked, in meters
+const BIKE_MIN_DIST: f32 = 300.0;
+/// Trips longer than this are too tiring to bike, in meters
+const BIKE_MAX_DIST: f32 = 3000.0;
+/// How far one is willing to walk to get to their bike, in meters
+const BIKE_MAX_FETCH_DIST: f32 = 50.0;
+/// How often drivers check whether their route got congested, in ticks
+const REROUTE_PERIOD: u64 = 500;
 
 #[derive(Inspect, Serialize, Deserialize)]
 pub struct Router {
@@ -18,7 +32,44 @@ pub struct Router {
     cur_dest: Option<Destination>,
     vehicle: Option<VehicleID>,
     pub personal_car: Option<VehicleID>,
+    /// Ridden instead of the car for trips of the right length
+    pub personal_bike: Option<VehicleID>,
     pub last_error: Option<RouterError>,
+    /// When the current trip was routed
+    #[inspect(skip)]
+    trip_start: Option<GameInstant>,
+    /// Building reached by the last completed trip and how long the trip took, in game seconds
+    #[inspect(skip)]
+    pub last_trip: Option<(BuildingID, f32)>,
+}
+
+/// The router before bikes and trip durations
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RouterV0 {
+    steps: Vec<RoutingStep>,
+    cur_step: Option<RoutingStep>,
+    target_dest: Option<Destination>,
+    cur_dest: Option<Destination>,
+    vehicle: Option<VehicleID>,
+    personal_car: Option<VehicleID>,
+    last_error: Option<RouterError>,
+}
+
+impl From<RouterV0> for Router {
+    fn from(old: RouterV0) -> Self {
+        Self {
+            steps: old.steps,
+            cur_step: old.cur_step,
+            target_dest: old.target_dest,
+            cur_dest: old.cur_dest,
+            vehicle: old.vehicle,
+            personal_car: old.personal_car,
+            personal_bike: None,
+            last_error: old.last_error,
+            trip_start: None,
+            last_trip: None,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -48,14 +99,84 @@ pub enum RoutingStep {
     GetOutVehicle(VehicleID),
     GetInBuilding(BuildingID),
     GetOutBuilding(BuildingID),
+    /// Wait at the stop of the line until a bus picks us up
+    GetInBus(TransitLineID, usize),
+    /// Stay in the bus until it reaches the stop of the line
+    GetOutBus(TransitLineID, usize),
+    /// Wait at the station of the line until a train stops there
+    GetInTrain(PassengerLineID, usize),
+    /// Stay in the train until it stops at the station of the line
+    GetOutTrain(PassengerLineID, usize),
 }
 
 debug_inspect_impl!(RoutingStep);
 
+/// A ride on public transport, part of a trip found by [`best_transit_trip`]
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum TransitLeg {
+    Bus(BusTrip),
+    Train(TrainTrip),
+}
+
+/// Finds the fastest trip from `start` to `end` by public transport with its estimated duration
+/// in seconds. The stations of a train trip are reached and left by bus when it beats walking.
+fn best_transit_trip(
+    map: &Map,
+    transit: &TransitLines,
+    rail: &PassengerLines,
+    start: Vec3,
+    end: Vec3,
+) -> Option<(f32, Vec<TransitLeg>)> {
+    let mut best = transit
+        .best_trip(map, start, end)
+        .map(|(time, trip)| (time, vec![TransitLeg::Bus(trip)]));
+
+    let Some((mut time, trip)) = rail.best_trip(map, start, end) else {
+        return best;
+    };
+    let line = &rail.lines()[trip.line];
+    let door = |i: usize| map.buildings().get(line.stations[i]).map(|b| b.door_pos);
+    let (Some(from), Some(to)) = (door(trip.from), door(trip.to)) else {
+        return best;
+    };
+
+    let mut legs = vec![];
+    let walk = start.distance(from) / WALK_SPEED_ESTIMATE;
+    if let Some((t, bus)) = transit
+        .best_trip(map, start, from)
+        .filter(|&(t, _)| t < walk)
+    {
+        time += t - walk;
+        legs.push(TransitLeg::Bus(bus));
+    }
+    legs.push(TransitLeg::Train(trip));
+    let walk = to.distance(end) / WALK_SPEED_ESTIMATE;
+    if let Some((t, bus)) = transit.best_trip(map, to, end).filter(|&(t, _)| t < walk) {
+        time += t - walk;
+        legs.push(TransitLeg::Bus(bus));
+    }
+
+    if best.as_ref().is_none_or(|&(t, _)| time < t) {
+        best = Some((time, legs));
+    }
+    best
+}
+
 pub fn routing_changed_system(world: &mut World, resources: &mut Resources) {
     profiling::scope!("map_dynamic::routing_changed_system");
     let map: &Map = &resources.read();
     let parking: &mut ParkingManagement = &mut resources.write();
+    let transit: &TransitLines = &resources.read();
+    let rail: &PassengerLines = &resources.read();
+    let registry: &GoodsCompanyRegistry = &resources.read();
+    let stats: &mut TrafficStats = &mut resources.write();
+    let now = resources.read::<GameTime>().instant();
+
+    let trip_end = |build: BuildingID| {
+        map.buildings
+            .get(build)
+            .map(|b| TripEnd::of(&b.kind, registry))
+    };
 
     world.humans.values_mut().for_each(|h| {
         let router = &mut h.router;
@@ -65,10 +186,30 @@ pub fn routing_changed_system(world: &mut World, resources: &mut Resources) {
         }
         let dest = unwrap_ret!(router.target_dest);
 
+        let trip = match (loc, dest) {
+            (Location::Building(origin), Destination::Outside(_)) => {
+                trip_end(*origin).map(|from| (from, TripEnd::Outside))
+            }
+            (Location::Building(origin), Destination::Building(build)) => {
+                trip_end(*origin).zip(trip_end(build))
+            }
+            _ => None,
+        };
+
         router.clear_steps(parking);
         match dest {
             Destination::Outside(pos) => {
-                router.steps = match router.steps_to(pos, parking, map, loc, &world.vehicles) {
+                router.steps = match router.steps_to(
+                    h.trans.position,
+                    pos,
+                    parking,
+                    map,
+                    transit,
+                    rail,
+                    loc,
+                    &world.vehicles,
+                    &world.trains,
+                ) {
                     Ok(x) => x,
                     Err(e) => {
                         router.last_error = Some(e);
@@ -92,7 +233,17 @@ pub fn routing_changed_system(world: &mut World, resources: &mut Resources) {
                     }
                 };
                 let door_pos = bobj.door_pos;
-                router.steps = match router.steps_to(door_pos, parking, map, loc, &world.vehicles) {
+                router.steps = match router.steps_to(
+                    h.trans.position,
+                    door_pos,
+                    parking,
+                    map,
+                    transit,
+                    rail,
+                    loc,
+                    &world.vehicles,
+                    &world.trains,
+                ) {
                     Ok(x) => x,
                     Err(e) => {
                         router.last_error = Some(e);
@@ -103,6 +254,10 @@ pub fn routing_changed_system(world: &mut World, resources: &mut Resources) {
             }
         }
 
+        if let Some((from, to)) = trip {
+            stats.record_trip(from, to);
+        }
+        router.trip_start = Some(now);
         router.cur_dest = router.target_dest;
 
         router.steps.reverse();
@@ -114,6 +269,11 @@ pub fn routing_update_system(world: &mut World, resources: &mut Resources) {
     let map: &Map = &resources.read();
     let cbuf_human: &ParCommandBuffer<HumanEnt> = &resources.read();
     let cbuf_vehicle: &ParCommandBuffer<VehicleEnt> = &resources.read();
+    let transit: &TransitLines = &resources.read();
+    let rail: &PassengerLines = &resources.read();
+    let times: &TravelTimes = &resources.read();
+    let tick = *resources.read::<Tick>();
+    let time: &GameTime = &resources.read();
 
     world.humans.iter_mut().for_each(|(body, h)| {
         if h.router.cur_step.is_none() && h.router.steps.is_empty() {
@@ -130,6 +290,11 @@ pub fn routing_update_system(world: &mut World, resources: &mut Resources) {
                 .get(id)
                 .map(|x| x.trans.position)
                 .unwrap_or_else(|| trans.position),
+            Location::Train(id) => world
+                .trains
+                .get(id)
+                .map(|x| x.trans.position)
+                .unwrap_or_else(|| trans.position),
             Location::Building(id) => map
                 .buildings()
                 .get(id)
@@ -159,8 +324,24 @@ pub fn routing_update_system(world: &mut World, resources: &mut Resources) {
                 RoutingStep::GetOutVehicle(_) => true,
                 RoutingStep::GetInBuilding(_) => true,
                 RoutingStep::GetOutBuilding(_) => true,
+                RoutingStep::GetInBus(_, _) => true,
+                RoutingStep::GetOutBus(_, _) => true,
+                RoutingStep::GetInTrain(_, _) => true,
+                RoutingStep::GetOutTrain(_, _) => true,
             };
         }
+
+        // drivers are spread over the period so that they don't all reroute at once
+        if let Some(RoutingStep::DriveTo(vehicle, _)) = h.router.cur_step {
+            if !cur_step_over
+                && (tick.0 as u64 + vehicle.data().as_ffi()).is_multiple_of(REROUTE_PERIOD)
+            {
+                if let Some(v) = world.vehicles.get_mut(vehicle) {
+                    v.it.reroute_if_slow(tick, map, times);
+                }
+            }
+        }
+
         let mut next_step_ready = true;
 
         if let Some(step) = h.router.steps.last() {
@@ -181,6 +362,37 @@ pub fn routing_update_system(world: &mut World, resources: &mut Resources) {
                     .map(|b| b.door_pos.is_close(pos, 3.0))
                     .unwrap_or(true),
                 RoutingStep::GetOutBuilding(_) => true,
+                RoutingStep::GetInBus(line, stop) => match transit.get(line) {
+                    Some(l) => transit.bus_at_stop(line, stop).is_some_and(|_| {
+                        l.stops
+                            .get(stop)
+                            .and_then(|s| s.walk_pos(map))
+                            .is_some_and(|p| p.is_close(pos, 10.0))
+                    }),
+                    None => true,
+                },
+                RoutingStep::GetOutBus(_, stop) => match h.location {
+                    Location::Vehicle(bus) => {
+                        transit.bus_info(bus).is_none() || transit.bus_stop(bus) == Some(stop)
+                    }
+                    _ => true,
+                },
+                RoutingStep::GetInTrain(line, station) => match rail.get(line) {
+                    Some(l) => rail.train_at_station(line, station).is_some_and(|_| {
+                        l.stations
+                            .get(station)
+                            .and_then(|&b| map.buildings().get(b))
+                            .is_some_and(|b| b.door_pos.is_close(pos, 10.0))
+                    }),
+                    None => true,
+                },
+                RoutingStep::GetOutTrain(_, station) => match h.location {
+                    Location::Train(train) => {
+                        rail.train_info(train).is_none()
+                            || rail.train_station(train) == Some(station)
+                    }
+                    _ => true,
+                },
             };
         }
 
@@ -196,9 +408,8 @@ pub fn routing_update_system(world: &mut World, resources: &mut Resources) {
                     h.it = Itinerary::wait_for_reroute(PathKind::Pedestrian, obj);
                 }
                 RoutingStep::DriveTo(vehicle, obj) => {
-                    let route = Itinerary::wait_for_reroute(PathKind::Vehicle, obj);
                     if let Some(x) = world.vehicles.get_mut(vehicle) {
-                        x.it = route
+                        x.it = Itinerary::wait_for_reroute(x.vehicle.kind.path_kind(), obj);
                     }
                 }
                 RoutingStep::Park(vehicle, ref mut spot) => {
@@ -240,6 +451,9 @@ pub fn routing_update_system(world: &mut World, resources: &mut Resources) {
                     }
                     h.location = Location::Building(build);
                     walk_inside(body, h, cbuf_human);
+                    if let Some(start) = h.router.trip_start.take() {
+                        h.router.last_trip = Some((build, start.elapsed(time) as f32));
+                    }
                 }
                 RoutingStep::GetOutBuilding(build) => {
                     let wpos = map
@@ -249,6 +463,39 @@ pub fn routing_update_system(world: &mut World, resources: &mut Resources) {
                         .unwrap_or(pos);
                     walk_outside(body, wpos, cbuf_human, &mut h.location);
                 }
+                RoutingStep::GetInBus(line, stop) => {
+                    let Some(bus) = transit.bus_at_stop(line, stop) else {
+                        h.router.reset_dest();
+                        return;
+                    };
+                    h.location = Location::Vehicle(bus);
+                    walk_inside(body, h, cbuf_human);
+                }
+                RoutingStep::GetOutBus(line, stop) => {
+                    let wpos = transit
+                        .get(line)
+                        .and_then(|l| l.stops.get(stop))
+                        .and_then(|s| s.walk_pos(map))
+                        .unwrap_or(pos);
+                    walk_outside(body, wpos, cbuf_human, &mut h.location);
+                }
+                RoutingStep::GetInTrain(line, station) => {
+                    let Some(train) = rail.train_at_station(line, station) else {
+                        h.router.reset_dest();
+                        return;
+                    };
+                    h.location = Location::Train(train);
+                    walk_inside(body, h, cbuf_human);
+                }
+                RoutingStep::GetOutTrain(line, station) => {
+                    let wpos = rail
+                        .get(line)
+                        .and_then(|l| l.stations.get(station))
+                        .and_then(|&b| map.buildings().get(b))
+                        .map(|b| b.door_pos)
+                        .unwrap_or(pos);
+                    walk_outside(body, wpos, cbuf_human, &mut h.location);
+                }
             }
         }
     })
@@ -293,15 +540,18 @@ fn park(map: &Map, vehicle: &mut VehicleEnt, spot_resa: SpotReservation) {
 }
 
 impl Router {
-    pub fn new(personal_car: Option<VehicleID>) -> Self {
+    pub fn new(personal_car: Option<VehicleID>, personal_bike: Option<VehicleID>) -> Self {
         Self {
             steps: vec![],
             cur_step: None,
             target_dest: None,
             personal_car,
+            personal_bike,
             vehicle: personal_car,
             cur_dest: None,
             last_error: None,
+            trip_start: None,
+            last_trip: None,
         }
     }
 
@@ -332,20 +582,56 @@ impl Router {
         false
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn steps_to(
         &mut self,
+        cur_pos: Vec3,
         obj: Vec3,
         parking: &mut ParkingManagement,
         map: &Map,
+        transit: &TransitLines,
+        rail: &PassengerLines,
         loc: &Location,
         cars: &HopSlotMap<VehicleID, VehicleEnt>,
+        trains: &HopSlotMap<TrainID, TrainEnt>,
     ) -> Result<Vec<RoutingStep>, RouterError> {
         let mut steps = vec![];
-        if let Location::Building(cur_build) = loc {
-            steps.push(RoutingStep::GetOutBuilding(*cur_build));
+        let mut start = cur_pos;
+        match *loc {
+            Location::Building(cur_build) => {
+                steps.push(RoutingStep::GetOutBuilding(cur_build));
+                if let Some(b) = map.buildings().get(cur_build) {
+                    start = b.door_pos;
+                }
+            }
+            Location::Vehicle(v) if Some(v) != self.vehicle && Some(v) != self.personal_bike => {
+                match transit.next_stop(v) {
+                    Some((line, stop)) => steps.push(RoutingStep::GetOutBus(line, stop)),
+                    None => steps.push(RoutingStep::GetOutVehicle(v)),
+                }
+                if let Some(x) = cars.get(v) {
+                    start = x.trans.position;
+                }
+            }
+            Location::Train(t) => {
+                if let Some((line, station)) = rail.next_station(t) {
+                    steps.push(RoutingStep::GetOutTrain(line, station));
+                }
+                if let Some(x) = trains.get(t) {
+                    start = x.trans.position;
+                }
+            }
+            _ => {}
         }
 
-        if let Some(car) = self.vehicle {
+        // the bike is left at home when using a work vehicle or already in the car
+        let bike = self
+            .personal_bike
+            .filter(|_| self.vehicle == self.personal_car)
+            .filter(|&bike| !matches!(*loc, Location::Vehicle(v) if v != bike))
+            .filter(|&bike| Self::bike_worth_it(bike, start, obj, loc, cars));
+
+        if let Some(car) = bike.or(self.vehicle) {
             let spot_resa = parking
                 .reserve_near(obj, map)
                 .map_err(RouterError::ReservingParkingSpot)?;
@@ -364,7 +650,11 @@ impl Router {
                     steps.push(RoutingStep::Unpark(car));
                 } else {
                     parking.free(spot_resa);
-                    self.vehicle = None;
+                    if bike.is_some() {
+                        self.personal_bike = None;
+                    } else {
+                        self.vehicle = None;
+                    }
                     return Err(RouterError::LocatingVehicle);
                 }
             }
@@ -372,9 +662,218 @@ impl Router {
             steps.push(RoutingStep::DriveTo(car, parking_pos));
             steps.push(RoutingStep::Park(car, Some(spot_resa)));
             steps.push(RoutingStep::GetOutVehicle(car));
+        } else {
+            // Take public transport only when it beats walking
+            let walk_time = start.distance(obj) / WALK_SPEED_ESTIMATE;
+            let legs = best_transit_trip(map, transit, rail, start, obj)
+                .filter(|&(t, _)| t < walk_time)
+                .map(|(_, legs)| legs)
+                .unwrap_or_default();
+
+            for leg in legs {
+                match leg {
+                    TransitLeg::Bus(trip) => {
+                        let line = &transit.lines()[trip.line];
+                        if let Some(walk_pos) = line.stops[trip.from].walk_pos(map) {
+                            steps.push(RoutingStep::WalkTo(walk_pos));
+                            steps.push(RoutingStep::GetInBus(trip.line, trip.from));
+                            steps.push(RoutingStep::GetOutBus(trip.line, trip.to));
+                        }
+                    }
+                    TransitLeg::Train(trip) => {
+                        let line = &rail.lines()[trip.line];
+                        if let Some(b) = map.buildings().get(line.stations[trip.from]) {
+                            steps.push(RoutingStep::WalkTo(b.door_pos));
+                            steps.push(RoutingStep::GetInTrain(trip.line, trip.from));
+                            steps.push(RoutingStep::GetOutTrain(trip.line, trip.to));
+                        }
+                    }
+                }
+            }
         }
 
         steps.push(RoutingStep::WalkTo(obj));
         Ok(steps)
     }
+
+    /// Whether the trip is worth biking rather than driving or walking:
+    /// not too short, not too long, and the bike is close by
+    fn bike_worth_it(
+        bike: VehicleID,
+        start: Vec3,
+        obj: Vec3,
+        loc: &Location,
+        cars: &HopSlotMap<VehicleID, VehicleEnt>,
+    ) -> bool {
+        let Some(bike_pos) = cars.get(bike).map(|x| x.trans.position) else {
+            return false;
+        };
+        if *loc == Location::Vehicle(bike) {
+            return true;
+        }
+        bike_pos.is_close(start, BIKE_MAX_FETCH_DIST)
+            && (BIKE_MIN_DIST..=BIKE_MAX_DIST).contains(&bike_pos.distance(obj))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{best_transit_trip, Router, RoutingStep, TransitLeg};
+    use crate::map::{LaneKind, LanePatternBuilder, MapProject};
+    use crate::map_dynamic::ParkingManagement;
+    use crate::tests::TestCtx;
+    use crate::transportation::passenger_rail::PassengerLines;
+    use crate::transportation::transit::{TransitLines, TransitStop};
+    use crate::transportation::{spawn_parked_vehicle, Location, VehicleKind};
+    use crate::world::VehicleID;
+    use crate::{BuildingKind, WorldCommand};
+    use common::descriptions::BuildingGen;
+    use geom::{vec2, vec3, Vec2, Vec3, OBB};
+
+    /// The vehicle driven to get to `obj`, if any
+    fn vehicle_used(
+        test: &TestCtx,
+        router: &mut Router,
+        start: Vec3,
+        obj: Vec3,
+    ) -> Option<VehicleID> {
+        let steps = router
+            .steps_to(
+                start,
+                obj,
+                &mut test.g.write::<ParkingManagement>(),
+                &test.g.map(),
+                &test.g.read::<TransitLines>(),
+                &test.g.read::<PassengerLines>(),
+                &Location::Outside,
+                &test.g.world().vehicles,
+                &test.g.world().trains,
+            )
+            .unwrap();
+        router.clear_steps(&mut test.g.write::<ParkingManagement>());
+        steps.iter().find_map(|s| match *s {
+            RoutingStep::DriveTo(v, _) => Some(v),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn test_bike_or_car() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(1000.0, 0.0, 0.0)]);
+        let start = vec3(50.0, 0.0, 0.0);
+        let car = spawn_parked_vehicle(&mut test.g, VehicleKind::Car, start).unwrap();
+        let bike = spawn_parked_vehicle(&mut test.g, VehicleKind::Bike, start).unwrap();
+        let start = test.g.world().vehicles[bike].trans.position;
+
+        let mut router = Router::new(Some(car), Some(bike));
+        // too close to bother getting the bike
+        assert_eq!(
+            vehicle_used(&test, &mut router, start, start + Vec3::x(100.0)),
+            Some(car)
+        );
+        // just right for the bike
+        assert_eq!(
+            vehicle_used(&test, &mut router, start, start + Vec3::x(800.0)),
+            Some(bike)
+        );
+        // the bike stays home when using another vehicle
+        router.use_vehicle(None);
+        assert_eq!(
+            vehicle_used(&test, &mut router, start, start + Vec3::x(800.0)),
+            None
+        );
+        router.use_vehicle(Some(car));
+        router.personal_bike = None;
+        assert_eq!(
+            vehicle_used(&test, &mut router, start, start + Vec3::x(800.0)),
+            Some(car)
+        );
+    }
+
+    #[test]
+    fn test_bus_to_train_station() {
+        let mut test = TestCtx::new();
+
+        test.g.map_mut().make_connection(
+            MapProject::ground(vec3(0.0, 0.0, 0.0)),
+            MapProject::ground(vec3(1000.0, 0.0, 0.0)),
+            None,
+            &LanePatternBuilder::new().rail(true).one_way(true).build(),
+        );
+        let mut stations = vec![];
+        for x in [100.0, 900.0] {
+            test.apply(&[WorldCommand::MapBuildSpecialBuilding {
+                pos: OBB::new(vec2(x, 30.0), Vec2::X, 40.0, 20.0),
+                kind: BuildingKind::TrainStation,
+                gen: BuildingGen::NoWalkway {
+                    door_pos: Vec2::ZERO,
+                },
+                zone: None,
+            }]);
+            let map = test.g.map();
+            let station = map
+                .buildings()
+                .values()
+                .find(|b| matches!(b.kind, BuildingKind::TrainStation) && !stations.contains(&b.id))
+                .unwrap()
+                .id;
+            drop(map);
+            stations.push(station);
+        }
+        test.apply(&[WorldCommand::MakePassengerLine {
+            stations,
+            headway: 30.0,
+        }]);
+        let rail_line = test
+            .g
+            .read::<PassengerLines>()
+            .lines()
+            .keys()
+            .next()
+            .unwrap();
+        test.apply(&[WorldCommand::AddPassengerTrain {
+            line: rail_line,
+            n_wagons: 2,
+        }]);
+
+        // a bus line brings people from far away to the first station
+        test.build_roads(&[vec3(150.0, 700.0, 0.0), vec3(150.0, 100.0, 0.0)]);
+        let map = test.g.map();
+        let lane = map
+            .lanes()
+            .values()
+            .find(|l| l.kind == LaneKind::Driving && l.points.first().y > l.points.last().y)
+            .unwrap();
+        let stops = vec![
+            TransitStop {
+                lane: lane.id,
+                dist: 10.0,
+            },
+            TransitStop {
+                lane: lane.id,
+                dist: lane.points.length() - 10.0,
+            },
+        ];
+        drop(map);
+        test.apply(&[WorldCommand::MakeTransitLine { stops }]);
+        let bus_line = test.g.read::<TransitLines>().lines().keys().next().unwrap();
+        test.apply(&[WorldCommand::AddBus { line: bus_line }]);
+
+        let map = test.g.map();
+        let (_, legs) = best_transit_trip(
+            &map,
+            &test.g.read::<TransitLines>(),
+            &test.g.read::<PassengerLines>(),
+            vec3(150.0, 700.0, 0.0),
+            vec3(900.0, 40.0, 0.0),
+        )
+        .unwrap();
+
+        assert!(
+            matches!(legs[..], [TransitLeg::Bus(bus), TransitLeg::Train(train)]
+                if bus.line == bus_line && train.line == rail_line && train.to == 1),
+            "{legs:?}"
+        );
+    }
 }
@@ -0,0 +1,335 @@
+use crate::economy::{ItemRegistry, Market};
+use crate::map::{BuildingID, BuildingKind, Map, UtilityKind};
+use crate::utils::resources::Resources;
+use crate::{SoulID, World};
+use common::descriptions::CompanyKind;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Productivity of a company that gets none of the utilities it needs
+pub const UNSERVED_PRODUCTIVITY: f32 = 0.5;
+
+/// Buildings connected by utility links of the same kind
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GridComponent {
+    pub kind: UtilityKind,
+    pub buildings: Vec<BuildingID>,
+    /// Units per second the producers can send through their links
+    pub supply: f32,
+    /// Units per second needed by the consumers
+    pub demand: f32,
+}
+
+impl GridComponent {
+    /// Fraction of the demand that is met
+    pub fn served(&self) -> f32 {
+        if self.demand <= 0.0 {
+            return 1.0;
+        }
+        (self.supply / self.demand).min(1.0)
+    }
+}
+
+/// Supply and demand of the utility networks, balanced each tick by [`utility_grid_system`].
+///
+/// A utility is only needed once a network company producing it exists,
+/// from then on the buildings that are not connected to a producer are unserved.
+#[derive(Default, Serialize, Deserialize)]
+pub struct UtilityGrid {
+    components: Vec<GridComponent>,
+    /// Fraction of its demand each consumer gets, for the utilities that are available
+    served: BTreeMap<(BuildingID, UtilityKind), f32>,
+}
+
+impl UtilityGrid {
+    pub fn components(&self) -> &[GridComponent] {
+        &self.components
+    }
+
+    /// Whether the city has utility links connecting some producers and consumers
+    pub fn has_network(&self) -> bool {
+        !self.components.is_empty()
+    }
+
+    pub fn component_of(&self, kind: UtilityKind, b: BuildingID) -> Option<&GridComponent> {
+        self.components
+            .iter()
+            .find(|c| c.kind == kind && c.buildings.contains(&b))
+    }
+
+    /// Fraction of the demand of the building that is met, 1 if it needs nothing
+    pub fn served(&self, b: BuildingID, kind: UtilityKind) -> f32 {
+        self.served.get(&(b, kind)).copied().unwrap_or(1.0)
+    }
+
+    /// Multiplier of the productivity of a company depending on how well it is served
+    pub fn productivity_factor(&self, b: BuildingID) -> f32 {
+        UtilityKind::ALL
+            .iter()
+            .map(|&kind| {
+                UNSERVED_PRODUCTIVITY + (1.0 - UNSERVED_PRODUCTIVITY) * self.served(b, kind)
+            })
+            .product()
+    }
+
+    fn update(&mut self, world: &World, map: &Map, market: &Market, registry: &ItemRegistry) {
+        self.components.clear();
+        self.served.clear();
+
+        for kind in UtilityKind::ALL {
+            let Some(item) = registry.try_id(kind.item_name()) else {
+                continue;
+            };
+
+            let mut supply = BTreeMap::new();
+            let mut demand = BTreeMap::new();
+            for (id, c) in world.companies.iter() {
+                let comp = &c.comp;
+                // the company is removed later in the tick
+                let Some(b) = map.buildings.get(comp.building) else {
+                    continue;
+                };
+                if !matches!(comp.kind, CompanyKind::Network) {
+                    if comp.max_workers > 0 {
+                        demand.insert(
+                            comp.building,
+                            kind.worker_demand() * comp.max_workers as f32,
+                        );
+                    }
+                    continue;
+                }
+                let Some(&(_, qty)) = comp.recipe.production.iter().find(|(i, _)| *i == item)
+                else {
+                    continue;
+                };
+                let rate = if comp.recipe.has_inputs(SoulID::GoodsCompany(id), market) {
+                    qty as f32 / comp.recipe.complexity as f32
+                        * comp.productivity(c.workers.0.len(), b.zone.as_ref(), 1.0)
+                } else {
+                    0.0
+                };
+                supply.insert(comp.building, rate);
+            }
+
+            if supply.is_empty() {
+                continue;
+            }
+
+            for b in map.buildings.values() {
+                if b.kind == BuildingKind::House {
+                    demand.insert(b.id, kind.house_demand());
+                }
+            }
+
+            // union find over the links of this kind
+            let mut parent: BTreeMap<BuildingID, BuildingID> = BTreeMap::new();
+            let mut capacity: BTreeMap<BuildingID, f32> = BTreeMap::new();
+            fn find(parent: &mut BTreeMap<BuildingID, BuildingID>, b: BuildingID) -> BuildingID {
+                let p = *parent.entry(b).or_insert(b);
+                if p == b {
+                    return b;
+                }
+                let root = find(parent, p);
+                parent.insert(b, root);
+                root
+            }
+            for link in map.utility_links.values().filter(|l| l.kind == kind) {
+                *capacity.entry(link.src).or_default() += link.capacity;
+                *capacity.entry(link.dst).or_default() += link.capacity;
+                let (a, b) = (find(&mut parent, link.src), find(&mut parent, link.dst));
+                parent.insert(a, b);
+            }
+
+            let mut groups: BTreeMap<BuildingID, Vec<BuildingID>> = BTreeMap::new();
+            let linked: Vec<BuildingID> = parent.keys().copied().collect();
+            for b in linked {
+                let root = find(&mut parent, b);
+                groups.entry(root).or_default().push(b);
+            }
+
+            // the unconnected consumers get nothing
+            for &b in demand.keys() {
+                self.served.insert((b, kind), 0.0);
+            }
+
+            for buildings in groups.into_values() {
+                // the producers cannot send more than what their own links carry
+                let cap = |b: &BuildingID| capacity.get(b).copied().unwrap_or(0.0);
+                let component = GridComponent {
+                    kind,
+                    supply: buildings
+                        .iter()
+                        .filter_map(|b| Some(supply.get(b)?.min(cap(b))))
+                        .sum(),
+                    demand: buildings.iter().filter_map(|b| demand.get(b)).sum(),
+                    buildings,
+                };
+
+                let served = component.served();
+                for b in &component.buildings {
+                    if let Some(&d) = demand.get(b) {
+                        self.served.insert((*b, kind), served.min(cap(b) / d));
+                    }
+                }
+                self.components.push(component);
+            }
+        }
+    }
+}
+
+pub fn utility_grid_system(world: &mut World, res: &mut Resources) {
+    profiling::scope!("map_dynamic::utility_grid_system");
+    let map = res.read::<Map>();
+    let market = res.read::<Market>();
+    let registry = res.read::<ItemRegistry>();
+    res.write::<UtilityGrid>()
+        .update(world, &map, &market, &registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UtilityGrid;
+    use crate::economy::Government;
+    use crate::map::{BuildingID, BuildingKind, UtilityKind};
+    use crate::souls::goods_company::GoodsCompanyRegistry;
+    use crate::souls::human::spawn_human;
+    use crate::tests::TestCtx;
+    use crate::WorldCommand;
+    use common::descriptions::BuildingGen;
+    use geom::{vec2, vec3, Vec2, OBB};
+
+    fn build_company(test: &mut TestCtx, name: &str, pos: Vec2) -> BuildingID {
+        let registry = test.g.read::<GoodsCompanyRegistry>();
+        let (id, _) = registry
+            .descriptions
+            .iter()
+            .find(|(_, d)| d.name == name)
+            .unwrap();
+        drop(registry);
+        test.apply(&[WorldCommand::MapBuildSpecialBuilding {
+            pos: OBB::new(pos, vec2(1.0, 0.0), 10.0, 10.0),
+            kind: BuildingKind::GoodsCompany(id),
+            gen: BuildingGen::NoWalkway {
+                door_pos: pos + vec2(0.0, 10.0),
+            },
+            zone: None,
+        }]);
+        test.g
+            .map()
+            .buildings()
+            .values()
+            .find(|b| b.kind == BuildingKind::GoodsCompany(id))
+            .unwrap()
+            .id
+    }
+
+    fn link(src: BuildingID, dst: BuildingID, capacity: f32) -> WorldCommand {
+        WorldCommand::MapMakeUtilityLink {
+            kind: UtilityKind::Water,
+            src,
+            dst,
+            capacity,
+        }
+    }
+
+    #[test]
+    fn test_grid_serves_linked_buildings() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0., 0., 0.), vec3(300., 0., 0.)]);
+        let house = test.build_house_near(vec2(250.0, 50.0));
+        let factory = build_company(&mut test, "Flour Factory", vec2(150.0, -25.0));
+        test.tick();
+
+        // nothing is needed until there is a water pump
+        assert_eq!(
+            test.g.read::<UtilityGrid>().productivity_factor(factory),
+            1.0
+        );
+
+        let pump = build_company(&mut test, "Water pump", vec2(50.0, -25.0));
+        // the company is created at the end of the tick
+        test.tick();
+        test.tick();
+        {
+            let grid = test.g.read::<UtilityGrid>();
+            assert_eq!(grid.served(house, UtilityKind::Water), 0.0);
+            assert_eq!(
+                grid.productivity_factor(factory),
+                super::UNSERVED_PRODUCTIVITY
+            );
+            assert_eq!(grid.served(house, UtilityKind::Electricity), 1.0);
+        }
+
+        let workers: Vec<_> = (0..2)
+            .map(|_| spawn_human(&mut test.g, house).unwrap())
+            .collect();
+        for (_, c) in test.g.world_mut_unchecked().companies.iter_mut() {
+            if c.comp.building == pump {
+                c.workers.0 = workers.clone();
+            }
+        }
+        test.apply(&[link(pump, house, 10.0), link(house, factory, 10.0)]);
+        test.tick();
+        {
+            let grid = test.g.read::<UtilityGrid>();
+            let c = grid.component_of(UtilityKind::Water, factory).unwrap();
+            assert_eq!(c.buildings.len(), 3);
+            assert!(c.supply > c.demand);
+            assert_eq!(grid.served(house, UtilityKind::Water), 1.0);
+            assert_eq!(grid.productivity_factor(factory), 1.0);
+        }
+
+        // the pump cannot send more than its link carries
+        test.apply(&[
+            WorldCommand::MapRemoveUtilityLink {
+                kind: UtilityKind::Water,
+                src: house,
+                dst: pump,
+            },
+            link(pump, house, 0.01),
+        ]);
+        test.tick();
+        {
+            let grid = test.g.read::<UtilityGrid>();
+            let served = grid.served(factory, UtilityKind::Water);
+            assert!(served > 0.0 && served < 0.2);
+        }
+
+        test.g.map_mut().remove_building(pump);
+        test.tick();
+        assert_eq!(test.g.map().utility_links().len(), 1);
+        assert_eq!(
+            test.g.read::<UtilityGrid>().productivity_factor(factory),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_utility_link_commands() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0., 0., 0.), vec3(300., 0., 0.)]);
+        let a = test.build_house_near(vec2(50.0, 50.0));
+        let b = test.build_house_near(vec2(250.0, 50.0));
+        let far = build_company(&mut test, "Flour Factory", vec2(900.0, 50.0));
+
+        let money = test.g.read::<Government>().money;
+        test.apply(&[link(a, b, 10.0), link(b, a, 10.0), link(a, far, 10.0)]);
+        assert_eq!(test.g.map().utility_links().len(), 1);
+        assert!(test.g.read::<Government>().money < money);
+
+        test.apply(&[WorldCommand::MapRemoveUtilityLink {
+            kind: UtilityKind::Water,
+            src: b,
+            dst: a,
+        }]);
+        assert!(test.g.map().utility_links().is_empty());
+
+        test.apply(&[WorldCommand::Undo]);
+        let map = test.g.map();
+        let id = map.find_utility_link(UtilityKind::Water, a, b).unwrap();
+        assert_eq!(map.utility_links()[id].capacity, 10.0);
+        assert!(map
+            .find_utility_link(UtilityKind::Electricity, a, b)
+            .is_none());
+    }
+}
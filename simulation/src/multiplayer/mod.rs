@@ -2,6 +2,9 @@ use crate::multiplayer::chat::Chat;
 use serde::{Deserialize, Serialize};
 
 pub mod chat;
+mod players;
+
+pub use players::*;
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct MultiplayerState {
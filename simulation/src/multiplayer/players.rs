@@ -0,0 +1,313 @@
+use crate::economy::{BudgetCategory, Government, Money};
+use crate::engine_interaction::WorldCommand;
+use crate::map::{BuildingID, IntersectionID, Map, MapProject, ProjectKind, RoadID};
+use crate::world::CompanyID;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Money given to a player the first time one of their commands is applied
+pub const PLAYER_STARTING_MONEY: Money = Money::new_bucks(50_000);
+
+/// Identifies a player, it is the id given by the server to the connected clients.
+/// In singleplayer every command comes from the host.
+#[derive(
+    Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct PlayerID(pub u32);
+
+debug_inspect_impl!(PlayerID);
+
+impl PlayerID {
+    /// The player hosting the game, it uses the treasury of the [`Government`]
+    pub const HOST: PlayerID = PlayerID(0);
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Player {
+    pub money: Money,
+}
+
+impl Default for Player {
+    fn default() -> Self {
+        Self {
+            money: PLAYER_STARTING_MONEY,
+        }
+    }
+}
+
+/// Which player built what. Assets built by the simulation itself have no owner.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Ownership {
+    roads: BTreeMap<RoadID, PlayerID>,
+    buildings: BTreeMap<BuildingID, PlayerID>,
+    companies: BTreeMap<CompanyID, PlayerID>,
+}
+
+impl Ownership {
+    pub fn road(&self, id: RoadID) -> Option<PlayerID> {
+        self.roads.get(&id).copied()
+    }
+
+    pub fn building(&self, id: BuildingID) -> Option<PlayerID> {
+        self.buildings.get(&id).copied()
+    }
+
+    pub fn company(&self, id: CompanyID) -> Option<PlayerID> {
+        self.companies.get(&id).copied()
+    }
+
+    pub fn set_building(&mut self, id: BuildingID, player: PlayerID) {
+        self.buildings.insert(id, player);
+    }
+
+    /// A company inherits the owner of its building
+    pub fn set_company(&mut self, id: CompanyID, building: BuildingID) {
+        if let Some(owner) = self.building(building) {
+            self.companies.insert(id, owner);
+        }
+    }
+
+    pub fn remove_company(&mut self, id: CompanyID) {
+        self.companies.remove(&id);
+    }
+
+    /// Records a road built by `make_connection` from `from` to `to`.
+    /// The halves of a road split by the connection keep the owner of the split road.
+    pub fn set_connection(
+        &mut self,
+        map: &Map,
+        from: &MapProject,
+        to: &MapProject,
+        road: RoadID,
+        player: PlayerID,
+    ) {
+        let Some(r) = map.roads().get(road) else {
+            return;
+        };
+        self.roads.insert(road, player);
+        for (proj, inter) in [(from, r.src), (to, r.dst)] {
+            let ProjectKind::Road(split) = proj.kind else {
+                continue;
+            };
+            let Some(owner) = self.roads.remove(&split) else {
+                continue;
+            };
+            for &half in &map.intersections()[inter].roads {
+                if half != road {
+                    self.roads.entry(half).or_insert(owner);
+                }
+            }
+        }
+    }
+
+    /// Gives an object built again by an undo back to the player who owned it
+    pub(crate) fn restore(&mut self, kind: ProjectKind, owner: Option<PlayerID>) {
+        match (kind, owner) {
+            (ProjectKind::Road(id), Some(owner)) => {
+                self.roads.insert(id, owner);
+            }
+            (ProjectKind::Road(id), None) => {
+                self.roads.remove(&id);
+            }
+            (ProjectKind::Building(id), Some(owner)) => {
+                self.buildings.insert(id, owner);
+            }
+            (ProjectKind::Building(id), None) => {
+                self.buildings.remove(&id);
+            }
+            _ => {}
+        }
+    }
+
+    /// The player who owns the object, if it is a road or a building
+    pub fn owner(&self, kind: ProjectKind) -> Option<PlayerID> {
+        match kind {
+            ProjectKind::Road(id) => self.road(id),
+            ProjectKind::Building(id) => self.building(id),
+            _ => None,
+        }
+    }
+
+    /// Forgets the assets that are not in the map anymore
+    pub fn clean(&mut self, map: &Map) {
+        self.roads.retain(|id, _| map.roads().contains_key(*id));
+        self.buildings
+            .retain(|id, _| map.buildings().contains_key(*id));
+    }
+
+    fn can_remove_road(&self, player: PlayerID, id: RoadID) -> bool {
+        self.road(id).is_none_or(|owner| owner == player)
+    }
+
+    fn can_remove_intersection(&self, map: &Map, player: PlayerID, id: IntersectionID) -> bool {
+        map.intersections()
+            .get(id)
+            .is_none_or(|i| i.roads.iter().all(|&r| self.can_remove_road(player, r)))
+    }
+}
+
+/// The players of the game, with their own treasury and the assets they built
+#[derive(Default, Serialize, Deserialize)]
+pub struct Players {
+    players: BTreeMap<PlayerID, Player>,
+    /// The player who issued the commands being applied, reset to the host every tick
+    acting: PlayerID,
+    /// If true, players cannot bulldoze the assets built by another player
+    pub protect_assets: bool,
+    pub owners: Ownership,
+}
+
+impl Players {
+    pub fn acting(&self) -> PlayerID {
+        self.acting
+    }
+
+    pub(crate) fn set_acting(&mut self, player: PlayerID) {
+        self.acting = player;
+        if player != PlayerID::HOST {
+            self.players.entry(player).or_default();
+        }
+    }
+
+    pub fn get(&self, player: PlayerID) -> Option<&Player> {
+        self.players.get(&player)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&PlayerID, &Player)> {
+        self.players.iter()
+    }
+
+    /// The money available to a player, the host spends the money of the government
+    pub fn money(&self, player: PlayerID, gvt: &Government) -> Money {
+        if player == PlayerID::HOST {
+            return gvt.money;
+        }
+        self.players
+            .get(&player)
+            .map_or(PLAYER_STARTING_MONEY, |p| p.money)
+    }
+
+    /// Whether the acting player has enough money for a command.
+    /// Only the players other than the host are limited, the government can go in debt.
+    pub fn can_afford(&self, gvt: &Government, cost: Money) -> bool {
+        self.acting == PlayerID::HOST || cost <= Money::ZERO || self.money(self.acting, gvt) >= cost
+    }
+
+    /// Charges the cost of a command to the acting player
+    pub fn charge(&mut self, gvt: &mut Government, cost: Money) {
+        if self.acting == PlayerID::HOST {
+            gvt.transaction(BudgetCategory::Construction, -cost);
+            return;
+        }
+        self.players.entry(self.acting).or_default().money -= cost;
+    }
+
+    /// Returns false if the acting player is not allowed to apply the command
+    pub fn is_allowed(&self, cmd: &WorldCommand, map: &Map) -> bool {
+        let player = self.acting;
+        match *cmd {
+            WorldCommand::SetAssetProtection(_) => player == PlayerID::HOST,
+            _ if !self.protect_assets => true,
+            WorldCommand::MapRemoveRoad(id)
+            | WorldCommand::MapSetRoadRestrictions { road: id, .. }
+            | WorldCommand::MapSetOneWay { road: id, .. }
+            | WorldCommand::MapSetRoadPattern { road: id, .. } => {
+                self.owners.can_remove_road(player, id)
+            }
+            WorldCommand::MapRemoveIntersection(id) => {
+                self.owners.can_remove_intersection(map, player, id)
+            }
+            WorldCommand::MapRemoveBuilding(id) => {
+                self.owners.building(id).is_none_or(|owner| owner == player)
+            }
+            // a line can be removed by the owner of either end
+            WorldCommand::MapRemoveUtilityLink { src, dst, .. } => [src, dst]
+                .iter()
+                .any(|&b| self.owners.building(b).is_none_or(|owner| owner == player)),
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PlayerID, Players, PLAYER_STARTING_MONEY};
+    use crate::economy::{Government, Money};
+    use crate::engine_interaction::WorldCommand;
+    use crate::map::{LanePatternBuilder, MapProject};
+    use crate::tests::TestCtx;
+    use geom::vec3;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn test_ownership_and_treasuries() {
+        let mut test = TestCtx::new();
+        let gvt_money = test.g.read::<Government>().money;
+        let before: BTreeSet<_> = test.g.map().roads().keys().collect();
+
+        test.apply(&[
+            WorldCommand::ActAs(PlayerID(1)),
+            WorldCommand::MapMakeConnection {
+                from: MapProject::ground(vec3(0.0, 0.0, 0.0)),
+                to: MapProject::ground(vec3(300.0, 0.0, 0.0)),
+                inter: None,
+                pat: LanePatternBuilder::new().build(),
+            },
+        ]);
+
+        let road = test
+            .g
+            .map()
+            .roads()
+            .keys()
+            .find(|r| !before.contains(r))
+            .unwrap();
+        {
+            let players = test.g.read::<Players>();
+            assert_eq!(players.owners.road(road), Some(PlayerID(1)));
+            assert!(players.get(PlayerID(1)).unwrap().money < PLAYER_STARTING_MONEY);
+        }
+        assert_eq!(test.g.read::<Government>().money, gvt_money);
+
+        test.apply(&[
+            WorldCommand::ActAs(PlayerID(2)),
+            WorldCommand::SetAssetProtection(true),
+            WorldCommand::ActAs(PlayerID::HOST),
+            WorldCommand::SetAssetProtection(true),
+            WorldCommand::ActAs(PlayerID(2)),
+            WorldCommand::MapRemoveRoad(road),
+        ]);
+        assert!(test.g.read::<Players>().protect_assets);
+        assert!(test.g.map().roads().contains_key(road));
+
+        test.apply(&[
+            WorldCommand::ActAs(PlayerID(1)),
+            WorldCommand::MapRemoveRoad(road),
+        ]);
+        assert!(!test.g.map().roads().contains_key(road));
+
+        // a player without money cannot build anymore
+        test.apply(&[WorldCommand::ActAs(PlayerID(3))]);
+        test.g
+            .write::<Players>()
+            .players
+            .get_mut(&PlayerID(3))
+            .unwrap()
+            .money = Money::ZERO;
+        let n_roads = test.g.map().roads().len();
+        test.apply(&[
+            WorldCommand::ActAs(PlayerID(3)),
+            WorldCommand::MapMakeConnection {
+                from: MapProject::ground(vec3(0.0, 100.0, 0.0)),
+                to: MapProject::ground(vec3(300.0, 100.0, 0.0)),
+                inter: None,
+                pat: LanePatternBuilder::new().build(),
+            },
+        ]);
+        assert_eq!(test.g.map().roads().len(), n_roads);
+        assert_eq!(
+            test.g.read::<Players>().get(PlayerID(3)).unwrap().money,
+            Money::ZERO
+        );
+    }
+}
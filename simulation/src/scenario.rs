@@ -0,0 +1,330 @@
+use crate::economy::{BudgetCategory, EcoStats, Government, ItemRegistry, Money};
+use crate::engine_interaction::WorldCommand;
+use crate::multiplayer::chat::{Message, MessageKind};
+use crate::multiplayer::{MultiplayerState, PlayerID, Players};
+use crate::utils::time::{GameTime, SECONDS_PER_HOUR};
+use crate::{Simulation, SimulationOptions};
+use common::saveload::Encoder;
+use geom::Color;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Where the scenarios shipped with the game are
+pub const SCENARIOS_DIR: &str = "assets/scenarios";
+
+/// A scripted game: how the city starts, what happens during the game and how to win or lose.
+/// Scenarios are written in JSON, see `assets/scenarios` for examples.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub options: SimulationOptions,
+    /// Money of the government at the start, in bucks
+    #[serde(default)]
+    pub starting_money: Option<i64>,
+    /// If true, the default starting layout of a new game is built before the commands
+    #[serde(default = "default_true")]
+    pub default_layout: bool,
+    /// Applied once after the default layout
+    #[serde(default)]
+    pub commands: Vec<WorldCommand>,
+    #[serde(default)]
+    pub triggers: Vec<Trigger>,
+    #[serde(default)]
+    pub win: Option<Condition>,
+    #[serde(default)]
+    pub lose: Option<Condition>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Actions applied once, the first time the condition is met
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trigger {
+    pub when: Condition,
+    pub actions: Vec<TriggerAction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// Game hours elapsed since the scenario started
+    ElapsedHours(f64),
+    /// Money of the government, in bucks
+    MoneyAbove(i64),
+    MoneyBelow(i64),
+    PopulationAbove(u32),
+    PopulationBelow(u32),
+    /// Exports of an item during the last complete game hour
+    ExportsAbove {
+        item: String,
+        qty: i64,
+    },
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerAction {
+    /// Shows a message in the chat
+    Message(String),
+    /// Gives money to the government, in bucks
+    Grant(i64),
+    /// Applies a command as if the host sent it, without charging it
+    Command(WorldCommand),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScenarioOutcome {
+    Won,
+    Lost,
+}
+
+/// The scenario being played, if any
+#[derive(Default, Serialize, Deserialize)]
+pub struct ScenarioState {
+    pub scenario: Option<Scenario>,
+    /// Whether each trigger was already fired
+    fired: Vec<bool>,
+    /// Timestamp at which the scenario started
+    started_at: f64,
+    pub outcome: Option<ScenarioOutcome>,
+}
+
+impl Scenario {
+    pub fn load(path: impl AsRef<Path>) -> Option<Scenario> {
+        let path = path.as_ref();
+        let source = common::saveload::load_string(path)
+            .map_err(|e| log::error!("could not read scenario {}: {}", path.display(), e))
+            .ok()?;
+        common::saveload::JSON::decode(source.as_bytes())
+            .map_err(|e| log::error!("could not decode scenario {}: {}", path.display(), e))
+            .ok()
+    }
+
+    /// Lists the scenario files of [`SCENARIOS_DIR`], sorted by path
+    pub fn list() -> Vec<PathBuf> {
+        let Ok(dir) = std::fs::read_dir(SCENARIOS_DIR) else {
+            return vec![];
+        };
+        let mut paths: Vec<PathBuf> = dir
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Builds the starting city and starts tracking the triggers and goals
+    pub(crate) fn start(&self, sim: &mut Simulation) {
+        let player = sim.read::<Players>().acting();
+        sim.write::<Players>().set_acting(PlayerID::HOST);
+
+        if self.default_layout {
+            for command in crate::start_commands() {
+                command.execute(sim);
+            }
+        }
+        for command in &self.commands {
+            command.execute(sim);
+        }
+        if let Some(money) = self.starting_money {
+            sim.write::<Government>().money = Money::new_bucks(money);
+        }
+
+        sim.write::<Players>().set_acting(player);
+
+        let started_at = sim.read::<GameTime>().timestamp;
+        *sim.write::<ScenarioState>() = ScenarioState {
+            fired: vec![false; self.triggers.len()],
+            scenario: Some(self.clone()),
+            started_at,
+            outcome: None,
+        };
+
+        if !self.description.is_empty() {
+            post_message(sim, self.description.clone(), MessageKind::Info);
+        }
+    }
+}
+
+impl Condition {
+    pub fn eval(&self, sim: &Simulation, started_at: f64) -> bool {
+        match *self {
+            Condition::ElapsedHours(hours) => {
+                sim.read::<GameTime>().timestamp - started_at >= hours * SECONDS_PER_HOUR as f64
+            }
+            Condition::MoneyAbove(bucks) => {
+                sim.read::<Government>().money > Money::new_bucks(bucks)
+            }
+            Condition::MoneyBelow(bucks) => {
+                sim.read::<Government>().money < Money::new_bucks(bucks)
+            }
+            Condition::PopulationAbove(n) => sim.world.humans.len() > n as usize,
+            Condition::PopulationBelow(n) => sim.world.humans.len() < n as usize,
+            Condition::ExportsAbove { ref item, qty } => {
+                let Some(item) = sim.read::<ItemRegistry>().try_id(item) else {
+                    return false;
+                };
+                sim.read::<EcoStats>().exports.last_complete(1, item) > qty
+            }
+            Condition::All(ref conds) => conds.iter().all(|c| c.eval(sim, started_at)),
+            Condition::Any(ref conds) => conds.iter().any(|c| c.eval(sim, started_at)),
+        }
+    }
+}
+
+impl TriggerAction {
+    fn apply(&self, sim: &mut Simulation) {
+        match *self {
+            TriggerAction::Message(ref text) => post_message(sim, text.clone(), MessageKind::Info),
+            TriggerAction::Grant(bucks) => {
+                sim.write::<Government>()
+                    .transaction(BudgetCategory::Grants, Money::new_bucks(bucks));
+            }
+            TriggerAction::Command(ref cmd) => {
+                let player = sim.read::<Players>().acting();
+                sim.write::<Players>().set_acting(PlayerID::HOST);
+                cmd.execute(sim);
+                sim.write::<Players>().set_acting(player);
+            }
+        }
+    }
+}
+
+fn post_message(sim: &mut Simulation, text: String, kind: MessageKind) {
+    let sent_at = sim.read::<GameTime>().instant();
+    let color = match kind {
+        MessageKind::Warning => Color::ORANGE,
+        _ => Color::CYAN,
+    };
+    sim.write::<MultiplayerState>().chat.add_message(Message {
+        name: "Scenario".to_string(),
+        text,
+        sent_at,
+        color,
+        kind,
+    });
+}
+
+/// Fires the triggers whose condition is met and checks the win and lose conditions
+pub fn scenario_system(sim: &mut Simulation) {
+    profiling::scope!("scenario::scenario_system");
+    let state = sim.read::<ScenarioState>();
+    let Some(ref scenario) = state.scenario else {
+        return;
+    };
+    if state.outcome.is_some() {
+        return;
+    }
+
+    let fired: Vec<usize> = scenario
+        .triggers
+        .iter()
+        .enumerate()
+        .filter(|(i, t)| !state.fired[*i] && t.when.eval(sim, state.started_at))
+        .map(|(i, _)| i)
+        .collect();
+    let actions: Vec<TriggerAction> = fired
+        .iter()
+        .flat_map(|&i| scenario.triggers[i].actions.iter().cloned())
+        .collect();
+
+    // losing takes precedence if both happen on the same tick
+    let outcome = if scenario
+        .lose
+        .as_ref()
+        .is_some_and(|c| c.eval(sim, state.started_at))
+    {
+        Some(ScenarioOutcome::Lost)
+    } else if scenario
+        .win
+        .as_ref()
+        .is_some_and(|c| c.eval(sim, state.started_at))
+    {
+        Some(ScenarioOutcome::Won)
+    } else {
+        None
+    };
+    let name = scenario.name.clone();
+    drop(state);
+
+    {
+        let mut state = sim.write::<ScenarioState>();
+        for i in fired {
+            state.fired[i] = true;
+        }
+    }
+    for action in &actions {
+        action.apply(sim);
+    }
+
+    let Some(outcome) = outcome else {
+        return;
+    };
+    sim.write::<ScenarioState>().outcome = Some(outcome);
+    log::info!("scenario {} finished: {:?}", name, outcome);
+    match outcome {
+        ScenarioOutcome::Won => {
+            post_message(sim, format!("Scenario \"{name}\" won!"), MessageKind::Info)
+        }
+        ScenarioOutcome::Lost => post_message(
+            sim,
+            format!("Scenario \"{name}\" lost."),
+            MessageKind::Warning,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Condition, Scenario, ScenarioOutcome, ScenarioState, Trigger, TriggerAction};
+    use crate::economy::{Government, Money};
+    use crate::engine_interaction::WorldCommand;
+    use crate::tests::TestCtx;
+
+    #[test]
+    fn test_shipped_scenarios_decode() {
+        for entry in std::fs::read_dir("../assets/scenarios").unwrap() {
+            let path = entry.unwrap().path();
+            assert!(Scenario::load(&path).is_some(), "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn test_scenario_triggers_and_outcome() {
+        let mut test = TestCtx::new();
+
+        let scenario = Scenario {
+            name: "test".to_string(),
+            description: String::new(),
+            options: Default::default(),
+            starting_money: Some(1000),
+            default_layout: false,
+            commands: vec![],
+            triggers: vec![Trigger {
+                when: Condition::ElapsedHours(0.0),
+                actions: vec![TriggerAction::Grant(5000)],
+            }],
+            win: Some(Condition::MoneyAbove(5500)),
+            lose: Some(Condition::MoneyBelow(0)),
+        };
+        test.apply(&[WorldCommand::StartScenario(Box::new(scenario))]);
+        assert_eq!(test.g.read::<Government>().money, Money::new_bucks(1000));
+
+        test.tick();
+        assert!(test.g.read::<Government>().money > Money::new_bucks(5500));
+        assert_eq!(test.g.read::<ScenarioState>().outcome, None);
+
+        test.tick();
+        assert_eq!(
+            test.g.read::<ScenarioState>().outcome,
+            Some(ScenarioOutcome::Won)
+        );
+    }
+}
@@ -37,6 +37,11 @@ impl BuyFood {
         }
     }
 
+    /// The item and quantity bought when this desire is chosen next, if it still has to buy
+    pub fn pending_purchase(&self) -> Option<(ItemID, u32)> {
+        matches!(self.state, BuyFoodState::Empty).then_some((self.bread, 1))
+    }
+
     pub fn score(&self, time: &GameTime, loc: &Location, bought: &Bought) -> f32 {
         if matches!(self.state, BuyFoodState::WaitingForTrade)
             && bought
@@ -0,0 +1,360 @@
+use crate::economy::{find_trade_place, Bought, ItemID, ItemRegistry, Market};
+use crate::map::BuildingID;
+use crate::map_dynamic::{BuildingInfos, Destination};
+use crate::souls::human::HumanDecisionKind;
+use crate::transportation::Location;
+use crate::utils::resources::Resources;
+use crate::utils::time::{GameInstant, GameTime, SECONDS_PER_HOUR};
+use crate::world::{HumanEnt, HumanID};
+use crate::{BuildingKind, GoodsCompanyRegistry, Map, ParCommandBuffer, SoulID, World};
+use common::descriptions::{DesireDescriptionJSON, DesireTarget};
+use common::saveload::Encoder;
+use egui_inspect::Inspect;
+use geom::Transform;
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use slotmapd::{new_key_type, SlotMap};
+
+#[cfg(not(test))]
+const DESIRES_PATH: &str = "assets/desires.json";
+#[cfg(test)]
+const DESIRES_PATH: &str = "../assets/desires.json";
+
+/// The shipped desires, used when the asset file cannot be read or decoded
+const BUILTIN_DESIRES: &str = include_str!("../../../../assets/desires.json");
+
+new_key_type! {
+    pub struct DesireID;
+}
+
+debug_inspect_impl!(DesireID);
+
+/// A desire defined in data, see `assets/desires.json`
+#[derive(Debug)]
+pub struct DesireDescription {
+    pub id: DesireID,
+    pub name: String,
+    pub item: ItemID,
+    pub qty: u32,
+    pub curve: Vec<(f32, f32)>,
+    pub target: DesirePlace,
+}
+
+/// Where a desire is satisfied, resolved from the [`DesireTarget`] of its description
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DesirePlace {
+    Seller,
+    Home,
+    /// The nearest building of this kind, or the seller if there are none
+    Building(BuildingKind),
+}
+
+impl DesireDescription {
+    /// Evaluates the score curve, constant before the first point and after the last one
+    pub fn curve_score(&self, hours: f32) -> f32 {
+        let (Some(&first), Some(&last)) = (self.curve.first(), self.curve.last()) else {
+            return 0.0;
+        };
+        if hours <= first.0 {
+            return first.1;
+        }
+        for w in self.curve.windows(2) {
+            let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+            if hours <= x1 {
+                return y0 + (y1 - y0) * (hours - x0) / (x1 - x0).max(f32::EPSILON);
+            }
+        }
+        last.1
+    }
+}
+
+#[derive(Default)]
+pub struct DesireRegistry {
+    pub descriptions: SlotMap<DesireID, DesireDescription>,
+}
+
+impl DesireRegistry {
+    pub fn load(
+        &mut self,
+        source: &str,
+        registry: &ItemRegistry,
+        companies: &GoodsCompanyRegistry,
+    ) {
+        let descriptions: Vec<DesireDescriptionJSON> =
+            match common::saveload::JSON::decode(source.as_ref()) {
+                Ok(x) => x,
+                Err(e) => {
+                    log::error!(
+                        "couldn't load desire descriptions, using the built-in ones: {}",
+                        e
+                    );
+                    common::saveload::JSON::decode(BUILTIN_DESIRES.as_ref())
+                        .expect("built-in desires should decode")
+                }
+            };
+
+        for descr in descriptions {
+            let Some(item) = registry.try_id(&descr.item) else {
+                log::error!("desire {} uses unknown item {}", descr.name, descr.item);
+                continue;
+            };
+            let target = match descr.target {
+                DesireTarget::Seller => DesirePlace::Seller,
+                DesireTarget::Home => DesirePlace::Home,
+                DesireTarget::Building(ref company) => {
+                    let Some(id) = companies
+                        .descriptions
+                        .iter()
+                        .find_map(|(id, c)| (&c.name == company).then_some(id))
+                    else {
+                        log::error!("desire {} targets unknown company {}", descr.name, company);
+                        continue;
+                    };
+                    DesirePlace::Building(BuildingKind::GoodsCompany(id))
+                }
+            };
+            self.descriptions
+                .insert_with_key(move |id| DesireDescription {
+                    id,
+                    name: descr.name,
+                    item,
+                    qty: descr.qty,
+                    curve: descr.curve,
+                    target,
+                });
+        }
+    }
+}
+
+pub fn init_desires(_: &mut World, res: &mut Resources) {
+    let source = common::saveload::load_string(DESIRES_PATH).unwrap_or_else(|e| {
+        log::error!(
+            "couldn't read {}, using the built-in desires: {}",
+            DESIRES_PATH,
+            e
+        );
+        BUILTIN_DESIRES.to_string()
+    });
+    res.write::<DesireRegistry>().load(
+        &source,
+        &res.read::<ItemRegistry>(),
+        &res.read::<GoodsCompanyRegistry>(),
+    );
+}
+
+/// Gives the humans the registered desires they do not have yet, like the humans of a world
+/// saved before desires were defined in data
+pub(crate) fn add_missing_desires(world: &mut World, res: &Resources) {
+    let registry = res.read::<DesireRegistry>();
+    let now = res.read::<GameTime>().instant();
+    for h in world.humans.values_mut() {
+        for id in registry.descriptions.keys() {
+            if !h.desires.iter().any(|d| d.desire == id) {
+                h.desires.push(GenericDesire::new(id, now));
+            }
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum GenericDesireState {
+    Empty,
+    WaitingForTrade,
+    BoughtAt(BuildingID),
+    BringingHome,
+}
+
+debug_inspect_impl!(GenericDesireState);
+
+/// The state of a human for one of the desires of the [`DesireRegistry`]
+#[derive(Inspect, Clone, Serialize, Deserialize, Debug)]
+pub struct GenericDesire {
+    pub desire: DesireID,
+    pub last_satisfied: GameInstant,
+    state: GenericDesireState,
+    pub last_score: f32,
+}
+
+impl GenericDesire {
+    /// The item and quantity bought when this desire is chosen next, if it still has to buy
+    pub fn pending_purchase(&self, descr: &DesireDescription) -> Option<(ItemID, u32)> {
+        matches!(self.state, GenericDesireState::Empty).then_some((descr.item, descr.qty))
+    }
+
+    pub fn new(desire: DesireID, start: GameInstant) -> Self {
+        Self {
+            desire,
+            last_satisfied: start,
+            state: GenericDesireState::Empty,
+            last_score: 0.0,
+        }
+    }
+
+    pub fn score(
+        &self,
+        descr: &DesireDescription,
+        time: &GameTime,
+        loc: &Location,
+        house: BuildingID,
+        bought: &Bought,
+    ) -> f32 {
+        match self.state {
+            GenericDesireState::WaitingForTrade => {
+                if bought.0.get(&descr.item).map(Vec::is_empty).unwrap_or(true) {
+                    return 0.0;
+                }
+            }
+            GenericDesireState::BoughtAt(id) => {
+                if loc == &Location::Building(id) {
+                    return 1.0;
+                }
+            }
+            GenericDesireState::BringingHome => {
+                if loc == &Location::Building(house) {
+                    return 1.0;
+                }
+            }
+            GenericDesireState::Empty => {}
+        }
+        let hours = self.last_satisfied.elapsed(time) as f32 / SECONDS_PER_HOUR as f32;
+        descr.curve_score(hours)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn apply(
+        &mut self,
+        descr: &DesireDescription,
+        cbuf: &ParCommandBuffer<HumanEnt>,
+        binfos: &BuildingInfos,
+        map: &Map,
+        time: &GameTime,
+        id: HumanID,
+        trans: &Transform,
+        loc: &Location,
+        house: BuildingID,
+        bought: &mut Bought,
+    ) -> HumanDecisionKind {
+        use HumanDecisionKind::*;
+        match self.state {
+            GenericDesireState::Empty => {
+                let pos = trans.position;
+                let (item, qty) = (descr.item, descr.qty);
+                cbuf.exec_on(id, move |market: &mut Market| {
+                    market.buy(SoulID::Human(id), pos.xy(), item, qty)
+                });
+                self.state = GenericDesireState::WaitingForTrade;
+                Yield
+            }
+            GenericDesireState::WaitingForTrade => {
+                for trade in bought.0.entry(descr.item).or_default().drain(..) {
+                    if let Some(b) =
+                        find_trade_place(trade.seller, trans.position.xy(), binfos, map)
+                    {
+                        self.state = GenericDesireState::BoughtAt(b);
+                    }
+                }
+                Yield
+            }
+            GenericDesireState::BoughtAt(b) => {
+                if loc != &Location::Building(b) {
+                    return GoTo(Destination::Building(b));
+                }
+                match descr.target {
+                    DesirePlace::Seller => {
+                        self.satisfy(descr, time, id);
+                        Yield
+                    }
+                    DesirePlace::Home => {
+                        self.state = GenericDesireState::BringingHome;
+                        GoTo(Destination::Building(house))
+                    }
+                    DesirePlace::Building(kind) => {
+                        let pos = trans.position.xy();
+                        let nearest = map.bkinds.get(&kind).and_then(|ids| {
+                            ids.iter()
+                                .filter_map(|&bid| map.buildings.get(bid))
+                                .min_by_key(|b| OrderedFloat(b.door_pos.xy().distance2(pos)))
+                                .map(|b| b.id)
+                        });
+                        match nearest {
+                            Some(target) if target != b => {
+                                self.state = GenericDesireState::BoughtAt(target);
+                                GoTo(Destination::Building(target))
+                            }
+                            _ => {
+                                self.satisfy(descr, time, id);
+                                Yield
+                            }
+                        }
+                    }
+                }
+            }
+            GenericDesireState::BringingHome => {
+                if loc != &Location::Building(house) {
+                    return GoTo(Destination::Building(house));
+                }
+                self.satisfy(descr, time, id);
+                Yield
+            }
+        }
+    }
+
+    fn satisfy(&mut self, descr: &DesireDescription, time: &GameTime, id: HumanID) {
+        self.state = GenericDesireState::Empty;
+        self.last_satisfied = time.instant();
+        log::debug!("{:?} satisfied {}", id, descr.name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DesireDescription, DesirePlace, DesireRegistry};
+    use crate::economy::ItemRegistry;
+    use crate::tests::TestCtx;
+    use crate::{BuildingKind, GoodsCompanyRegistry};
+
+    #[test]
+    fn test_curve_score() {
+        let descr = DesireDescription {
+            id: Default::default(),
+            name: "test".to_string(),
+            item: Default::default(),
+            qty: 1,
+            curve: vec![(0.0, -1.0), (24.0, 0.0), (48.0, 0.5)],
+            target: DesirePlace::Seller,
+        };
+
+        assert_eq!(descr.curve_score(-5.0), -1.0);
+        assert_eq!(descr.curve_score(12.0), -0.5);
+        assert_eq!(descr.curve_score(24.0), 0.0);
+        assert_eq!(descr.curve_score(36.0), 0.25);
+        assert_eq!(descr.curve_score(100.0), 0.5);
+    }
+
+    #[test]
+    fn test_building_targets_and_builtin_fallback() {
+        let test = TestCtx::new();
+        let items = test.g.read::<ItemRegistry>();
+        let companies = test.g.read::<GoodsCompanyRegistry>();
+        let clinic = companies
+            .descriptions
+            .iter()
+            .find_map(|(id, c)| (c.name == "Clinic").then_some(id))
+            .unwrap();
+
+        let shipped = test.g.read::<DesireRegistry>();
+        let healthcare = shipped
+            .descriptions
+            .values()
+            .find(|d| d.name == "Healthcare")
+            .unwrap();
+        assert_eq!(
+            healthcare.target,
+            DesirePlace::Building(BuildingKind::GoodsCompany(clinic))
+        );
+
+        let mut fallback = DesireRegistry::default();
+        fallback.load("not json", &items, &companies);
+        assert_eq!(fallback.descriptions.len(), shipped.descriptions.len());
+    }
+}
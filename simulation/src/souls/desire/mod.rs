@@ -1,7 +1,9 @@
 mod buyfood;
+mod generic;
 mod home;
 mod work;
 
 pub use buyfood::*;
+pub use generic::*;
 pub use home::*;
 pub use work::*;
@@ -7,14 +7,39 @@ use crate::world::VehicleID;
 use egui_inspect::Inspect;
 use serde::{Deserialize, Serialize};
 
+/// Goods loaded in a truck for one destination
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct Delivery {
+    pub to: BuildingID,
+    /// Number of trades, or parts of trades, in the load
+    pub trades: u32,
+    pub qty: i32,
+    /// The goods were unloaded at the destination, the load is only given up then so that
+    /// a driver interrupted on the way takes it again when back at work
+    pub delivered: bool,
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum WorkKind {
     Driver {
-        deliver_order: Option<BuildingID>,
+        deliver_order: Option<Delivery>,
         truck: VehicleID,
     },
     Worker,
 }
+
+impl WorkKind {
+    /// Returns true if the human drives a truck and has nothing to deliver
+    pub fn is_idle_driver(&self) -> bool {
+        matches!(
+            self,
+            WorkKind::Driver {
+                deliver_order: None,
+                ..
+            }
+        )
+    }
+}
 debug_inspect_impl!(WorkKind);
 
 #[derive(Inspect, Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +50,50 @@ pub struct Work {
     pub last_score: f32,
 }
 
+/// The work before trucks carried loads, a driver only knew where to deliver
+#[derive(Serialize, Deserialize)]
+pub(crate) struct WorkV0 {
+    workplace: BuildingID,
+    work_inter: RecTimeInterval,
+    kind: WorkKindV0,
+    last_score: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+enum WorkKindV0 {
+    Driver {
+        deliver_order: Option<BuildingID>,
+        truck: VehicleID,
+    },
+    Worker,
+}
+
+impl From<WorkV0> for Work {
+    fn from(old: WorkV0) -> Self {
+        Self {
+            workplace: old.workplace,
+            work_inter: old.work_inter,
+            kind: match old.kind {
+                // a delivery used to be a single unit of cargo
+                WorkKindV0::Driver {
+                    deliver_order,
+                    truck,
+                } => WorkKind::Driver {
+                    deliver_order: deliver_order.map(|to| Delivery {
+                        to,
+                        trades: 1,
+                        qty: 0,
+                        delivered: false,
+                    }),
+                    truck,
+                },
+                WorkKindV0::Worker => WorkKind::Worker,
+            },
+            last_score: old.last_score,
+        }
+    }
+}
+
 impl Work {
     pub fn new(workplace: BuildingID, kind: WorkKind, offset: f32) -> Self {
         Work {
@@ -43,24 +112,31 @@ impl Work {
         match self.kind {
             WorkKind::Worker => GoTo(Destination::Building(self.workplace)),
             WorkKind::Driver {
-                deliver_order,
+                ref mut deliver_order,
                 truck,
             } => {
                 if &Location::Building(self.workplace) != loc {
-                    MultiStack(vec![
+                    return MultiStack(vec![
                         GoTo(Destination::Building(self.workplace)),
                         SetVehicle(router.personal_car),
-                    ])
-                } else if let Some(b) = deliver_order {
-                    MultiStack(vec![
+                    ]);
+                }
+                match deliver_order {
+                    // back from the delivery, the truck is free again
+                    Some(Delivery {
+                        delivered: true, ..
+                    }) => {
+                        *deliver_order = None;
+                        Yield
+                    }
+                    Some(d) => MultiStack(vec![
                         SetVehicle(router.personal_car),
                         GoTo(Destination::Building(self.workplace)),
-                        DeliverAtBuilding(b),
-                        GoTo(Destination::Building(b)),
+                        DeliverAtBuilding(d.to, d.trades),
+                        GoTo(Destination::Building(d.to)),
                         SetVehicle(Some(truck)),
-                    ])
-                } else {
-                    Yield
+                    ]),
+                    None => Yield,
                 }
             }
         }
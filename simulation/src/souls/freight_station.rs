@@ -1,4 +1,4 @@
-use crate::map::{BuildingID, BuildingKind, Map, PathKind};
+use crate::map::{BuildingID, BuildingKind, Map, PathKind, TravelTimes};
 use crate::map_dynamic::{
     BuildingInfos, DispatchID, DispatchKind, DispatchQueryTarget, Dispatcher, Itinerary,
 };
@@ -69,6 +69,7 @@ pub fn freight_station_system(world: &mut World, resources: &mut Resources) {
     let cbuf = resources.read::<ParCommandBuffer<FreightStationEnt>>();
     let mut dispatch = resources.write::<Dispatcher>();
     let map = resources.read::<Map>();
+    let times = resources.read::<TravelTimes>();
     let time = resources.read::<GameTime>();
     let tick = *resources.read::<Tick>();
 
@@ -103,9 +104,14 @@ pub fn freight_station_system(world: &mut World, resources: &mut Resources) {
                         let ext = map.bkinds.get(&BuildingKind::ExternalTrading).unwrap()[0];
                         let bpos = map.buildings[ext].obb.center().z(0.0);
 
-                        *itin = if let Some(r) =
-                            Itinerary::route(tick, train.trans.position, bpos, &map, PathKind::Rail)
-                        {
+                        *itin = if let Some(r) = Itinerary::route(
+                            tick,
+                            train.trans.position,
+                            bpos,
+                            &map,
+                            &times,
+                            PathKind::Rail,
+                        ) {
                             r
                         } else {
                             Itinerary::wait_until(time.timestamp + 10.0);
@@ -152,6 +158,7 @@ pub fn freight_station_system(world: &mut World, resources: &mut Resources) {
                 train.trans.position,
                 destination,
                 &map,
+                &times,
                 PathKind::Rail,
             ),
             continue
@@ -203,7 +210,7 @@ mod tests {
             .get_mut(human)
             .unwrap()
             .decision
-            .kind = HumanDecisionKind::DeliverAtBuilding(station);
+            .kind = HumanDecisionKind::DeliverAtBuilding(station, 1);
 
         let binfos = test.g.read::<BuildingInfos>();
         let SoulID::FreightStation(stationsoul) = binfos.owner(station).unwrap() else {
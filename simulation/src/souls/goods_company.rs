@@ -1,8 +1,15 @@
 use super::desire::Work;
-use crate::economy::{find_trade_place, ItemID, ItemRegistry, Market};
-use crate::map::{Building, BuildingID, Map, Zone, MAX_ZONE_AREA};
-use crate::map_dynamic::BuildingInfos;
-use crate::souls::desire::WorkKind;
+use crate::economy::{
+    find_trade_place, ItemID, ItemRegistry, Market, Money, Trade, TradeTarget, Wallets,
+};
+use crate::map::{Building, BuildingID, LaneKind, Map, Zone, MAX_ZONE_AREA};
+use crate::map_dynamic::{
+    BuildingInfos, DispatchID, DispatchKind, DispatchQueryTarget, Dispatcher, UtilityGrid,
+};
+use crate::multiplayer::Players;
+use crate::souls::desire::{Delivery, WorkKind};
+use crate::souls::satisfaction::workers_productivity;
+use crate::transportation::VehicleKind;
 use crate::utils::resources::Resources;
 use crate::utils::time::GameTime;
 use crate::world::{CompanyEnt, HumanEnt, HumanID, VehicleID};
@@ -123,11 +130,15 @@ impl Recipe {
         }
     }
 
-    pub fn should_produce(&self, soul: SoulID, market: &Market) -> bool {
-        // Has enough resources
+    pub fn has_inputs(&self, soul: SoulID, market: &Market) -> bool {
         self.consumption
             .iter()
             .all(move |&(kind, qty)| market.capital(soul, kind) >= qty)
+    }
+
+    pub fn should_produce(&self, soul: SoulID, market: &Market) -> bool {
+        // Has enough resources
+        self.has_inputs(soul, market)
             &&
             // Has enough storage
             self.production.iter().all(move |&(kind, qty)| {
@@ -135,6 +146,14 @@ impl Recipe {
             })
     }
 
+    /// What restocking the inputs consumed by one production costs
+    pub fn inputs_cost(&self, market: &Market) -> Money {
+        self.consumption
+            .iter()
+            .map(|&(kind, qty)| market.cost(kind, qty as u32))
+            .sum()
+    }
+
     pub fn act(&self, soul: SoulID, near: Vec2, market: &mut Market) {
         for &(kind, qty) in &self.consumption {
             market.produce(soul, kind, -qty);
@@ -149,19 +168,132 @@ impl Recipe {
 
 #[derive(Clone, Serialize, Deserialize, Inspect)]
 pub struct GoodsCompany {
+    #[serde(with = "company_kind_serde")]
     pub kind: CompanyKind,
     pub recipe: Recipe,
     pub building: BuildingID,
     pub max_workers: i32,
     /// In [0; 1] range, to show how much has been made until new product
     pub progress: f32,
-    pub driver: Option<HumanID>,
+    /// One driver per truck at most
+    pub drivers: Vec<HumanID>,
     pub trucks: Vec<VehicleID>,
 }
 
+/// [`CompanyKind`] is internally tagged to be read from the json descriptions,
+/// which bincode cannot deserialize, so it is saved as a plain enum
+mod company_kind_serde {
+    use common::descriptions::CompanyKind;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    enum Repr {
+        Store,
+        Factory { n_trucks: u32 },
+        Network,
+    }
+
+    pub fn serialize<S: Serializer>(kind: &CompanyKind, s: S) -> Result<S::Ok, S::Error> {
+        match *kind {
+            CompanyKind::Store => Repr::Store,
+            CompanyKind::Factory { n_trucks } => Repr::Factory { n_trucks },
+            CompanyKind::Network => Repr::Network,
+        }
+        .serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<CompanyKind, D::Error> {
+        Ok(match Repr::deserialize(d)? {
+            Repr::Store => CompanyKind::Store,
+            Repr::Factory { n_trucks } => CompanyKind::Factory { n_trucks },
+            Repr::Network => CompanyKind::Network,
+        })
+    }
+}
+
+/// The company before it had one driver per truck
+#[derive(Serialize, Deserialize)]
+pub(crate) struct GoodsCompanyV0 {
+    #[serde(with = "company_kind_v0_serde")]
+    kind: CompanyKind,
+    recipe: Recipe,
+    building: BuildingID,
+    max_workers: i32,
+    progress: f32,
+    driver: Option<HumanID>,
+    trucks: Vec<VehicleID>,
+}
+
+impl From<GoodsCompanyV0> for GoodsCompany {
+    fn from(old: GoodsCompanyV0) -> Self {
+        Self {
+            kind: old.kind,
+            recipe: old.recipe,
+            building: old.building,
+            max_workers: old.max_workers,
+            progress: old.progress,
+            drivers: old.driver.into_iter().collect(),
+            trucks: old.trucks,
+        }
+    }
+}
+
+/// [`CompanyKind`] used to be saved internally tagged, as its name followed by its fields,
+/// which can be written but not read back through serde with bincode
+mod company_kind_v0_serde {
+    use common::descriptions::CompanyKind;
+    use serde::de::{Error, SeqAccess, Visitor};
+    use serde::{Deserializer, Serialize, Serializer};
+    use std::fmt::Formatter;
+
+    pub fn serialize<S: Serializer>(kind: &CompanyKind, s: S) -> Result<S::Ok, S::Error> {
+        kind.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<CompanyKind, D::Error> {
+        struct KindVisitor;
+
+        impl<'de> Visitor<'de> for KindVisitor {
+            type Value = CompanyKind;
+
+            fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+                f.write_str("the name of a company kind followed by its fields")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<CompanyKind, A::Error> {
+                let name: String = seq
+                    .next_element()?
+                    .ok_or_else(|| Error::invalid_length(0, &self))?;
+                Ok(match &*name {
+                    "store" => CompanyKind::Store,
+                    "factory" => CompanyKind::Factory {
+                        n_trucks: seq
+                            .next_element()?
+                            .ok_or_else(|| Error::invalid_length(1, &self))?,
+                    },
+                    "network" => CompanyKind::Network,
+                    _ => {
+                        return Err(Error::unknown_variant(
+                            &name,
+                            &["store", "factory", "network"],
+                        ))
+                    }
+                })
+            }
+        }
+
+        d.deserialize_tuple(2, KindVisitor)
+    }
+}
+
 impl GoodsCompany {
-    pub fn productivity(&self, workers: usize, zone: Option<&Zone>) -> f32 {
-        workers as f32 / self.max_workers as f32 * zone.map_or(1.0, |z| z.area / MAX_ZONE_AREA)
+    /// `factor` multiplies the result, e.g. how well the building is served by the utility
+    /// networks (see [`UtilityGrid::productivity_factor`]) and how satisfied the workers are
+    /// (see [`workers_productivity`])
+    pub fn productivity(&self, workers: usize, zone: Option<&Zone>, factor: f32) -> f32 {
+        workers as f32 / self.max_workers as f32
+            * zone.map_or(1.0, |z| z.area / MAX_ZONE_AREA)
+            * factor
     }
 }
 
@@ -197,10 +329,51 @@ pub fn company_soul(sim: &mut Simulation, company: GoodsCompany) -> Option<SoulI
 
     sim.write::<BuildingInfos>()
         .set_owner(company.building, soul);
+    sim.write::<Players>()
+        .owners
+        .set_company(id, company.building);
 
     Some(soul)
 }
 
+/// Takes the next load from the sold goods waiting for a truck: the oldest trade that can be
+/// delivered and the following ones going to the same building, up to the capacity of a truck.
+/// Trades that cannot be delivered anywhere yet wait for a place to deliver to.
+fn next_load(
+    sold: &mut Vec<Trade>,
+    from: Vec2,
+    binfos: &BuildingInfos,
+    map: &Map,
+) -> Option<Delivery> {
+    let capacity = VehicleKind::Truck.cargo_capacity();
+    let to = sold
+        .iter()
+        .find_map(|trade| find_trade_place(trade.buyer, from, binfos, map))?;
+
+    let mut delivery = Delivery {
+        to,
+        trades: 0,
+        qty: 0,
+        delivered: false,
+    };
+    let mut i = 0;
+    while i < sold.len() && delivery.qty < capacity {
+        let trade = &mut sold[i];
+        if find_trade_place(trade.buyer, from, binfos, map) != Some(to) {
+            i += 1;
+            continue;
+        }
+        let taken = trade.qty.min(capacity - delivery.qty);
+        delivery.qty += taken;
+        delivery.trades += 1;
+        trade.qty -= taken;
+        if trade.qty <= 0 {
+            sold.remove(i);
+        }
+    }
+    Some(delivery)
+}
+
 pub fn company_system(world: &mut World, res: &mut Resources) {
     profiling::scope!("souls::company_system");
     let delta = res.read::<GameTime>().realdelta;
@@ -208,7 +381,11 @@ pub fn company_system(world: &mut World, res: &mut Resources) {
     let cbuf_human: &ParCommandBuffer<HumanEnt> = &res.read();
     let binfos: &BuildingInfos = &res.read();
     let market: &Market = &res.read();
+    let wallets: &Wallets = &res.read();
     let map: &Map = &res.read();
+    let dispatcher: &mut Dispatcher = &mut res.write();
+    let grid: &UtilityGrid = &res.read();
+    let humans = &world.humans;
 
     world.companies.iter_mut().for_each(|(me, c)| {
         let n_workers = c.workers.0.len();
@@ -218,8 +395,12 @@ pub fn company_system(world: &mut World, res: &mut Resources) {
             return;
         });
 
-        if c.comp.recipe.should_produce(soul, market) {
-            c.comp.progress += c.comp.productivity(n_workers, b.zone.as_ref())
+        if c.comp.recipe.should_produce(soul, market)
+            && wallets.can_afford(soul, c.comp.recipe.inputs_cost(market))
+        {
+            let factor =
+                grid.productivity_factor(b.id) * workers_productivity(humans, &c.workers.0);
+            c.comp.progress += c.comp.productivity(n_workers, b.zone.as_ref(), factor)
                 / c.comp.recipe.complexity as f32
                 * delta;
         }
@@ -254,43 +435,86 @@ pub fn company_system(world: &mut World, res: &mut Resources) {
             }
         }
 
-        (|| {
-            let Some(trade) = c.sold.0.pop() else {
-                return;
-            };
-            let Some(driver) = c.comp.driver else {
-                return;
-            };
-            let Some(w) = world.humans.get(driver).and_then(|h| h.work.as_ref()) else {
-                return;
-            };
-            if !matches!(
-                w.kind,
-                WorkKind::Driver {
-                    deliver_order: None,
-                    ..
-                }
-            ) {
-                return;
-            }
-            let Some(owner_build) = find_trade_place(trade.buyer, b.door_pos.xy(), binfos, map)
+        c.comp
+            .drivers
+            .retain(|&driver| world.humans.contains_key(driver));
+
+        // the trucks of the idle drivers can take a new load
+        let mut idle = Vec::with_capacity(c.comp.drivers.len());
+        for &driver in &c.comp.drivers {
+            let Some(Work {
+                kind: kind @ WorkKind::Driver { truck, .. },
+                ..
+            }) = world.humans.get(driver).and_then(|h| h.work.as_ref())
             else {
-                log::warn!("driver can't find the place to deliver for {:?}", &trade);
-                return;
+                continue;
             };
-            cbuf.exec_ent(me, move |sim| {
-                let Some(h) = sim.world.humans.get_mut(driver) else {
-                    return;
-                };
-                let Some(w) = h.work.as_mut() else {
+            if kind.is_idle_driver() {
+                dispatcher.free(DispatchID::SmallTruck(*truck));
+                idle.push((driver, *truck));
+            }
+        }
+
+        if c.comp.trucks.is_empty() {
+            // nobody picks the goods up, they are handed over right away
+            c.sold.0.clear();
+        } else {
+            // humans collect what they buy themselves, the rest waits for a truck
+            c.sold
+                .0
+                .retain(|t| !matches!(t.buyer, TradeTarget::Soul(SoulID::Human(_))));
+        }
+
+        if !idle.is_empty() {
+            (|| {
+                let door = b.door_pos.xy();
+                let Some(delivery) = next_load(&mut c.sold.0, door, binfos, map) else {
                     return;
                 };
-                let WorkKind::Driver { deliver_order, .. } = &mut w.kind else {
-                    return;
+
+                let truck = map
+                    .nearest_lane(b.door_pos, LaneKind::Driving, None)
+                    .and_then(|lane| {
+                        dispatcher.query_filtered(
+                            map,
+                            DispatchKind::SmallTruck,
+                            DispatchQueryTarget::Lane(lane),
+                            |id| idle.iter().any(|&(_, t)| id == DispatchID::SmallTruck(t)),
+                        )
+                    });
+                let (driver, truck) = match truck {
+                    Some(DispatchID::SmallTruck(truck)) => {
+                        *unwrap_ret!(idle.iter().find(|&&(_, t)| t == truck))
+                    }
+                    // the trucks were not located yet
+                    _ => idle[0],
                 };
-                *deliver_order = Some(owner_build)
-            });
-        })();
+                dispatcher.reserve(DispatchID::SmallTruck(truck));
+
+                cbuf.exec_ent(me, move |sim| {
+                    let Some(h) = sim.world.humans.get_mut(driver) else {
+                        return;
+                    };
+                    let Some(w) = h.work.as_mut() else {
+                        return;
+                    };
+                    let WorkKind::Driver { deliver_order, .. } = &mut w.kind else {
+                        return;
+                    };
+                    *deliver_order = Some(delivery)
+                });
+            })();
+        }
+
+        let mut driven: Vec<VehicleID> = c
+            .comp
+            .drivers
+            .iter()
+            .filter_map(|&d| match world.humans.get(d)?.work.as_ref()?.kind {
+                WorkKind::Driver { truck, .. } => Some(truck),
+                WorkKind::Worker => None,
+            })
+            .collect();
 
         for &worker in c.workers.0.iter() {
             let Some(w) = world.humans.get(worker) else {
@@ -300,15 +524,15 @@ pub fn company_system(world: &mut World, res: &mut Resources) {
             if w.work.is_none() {
                 let mut kind = WorkKind::Worker;
 
-                if let Some(truck) = c.comp.trucks.get(0) {
-                    if matches!(c.comp.kind, CompanyKind::Factory { .. }) && c.comp.driver.is_none()
-                    {
+                if matches!(c.comp.kind, CompanyKind::Factory { .. }) {
+                    if let Some(&truck) = c.comp.trucks.iter().find(|t| !driven.contains(t)) {
                         kind = WorkKind::Driver {
                             deliver_order: None,
-                            truck: *truck,
+                            truck,
                         };
 
-                        c.comp.driver = Some(worker);
+                        driven.push(truck);
+                        c.comp.drivers.push(worker);
                     }
                 }
 
@@ -325,3 +549,187 @@ pub fn company_system(world: &mut World, res: &mut Resources) {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{next_load, GoodsCompanyRegistry};
+    use crate::economy::{ItemRegistry, Market, Money, Trade, TradeTarget};
+    use crate::map::BuildingKind;
+    use crate::map_dynamic::BuildingInfos;
+    use crate::souls::desire::WorkKind;
+    use crate::souls::human::spawn_human;
+    use crate::tests::TestCtx;
+    use crate::transportation::VehicleKind;
+    use crate::{SoulID, WorldCommand};
+    use common::descriptions::BuildingGen;
+    use geom::{vec2, vec3, OBB};
+
+    fn build_freight_station(test: &mut TestCtx) {
+        test.apply(&[WorldCommand::MapBuildSpecialBuilding {
+            pos: OBB::new(vec2(50.0, 50.0), vec2(1.0, 0.0), 5.0, 5.0),
+            kind: BuildingKind::RailFreightStation,
+            gen: BuildingGen::NoWalkway {
+                door_pos: vec2(50.0, 50.0),
+            },
+            zone: None,
+        }]);
+    }
+
+    fn trade(buyer: TradeTarget, qty: i32) -> Trade {
+        Trade {
+            buyer,
+            seller: TradeTarget::ExternalTrade,
+            qty,
+            kind: Default::default(),
+            money_delta: Money::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_loads_are_batched_per_destination() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0., 0., 0.), vec3(100., 0., 0.)]);
+        build_freight_station(&mut test);
+
+        let nowhere = TradeTarget::Soul(SoulID::GoodsCompany(Default::default()));
+        let mut sold = vec![
+            trade(TradeTarget::ExternalTrade, 15),
+            trade(nowhere, 5),
+            trade(TradeTarget::ExternalTrade, 10),
+            trade(TradeTarget::ExternalTrade, 30),
+        ];
+
+        let map = test.g.map();
+        let binfos = test.g.read::<BuildingInfos>();
+
+        let d = next_load(&mut sold, vec2(0.0, 0.0), &binfos, &map).unwrap();
+        assert_eq!((d.trades, d.qty), (2, 20));
+        assert_eq!(
+            sold.iter().map(|t| t.qty).collect::<Vec<_>>(),
+            vec![5, 5, 30]
+        );
+
+        // the trade with no destination waits
+        let d = next_load(&mut sold, vec2(0.0, 0.0), &binfos, &map).unwrap();
+        assert_eq!((d.trades, d.qty), (2, 20));
+        assert_eq!(sold.iter().map(|t| t.qty).collect::<Vec<_>>(), vec![5, 15]);
+
+        let d = next_load(&mut sold, vec2(0.0, 0.0), &binfos, &map).unwrap();
+        assert_eq!((d.trades, d.qty), (1, 15));
+        assert_eq!(sold.iter().map(|t| t.qty).collect::<Vec<_>>(), vec![5]);
+        assert!(next_load(&mut sold, vec2(0.0, 0.0), &binfos, &map).is_none());
+    }
+
+    #[test]
+    fn test_every_truck_gets_a_driver_and_a_load() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0., 0., 0.), vec3(300., 0., 0.)]);
+        build_freight_station(&mut test);
+
+        let house = test.build_house_near(vec2(250.0, 50.0));
+        let humans: Vec<_> = (0..3)
+            .map(|_| spawn_human(&mut test.g, house).unwrap())
+            .collect();
+
+        let registry = test.g.read::<GoodsCompanyRegistry>();
+        let (flour, descr) = registry
+            .descriptions
+            .iter()
+            .find(|(_, d)| d.name == "Flour Factory")
+            .unwrap();
+        let n_trucks = match descr.kind {
+            common::descriptions::CompanyKind::Factory { n_trucks } => n_trucks as usize,
+            _ => unreachable!(),
+        };
+        drop(registry);
+
+        test.apply(&[WorldCommand::MapBuildSpecialBuilding {
+            pos: OBB::new(vec2(150.0, -25.0), vec2(1.0, 0.0), 10.0, 10.0),
+            kind: BuildingKind::GoodsCompany(flour),
+            gen: BuildingGen::NoWalkway {
+                door_pos: vec2(150.0, -15.0),
+            },
+            zone: None,
+        }]);
+        test.tick();
+
+        let company = {
+            let world = test.g.world_mut_unchecked();
+            let (id, c) = world.companies.iter_mut().next().unwrap();
+            assert_eq!(c.comp.trucks.len(), n_trucks);
+            c.workers.0 = humans.clone();
+            c.sold.0 = vec![trade(TradeTarget::ExternalTrade, 50)];
+            id
+        };
+
+        for _ in 0..5 {
+            test.tick();
+        }
+
+        let world = test.g.world();
+        let c = world.companies.get(company).unwrap();
+        assert_eq!(c.comp.drivers.len(), n_trucks);
+
+        let mut trucks = vec![];
+        let mut loaded = 0;
+        for &h in &humans {
+            match world.humans.get(h).unwrap().work.as_ref().unwrap().kind {
+                WorkKind::Driver {
+                    deliver_order,
+                    truck,
+                } => {
+                    assert!(!trucks.contains(&truck));
+                    trucks.push(truck);
+                    loaded += deliver_order.map_or(0, |d| d.qty);
+                }
+                WorkKind::Worker => {}
+            }
+        }
+        assert_eq!(trucks.len(), n_trucks);
+        let capacity = VehicleKind::Truck.cargo_capacity();
+        assert_eq!(loaded, (capacity * n_trucks as i32).min(50));
+        // the rest waits at the factory
+        assert_eq!(c.sold.0.iter().map(|t| t.qty).sum::<i32>(), 50 - loaded);
+    }
+
+    #[test]
+    fn test_store_exports_its_surplus() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0., 0., 0.), vec3(300., 0., 0.)]);
+
+        let bakery = test
+            .g
+            .read::<GoodsCompanyRegistry>()
+            .descriptions
+            .iter()
+            .find(|(_, d)| d.name == "Bakery")
+            .unwrap()
+            .0;
+        test.apply(&[WorldCommand::MapBuildSpecialBuilding {
+            pos: OBB::new(vec2(150.0, -25.0), vec2(1.0, 0.0), 10.0, 10.0),
+            kind: BuildingKind::GoodsCompany(bakery),
+            gen: BuildingGen::NoWalkway {
+                door_pos: vec2(150.0, -15.0),
+            },
+            zone: None,
+        }]);
+        test.tick();
+
+        let company = test.g.world().companies.keys().next().unwrap();
+        let soul = SoulID::GoodsCompany(company);
+        let bread = test.g.read::<ItemRegistry>().id("bread");
+        {
+            let mut market = test.g.write::<Market>();
+            market.produce(soul, bread, 10);
+            market.sell_all(soul, vec2(150.0, -15.0), bread, 5);
+        }
+
+        for _ in 0..5 {
+            test.tick();
+        }
+
+        // the surplus was exported and no truck will ever pick it up
+        assert_eq!(test.g.read::<Market>().capital(soul, bread), 5);
+        assert!(test.g.world().companies[company].sold.0.is_empty());
+    }
+}
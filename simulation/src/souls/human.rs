@@ -1,8 +1,9 @@
-use crate::economy::{Bought, ItemRegistry, Market};
+use crate::economy::{Bought, ItemID, ItemRegistry, Market, Wallets};
 use crate::map::BuildingID;
 use crate::map_dynamic::{BuildingInfos, Destination, Itinerary, Router};
 use crate::physics::Speed;
-use crate::souls::desire::{BuyFood, Home, Work};
+use crate::souls::desire::{BuyFood, DesireRegistry, GenericDesire, Home, Work, WorkKind};
+use crate::souls::satisfaction::Satisfaction;
 use crate::transportation::{
     random_pedestrian_shirt_color, spawn_parked_vehicle, Location, Pedestrian, VehicleKind,
 };
@@ -28,10 +29,50 @@ pub enum HumanDecisionKind {
     Yield,
     SetVehicle(Option<VehicleID>),
     GoTo(Destination),
-    DeliverAtBuilding(BuildingID),
+    /// Unloads the given number of trades at the building
+    DeliverAtBuilding(BuildingID, u32),
     MultiStack(Vec<HumanDecisionKind>),
 }
 
+/// The decision before deliveries carried a number of trades
+#[derive(Serialize, Deserialize)]
+pub(crate) struct HumanDecisionV0 {
+    kind: HumanDecisionKindV0,
+    wait: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+enum HumanDecisionKindV0 {
+    Yield,
+    SetVehicle(Option<VehicleID>),
+    GoTo(Destination),
+    DeliverAtBuilding(BuildingID),
+    MultiStack(Vec<HumanDecisionKindV0>),
+}
+
+impl From<HumanDecisionKindV0> for HumanDecisionKind {
+    fn from(old: HumanDecisionKindV0) -> Self {
+        match old {
+            HumanDecisionKindV0::Yield => Self::Yield,
+            HumanDecisionKindV0::SetVehicle(v) => Self::SetVehicle(v),
+            HumanDecisionKindV0::GoTo(dest) => Self::GoTo(dest),
+            HumanDecisionKindV0::DeliverAtBuilding(b) => Self::DeliverAtBuilding(b, 1),
+            HumanDecisionKindV0::MultiStack(stack) => {
+                Self::MultiStack(stack.into_iter().map(Self::from).collect())
+            }
+        }
+    }
+}
+
+impl From<HumanDecisionV0> for HumanDecision {
+    fn from(old: HumanDecisionV0) -> Self {
+        Self {
+            kind: old.kind.into(),
+            wait: old.wait,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Gender {
     M,
@@ -43,10 +84,25 @@ debug_inspect_impl!(Gender);
 #[derive(Inspect, Serialize, Deserialize)]
 pub struct PersonalInfo {
     pub name: String,
+    /// In years, humans get one year older every day, see [`crate::souls::population`]
     pub age: u8,
     pub gender: Gender,
 }
 
+/// Age at which children start looking for a job
+pub const ADULT_AGE: u8 = 18;
+/// Age at which workers leave their job
+pub const RETIREMENT_AGE: u8 = 65;
+/// Chance for an adult with a car to also own a bike, to ride it for trips of the right length
+const BIKE_OWNERSHIP: f32 = 0.5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LifeStage {
+    Child,
+    Worker,
+    Retiree,
+}
+
 debug_inspect_impl!(HumanDecisionKind);
 
 static FIRST_NAMES_BYTES: &str = include_str!("first_names.txt");
@@ -58,8 +114,14 @@ lazy_static! {
 }
 
 impl PersonalInfo {
+    /// A working age adult
     pub fn new(rng: &mut RandProvider) -> Self {
         let age = (rng.next_f32() * 30.0 + 20.0) as u8;
+        Self::new_aged(rng, age, None)
+    }
+
+    /// A human of the given age, who takes the last name of their family if any
+    pub fn new_aged(rng: &mut RandProvider, age: u8, family: Option<&str>) -> Self {
         let gender = match rng.next_u32() % 2 {
             0 => Gender::M,
             1 => Gender::F,
@@ -67,12 +129,29 @@ impl PersonalInfo {
         };
 
         let first_name = FIRST_NAMES[rng.next_u32() as usize % FIRST_NAMES.len()];
-        let last_name = LAST_NAMES[rng.next_u32() as usize % LAST_NAMES.len()];
+        let last_name = match family {
+            Some(family) => family,
+            None => LAST_NAMES[rng.next_u32() as usize % LAST_NAMES.len()],
+        };
 
         let name = format!("{} {}", first_name, last_name);
 
         Self { name, age, gender }
     }
+
+    pub fn last_name(&self) -> &str {
+        self.name
+            .split_once(' ')
+            .map_or(&*self.name, |(_, last)| last)
+    }
+
+    pub fn stage(&self) -> LifeStage {
+        match self.age {
+            ..ADULT_AGE => LifeStage::Child,
+            ADULT_AGE..RETIREMENT_AGE => LifeStage::Worker,
+            _ => LifeStage::Retiree,
+        }
+    }
 }
 
 impl Default for HumanDecisionKind {
@@ -88,12 +167,13 @@ impl HumanDecisionKind {
         binfos: &BuildingInfos,
         map: &Map,
         cbuf_freight: &ParCommandBuffer<FreightStationEnt>,
+        work: Option<&mut Work>,
     ) -> bool {
         match *self {
             HumanDecisionKind::GoTo(dest) => router.go_to(dest),
             HumanDecisionKind::MultiStack(ref mut decisions) => {
                 if let Some(d) = decisions.last_mut() {
-                    if d.update(router, binfos, map, cbuf_freight) {
+                    if d.update(router, binfos, map, cbuf_freight, work) {
                         decisions.pop();
                     }
                     false
@@ -105,7 +185,18 @@ impl HumanDecisionKind {
                 router.use_vehicle(id);
                 true
             }
-            HumanDecisionKind::DeliverAtBuilding(bid) => {
+            HumanDecisionKind::DeliverAtBuilding(bid, trades) => {
+                if let Some(Work {
+                    kind:
+                        WorkKind::Driver {
+                            deliver_order: Some(d),
+                            ..
+                        },
+                    ..
+                }) = work
+                {
+                    d.delivered = true;
+                }
                 let Some(b) = map.buildings().get(bid) else {
                     return true;
                 };
@@ -115,7 +206,7 @@ impl HumanDecisionKind {
                     };
                     cbuf_freight.exec_ent(fid, move |e| {
                         if let Some(f) = e.world.freight_stations.get_mut(fid) {
-                            f.f.waiting_cargo += 1;
+                            f.f.waiting_cargo += trades;
                         }
                     });
                 }
@@ -132,6 +223,7 @@ enum NextDesire<'a> {
     Home(&'a mut Home),
     Work(&'a mut Work),
     Food(&'a mut BuyFood),
+    Generic(&'a mut GenericDesire),
 }
 
 pub fn update_decision_system(world: &mut World, resources: &mut Resources) {
@@ -141,14 +233,20 @@ pub fn update_decision_system(world: &mut World, resources: &mut Resources) {
     let rc = &*resources.read();
     let rd = &*resources.read();
     let re = &*resources.read();
+    let rf = &*resources.read();
+    let market = &*resources.read::<Market>();
+    let wallets = &*resources.read::<Wallets>();
 
     world.humans.iter_mut().for_each(|(ent, h)| {
+        let can_buy = |item, qty| wallets.can_afford(SoulID::Human(ent), market.cost(item, qty));
         update_decision(
             ra,
             rb,
             rc,
             rd,
             re,
+            rf,
+            &can_buy,
             ent,
             &h.trans,
             &h.location,
@@ -158,6 +256,7 @@ pub fn update_decision_system(world: &mut World, resources: &mut Resources) {
             Some(&mut h.food),
             Some(&mut h.home),
             h.work.as_mut(),
+            &mut h.desires,
         )
     });
 }
@@ -169,6 +268,8 @@ pub fn update_decision(
     time: &GameTime,
     binfos: &BuildingInfos,
     map: &Map,
+    desires_registry: &DesireRegistry,
+    can_buy: &dyn Fn(ItemID, u32) -> bool,
     me: HumanID,
     trans: &Transform,
     loc: &Location,
@@ -177,7 +278,8 @@ pub fn update_decision(
     decision: &mut HumanDecision,
     food: Option<&mut BuyFood>,
     home: Option<&mut Home>,
-    work: Option<&mut Work>,
+    mut work: Option<&mut Work>,
+    desires: &mut [GenericDesire],
 ) {
     if decision.wait != 0 {
         decision.wait -= 1;
@@ -185,12 +287,16 @@ pub fn update_decision(
     }
     let pos = trans.position;
     decision.wait = (30.0 + common::rand::rand2(pos.x, pos.y) * 50.0) as u8;
-    if !decision.kind.update(router, binfos, map, cbuf_freight) {
+    if !decision
+        .kind
+        .update(router, binfos, map, cbuf_freight, work.as_deref_mut())
+    {
         return;
     }
 
     let mut decision_id = NextDesire::None;
     let mut max_score = f32::NEG_INFINITY;
+    let house = home.as_ref().map(|h| h.house);
 
     if let Some(home) = home {
         let score = home.score();
@@ -212,28 +318,72 @@ pub fn update_decision(
         }
     }
 
-    if let Some(food) = food {
+    // desires that still have to buy something are not chosen when it cannot be afforded
+    let affordable =
+        |purchase: Option<(ItemID, u32)>| purchase.is_none_or(|(item, qty)| can_buy(item, qty));
+
+    if let Some(food) = food.filter(|food| affordable(food.pending_purchase())) {
         let score = food.score(time, loc, bought);
         food.last_score = score;
 
-        #[allow(unused_assignments)]
         if score > max_score {
             max_score = score;
             decision_id = NextDesire::Food(food);
         }
     }
 
+    if let Some(house) = house {
+        for desire in desires {
+            let Some(descr) = desires_registry.descriptions.get(desire.desire) else {
+                continue;
+            };
+            if !affordable(desire.pending_purchase(descr)) {
+                continue;
+            }
+            let score = desire.score(descr, time, loc, house, bought);
+            desire.last_score = score;
+
+            if score > max_score {
+                max_score = score;
+                decision_id = NextDesire::Generic(desire);
+            }
+        }
+    }
+
     match decision_id {
         NextDesire::Home(home) => decision.kind = home.apply(),
         NextDesire::Work(work) => decision.kind = work.apply(loc, router),
         NextDesire::Food(food) => {
             decision.kind = food.apply(cbuf, binfos, map, time, me, trans, loc, bought)
         }
+        NextDesire::Generic(desire) => {
+            // Generic desires are only chosen when there is a house
+            let (Some(descr), Some(house)) =
+                (desires_registry.descriptions.get(desire.desire), house)
+            else {
+                return;
+            };
+            decision.kind = desire.apply(
+                descr, cbuf, binfos, map, time, me, trans, loc, house, bought,
+            )
+        }
         NextDesire::None => {}
     }
 }
 
+/// Spawns a working age adult living in the house
 pub fn spawn_human(sim: &mut Simulation, house: BuildingID) -> Option<HumanID> {
+    let personal_info = PersonalInfo::new(&mut sim.write::<RandProvider>());
+    spawn_human_with(sim, house, personal_info)
+}
+
+/// Spawns a human living in the house, only working age adults look for a job
+/// and children do not have a vehicle
+pub fn spawn_human_with(
+    sim: &mut Simulation,
+    house: BuildingID,
+    personal_info: PersonalInfo,
+) -> Option<HumanID> {
     profiling::scope!("spawn_human");
     let map = sim.map();
     let housepos = map.buildings().get(house)?.door_pos;
@@ -250,9 +400,23 @@ pub fn spawn_human(sim: &mut Simulation, house: BuildingID) -> Option<HumanID> {
     let food = BuyFood::new(time, &registry);
     drop(registry);
 
-    let car = spawn_parked_vehicle(sim, VehicleKind::Car, housepos);
-
-    let personal_info = Box::new(PersonalInfo::new(&mut sim.write::<RandProvider>()));
+    let desires = sim
+        .read::<DesireRegistry>()
+        .descriptions
+        .keys()
+        .map(|id| GenericDesire::new(id, time))
+        .collect();
+
+    let stage = personal_info.stage();
+    let adult = stage != LifeStage::Child;
+    let car = adult
+        .then(|| spawn_parked_vehicle(sim, VehicleKind::Car, housepos))
+        .flatten();
+    // Those who can't get a car get around by bike
+    let wants_bike = car.is_none() || sim.write::<RandProvider>().next_f32() < BIKE_OWNERSHIP;
+    let bike = (adult && wants_bike)
+        .then(|| spawn_parked_vehicle(sim, VehicleKind::Bike, housepos))
+        .flatten();
 
     let id = sim.world.insert(HumanEnt {
         trans: Transform::new(hpos),
@@ -263,20 +427,28 @@ pub fn spawn_human(sim: &mut Simulation, house: BuildingID) -> Option<HumanID> {
         decision: HumanDecision::default(),
         home: Home::new(house),
         food,
+        desires,
         bought: Bought::default(),
-        router: Router::new(car),
+        router: Router::new(car, bike),
         collider: None,
         work: None,
-        personal_info,
+        satisfaction: Satisfaction::default(),
+        personal_info: Box::new(personal_info),
     });
 
     let soul = SoulID::Human(id);
-    let mut m = sim.write::<Market>();
-    let registry = sim.read::<ItemRegistry>();
-    m.buy(soul, housepos.xy(), registry.id("job-opening"), 1);
+    if stage == LifeStage::Worker {
+        let mut m = sim.write::<Market>();
+        let registry = sim.read::<ItemRegistry>();
+        m.buy(soul, housepos.xy(), registry.id("job-opening"), 1);
+    }
 
-    sim.write::<BuildingInfos>().get_in(house, soul);
-    sim.write::<BuildingInfos>().set_owner(house, soul);
+    let mut binfos = sim.write::<BuildingInfos>();
+    binfos.get_in(house, soul);
+    binfos.add_resident(house, soul);
+    if binfos.owner(house).is_none() {
+        binfos.set_owner(house, soul);
+    }
 
     Some(id)
 }
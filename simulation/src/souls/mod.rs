@@ -2,7 +2,6 @@ use crate::map::{BuildingID, BuildingKind};
 use crate::map_dynamic::BuildingInfos;
 use crate::souls::freight_station::freight_station_soul;
 use crate::souls::goods_company::{company_soul, GoodsCompany, GoodsCompanyRegistry};
-use crate::souls::human::spawn_human;
 use crate::transportation::{spawn_parked_vehicle, VehicleKind};
 use crate::Simulation;
 use common::descriptions::CompanyKind;
@@ -15,8 +14,11 @@ pub mod desire;
 pub mod freight_station;
 pub mod goods_company;
 pub mod human;
+pub mod population;
+pub mod satisfaction;
 
-/// Adds souls to empty buildings
+/// Adds souls to empty buildings, except houses which are filled by the newcomers of
+/// [`population::population_system`]
 pub(crate) fn add_souls_to_empty_buildings(sim: &mut Simulation) {
     profiling::scope!("souls::add_souls_to_empty_buildings");
     let map = sim.map();
@@ -38,16 +40,6 @@ pub(crate) fn add_souls_to_empty_buildings(sim: &mut Simulation) {
 
     let mut n_souls_added = 0;
 
-    for &(build_id, _) in empty_buildings
-        .get(&BuildingKind::House)
-        .unwrap_or(&vec![])
-        .iter()
-        .take(50)
-    {
-        spawn_human(sim, build_id);
-        n_souls_added += 1;
-    }
-
     for &(build_id, _) in empty_buildings
         .get(&BuildingKind::RailFreightStation)
         .unwrap_or(&vec![])
@@ -85,7 +77,7 @@ pub(crate) fn add_souls_to_empty_buildings(sim: &mut Simulation) {
             recipe: des.recipe.clone(),
             max_workers: des.n_workers,
             progress: 0.0,
-            driver: None,
+            drivers: vec![],
             trucks: {
                 drop(registry);
                 unwrap_or!(mk_trucks(sim), continue)
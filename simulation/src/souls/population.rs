@@ -0,0 +1,608 @@
+//! Population dynamics
+//!
+//! Every in-game day, humans get one year older, some are born and some die.
+//! Working age adults move in when companies cannot find workers and leave when they cannot find a job.
+//! Newcomers fill the houses with room left first, then move into the empty houses as a household.
+
+use crate::economy::{ItemID, ItemRegistry, Market};
+use crate::map::{BuildingID, BuildingKind};
+use crate::map_dynamic::BuildingInfos;
+use crate::souls::desire::WorkKind;
+use crate::souls::human::{
+    spawn_human, spawn_human_with, Gender, LifeStage, PersonalInfo, ADULT_AGE, RETIREMENT_AGE,
+};
+use crate::souls::satisfaction::{SatisfactionStats, UNHAPPY_SATISFACTION};
+use crate::transportation::Location;
+use crate::utils::rand_provider::RandProvider;
+use crate::utils::time::GameTime;
+use crate::world::{HumanEnt, HumanID, VehicleEnt};
+use crate::{ParCommandBuffer, Simulation, SoulID};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, VecDeque};
+
+/// Nobody lives longer than this
+pub const MAX_AGE: u8 = 100;
+/// Age from which the chance to die each year rises
+const OLD_AGE: u8 = 60;
+/// Chance to die each year before [`OLD_AGE`]
+const YOUNG_DEATH_CHANCE: f32 = 0.001;
+/// Women can give birth up to this age
+const MAX_MOTHER_AGE: u8 = 45;
+/// Chance each year for a household with a possible mother and some room to have a child
+const BIRTH_CHANCE: f32 = 0.2;
+/// Most humans moving in or out of the city each day
+pub const MAX_MIGRANTS_PER_DAY: usize = 10;
+/// Chance each day for an unhappy adult who does not own its house to leave the city
+const UNHAPPY_LEAVE_CHANCE: f32 = 0.2;
+/// How many days of population are kept for the graphs
+pub const POPULATION_HISTORY_DAYS: usize = 100;
+
+/// The population of the city at the end of a day and what changed it during the day
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
+pub struct PopulationDay {
+    pub children: u32,
+    pub workers: u32,
+    pub retirees: u32,
+    pub births: u32,
+    pub deaths: u32,
+    pub immigrants: u32,
+    pub emigrants: u32,
+}
+
+impl PopulationDay {
+    pub fn total(&self) -> u32 {
+        self.children + self.workers + self.retirees
+    }
+}
+
+/// Population history, updated every day by [`population_system`]
+#[derive(Default, Serialize, Deserialize)]
+pub struct Population {
+    last_day: Option<i32>,
+    history: VecDeque<PopulationDay>,
+}
+
+impl Population {
+    /// Oldest day first
+    pub fn history(&self) -> impl Iterator<Item = &PopulationDay> + '_ {
+        self.history.iter()
+    }
+
+    pub fn last_day(&self) -> Option<&PopulationDay> {
+        self.history.back()
+    }
+}
+
+/// Chance to die during a year at the given age
+fn death_chance(age: u8) -> f32 {
+    if age >= MAX_AGE {
+        return 1.0;
+    }
+    if age < OLD_AGE {
+        return YOUNG_DEATH_CHANCE;
+    }
+    let t = (age - OLD_AGE) as f32 / (MAX_AGE - OLD_AGE) as f32;
+    YOUNG_DEATH_CHANCE + 0.5 * t * t
+}
+
+/// Drivers on a delivery finish it before leaving their job
+fn is_busy(h: &HumanEnt) -> bool {
+    h.work
+        .as_ref()
+        .is_some_and(|w| matches!(w.kind, WorkKind::Driver { .. }) && !w.kind.is_idle_driver())
+}
+
+/// Humans can only leave the city or die when they are in a building, so that they do not
+/// leave a vehicle without a driver
+fn can_leave(h: &HumanEnt) -> bool {
+    matches!(h.location, Location::Building(_)) && !is_busy(h)
+}
+
+/// Moves a family into an empty house: an adult, maybe a partner, children and a grandparent,
+/// as many as the house can hold and at most `max`
+pub fn spawn_household(sim: &mut Simulation, house: BuildingID, max: u32) -> Vec<HumanID> {
+    let Some(capacity) = sim.map().buildings().get(house).map(|b| b.capacity()) else {
+        return vec![];
+    };
+
+    let mut rng = sim.write::<RandProvider>();
+    let head = PersonalInfo::new(&mut rng);
+    let family = head.last_name().to_string();
+    let mut members = vec![head];
+    if rng.next_f32() < 0.5 {
+        let age = (rng.next_f32() * 30.0 + 20.0) as u8;
+        members.push(PersonalInfo::new_aged(&mut rng, age, Some(&family)));
+    }
+    for _ in 0..rng.next_u32() % 3 {
+        let age = (rng.next_f32() * ADULT_AGE as f32) as u8;
+        members.push(PersonalInfo::new_aged(&mut rng, age, Some(&family)));
+    }
+    if rng.next_f32() < 0.1 {
+        let age = RETIREMENT_AGE + (rng.next_f32() * 20.0) as u8;
+        members.push(PersonalInfo::new_aged(&mut rng, age, Some(&family)));
+    }
+    members.truncate(capacity.min(max) as usize);
+    drop(rng);
+
+    members
+        .into_iter()
+        .filter_map(|info| spawn_human_with(sim, house, info))
+        .collect()
+}
+
+/// The human leaves their job if they have one, the company offers it again
+fn leave_job(sim: &mut Simulation, id: HumanID, job_opening: ItemID) {
+    if let Some(h) = sim.world.humans.get_mut(id) {
+        h.work = None;
+    }
+    let Some((company, c)) = sim
+        .world
+        .companies
+        .iter_mut()
+        .find(|(_, c)| c.workers.0.contains(&id))
+    else {
+        return;
+    };
+    c.workers.0.retain(|&w| w != id);
+    c.comp.drivers.retain(|&w| w != id);
+    let building = c.comp.building;
+
+    let Some(door) = sim.map().buildings().get(building).map(|b| b.door_pos.xy()) else {
+        return;
+    };
+    let soul = SoulID::GoodsCompany(company);
+    let mut m = sim.write::<Market>();
+    m.produce(soul, job_opening, 1);
+    m.sell_all(soul, door, job_opening, 0);
+}
+
+/// The human is removed from the city with their vehicle
+fn remove_human(sim: &mut Simulation, id: HumanID, job_opening: ItemID) {
+    leave_job(sim, id, job_opening);
+    let Some(h) = sim.world.humans.get(id) else {
+        return;
+    };
+    for vehicle in [h.router.personal_car, h.router.personal_bike]
+        .into_iter()
+        .flatten()
+    {
+        sim.read::<ParCommandBuffer<VehicleEnt>>().kill(vehicle);
+    }
+    sim.read::<ParCommandBuffer<HumanEnt>>().kill(id);
+}
+
+/// Ages the humans, makes them be born, die and migrate once per day
+pub fn population_system(sim: &mut Simulation) {
+    profiling::scope!("souls::population_system");
+    let today = sim.read::<GameTime>().daytime.day;
+    {
+        let mut pop = sim.write::<Population>();
+        let last_day = pop.last_day.replace(today);
+        // the first day of a game only starts the count
+        if last_day.is_none_or(|d| d >= today) {
+            return;
+        }
+    }
+
+    let job_opening = sim.read::<ItemRegistry>().id("job-opening");
+    let mut record = PopulationDay::default();
+
+    // get older, and die
+    let mut dead = vec![];
+    {
+        let mut rng = sim.resources.write::<RandProvider>();
+        for (id, h) in sim.world.humans.iter_mut() {
+            let info = &mut h.personal_info;
+            info.age = info.age.saturating_add(1);
+            let chance = death_chance(info.age);
+            if rng.next_f32() < chance && can_leave(h) {
+                dead.push(id);
+            }
+        }
+    }
+    record.deaths = dead.len() as u32;
+    for &id in &dead {
+        remove_human(sim, id, job_opening);
+    }
+    // the removed humans are still in the world until the end of the system
+    let mut gone = dead;
+
+    // children look for a job once they are adults and workers leave it when they retire
+    let hired: BTreeSet<HumanID> = sim
+        .world
+        .companies
+        .values()
+        .flat_map(|c| c.workers.0.iter().copied())
+        .collect();
+    let mut seekers = vec![];
+    let mut retired = vec![];
+    let mut new_adults = vec![];
+    {
+        let market = sim.read::<Market>();
+        let jobs = &market.inner()[&job_opening];
+        for (id, h) in sim.world.humans.iter() {
+            if gone.contains(&id) {
+                continue;
+            }
+            let soul = SoulID::Human(id);
+            let employed = h.work.is_some() || hired.contains(&id);
+            match h.personal_info.stage() {
+                LifeStage::Worker if employed => {}
+                LifeStage::Worker => match jobs.buy_order(soul) {
+                    Some(_) => seekers.push(id),
+                    None => new_adults.push(id),
+                },
+                _ if (employed && !is_busy(h)) || jobs.buy_order(soul).is_some() => {
+                    retired.push(id)
+                }
+                _ => {}
+            }
+        }
+    }
+    for id in retired {
+        leave_job(sim, id, job_opening);
+        sim.write::<Market>()
+            .cancel_buy(SoulID::Human(id), job_opening);
+    }
+    for id in new_adults {
+        let Some(house) = sim.world.humans.get(id).map(|h| h.home.house) else {
+            continue;
+        };
+        let Some(pos) = sim.map().buildings().get(house).map(|b| b.door_pos.xy()) else {
+            continue;
+        };
+        sim.write::<Market>()
+            .buy(SoulID::Human(id), pos, job_opening, 1);
+        seekers.push(id);
+    }
+
+    // migrate depending on the jobs that are offered and the room in the houses
+    let openings: i32 = sim
+        .world
+        .companies
+        .keys()
+        .map(|c| {
+            sim.read::<Market>()
+                .capital(SoulID::GoodsCompany(c), job_opening)
+                .max(0)
+        })
+        .sum();
+    let mut openings = openings as usize;
+    // a new city attracts its first inhabitants, see the zoning of the first house
+    if sim.world.humans.len() == gone.len() {
+        openings = openings.max(1);
+    }
+    if seekers.len() > openings {
+        // the owners stay to keep the house
+        let binfos = sim.read::<BuildingInfos>();
+        let emigrants: Vec<HumanID> = seekers
+            .iter()
+            .copied()
+            .filter(|&id| {
+                let h = &sim.world.humans[id];
+                can_leave(h) && binfos.owner(h.home.house) != Some(SoulID::Human(id))
+            })
+            .take((seekers.len() - openings).min(MAX_MIGRANTS_PER_DAY))
+            .collect();
+        drop(binfos);
+        record.emigrants = emigrants.len() as u32;
+        for &id in &emigrants {
+            remove_human(sim, id, job_opening);
+        }
+        gone.extend(emigrants);
+    } else if openings > seekers.len() {
+        // fewer newcomers want to live in a city where people are unhappy
+        let attractiveness = sim.read::<SatisfactionStats>().attractiveness();
+        let n = (openings - seekers.len()).min(MAX_MIGRANTS_PER_DAY);
+        let mut n = (n as f32 * attractiveness).ceil() as usize;
+        let mut houses: Vec<(BuildingID, u32, bool)> = {
+            let map = sim.map();
+            let binfos = sim.read::<BuildingInfos>();
+            map.buildings()
+                .values()
+                .filter(|b| b.kind == BuildingKind::House)
+                .filter_map(|b| {
+                    let residents = binfos.residents(b.id).len() as u32;
+                    (residents < b.capacity())
+                        .then(|| (b.id, b.capacity() - residents, residents == 0))
+                })
+                .collect()
+        };
+        houses.sort_by_key(|&(_, _, empty)| empty);
+        for (house, room, empty) in houses {
+            if n == 0 {
+                break;
+            }
+            if empty {
+                let household = spawn_household(sim, house, n as u32);
+                record.immigrants += household.len() as u32;
+                n -= household.len();
+                continue;
+            }
+            for _ in 0..room.min(n as u32) {
+                if spawn_human(sim, house).is_some() {
+                    record.immigrants += 1;
+                    n -= 1;
+                }
+            }
+        }
+    }
+
+    // unhappy humans move out too
+    let unhappy: Vec<HumanID> = {
+        let binfos = sim.read::<BuildingInfos>();
+        let mut rng = sim.resources.write::<RandProvider>();
+        sim.world
+            .humans
+            .iter()
+            .filter(|&(id, h)| {
+                !gone.contains(&id)
+                    && h.personal_info.stage() != LifeStage::Child
+                    && h.satisfaction.value < UNHAPPY_SATISFACTION
+                    && can_leave(h)
+                    && binfos.owner(h.home.house) != Some(SoulID::Human(id))
+            })
+            .map(|(id, _)| id)
+            .filter(|_| rng.next_f32() < UNHAPPY_LEAVE_CHANCE)
+            .take(MAX_MIGRANTS_PER_DAY.saturating_sub(record.emigrants as usize))
+            .collect()
+    };
+    record.emigrants += unhappy.len() as u32;
+    for &id in &unhappy {
+        remove_human(sim, id, job_opening);
+    }
+    gone.extend(unhappy);
+
+    // mothers with room in their house give birth
+    let mut births = vec![];
+    {
+        let map = sim.map();
+        let binfos = sim.read::<BuildingInfos>();
+        let mut rng = sim.write::<RandProvider>();
+        let mut houses_done = BTreeSet::new();
+        for (id, h) in sim.world.humans.iter() {
+            if gone.contains(&id) {
+                continue;
+            }
+            let info = &h.personal_info;
+            if !matches!(info.gender, Gender::F)
+                || !(ADULT_AGE..=MAX_MOTHER_AGE).contains(&info.age)
+            {
+                continue;
+            }
+            let house = h.home.house;
+            let Some(b) = map.buildings().get(house) else {
+                continue;
+            };
+            if !houses_done.insert(house) || binfos.residents(house).len() as u32 >= b.capacity() {
+                continue;
+            }
+            if rng.next_f32() < BIRTH_CHANCE {
+                let child = PersonalInfo::new_aged(&mut rng, 0, Some(info.last_name()));
+                births.push((house, child));
+            }
+        }
+    }
+    for (house, child) in births {
+        if spawn_human_with(sim, house, child).is_some() {
+            record.births += 1;
+        }
+    }
+
+    for (id, h) in sim.world.humans.iter() {
+        if gone.contains(&id) {
+            continue;
+        }
+        match h.personal_info.stage() {
+            LifeStage::Child => record.children += 1,
+            LifeStage::Worker => record.workers += 1,
+            LifeStage::Retiree => record.retirees += 1,
+        }
+    }
+
+    let mut pop = sim.write::<Population>();
+    pop.history.push_back(record);
+    if pop.history.len() > POPULATION_HISTORY_DAYS {
+        pop.history.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{population_system, spawn_household, Population, MAX_AGE};
+    use crate::economy::{ItemRegistry, Market};
+    use crate::map::{BuildingID, BuildingKind};
+    use crate::map_dynamic::BuildingInfos;
+    use crate::souls::desire::{Work, WorkKind};
+    use crate::souls::goods_company::GoodsCompanyRegistry;
+    use crate::souls::human::{spawn_human, RETIREMENT_AGE};
+    use crate::tests::TestCtx;
+    use crate::utils::time::{GameTime, SECONDS_PER_DAY};
+    use crate::world::HumanID;
+    use crate::{SoulID, WorldCommand};
+    use common::descriptions::BuildingGen;
+    use geom::{vec2, vec3, OBB};
+
+    /// Runs the population update of the next day while the humans are still at home,
+    /// the removed humans are gone after the next tick
+    fn next_day(test: &mut TestCtx) {
+        let mut time = test.g.write::<GameTime>();
+        *time = GameTime::new(0.0, time.timestamp + SECONDS_PER_DAY as f64);
+        drop(time);
+        population_system(&mut test.g);
+    }
+
+    fn residents(test: &TestCtx, house: BuildingID) -> Vec<HumanID> {
+        test.g
+            .read::<BuildingInfos>()
+            .residents(house)
+            .iter()
+            .filter_map(|&s| match s {
+                SoulID::Human(id) => Some(id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Builds a house on the biggest lot
+    fn build_big_house(test: &TestCtx) -> BuildingID {
+        let lot = test
+            .g
+            .map()
+            .lots()
+            .values()
+            .max_by_key(|l| l.shape.axis()[0].mag() as i32)
+            .unwrap()
+            .id;
+        let b = test.g.map_mut().build_house(lot).unwrap();
+        test.g.write::<BuildingInfos>().insert(b);
+        b
+    }
+
+    #[test]
+    fn test_households_age_and_die() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0., 0., 0.), vec3(300., 0., 0.)]);
+        let house = build_big_house(&test);
+        spawn_household(&mut test.g, house, u32::MAX);
+        test.tick();
+
+        let members = residents(&test, house);
+        let capacity = test.g.map().buildings()[house].capacity();
+        assert_eq!(capacity, 8);
+        assert!(!members.is_empty() && members.len() as u32 <= capacity);
+        assert_eq!(members.len(), test.g.world().humans.len());
+
+        let owner = test.g.read::<BuildingInfos>().owner(house).unwrap();
+        let SoulID::Human(head) = owner else {
+            panic!("the house is owned by {owner:?}");
+        };
+        let ages: Vec<u8> = members
+            .iter()
+            .map(|&id| test.g.world().humans[id].personal_info.age)
+            .collect();
+        test.g.world_mut_unchecked().humans[head].personal_info.age = MAX_AGE - 1;
+
+        next_day(&mut test);
+        test.tick();
+        assert!(!test.g.world().humans.contains_key(head));
+        assert!(test.g.read::<Population>().last_day().unwrap().deaths >= 1);
+        let left = residents(&test, house);
+        assert!(!left.contains(&head));
+        assert_eq!(
+            test.g.read::<BuildingInfos>().owner(house),
+            left.first().map(|&id| SoulID::Human(id))
+        );
+        for (&id, age) in members.iter().zip(ages) {
+            if let Some(h) = test.g.world().humans.get(id) {
+                assert_eq!(h.personal_info.age, age + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_retirement_and_immigration() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0., 0., 0.), vec3(300., 0., 0.)]);
+        let house = build_big_house(&test);
+        let registry = test.g.read::<GoodsCompanyRegistry>();
+        let (factory, _) = registry
+            .descriptions
+            .iter()
+            .find(|(_, d)| d.name == "Flour Factory")
+            .unwrap();
+        drop(registry);
+        let pos = vec2(150.0, -25.0);
+        test.apply(&[WorldCommand::MapBuildSpecialBuilding {
+            pos: OBB::new(pos, vec2(1.0, 0.0), 10.0, 10.0),
+            kind: BuildingKind::GoodsCompany(factory),
+            gen: BuildingGen::NoWalkway {
+                door_pos: pos + vec2(0.0, 10.0),
+            },
+            zone: None,
+        }]);
+        spawn_household(&mut test.g, house, u32::MAX);
+        test.tick();
+
+        let head = residents(&test, house)[0];
+        let (company, _) = test.g.world().companies.iter().next().unwrap();
+        let job_opening = test.g.read::<ItemRegistry>().id("job-opening");
+        let capital = |test: &TestCtx| {
+            test.g
+                .read::<Market>()
+                .capital(SoulID::GoodsCompany(company), job_opening)
+        };
+        // the head works at the factory until retirement
+        let world = test.g.world_mut_unchecked();
+        world.companies[company].workers.0.push(head);
+        world.humans[head].personal_info.age = RETIREMENT_AGE - 1;
+        let openings = capital(&test);
+        let n_residents = residents(&test, house).len();
+
+        next_day(&mut test);
+        let c = &test.g.world().companies[company];
+        assert!(!c.workers.0.contains(&head));
+        assert_eq!(capital(&test), openings + 1);
+
+        // the factory cannot find enough workers, some come to live in the house
+        let record = *test.g.read::<Population>().last_day().unwrap();
+        assert!(record.immigrants > 0);
+        assert!(record.retirees >= 1);
+        assert!(residents(&test, house).len() > n_residents);
+        assert!(residents(&test, house).len() <= 8);
+    }
+
+    #[test]
+    fn test_empty_houses_need_openings() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0., 0., 0.), vec3(300., 0., 0.)]);
+        let house = build_big_house(&test);
+        let empty = test.build_house_near(vec2(50.0, 30.0));
+        assert_ne!(house, empty);
+
+        // the house is full of humans who already work
+        let fill = |test: &mut TestCtx| {
+            for _ in residents(test, house).len()..8 {
+                spawn_human(&mut test.g, house);
+            }
+            for h in test.g.world_mut_unchecked().humans.values_mut() {
+                h.work = Some(Work::new(house, WorkKind::Worker, 0.0));
+            }
+            test.tick();
+        };
+        fill(&mut test);
+
+        // nobody moves in without jobs
+        next_day(&mut test);
+        assert_eq!(
+            test.g.read::<Population>().last_day().unwrap().immigrants,
+            0
+        );
+        assert!(residents(&test, empty).is_empty());
+
+        let registry = test.g.read::<GoodsCompanyRegistry>();
+        let (factory, _) = registry
+            .descriptions
+            .iter()
+            .find(|(_, d)| d.name == "Flour Factory")
+            .unwrap();
+        drop(registry);
+        let pos = vec2(150.0, -25.0);
+        test.apply(&[WorldCommand::MapBuildSpecialBuilding {
+            pos: OBB::new(pos, vec2(1.0, 0.0), 10.0, 10.0),
+            kind: BuildingKind::GoodsCompany(factory),
+            gen: BuildingGen::NoWalkway {
+                door_pos: pos + vec2(0.0, 10.0),
+            },
+            zone: None,
+        }]);
+        fill(&mut test);
+
+        // the newcomers for the jobs of the factory move into the empty house
+        next_day(&mut test);
+        let record = *test.g.read::<Population>().last_day().unwrap();
+        assert!(record.immigrants > 0);
+        assert!(record.immigrants as usize <= super::MAX_MIGRANTS_PER_DAY);
+        assert_eq!(residents(&test, empty).len(), record.immigrants as usize);
+    }
+}
@@ -0,0 +1,338 @@
+//! Citizen satisfaction
+//!
+//! How well the needs of each human are met, from their commute, food, job,
+//! the noise and pollution around their home and its utilities.
+//! Unhappy humans are more likely to leave the city and work less.
+
+use crate::economy::LEVEL_FREQS;
+use crate::map::{BuildingID, Map, ProjectFilter, ProjectKind, UtilityKind};
+use crate::map_dynamic::{BuildingInfos, UtilityGrid};
+use crate::souls::human::LifeStage;
+use crate::transportation::traffic_stats::TrafficStats;
+use crate::utils::resources::Resources;
+use crate::utils::time::{GameTime, Tick, SECONDS_PER_HOUR};
+use crate::world::{HumanEnt, HumanID};
+use crate::{SoulID, World};
+use common::descriptions::CompanyKind;
+use egui_inspect::Inspect;
+use serde::{Deserialize, Serialize};
+use slotmapd::HopSlotMap;
+use std::collections::BTreeMap;
+
+/// Every how many ticks the satisfaction is updated, the traffic is counted over the same period
+pub const SATISFACTION_PERIOD: u32 = LEVEL_FREQS[0];
+/// Satisfaction under which humans may leave the city
+pub const UNHAPPY_SATISFACTION: f32 = 0.25;
+/// A commute this long in game seconds is as bad as it gets
+const MAX_COMMUTE: f32 = SECONDS_PER_HOUR as f32;
+/// Roads closer than this to a home are heard, in meters
+const NOISE_RADIUS: f32 = 40.0;
+/// Vehicles passing near a home during a period to make it as noisy as it gets
+const MAX_NOISE_VEHICLES: f32 = 200.0;
+/// Factories closer than this to a home pollute it, in meters
+const POLLUTION_RADIUS: f32 = 150.0;
+/// Jobs of the factories around a home to make it as polluted as it gets
+const MAX_POLLUTION_JOBS: f32 = 50.0;
+
+/// How well the needs of a human are met, each part goes from 0 (bad) to 1 (good)
+#[derive(Inspect, Clone, Debug, Serialize, Deserialize)]
+pub struct Satisfaction {
+    /// Average of the parts that apply to the human
+    pub value: f32,
+    /// Only for humans who went to work at least once
+    pub commute: Option<f32>,
+    pub food: f32,
+    /// Only for working age adults
+    pub employment: Option<f32>,
+    pub noise: f32,
+    pub pollution: f32,
+    /// Only when the city has a utility network
+    pub utilities: Option<f32>,
+    /// Duration of the last trip to work, in game seconds
+    pub last_commute: Option<f32>,
+}
+
+impl Default for Satisfaction {
+    /// Neutral until the first update
+    fn default() -> Self {
+        Self {
+            value: 0.5,
+            commute: None,
+            food: 0.5,
+            employment: None,
+            noise: 0.5,
+            pollution: 0.5,
+            utilities: None,
+            last_commute: None,
+        }
+    }
+}
+
+impl Satisfaction {
+    /// Multiplier of the work done by the human, satisfied humans work harder
+    pub fn productivity(&self) -> f32 {
+        0.75 + 0.5 * self.value
+    }
+
+    fn update_value(&mut self) {
+        let parts = [
+            self.commute,
+            Some(self.food),
+            self.employment,
+            Some(self.noise),
+            Some(self.pollution),
+            self.utilities,
+        ];
+        let (sum, n) = parts
+            .iter()
+            .flatten()
+            .fold((0.0, 0), |(sum, n), v| (sum + v, n + 1));
+        self.value = sum / n as f32;
+    }
+}
+
+/// Average productivity multiplier of the workers of a company, 1 if there are none
+pub fn workers_productivity(humans: &HopSlotMap<HumanID, HumanEnt>, workers: &[HumanID]) -> f32 {
+    let (sum, n) = workers
+        .iter()
+        .filter_map(|&w| humans.get(w))
+        .fold((0.0, 0), |(sum, n), h| {
+            (sum + h.satisfaction.productivity(), n + 1)
+        });
+    if n == 0 {
+        return 1.0;
+    }
+    sum / n as f32
+}
+
+/// City-wide averages of the satisfaction, updated by [`satisfaction_system`]
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct SatisfactionStats {
+    pub humans: u32,
+    /// Humans under [`UNHAPPY_SATISFACTION`]
+    pub unhappy: u32,
+    pub satisfaction: f32,
+    pub commute: f32,
+    pub food: f32,
+    pub employment: f32,
+    pub noise: f32,
+    pub pollution: f32,
+    pub utilities: f32,
+}
+
+impl SatisfactionStats {
+    /// How much newcomers want to move in, from 0 to 1
+    pub fn attractiveness(&self) -> f32 {
+        if self.humans == 0 {
+            return 1.0;
+        }
+        self.satisfaction
+    }
+}
+
+/// Noise and pollution around a home, 0 is quiet and clean
+fn nuisances(
+    map: &Map,
+    binfos: &BuildingInfos,
+    traffic: &TrafficStats,
+    world: &World,
+    house: BuildingID,
+) -> (f32, f32) {
+    let Some(b) = map.buildings().get(house) else {
+        return (0.0, 0.0);
+    };
+    let pos = b.door_pos.xy();
+
+    let mut vehicles = 0;
+    for kind in map
+        .spatial_map()
+        .query_around(pos, NOISE_RADIUS, ProjectFilter::ROAD)
+    {
+        let ProjectKind::Road(r) = kind else {
+            continue;
+        };
+        let Some(road) = map.roads().get(r) else {
+            continue;
+        };
+        for (lane, _) in road.lanes_iter() {
+            if let Some(t) = traffic.lane(lane) {
+                vehicles += traffic.last_complete(0, &t.vehicles);
+            }
+        }
+    }
+
+    let mut jobs = 0;
+    for kind in map
+        .spatial_map()
+        .query_around(pos, POLLUTION_RADIUS, ProjectFilter::BUILDING)
+    {
+        let ProjectKind::Building(id) = kind else {
+            continue;
+        };
+        let Some(SoulID::GoodsCompany(c)) = binfos.owner(id) else {
+            continue;
+        };
+        let Some(c) = world.companies.get(c) else {
+            continue;
+        };
+        if matches!(c.comp.kind, CompanyKind::Factory { .. }) {
+            jobs += c.comp.max_workers.max(0);
+        }
+    }
+
+    (
+        (vehicles as f32 / MAX_NOISE_VEHICLES).min(1.0),
+        (jobs as f32 / MAX_POLLUTION_JOBS).min(1.0),
+    )
+}
+
+/// Keeps the duration of the trips to work completed by the router and updates the
+/// satisfaction of every human periodically
+pub fn satisfaction_system(world: &mut World, res: &mut Resources) {
+    profiling::scope!("souls::satisfaction_system");
+    let time = res.read::<GameTime>();
+
+    for h in world.humans.values_mut() {
+        let Some((to, secs)) = h.router.last_trip.take() else {
+            continue;
+        };
+        if h.work.as_ref().is_some_and(|w| w.workplace == to) {
+            h.satisfaction.last_commute = Some(secs);
+        }
+    }
+
+    if !res.read::<Tick>().0.is_multiple_of(SATISFACTION_PERIOD) {
+        return;
+    }
+
+    let map = res.read::<Map>();
+    let binfos = res.read::<BuildingInfos>();
+    let traffic = res.read::<TrafficStats>();
+    let grid = res.read::<UtilityGrid>();
+
+    let mut homes = BTreeMap::new();
+    for h in world.humans.values() {
+        homes
+            .entry(h.home.house)
+            .or_insert_with(|| nuisances(&map, &binfos, &traffic, world, h.home.house));
+    }
+
+    let mut stats = SatisfactionStats::default();
+    let mut n_commute = 0;
+    let mut n_employment = 0;
+    let mut n_utilities = 0;
+    let has_network = grid.has_network();
+    for h in world.humans.values_mut() {
+        let house = h.home.house;
+        let (noise, pollution) = homes[&house];
+        let s = &mut h.satisfaction;
+
+        s.commute = s
+            .last_commute
+            .map(|secs| 1.0 - (secs / MAX_COMMUTE).clamp(0.0, 1.0));
+        let hunger = h.food.last_ate.elapsed(&time) as f32 / GameTime::DAY as f32 - 1.0;
+        s.food = 1.0 - hunger.clamp(0.0, 1.0);
+        s.employment = (h.personal_info.stage() == LifeStage::Worker)
+            .then_some(if h.work.is_some() { 1.0 } else { 0.0 });
+        s.noise = 1.0 - noise;
+        s.pollution = 1.0 - pollution;
+        s.utilities = has_network.then(|| {
+            UtilityKind::ALL
+                .iter()
+                .map(|&kind| grid.served(house, kind))
+                .sum::<f32>()
+                / UtilityKind::ALL.len() as f32
+        });
+        s.update_value();
+
+        stats.humans += 1;
+        if s.value < UNHAPPY_SATISFACTION {
+            stats.unhappy += 1;
+        }
+        stats.satisfaction += s.value;
+        if let Some(commute) = s.commute {
+            stats.commute += commute;
+            n_commute += 1;
+        }
+        stats.food += s.food;
+        if let Some(employment) = s.employment {
+            stats.employment += employment;
+            n_employment += 1;
+        }
+        stats.noise += s.noise;
+        stats.pollution += s.pollution;
+        if let Some(utilities) = s.utilities {
+            stats.utilities += utilities;
+            n_utilities += 1;
+        }
+    }
+
+    let n = stats.humans.max(1) as f32;
+    stats.satisfaction /= n;
+    stats.food /= n;
+    stats.noise /= n;
+    stats.pollution /= n;
+    stats.commute /= n_commute.max(1) as f32;
+    stats.employment /= n_employment.max(1) as f32;
+    stats.utilities /= n_utilities.max(1) as f32;
+
+    *res.write::<SatisfactionStats>() = stats;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        satisfaction_system, workers_productivity, SatisfactionStats, SATISFACTION_PERIOD,
+    };
+    use crate::souls::desire::{Work, WorkKind};
+    use crate::souls::population::spawn_household;
+    use crate::tests::TestCtx;
+    use crate::utils::time::{GameInstant, GameTime, Tick, SECONDS_PER_DAY, SECONDS_PER_HOUR};
+    use crate::world::HumanID;
+    use geom::{vec2, vec3};
+
+    #[test]
+    fn test_satisfaction() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0., 0., 0.), vec3(300., 0., 0.)]);
+        let house = test.build_house_near(vec2(150.0, 30.0));
+        spawn_household(&mut test.g, house, u32::MAX);
+        test.tick();
+
+        let humans: Vec<HumanID> = test.g.world().humans.keys().collect();
+        assert!(!humans.is_empty());
+        let id = humans[0];
+
+        // just got to work after half an hour without having eaten for two days
+        let now = test.g.read::<GameTime>().timestamp;
+        let h = &mut test.g.world_mut_unchecked().humans[id];
+        h.work = Some(Work::new(house, WorkKind::Worker, 0.0));
+        h.router.last_trip = Some((house, SECONDS_PER_HOUR as f32 / 2.0));
+        h.food.last_ate = GameInstant {
+            timestamp: now + SECONDS_PER_HOUR as f64 / 2.0 - 2.0 * SECONDS_PER_DAY as f64,
+        };
+        *test.g.write::<GameTime>() = GameTime::new(0.0, now + SECONDS_PER_HOUR as f64 / 2.0);
+        test.g.write::<Tick>().0 = SATISFACTION_PERIOD;
+        satisfaction_system(&mut test.g.world, &mut test.g.resources);
+
+        let s = &test.g.world().humans[id].satisfaction;
+        assert_eq!(s.last_commute, Some(SECONDS_PER_HOUR as f32 / 2.0));
+        assert_eq!(s.commute, Some(0.5));
+        assert_eq!(s.food, 0.0);
+        // there is no utility network
+        assert_eq!(s.utilities, None);
+        assert!((0.0..=1.0).contains(&s.value));
+
+        let stats = test.g.read::<SatisfactionStats>().clone();
+        assert_eq!(stats.humans as usize, humans.len());
+        assert_eq!(stats.commute, 0.5);
+        assert!(stats.food < 1.0);
+        assert!((stats.attractiveness() - stats.satisfaction).abs() < 1e-6);
+
+        // satisfied workers work harder
+        let p = workers_productivity(&test.g.world().humans, &[id]);
+        assert_eq!(p, s.productivity());
+        assert!((0.75..=1.25).contains(&p));
+        assert_eq!(workers_productivity(&test.g.world().humans, &[]), 1.0);
+    }
+}
@@ -5,7 +5,7 @@ use crate::utils::time::Tick;
 use crate::World;
 use crate::{Replay, Simulation};
 use common::logger::MyLog;
-use common::saveload::{Bincode, Encoder};
+use common::saveload::{Bincode, Encoder, SaveStore};
 use geom::vec3;
 use quickcheck::{Arbitrary, Gen};
 
@@ -276,19 +276,19 @@ fn test_world_survives_serde() {
 
         if !deser.is_equal(&sim) {
             println!("not equal");
-            deser.save_to_disk("world");
-            sim.save_to_disk("world2");
+            deser.save_to_slot(&SaveStore::default(), "world");
+            sim.save_to_slot(&SaveStore::default(), "world2");
             assert!(false);
         }
         if !deser.is_equal(&sim2) {
             println!("not equal");
-            deser.save_to_disk("world");
-            sim2.save_to_disk("world2");
+            deser.save_to_slot(&SaveStore::default(), "world");
+            sim2.save_to_slot(&SaveStore::default(), "world2");
             assert!(false);
         }
 
         std::mem::swap(&mut deser, &mut sim2);
     }
 
-    sim.save_to_disk("world2");
+    sim.save_to_slot(&SaveStore::default(), "world2");
 }
@@ -1,13 +1,16 @@
 use crate::map::BuildingID;
 use serde::{Deserialize, Serialize};
 
+pub mod passenger_rail;
 pub mod pedestrian;
 pub mod road;
 pub mod testing_vehicles;
+pub mod traffic_stats;
 pub mod train;
+pub mod transit;
 mod vehicle;
 
-use crate::world::VehicleID;
+use crate::world::{TrainID, VehicleID};
 pub use pedestrian::*;
 pub use vehicle::*;
 
@@ -16,5 +19,6 @@ pub enum Location {
     Outside,
     Vehicle(VehicleID),
     Building(BuildingID),
+    Train(TrainID),
 }
 debug_inspect_impl!(Location);
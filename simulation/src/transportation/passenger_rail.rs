@@ -0,0 +1,401 @@
+use crate::map::{BuildingID, BuildingKind, LaneID, LaneKind, Map, PathKind, TravelTimes};
+use crate::map_dynamic::{DispatchID, Dispatcher, Itinerary};
+use crate::transportation::train::{spawn_train, RailWagonKind};
+use crate::transportation::transit::{closest, WALK_SPEED_ESTIMATE};
+use crate::utils::resources::Resources;
+use crate::utils::time::{GameTime, Tick};
+use crate::world::TrainID;
+use crate::{Simulation, World};
+use geom::Vec3;
+use serde::{Deserialize, Serialize};
+use slotmapd::{new_key_type, SlotMap};
+use std::collections::BTreeMap;
+
+new_key_type! {
+    pub struct PassengerLineID;
+}
+
+debug_inspect_impl!(PassengerLineID);
+
+/// Minimum time a train stays at a station so passengers can get in and out, in seconds
+pub const MIN_DWELL_TIME: f64 = 30.0;
+
+/// How far a station can be from the rails serving it
+const MAX_PLATFORM_DIST: f32 = 100.0;
+
+/// Rough speed used to estimate the duration of a train ride, in m/s
+const TRAIN_SPEED_ESTIMATE: f32 = 25.0;
+
+/// Returns the lane and the position where trains stop at the given station
+pub fn station_platform(map: &Map, station: BuildingID) -> Option<(LaneID, Vec3)> {
+    let b = map.buildings().get(station)?;
+    if !matches!(b.kind, BuildingKind::TrainStation) {
+        return None;
+    }
+    let center = b.obb.center().z(b.door_pos.z);
+    let lane = map.nearest_lane(center, LaneKind::Rail, Some(MAX_PLATFORM_DIST))?;
+    Some((lane, map.lanes()[lane].points.project(center)))
+}
+
+/// A passenger line going through train stations in order, then coming back to the first one.
+/// Trains follow a clock-face timetable: they only leave stations at multiples of `headway`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PassengerLine {
+    pub id: PassengerLineID,
+    pub stations: Vec<BuildingID>,
+    /// Time between two departures, in seconds
+    pub headway: f64,
+    pub trains: Vec<TrainID>,
+}
+
+impl PassengerLine {
+    /// Returns the first scheduled departure at or after the given timestamp
+    pub fn next_departure(&self, timestamp: f64) -> f64 {
+        if self.headway <= 0.0 {
+            return timestamp;
+        }
+        (timestamp / self.headway).ceil() * self.headway
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PassengerTrainState {
+    /// Going towards the station at the given index
+    Arriving(usize),
+    /// Stopped at the station at the given index, leaving at the given timestamp
+    Boarding(usize, f64),
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct PassengerTrainInfo {
+    pub line: PassengerLineID,
+    pub state: PassengerTrainState,
+}
+
+/// A train trip from one station to another of the same line
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrainTrip {
+    pub line: PassengerLineID,
+    pub from: usize,
+    pub to: usize,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct PassengerLines {
+    lines: SlotMap<PassengerLineID, PassengerLine>,
+    trains: BTreeMap<TrainID, PassengerTrainInfo>,
+}
+
+impl PassengerLines {
+    pub fn lines(&self) -> &SlotMap<PassengerLineID, PassengerLine> {
+        &self.lines
+    }
+
+    pub fn get(&self, id: PassengerLineID) -> Option<&PassengerLine> {
+        self.lines.get(id)
+    }
+
+    pub fn train_info(&self, train: TrainID) -> Option<&PassengerTrainInfo> {
+        self.trains.get(&train)
+    }
+
+    pub fn make_line(&mut self, stations: Vec<BuildingID>, headway: f64) -> PassengerLineID {
+        self.lines.insert_with_key(|id| PassengerLine {
+            id,
+            stations,
+            headway,
+            trains: vec![],
+        })
+    }
+
+    /// Replaces the stations and timetable of the line, trains restart from the first station
+    pub fn update_line(&mut self, id: PassengerLineID, stations: Vec<BuildingID>, headway: f64) {
+        let Some(line) = self.lines.get_mut(id) else {
+            return;
+        };
+        line.stations = stations;
+        line.headway = headway;
+        for train in &line.trains {
+            if let Some(info) = self.trains.get_mut(train) {
+                info.state = PassengerTrainState::Arriving(0);
+            }
+        }
+    }
+
+    /// Removes the line, returning the trains that were running it
+    pub fn remove_line(&mut self, id: PassengerLineID) -> Vec<TrainID> {
+        let Some(line) = self.lines.remove(id) else {
+            return vec![];
+        };
+        for train in &line.trains {
+            self.trains.remove(train);
+        }
+        line.trains
+    }
+
+    pub fn add_train(&mut self, line: PassengerLineID, train: TrainID) {
+        let Some(l) = self.lines.get_mut(line) else {
+            return;
+        };
+        l.trains.push(train);
+        self.trains.insert(
+            train,
+            PassengerTrainInfo {
+                line,
+                state: PassengerTrainState::Arriving(0),
+            },
+        );
+    }
+
+    pub fn remove_train(&mut self, train: TrainID) {
+        let Some(info) = self.trains.remove(&train) else {
+            return;
+        };
+        if let Some(l) = self.lines.get_mut(info.line) {
+            l.trains.retain(|&x| x != train);
+        }
+    }
+
+    /// Returns the train of the line currently stopped at the given station, if any
+    pub fn train_at_station(&self, line: PassengerLineID, station: usize) -> Option<TrainID> {
+        let l = self.lines.get(line)?;
+        l.trains.iter().copied().find(|train| {
+            self.trains.get(train).is_some_and(
+                |info| matches!(info.state, PassengerTrainState::Boarding(s, _) if s == station),
+            )
+        })
+    }
+
+    /// Returns the station the train is currently stopped at, if any
+    pub fn train_station(&self, train: TrainID) -> Option<usize> {
+        match self.trains.get(&train)?.state {
+            PassengerTrainState::Boarding(s, _) => Some(s),
+            PassengerTrainState::Arriving(_) => None,
+        }
+    }
+
+    /// Returns the line of the train and the station it is at or going to
+    pub fn next_station(&self, train: TrainID) -> Option<(PassengerLineID, usize)> {
+        let info = self.trains.get(&train)?;
+        match info.state {
+            PassengerTrainState::Arriving(s) | PassengerTrainState::Boarding(s, _) => {
+                Some((info.line, s))
+            }
+        }
+    }
+
+    /// Finds the fastest train trip from `start` to `end` with its estimated duration in seconds,
+    /// including the walk to and from the stations
+    pub fn best_trip(&self, map: &Map, start: Vec3, end: Vec3) -> Option<(f32, TrainTrip)> {
+        let mut best: Option<(f32, TrainTrip)> = None;
+
+        for (id, line) in &self.lines {
+            if line.trains.is_empty() {
+                continue;
+            }
+            let doors: Vec<Vec3> = line
+                .stations
+                .iter()
+                .filter_map(|&s| map.buildings().get(s).map(|b| b.door_pos))
+                .collect();
+            if doors.len() != line.stations.len() || doors.len() < 2 {
+                continue;
+            }
+
+            let (from, from_d) = closest(&doors, start);
+            let (to, to_d) = closest(&doors, end);
+            if from == to {
+                continue;
+            }
+
+            let mut ride_dist = 0.0;
+            let mut n_stops = 0;
+            let mut i = from;
+            while i != to {
+                let next = (i + 1) % doors.len();
+                ride_dist += doors[i].distance(doors[next]);
+                n_stops += 1;
+                i = next;
+            }
+
+            let time = (from_d + to_d) / WALK_SPEED_ESTIMATE
+                + line.headway.max(MIN_DWELL_TIME) as f32 * 0.5
+                + n_stops as f32 * MIN_DWELL_TIME as f32
+                + ride_dist / TRAIN_SPEED_ESTIMATE;
+
+            if best.is_some_and(|(t, _)| t <= time) {
+                continue;
+            }
+            best = Some((time, TrainTrip { line: id, from, to }));
+        }
+
+        best
+    }
+}
+
+/// Spawns a passenger train at the first station of the line
+pub fn spawn_passenger_train(
+    sim: &mut Simulation,
+    line: PassengerLineID,
+    n_wagons: u32,
+) -> Option<TrainID> {
+    let station = *sim.read::<PassengerLines>().get(line)?.stations.first()?;
+
+    let map = sim.map();
+    let (lane, pos) = station_platform(&map, station)?;
+    let dist = map.lanes()[lane].points.length_at_proj(pos);
+    drop(map);
+
+    let train = spawn_train(sim, dist, n_wagons, lane, RailWagonKind::Passenger)?;
+
+    // passenger trains are not available to freight stations
+    sim.write::<Dispatcher>()
+        .reserve(DispatchID::FreightTrain(train));
+    sim.write::<PassengerLines>().add_train(line, train);
+    Some(train)
+}
+
+/// Makes the passenger trains go from station to station following the timetable
+pub fn passenger_rail_system(world: &mut World, resources: &mut Resources) {
+    profiling::scope!("transportation::passenger_rail_system");
+    let lines = &mut *resources.write::<PassengerLines>();
+    let map = &*resources.read::<Map>();
+    let times = &*resources.read::<TravelTimes>();
+    let time = &*resources.read::<GameTime>();
+    let tick = *resources.read::<Tick>();
+
+    let mut dead = vec![];
+
+    for (&id, info) in lines.trains.iter_mut() {
+        let Some(train) = world.trains.get_mut(id) else {
+            dead.push(id);
+            continue;
+        };
+        let Some(line) = lines.lines.get(info.line) else {
+            dead.push(id);
+            continue;
+        };
+        if line.stations.is_empty() {
+            continue;
+        }
+
+        match info.state {
+            PassengerTrainState::Arriving(station) => {
+                let Some((_, platform)) = line
+                    .stations
+                    .get(station)
+                    .and_then(|&s| station_platform(map, s))
+                else {
+                    info.state = PassengerTrainState::Arriving((station + 1) % line.stations.len());
+                    continue;
+                };
+
+                if !train.it.has_ended(time.timestamp) {
+                    continue;
+                }
+
+                if train.trans.position.is_close(platform, 10.0) {
+                    let depart = line.next_departure(time.timestamp + MIN_DWELL_TIME);
+                    info.state = PassengerTrainState::Boarding(station, depart);
+                    train.it = Itinerary::wait_until(depart);
+                    continue;
+                }
+
+                train.it = Itinerary::route(
+                    tick,
+                    train.trans.position,
+                    platform,
+                    map,
+                    times,
+                    PathKind::Rail,
+                )
+                .unwrap_or_else(|| Itinerary::wait_until(time.timestamp + 10.0));
+            }
+            PassengerTrainState::Boarding(station, depart) => {
+                if time.timestamp < depart {
+                    continue;
+                }
+                info.state = PassengerTrainState::Arriving((station + 1) % line.stations.len());
+                train.it = Itinerary::NONE;
+            }
+        }
+    }
+
+    for train in dead {
+        lines.remove_train(train);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PassengerLines, PassengerTrainState};
+    use crate::map::{LanePatternBuilder, MapProject};
+    use crate::tests::TestCtx;
+    use crate::{BuildingKind, WorldCommand};
+    use common::descriptions::BuildingGen;
+    use geom::{vec2, vec3, Vec2, OBB};
+
+    #[test]
+    fn test_passenger_train_goes_to_next_station() {
+        let mut test = TestCtx::new();
+
+        test.g.map_mut().make_connection(
+            MapProject::ground(vec3(0.0, 0.0, 0.0)),
+            MapProject::ground(vec3(400.0, 0.0, 0.0)),
+            None,
+            &LanePatternBuilder::new().rail(true).one_way(true).build(),
+        );
+
+        let mut stations = vec![];
+        for x in [100.0, 300.0] {
+            test.apply(&[WorldCommand::MapBuildSpecialBuilding {
+                pos: OBB::new(vec2(x, 30.0), Vec2::X, 40.0, 20.0),
+                kind: BuildingKind::TrainStation,
+                gen: BuildingGen::NoWalkway {
+                    door_pos: Vec2::ZERO,
+                },
+                zone: None,
+            }]);
+            let map = test.g.map();
+            let station = map
+                .buildings()
+                .values()
+                .find(|b| matches!(b.kind, BuildingKind::TrainStation) && !stations.contains(&b.id))
+                .unwrap()
+                .id;
+            drop(map);
+            stations.push(station);
+        }
+
+        test.apply(&[WorldCommand::MakePassengerLine {
+            stations,
+            headway: 30.0,
+        }]);
+        let line = test
+            .g
+            .read::<PassengerLines>()
+            .lines()
+            .keys()
+            .next()
+            .unwrap();
+        test.apply(&[WorldCommand::AddPassengerTrain { line, n_wagons: 2 }]);
+
+        let train = test.g.read::<PassengerLines>().get(line).unwrap().trains[0];
+
+        for _ in 0..3000 {
+            test.tick();
+
+            let state = test
+                .g
+                .read::<PassengerLines>()
+                .train_info(train)
+                .unwrap()
+                .state;
+            if let PassengerTrainState::Boarding(1, _) = state {
+                return;
+            }
+        }
+
+        panic!("train never reached the second station");
+    }
+}
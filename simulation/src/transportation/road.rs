@@ -1,18 +1,24 @@
-use crate::map::{Map, TrafficBehavior, Traversable, TraverseKind};
+use crate::map::{
+    update_actuated_lights, LaneID, LaneObservation, LightPolicy, Map, TrafficBehavior,
+    TravelTimes, Traversable, TraverseKind,
+};
 use crate::map_dynamic::{Itinerary, OBJECTIVE_OK_DIST};
 use crate::physics::Speed;
 use crate::physics::{Collider, CollisionWorld, PhysicsGroup, PhysicsObject};
 use crate::transportation::{Vehicle, VehicleState, TIME_TO_PARK};
 use crate::utils::resources::Resources;
-use crate::utils::time::GameTime;
+use crate::utils::time::{GameTime, Tick, TICKS_PER_SECOND};
 use crate::world::{VehicleEnt, VehicleID};
 use crate::ParCommandBuffer;
 use crate::World;
 use geom::{angle_lerpxy, Ray, Transform, Vec2, Vec3};
 use slotmapd::Key;
+use std::collections::BTreeMap;
 
 pub fn vehicle_decision_system(world: &mut World, resources: &mut Resources) {
     profiling::scope!("transportation::vehicle_decision_system");
+    actuated_lights(world, resources);
+
     let ra = &*resources.read();
     let rb = &*resources.read();
     let rc = &*resources.read();
@@ -36,6 +42,71 @@ pub fn vehicle_decision_system(world: &mut World, resources: &mut Resources) {
     });
 }
 
+/// Vehicles slower than this are waiting at the lights
+pub(crate) const WAITING_SPEED: f32 = 1.0;
+
+/// Switches the actuated lights before the vehicles decide,
+/// using the number of vehicles waiting on each lane
+fn actuated_lights(world: &World, resources: &mut Resources) {
+    let seconds = resources.read::<GameTime>().seconds;
+    let mut map = resources.write::<Map>();
+    if !map
+        .intersections()
+        .values()
+        .any(|i| matches!(i.light_policy, LightPolicy::Actuated(_)))
+    {
+        return;
+    }
+
+    let mut waiting: BTreeMap<LaneID, u32> = BTreeMap::new();
+    for (_, v) in world.vehicles.iter() {
+        if v.speed.0 > WAITING_SPEED || !matches!(v.vehicle.state, VehicleState::Driving) {
+            continue;
+        }
+        if let Some(Traversable {
+            kind: TraverseKind::Lane(lane),
+            ..
+        }) = v.it.get_travers()
+        {
+            *waiting.entry(*lane).or_default() += 1;
+        }
+    }
+
+    update_actuated_lights(&mut map, seconds, &waiting);
+}
+
+/// Updates the travel time estimates of the lanes from the vehicles driving on them, every second
+pub fn travel_times_system(world: &mut World, resources: &mut Resources) {
+    profiling::scope!("transportation::travel_times_system");
+    if !resources.read::<Tick>().0.is_multiple_of(TICKS_PER_SECOND) {
+        return;
+    }
+    let map = resources.read::<Map>();
+    let mut times = resources.write::<TravelTimes>();
+
+    let mut observations: BTreeMap<LaneID, LaneObservation> = BTreeMap::new();
+    for (_, v) in world.vehicles.iter() {
+        if !matches!(v.vehicle.state, VehicleState::Driving) {
+            continue;
+        }
+        let Some(Traversable {
+            kind: TraverseKind::Lane(lane),
+            ..
+        }) = v.it.get_travers()
+        else {
+            continue;
+        };
+        let obs = observations.entry(*lane).or_default();
+        obs.vehicles += 1;
+        obs.speed_sum += v.speed.0;
+        if v.speed.0 <= WAITING_SPEED {
+            obs.waiting += 1;
+        }
+    }
+
+    times.observe(&map, &observations);
+}
+
 pub fn vehicle_decision(
     map: &Map,
     time: &GameTime,
@@ -302,7 +373,8 @@ pub fn calc_decision<'a>(
     }
 
     (
-        vehicle.kind.speed_factor() * vehicle.max_speed_multiplier * speed,
+        (vehicle.kind.speed_factor() * vehicle.max_speed_multiplier * speed)
+            .min(vehicle.kind.max_speed()),
         dir_to_pos,
     )
 }
@@ -1,4 +1,4 @@
-use crate::map::{Map, PathKind};
+use crate::map::{Map, PathKind, TravelTimes};
 use crate::map_dynamic::Itinerary;
 use crate::utils::resources::Resources;
 use crate::utils::time::Tick;
@@ -16,6 +16,7 @@ pub struct RandomVehicles {
 pub fn random_vehicles_update(world: &mut World, res: &mut Resources) {
     let rv = &mut *res.write::<RandomVehicles>();
     let map = res.read::<Map>();
+    let times = res.read::<TravelTimes>();
 
     let mut to_kill = Vec::new();
 
@@ -35,9 +36,14 @@ pub fn random_vehicles_update(world: &mut World, res: &mut Resources) {
         }
         let rng = common::hash_u64((tick.0, v_id));
 
-        if let Some(it) =
-            Itinerary::random_route(rng, v.trans.position, *tick, &map, PathKind::Vehicle)
-        {
+        if let Some(it) = Itinerary::random_route(
+            rng,
+            v.trans.position,
+            *tick,
+            &map,
+            &times,
+            PathKind::Vehicle,
+        ) {
             v.it = it;
         }
     }
@@ -0,0 +1,396 @@
+use crate::economy::{HISTORY_SIZE, LEVEL_FREQS};
+use crate::map::{BuildingKind, IntersectionID, LaneID, Map, TraverseKind, TurnID};
+use crate::souls::goods_company::GoodsCompanyRegistry;
+use crate::transportation::road::WAITING_SPEED;
+use crate::transportation::{Location, VehicleState};
+use crate::utils::resources::Resources;
+use crate::utils::time::{Tick, SECONDS_PER_REALTIME_SECOND, TICKS_PER_SECOND};
+use crate::world::{HumanID, VehicleID};
+use crate::World;
+use common::descriptions::CompanyKind;
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
+use std::collections::BTreeMap;
+
+/// One trip out of this many is recorded in the origin-destination matrix
+pub const TRIP_SAMPLING: u32 = 4;
+/// How fast the average speeds follow the observations, between 0 and 1
+const SPEED_SMOOTHING: f32 = 0.1;
+
+/// One count history at one frequency level
+/// The past_ring is controlled by the cursors of [`TrafficStats`]
+#[derive(Serialize, Deserialize)]
+pub struct CountHistoryLevel {
+    #[serde(with = "BigArray")]
+    pub past_ring: [u32; HISTORY_SIZE],
+}
+
+impl Default for CountHistoryLevel {
+    fn default() -> Self {
+        Self {
+            past_ring: [0; HISTORY_SIZE],
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct CountHistory {
+    levels: [CountHistoryLevel; LEVEL_FREQS.len()],
+}
+
+impl CountHistory {
+    pub fn level(&self, level: usize) -> &CountHistoryLevel {
+        &self.levels[level]
+    }
+
+    fn add(&mut self, cursors: &[usize], n: u32) {
+        for (level, cursor) in self.levels.iter_mut().zip(cursors) {
+            let v = &mut level.past_ring[*cursor];
+            *v = v.saturating_add(n);
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct LaneTraffic {
+    /// Vehicles entering the lane
+    pub vehicles: CountHistory,
+    /// Pedestrians entering the lane
+    pub pedestrians: CountHistory,
+    /// Smoothed speed of the vehicles driving on the lane, in m/s
+    pub avg_speed: f32,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct IntersectionTraffic {
+    /// Vehicles going through the intersection
+    pub vehicles: CountHistory,
+    /// Game seconds spent waiting by the vehicles on the lanes leading to the intersection and inside it
+    pub delay: CountHistory,
+}
+
+/// What kind of building a trip starts or ends at
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TripEnd {
+    Home,
+    Work,
+    Shop,
+    Station,
+    Outside,
+}
+
+pub const TRIP_ENDS: [TripEnd; 5] = [
+    TripEnd::Home,
+    TripEnd::Work,
+    TripEnd::Shop,
+    TripEnd::Station,
+    TripEnd::Outside,
+];
+
+impl TripEnd {
+    pub fn of(kind: &BuildingKind, registry: &GoodsCompanyRegistry) -> Self {
+        match *kind {
+            BuildingKind::House => TripEnd::Home,
+            BuildingKind::GoodsCompany(id) => {
+                match registry.descriptions.get(id).map(|d| &d.kind) {
+                    Some(CompanyKind::Store) => TripEnd::Shop,
+                    _ => TripEnd::Work,
+                }
+            }
+            BuildingKind::RailFreightStation | BuildingKind::TrainStation => TripEnd::Station,
+            BuildingKind::ExternalTrading => TripEnd::Outside,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TripEnd::Home => "home",
+            TripEnd::Work => "work",
+            TripEnd::Shop => "shop",
+            TripEnd::Station => "station",
+            TripEnd::Outside => "outside",
+        }
+    }
+}
+
+/// Traffic flows on the map: how many vehicles and pedestrians use each lane and turn,
+/// how long vehicles wait at intersections and a sample of the trips made by the humans.
+/// Counts are kept over the same frequency levels as the economy stats.
+#[derive(Default, Serialize, Deserialize)]
+pub struct TrafficStats {
+    lanes: BTreeMap<LaneID, LaneTraffic>,
+    turns: BTreeMap<TurnID, CountHistory>,
+    intersections: BTreeMap<IntersectionID, IntersectionTraffic>,
+    /// Sampled trips by origin and destination, see [`TRIP_SAMPLING`]
+    trips: BTreeMap<(TripEnd, TripEnd), CountHistory>,
+    cursors: [usize; LEVEL_FREQS.len()],
+    trips_seen: u32,
+    /// Where the vehicles and pedestrians were on the last tick, to count them once per lane or turn
+    vehicles_on: BTreeMap<VehicleID, TraverseKind>,
+    pedestrians_on: BTreeMap<HumanID, LaneID>,
+}
+
+impl TrafficStats {
+    pub fn cursors(&self) -> &[usize] {
+        &self.cursors
+    }
+
+    pub fn lanes(&self) -> impl Iterator<Item = (LaneID, &LaneTraffic)> {
+        self.lanes.iter().map(|(id, t)| (*id, t))
+    }
+
+    pub fn turns(&self) -> impl Iterator<Item = (TurnID, &CountHistory)> {
+        self.turns.iter().map(|(id, t)| (*id, t))
+    }
+
+    pub fn intersections(&self) -> impl Iterator<Item = (IntersectionID, &IntersectionTraffic)> {
+        self.intersections.iter().map(|(id, t)| (*id, t))
+    }
+
+    pub fn lane(&self, id: LaneID) -> Option<&LaneTraffic> {
+        self.lanes.get(&id)
+    }
+
+    pub fn intersection(&self, id: IntersectionID) -> Option<&IntersectionTraffic> {
+        self.intersections.get(&id)
+    }
+
+    /// The count during the last complete bin of the level
+    pub fn last_complete(&self, level: usize, h: &CountHistory) -> u32 {
+        let cursor = (self.cursors[level] + HISTORY_SIZE - 1) % HISTORY_SIZE;
+        h.levels[level].past_ring[cursor]
+    }
+
+    /// Average waiting time per vehicle at the intersection during the last complete bin, in game seconds
+    pub fn avg_delay(&self, level: usize, id: IntersectionID) -> Option<f32> {
+        let t = self.intersections.get(&id)?;
+        let vehicles = self.last_complete(level, &t.vehicles);
+        if vehicles == 0 {
+            return None;
+        }
+        Some(self.last_complete(level, &t.delay) as f32 / vehicles as f32)
+    }
+
+    /// Sampled trips from the origin to the destination during the last complete bin
+    pub fn trips(&self, level: usize, from: TripEnd, to: TripEnd) -> u32 {
+        self.trips
+            .get(&(from, to))
+            .map_or(0, |h| self.last_complete(level, h))
+    }
+
+    pub fn trip_history(&self, from: TripEnd, to: TripEnd) -> Option<&CountHistory> {
+        self.trips.get(&(from, to))
+    }
+
+    pub fn record_trip(&mut self, from: TripEnd, to: TripEnd) {
+        self.trips_seen = self.trips_seen.wrapping_add(1);
+        if !self.trips_seen.is_multiple_of(TRIP_SAMPLING) {
+            return;
+        }
+        self.trips
+            .entry((from, to))
+            .or_default()
+            .add(&self.cursors, 1);
+    }
+
+    pub fn advance(&mut self, tick: u32) {
+        for (c_i, (c, freq)) in self.cursors.iter_mut().zip(&LEVEL_FREQS).enumerate() {
+            if !tick.is_multiple_of(*freq) {
+                continue;
+            }
+            *c = (*c + 1) % HISTORY_SIZE;
+            let clear = |h: &mut CountHistory| h.levels[c_i].past_ring[*c] = 0;
+            for t in self.lanes.values_mut() {
+                clear(&mut t.vehicles);
+                clear(&mut t.pedestrians);
+            }
+            for t in self.intersections.values_mut() {
+                clear(&mut t.vehicles);
+                clear(&mut t.delay);
+            }
+            self.turns.values_mut().for_each(clear);
+            self.trips.values_mut().for_each(clear);
+        }
+    }
+
+    /// Counts the vehicles and pedestrians entering lanes and turns
+    fn count(&mut self, world: &World) {
+        for (id, v) in world.vehicles.iter() {
+            let travers =
+                v.it.get_travers()
+                    .filter(|_| matches!(v.vehicle.state, VehicleState::Driving));
+            let Some(travers) = travers else {
+                self.vehicles_on.remove(&id);
+                continue;
+            };
+            if self.vehicles_on.insert(id, travers.kind) == Some(travers.kind) {
+                continue;
+            }
+            match travers.kind {
+                TraverseKind::Lane(lane) => {
+                    self.lanes
+                        .entry(lane)
+                        .or_default()
+                        .vehicles
+                        .add(&self.cursors, 1);
+                }
+                TraverseKind::Turn(turn) => {
+                    self.turns.entry(turn).or_default().add(&self.cursors, 1);
+                    self.intersections
+                        .entry(turn.parent)
+                        .or_default()
+                        .vehicles
+                        .add(&self.cursors, 1);
+                }
+            }
+        }
+
+        for (id, h) in world.humans.iter() {
+            let lane = match h.it.get_travers().map(|t| t.kind) {
+                Some(TraverseKind::Lane(lane)) if matches!(h.location, Location::Outside) => lane,
+                _ => {
+                    self.pedestrians_on.remove(&id);
+                    continue;
+                }
+            };
+            if self.pedestrians_on.insert(id, lane) == Some(lane) {
+                continue;
+            }
+            self.lanes
+                .entry(lane)
+                .or_default()
+                .pedestrians
+                .add(&self.cursors, 1);
+        }
+    }
+
+    /// Updates the average speeds and the delays, called every real second
+    fn observe(&mut self, world: &World, map: &Map) {
+        let mut speeds: BTreeMap<LaneID, (f32, u32)> = BTreeMap::new();
+        for (_, v) in world.vehicles.iter() {
+            if !matches!(v.vehicle.state, VehicleState::Driving) {
+                continue;
+            }
+            let Some(travers) = v.it.get_travers() else {
+                continue;
+            };
+            let waiting = v.speed.0 <= WAITING_SPEED;
+            let inter = match travers.kind {
+                TraverseKind::Lane(lane) => {
+                    let s = speeds.entry(lane).or_default();
+                    s.0 += v.speed.0;
+                    s.1 += 1;
+                    map.lanes.get(lane).map(|l| l.dst)
+                }
+                TraverseKind::Turn(turn) => Some(turn.parent),
+            };
+            if let Some(inter) = inter.filter(|_| waiting) {
+                self.intersections
+                    .entry(inter)
+                    .or_default()
+                    .delay
+                    .add(&self.cursors, SECONDS_PER_REALTIME_SECOND);
+            }
+        }
+
+        for (lane, (sum, n)) in speeds {
+            let t = self.lanes.entry(lane).or_default();
+            let speed = sum / n as f32;
+            if t.avg_speed == 0.0 {
+                t.avg_speed = speed;
+            }
+            t.avg_speed += (speed - t.avg_speed) * SPEED_SMOOTHING;
+        }
+
+        self.vehicles_on
+            .retain(|id, _| world.vehicles.contains_key(*id));
+        self.pedestrians_on
+            .retain(|id, _| world.humans.contains_key(*id));
+        self.lanes.retain(|id, _| map.lanes.contains_key(*id));
+        self.intersections
+            .retain(|id, _| map.intersections.contains_key(*id));
+        self.turns.retain(|id, _| {
+            map.intersections
+                .get(id.parent)
+                .is_some_and(|i| i.find_turn(*id).is_some())
+        });
+    }
+}
+
+/// Updates the traffic statistics from the vehicles and pedestrians on the map
+pub fn traffic_stats_system(world: &mut World, resources: &mut Resources) {
+    profiling::scope!("transportation::traffic_stats_system");
+    let tick = resources.read::<Tick>().0;
+    let map = resources.read::<Map>();
+    let mut stats = resources.write::<TrafficStats>();
+
+    stats.advance(tick);
+    stats.count(world);
+    if tick.is_multiple_of(TICKS_PER_SECOND) {
+        stats.observe(world, &map);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TrafficStats, TripEnd, TRIP_SAMPLING};
+    use crate::economy::LEVEL_FREQS;
+    use crate::engine_interaction::WorldCommand;
+    use crate::map_dynamic::Destination;
+    use crate::souls::human::spawn_human;
+    use crate::tests::TestCtx;
+    use geom::{vec2, vec3};
+
+    #[test]
+    fn test_trip_sampling() {
+        let mut stats = TrafficStats::default();
+        for _ in 0..TRIP_SAMPLING * 10 {
+            stats.record_trip(TripEnd::Home, TripEnd::Work);
+        }
+        assert_eq!(stats.trips(0, TripEnd::Home, TripEnd::Work), 0);
+        stats.advance(LEVEL_FREQS[0]);
+        assert_eq!(stats.trips(0, TripEnd::Home, TripEnd::Work), 10);
+        assert_eq!(stats.trips(0, TripEnd::Work, TripEnd::Shop), 0);
+        stats.advance(LEVEL_FREQS[0] * 2);
+        assert_eq!(stats.trips(0, TripEnd::Home, TripEnd::Work), 0);
+    }
+
+    #[test]
+    fn test_trips_are_recorded() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+        let home = test.build_house_near(vec2(50.0, 0.0));
+        let other = test.build_house_near(vec2(250.0, 0.0));
+        let human = spawn_human(&mut test.g, home).unwrap();
+
+        let before = test.g.read::<TrafficStats>().trips_seen;
+
+        let h = test.g.world.humans.get_mut(human).unwrap();
+        // keep the human from deciding where to go by themselves
+        h.decision.wait = u8::MAX;
+        h.router.go_to(Destination::Building(other));
+        test.tick();
+
+        assert_eq!(test.g.read::<TrafficStats>().trips_seen, before + 1);
+    }
+
+    #[test]
+    fn test_lane_counts() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(300.0, 0.0, 0.0)]);
+        test.apply(&[WorldCommand::SpawnRandomCars { n_cars: 20 }]);
+
+        for _ in 0..=LEVEL_FREQS[0] {
+            test.tick();
+        }
+
+        let stats = test.g.read::<TrafficStats>();
+        let vehicles: u32 = stats
+            .lanes()
+            .map(|(_, t)| stats.last_complete(0, &t.vehicles))
+            .sum();
+        assert!(vehicles > 0);
+        let map = test.g.map();
+        assert!(stats.lanes().all(|(id, _)| map.lanes().contains_key(id)));
+    }
+}
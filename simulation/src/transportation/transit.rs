@@ -0,0 +1,448 @@
+use crate::map::{LaneID, LaneKind, Map, PathKind, Pathfinder};
+use crate::map_dynamic::Itinerary;
+use crate::transportation::{make_vehicle_entity, Vehicle, VehicleKind, VehicleState};
+use crate::utils::resources::Resources;
+use crate::utils::time::GameTime;
+use crate::world::VehicleID;
+use crate::{Simulation, World};
+use geom::{Color, Transform, Vec3};
+use serde::{Deserialize, Serialize};
+use slotmapd::{new_key_type, SlotMap};
+use std::collections::BTreeMap;
+
+new_key_type! {
+    pub struct TransitLineID;
+}
+
+debug_inspect_impl!(TransitLineID);
+
+/// How long a bus waits at a stop for passengers to get in and out, in seconds
+pub const BUS_STOP_WAIT: f64 = 20.0;
+
+/// How close a bus needs to be from a stop to be considered at the stop
+const BUS_STOP_DIST: f32 = 15.0;
+
+/// Rough speeds used to compare a bus trip with walking, in m/s
+pub const WALK_SPEED_ESTIMATE: f32 = 1.2;
+const BUS_SPEED_ESTIMATE: f32 = 8.0;
+
+/// Rough time spent waiting for the bus at the stop, in seconds
+const BUS_WAIT_ESTIMATE: f32 = 60.0;
+
+/// A stop of a transit line, placed along a lane where buses can drive
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransitStop {
+    pub lane: LaneID,
+    /// Distance from the start of the lane
+    pub dist: f32,
+}
+
+impl TransitStop {
+    /// Whether the stop is on an existing lane where buses can drive
+    pub fn is_valid(&self, map: &Map) -> bool {
+        let Some(l) = map.lanes().get(self.lane) else {
+            return false;
+        };
+        matches!(l.kind, LaneKind::Driving | LaneKind::Bus)
+            && (0.0..=l.points.length()).contains(&self.dist)
+    }
+
+    pub fn trans(&self, map: &Map) -> Option<Transform> {
+        let l = map.lanes().get(self.lane)?;
+        let (pos, dir) = l.points.point_dir_along(self.dist);
+        Some(Transform::new_dir(pos, dir))
+    }
+
+    pub fn pos(&self, map: &Map) -> Option<Vec3> {
+        let l = map.lanes().get(self.lane)?;
+        Some(l.points.point_along(self.dist))
+    }
+
+    /// Where pedestrians wait for the bus, on the nearest sidewalk
+    pub fn walk_pos(&self, map: &Map) -> Option<Vec3> {
+        let pos = self.pos(map)?;
+        let Some(sidewalk) = PathKind::Pedestrian.nearest_lane(map, pos) else {
+            return Some(pos);
+        };
+        Some(map.lanes()[sidewalk].points.project(pos))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransitLine {
+    pub id: TransitLineID,
+    /// The stops are served in order, looping back to the first one after the last one
+    pub stops: Vec<TransitStop>,
+    pub buses: Vec<VehicleID>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BusState {
+    /// Driving towards the stop at the given index
+    Driving(usize),
+    /// Waiting at the stop at the given index until the given timestamp
+    AtStop(usize, f64),
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct BusInfo {
+    pub line: TransitLineID,
+    pub state: BusState,
+}
+
+/// A bus trip from one stop to another of the same line
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BusTrip {
+    pub line: TransitLineID,
+    pub from: usize,
+    pub to: usize,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct TransitLines {
+    lines: SlotMap<TransitLineID, TransitLine>,
+    buses: BTreeMap<VehicleID, BusInfo>,
+}
+
+impl TransitLines {
+    pub fn lines(&self) -> &SlotMap<TransitLineID, TransitLine> {
+        &self.lines
+    }
+
+    pub fn get(&self, id: TransitLineID) -> Option<&TransitLine> {
+        self.lines.get(id)
+    }
+
+    pub fn bus_info(&self, bus: VehicleID) -> Option<&BusInfo> {
+        self.buses.get(&bus)
+    }
+
+    pub fn make_line(&mut self, stops: Vec<TransitStop>) -> TransitLineID {
+        self.lines.insert_with_key(|id| TransitLine {
+            id,
+            stops,
+            buses: vec![],
+        })
+    }
+
+    /// Replaces the stops of the line, buses restart from the first stop
+    pub fn update_line(&mut self, id: TransitLineID, stops: Vec<TransitStop>) {
+        let Some(line) = self.lines.get_mut(id) else {
+            return;
+        };
+        line.stops = stops;
+        for bus in &line.buses {
+            if let Some(info) = self.buses.get_mut(bus) {
+                info.state = BusState::Driving(0);
+            }
+        }
+    }
+
+    /// Removes the line, returning the buses that were running it
+    pub fn remove_line(&mut self, id: TransitLineID) -> Vec<VehicleID> {
+        let Some(line) = self.lines.remove(id) else {
+            return vec![];
+        };
+        for bus in &line.buses {
+            self.buses.remove(bus);
+        }
+        line.buses
+    }
+
+    pub fn add_bus(&mut self, line: TransitLineID, bus: VehicleID) {
+        let Some(l) = self.lines.get_mut(line) else {
+            return;
+        };
+        l.buses.push(bus);
+        self.buses.insert(
+            bus,
+            BusInfo {
+                line,
+                state: BusState::Driving(0),
+            },
+        );
+    }
+
+    pub fn remove_bus(&mut self, bus: VehicleID) {
+        let Some(info) = self.buses.remove(&bus) else {
+            return;
+        };
+        if let Some(l) = self.lines.get_mut(info.line) {
+            l.buses.retain(|&x| x != bus);
+        }
+    }
+
+    /// Returns the bus of the line currently waiting at the given stop, if any
+    pub fn bus_at_stop(&self, line: TransitLineID, stop: usize) -> Option<VehicleID> {
+        let l = self.lines.get(line)?;
+        l.buses.iter().copied().find(|bus| {
+            self.buses
+                .get(bus)
+                .map(|info| matches!(info.state, BusState::AtStop(s, _) if s == stop))
+                .unwrap_or(false)
+        })
+    }
+
+    /// Returns the stop the bus is currently waiting at, if any
+    pub fn bus_stop(&self, bus: VehicleID) -> Option<usize> {
+        match self.buses.get(&bus)?.state {
+            BusState::AtStop(s, _) => Some(s),
+            BusState::Driving(_) => None,
+        }
+    }
+
+    /// Returns the line of the bus and the stop it is at or going to
+    pub fn next_stop(&self, bus: VehicleID) -> Option<(TransitLineID, usize)> {
+        let info = self.buses.get(&bus)?;
+        match info.state {
+            BusState::Driving(s) | BusState::AtStop(s, _) => Some((info.line, s)),
+        }
+    }
+
+    /// Finds the fastest bus trip from `start` to `end` with its estimated duration in seconds,
+    /// including the walk to and from the stops
+    pub fn best_trip(&self, map: &Map, start: Vec3, end: Vec3) -> Option<(f32, BusTrip)> {
+        let mut best: Option<(f32, BusTrip)> = None;
+
+        for (id, line) in &self.lines {
+            if line.buses.is_empty() {
+                continue;
+            }
+            let stops: Vec<Vec3> = line.stops.iter().filter_map(|s| s.pos(map)).collect();
+            if stops.len() != line.stops.len() || stops.len() < 2 {
+                continue;
+            }
+
+            let (from, from_d) = closest(&stops, start);
+            let (to, to_d) = closest(&stops, end);
+            if from == to {
+                continue;
+            }
+
+            let mut ride_dist = 0.0;
+            let mut i = from;
+            while i != to {
+                let next = (i + 1) % stops.len();
+                ride_dist += stops[i].distance(stops[next]);
+                i = next;
+            }
+
+            let time = (from_d + to_d) / WALK_SPEED_ESTIMATE
+                + BUS_WAIT_ESTIMATE
+                + ride_dist / BUS_SPEED_ESTIMATE;
+
+            if best.is_some_and(|(t, _)| t <= time) {
+                continue;
+            }
+            best = Some((time, BusTrip { line: id, from, to }));
+        }
+
+        best
+    }
+}
+
+/// Returns the index of the closest point and its distance
+pub(crate) fn closest(points: &[Vec3], p: Vec3) -> (usize, f32) {
+    let mut best = (0, f32::INFINITY);
+    for (i, &x) in points.iter().enumerate() {
+        let d = x.distance(p);
+        if d < best.1 {
+            best = (i, d);
+        }
+    }
+    best
+}
+
+pub fn spawn_bus(sim: &mut Simulation, line: TransitLineID) -> Option<VehicleID> {
+    let transit = sim.read::<TransitLines>();
+    let stop = *transit.get(line)?.stops.first()?;
+    drop(transit);
+
+    let trans = stop.trans(&sim.map())?;
+
+    let vehicle = Vehicle {
+        ang_velocity: 0.0,
+        wait_time: 0.0,
+        max_speed_multiplier: 1.0,
+        state: VehicleState::Driving,
+        kind: VehicleKind::Bus,
+        tint: Color::from_hex(0xf2_c1_1b),
+        flag: 0,
+    };
+
+    let id = make_vehicle_entity(sim, trans, vehicle, Itinerary::NONE, true);
+    sim.write::<TransitLines>().add_bus(line, id);
+    Some(id)
+}
+
+/// Makes the buses go from stop to stop
+pub fn transit_system(world: &mut World, resources: &mut Resources) {
+    profiling::scope!("transportation::transit_system");
+    let transit = &mut *resources.write::<TransitLines>();
+    let map = &*resources.read::<Map>();
+    let time = &*resources.read::<GameTime>();
+
+    let mut dead = vec![];
+
+    for (&bus, info) in transit.buses.iter_mut() {
+        let Some(v) = world.vehicles.get_mut(bus) else {
+            dead.push(bus);
+            continue;
+        };
+        let Some(line) = transit.lines.get(info.line) else {
+            dead.push(bus);
+            continue;
+        };
+        if line.stops.is_empty() {
+            continue;
+        }
+
+        match info.state {
+            BusState::Driving(stop) => {
+                let Some(stop_pos) = line.stops.get(stop).and_then(|s| s.pos(map)) else {
+                    info.state = BusState::Driving((stop + 1) % line.stops.len());
+                    continue;
+                };
+                if v.it.has_ended(0.0) {
+                    if v.trans.position.is_close(stop_pos, BUS_STOP_DIST) {
+                        info.state = BusState::AtStop(stop, time.timestamp + BUS_STOP_WAIT);
+                    } else {
+                        v.it = Itinerary::wait_for_reroute(PathKind::Bus, stop_pos);
+                    }
+                }
+            }
+            BusState::AtStop(stop, until) => {
+                if time.timestamp < until {
+                    continue;
+                }
+                let next = (stop + 1) % line.stops.len();
+                info.state = BusState::Driving(next);
+                if let Some(pos) = line.stops[next].pos(map) {
+                    v.it = Itinerary::wait_for_reroute(PathKind::Bus, pos);
+                }
+            }
+        }
+    }
+
+    for bus in dead {
+        transit.remove_bus(bus);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BusState, TransitLines, TransitStop};
+    use crate::economy::Government;
+    use crate::map::LaneKind;
+    use crate::tests::TestCtx;
+    use crate::utils::time::GameTime;
+    use crate::WorldCommand;
+    use geom::vec3;
+
+    #[test]
+    fn test_bus_goes_to_next_stop() {
+        let mut test = TestCtx::new();
+
+        test.build_roads(&[vec3(0., 0., 0.), vec3(150., 0., 0.)]);
+
+        let lane = test
+            .g
+            .map()
+            .lanes()
+            .values()
+            .find(|l| l.kind == LaneKind::Driving)
+            .unwrap()
+            .id;
+
+        test.apply(&[WorldCommand::MakeTransitLine {
+            stops: vec![
+                TransitStop { lane, dist: 20.0 },
+                TransitStop { lane, dist: 100.0 },
+            ],
+        }]);
+        let line = test.g.read::<TransitLines>().lines().keys().next().unwrap();
+        test.apply(&[WorldCommand::AddBus { line }]);
+
+        let bus = test.g.read::<TransitLines>().get(line).unwrap().buses[0];
+
+        for _ in 0..2000 {
+            test.tick();
+
+            let state = test.g.read::<TransitLines>().bus_info(bus).unwrap().state;
+            match state {
+                BusState::AtStop(0, _) => {
+                    // skip the wait at the first stop
+                    let mut gt = *test.g.read::<GameTime>();
+                    gt.timestamp += 100.0;
+                    test.apply(&[WorldCommand::SetGameTime(gt)]);
+                }
+                BusState::AtStop(1, _) => return,
+                _ => {}
+            }
+        }
+
+        panic!("bus never reached the second stop");
+    }
+
+    #[test]
+    fn test_invalid_stops_are_refused() {
+        let mut test = TestCtx::new();
+
+        test.build_roads(&[vec3(0., 0., 0.), vec3(150., 0., 0.)]);
+
+        let lane_of = |test: &TestCtx, kind: LaneKind| {
+            test.g
+                .map()
+                .lanes()
+                .values()
+                .find(|l| l.kind == kind)
+                .unwrap()
+                .id
+        };
+        let driving = lane_of(&test, LaneKind::Driving);
+        let walking = lane_of(&test, LaneKind::Walking);
+        let money = |test: &TestCtx| test.g.read::<Government>().money;
+        let before = money(&test);
+
+        test.apply(&[WorldCommand::MakeTransitLine {
+            stops: vec![
+                TransitStop {
+                    lane: driving,
+                    dist: 20.0,
+                },
+                TransitStop {
+                    lane: walking,
+                    dist: 20.0,
+                },
+            ],
+        }]);
+        assert!(test.g.read::<TransitLines>().lines().is_empty());
+        assert_eq!(money(&test), before);
+
+        let stops = vec![TransitStop {
+            lane: driving,
+            dist: 20.0,
+        }];
+        test.apply(&[WorldCommand::MakeTransitLine {
+            stops: stops.clone(),
+        }]);
+        let line = test.g.read::<TransitLines>().lines().keys().next().unwrap();
+
+        // the lane of the new stop does not exist anymore
+        let road = test.g.map().lanes()[driving].parent;
+        test.apply(&[WorldCommand::MapRemoveRoad(road)]);
+        let before = money(&test);
+        test.apply(&[WorldCommand::UpdateTransitLine {
+            line,
+            stops: vec![TransitStop {
+                lane: driving,
+                dist: 50.0,
+            }],
+        }]);
+        assert_eq!(
+            test.g.read::<TransitLines>().get(line).unwrap().stops,
+            stops
+        );
+        assert_eq!(money(&test), before);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::map::PathKind;
 use crate::map_dynamic::{Itinerary, ParkingManagement, SpotReservation};
 use crate::physics::{Collider, CollisionWorld, PhysicsGroup, PhysicsObject};
 use crate::utils::rand_provider::RandProvider;
@@ -28,6 +29,7 @@ pub enum VehicleKind {
     Car,
     Truck,
     Bus,
+    Bike,
 }
 
 #[derive(Debug, Serialize, Deserialize, Inspect)]
@@ -63,6 +65,7 @@ impl VehicleKind {
             VehicleKind::Car => 4.5,
             VehicleKind::Truck => 6.0,
             VehicleKind::Bus => 9.0,
+            VehicleKind::Bike => 2.0,
         }
     }
 
@@ -71,12 +74,14 @@ impl VehicleKind {
             VehicleKind::Car => 3.0,
             VehicleKind::Truck => 2.5,
             VehicleKind::Bus => 2.0,
+            VehicleKind::Bike => 1.5,
         }
     }
 
     pub fn deceleration(self) -> f32 {
         match self {
             VehicleKind::Car | VehicleKind::Bus | VehicleKind::Truck => 6.0,
+            VehicleKind::Bike => 4.0,
         }
     }
 
@@ -85,6 +90,7 @@ impl VehicleKind {
             VehicleKind::Car => 0.5,
             VehicleKind::Truck => 3.0,
             VehicleKind::Bus => 4.0,
+            VehicleKind::Bike => 0.2,
         }
     }
 
@@ -92,6 +98,32 @@ impl VehicleKind {
         match self {
             VehicleKind::Car => 1.0,
             VehicleKind::Truck | VehicleKind::Bus => 0.8,
+            VehicleKind::Bike => 1.0,
+        }
+    }
+
+    /// Speed the vehicle never goes above whatever the speed limit, in m/s
+    pub fn max_speed(self) -> f32 {
+        match self {
+            VehicleKind::Car | VehicleKind::Truck | VehicleKind::Bus => f32::INFINITY,
+            VehicleKind::Bike => 6.0,
+        }
+    }
+
+    /// Quantity of goods the vehicle can carry in one delivery
+    pub fn cargo_capacity(self) -> i32 {
+        match self {
+            VehicleKind::Truck => 20,
+            VehicleKind::Car | VehicleKind::Bus | VehicleKind::Bike => 0,
+        }
+    }
+
+    pub fn path_kind(self) -> PathKind {
+        match self {
+            VehicleKind::Car => PathKind::Vehicle,
+            VehicleKind::Truck => PathKind::Truck,
+            VehicleKind::Bus => PathKind::Bus,
+            VehicleKind::Bike => PathKind::Bike,
         }
     }
 
@@ -100,6 +132,7 @@ impl VehicleKind {
             VehicleKind::Car => 1.0,
             VehicleKind::Truck => 0.9,
             VehicleKind::Bus => 0.8,
+            VehicleKind::Bike => 1.5,
         }
     }
 }
@@ -109,12 +142,9 @@ pub fn unpark(sim: &mut Simulation, vehicle: VehicleID) {
     let w = v.vehicle.kind.width();
     let trans = v.trans;
 
-    if let VehicleState::Parked(spot) =
-        std::mem::replace(&mut v.vehicle.state, VehicleState::Driving)
-    {
-        sim.write::<ParkingManagement>().free(spot);
-    } else {
-        log::warn!("Trying to unpark {:?} that wasn't parked", vehicle);
+    match std::mem::replace(&mut v.vehicle.state, VehicleState::Driving) {
+        VehicleState::Parked(spot) => sim.write::<ParkingManagement>().free(spot),
+        _ => log::warn!("Trying to unpark {:?} that wasn't parked", vehicle),
     }
 
     let coll = put_vehicle_in_coworld(sim, w, trans);
@@ -147,7 +177,9 @@ pub fn spawn_parked_vehicle_with_spot(
     drop(map);
 
     let tint = match kind {
-        VehicleKind::Car => get_random_car_color(&mut sim.write::<RandProvider>()),
+        VehicleKind::Car | VehicleKind::Bike => {
+            get_random_car_color(&mut sim.write::<RandProvider>())
+        }
         _ => Color::WHITE,
     };
 
@@ -35,6 +35,7 @@ pub struct Config {
     pub lot_unassigned_col: Color,
     pub lot_residential_col: Color,
     pub lot_commercial_col: Color,
+    pub lot_industrial_col: Color,
 
     pub special_building_col: Color,
     pub special_building_invalid_col: Color,
@@ -5,5 +5,6 @@ pub mod replay;
 pub mod resources;
 pub mod scheduler;
 pub mod time;
+pub mod undo;
 
 pub use config::*;
@@ -3,11 +3,72 @@ use crate::utils::scheduler::SeqSchedule;
 use crate::utils::time::Tick;
 use crate::Simulation;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Replay {
     pub enabled: bool,
     pub commands: Vec<(Tick, WorldCommand)>,
+    /// Every how many ticks a checkpoint is recorded, 0 to disable
+    #[serde(default)]
+    pub checkpoint_every: u32,
+    #[serde(default)]
+    pub checkpoints: Vec<ReplayCheckpoint>,
+}
+
+/// The replay before checkpoints were added
+#[derive(Deserialize)]
+pub(crate) struct ReplayV0 {
+    enabled: bool,
+    commands: Vec<(Tick, WorldCommand)>,
+}
+
+impl From<ReplayV0> for Replay {
+    fn from(old: ReplayV0) -> Self {
+        Self {
+            enabled: old.enabled,
+            commands: old.commands,
+            checkpoint_every: 0,
+            checkpoints: vec![],
+        }
+    }
+}
+
+/// Hashes of the world and of every saved resource at the end of a tick,
+/// used to check that playing back the replay gives the same state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayCheckpoint {
+    pub tick: Tick,
+    /// In the order they are saved, see [`Simulation::ordered_hashes`]
+    pub hashes: Vec<(String, u64)>,
+}
+
+/// Where the replay playback first went out of sync with the recorded checkpoints
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayDivergence {
+    pub tick: Tick,
+    /// "world" or the name of the first resource that differs
+    pub resource: String,
+}
+
+impl Replay {
+    /// Records a checkpoint if one is due at the current tick
+    pub(crate) fn record_checkpoint(sim: &Simulation) {
+        let rep = sim.read::<Replay>();
+        let tick = *sim.read::<Tick>();
+        if !rep.enabled || rep.checkpoint_every == 0 || !tick.0.is_multiple_of(rep.checkpoint_every)
+        {
+            return;
+        }
+        drop(rep);
+
+        let hashes = checkpoint_hashes(sim)
+            .map(|(name, hash)| (name.to_string(), hash))
+            .collect();
+        sim.write::<Replay>()
+            .checkpoints
+            .push(ReplayCheckpoint { tick, hashes });
+    }
 }
 
 pub struct SimulationReplayLoader {
@@ -16,6 +77,9 @@ pub struct SimulationReplayLoader {
     pub idx: usize,
     pub speed: usize,
     pub advance_n_ticks: usize,
+    /// Index of the next checkpoint to verify
+    pub checkpoint_idx: usize,
+    pub divergence: Option<ReplayDivergence>,
 }
 
 impl SimulationReplayLoader {
@@ -33,6 +97,7 @@ impl SimulationReplayLoader {
             let curt = self.replay.commands[self.idx].0;
             while self.pastt < curt {
                 sim.tick(schedule, &[]);
+                self.verify_checkpoint(sim);
                 self.pastt.0 += 1;
                 ticks_left -= 1;
                 if ticks_left == 0 {
@@ -53,9 +118,127 @@ impl SimulationReplayLoader {
                 command_slice.len()
             );
             sim.tick(schedule, command_slice.iter().map(|(_, c)| c));
+            self.verify_checkpoint(sim);
             self.pastt.0 += 1;
             ticks_left -= 1;
         }
-        self.idx >= self.replay.commands.len()
+
+        // keep going after the last command until every checkpoint was verified
+        while self.idx >= self.replay.commands.len() && !self.checkpoints_done() && ticks_left > 0 {
+            sim.tick(schedule, &[]);
+            self.verify_checkpoint(sim);
+            self.pastt.0 += 1;
+            ticks_left -= 1;
+        }
+        self.idx >= self.replay.commands.len() && self.checkpoints_done()
+    }
+
+    /// Returns true once there is nothing left to verify
+    fn checkpoints_done(&self) -> bool {
+        self.divergence.is_some() || self.checkpoint_idx >= self.replay.checkpoints.len()
+    }
+
+    /// Compares the state with the recorded checkpoint of the current tick if there is one.
+    /// Only the first divergence is reported as everything differs afterwards anyway.
+    fn verify_checkpoint(&mut self, sim: &Simulation) {
+        if self.divergence.is_some() {
+            return;
+        }
+        let tick = *sim.read::<Tick>();
+        let checkpoints = &self.replay.checkpoints;
+        while self.checkpoint_idx < checkpoints.len()
+            && checkpoints[self.checkpoint_idx].tick < tick
+        {
+            self.checkpoint_idx += 1;
+        }
+        let Some(checkpoint) = checkpoints.get(self.checkpoint_idx) else {
+            return;
+        };
+        if checkpoint.tick != tick {
+            return;
+        }
+        self.checkpoint_idx += 1;
+
+        let hashes: BTreeMap<_, _> = checkpoint_hashes(sim).collect();
+        let Some((resource, _)) = checkpoint
+            .hashes
+            .iter()
+            .find(|(name, hash)| hashes.get(name.as_str()) != Some(hash))
+        else {
+            return;
+        };
+
+        log::error!(
+            "[replay] state diverged at tick {:?} starting with {}",
+            tick,
+            resource
+        );
+        self.divergence = Some(ReplayDivergence {
+            tick,
+            resource: resource.clone(),
+        });
+    }
+}
+
+/// The replay itself isn't part of the simulated state
+fn checkpoint_hashes(sim: &Simulation) -> impl Iterator<Item = (&'static str, u64)> {
+    sim.ordered_hashes()
+        .into_iter()
+        .filter(|(name, _)| *name != "replay")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Replay, ReplayDivergence, Simulation, SimulationOptions};
+
+    fn record() -> Replay {
+        crate::init::init();
+        let mut sim = Simulation::new_with_options(SimulationOptions {
+            terrain_size: 0,
+            save_replay: true,
+            replay_checkpoints: 10,
+        });
+        let mut sched = Simulation::schedule();
+        for _ in 0..50 {
+            sim.tick(&mut sched, &[]);
+        }
+        let rep = sim.read::<Replay>();
+        rep.clone()
+    }
+
+    fn play(replay: Replay) -> Option<ReplayDivergence> {
+        let (mut sim, mut loader) = Simulation::from_replay(replay);
+        let mut sched = Simulation::schedule();
+        loader.speed = 0;
+        loader.advance_n_ticks = 100;
+        assert!(loader.advance_tick(&mut sim, &mut sched));
+        loader.divergence
+    }
+
+    #[test]
+    fn test_replay_checkpoints() {
+        let mut replay = record();
+        assert_eq!(replay.checkpoints.len(), 5);
+        assert!(replay.commands.last().unwrap().0 < replay.checkpoints[0].tick);
+
+        assert_eq!(play(replay.clone()), None);
+
+        // checkpoints after the last command are verified too
+        let checkpoint = &mut replay.checkpoints[4];
+        let (_, hash) = checkpoint
+            .hashes
+            .iter_mut()
+            .find(|(name, _)| name == "transit_lines")
+            .unwrap();
+        *hash += 1;
+        let tick = checkpoint.tick;
+
+        assert_eq!(
+            play(replay),
+            Some(ReplayDivergence {
+                tick,
+                resource: "transit_lines".to_string(),
+            })
+        );
     }
 }
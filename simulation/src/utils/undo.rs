@@ -0,0 +1,838 @@
+use crate::economy::{Government, Money};
+use crate::engine_interaction::WorldCommand;
+use crate::map::{
+    Building, BuildingKind, IntersectionID, LotID, LotKind, Map, MapProject, ProjectKind, Road,
+};
+use crate::multiplayer::{Ownership, PlayerID, Players};
+use crate::souls::goods_company::GoodsCompanyRegistry;
+use crate::Simulation;
+use common::descriptions::BuildingGen;
+use geom::Vec2;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// How many actions each player can undo
+pub const UNDO_HISTORY_SIZE: usize = 30;
+
+/// A command of an undo entry
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct UndoCommand {
+    cmd: WorldCommand,
+    /// The removed objects that the command builds again, in the order they are built, with their owner.
+    /// The commands referring to them are updated to refer to the new objects.
+    restores: Vec<(ProjectKind, Option<PlayerID>)>,
+}
+
+impl From<WorldCommand> for UndoCommand {
+    fn from(cmd: WorldCommand) -> Self {
+        Self {
+            cmd,
+            restores: vec![],
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct UndoEntry {
+    /// Commands reverting the action, or applying it again for the redo stack
+    commands: Vec<UndoCommand>,
+    /// Money spent on the action, refunded when undone and charged again when redone
+    cost: Money,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct PlayerHistory {
+    undo: VecDeque<UndoEntry>,
+    redo: Vec<UndoEntry>,
+}
+
+/// The map edits that each player can undo and redo.
+/// It is part of the simulation so that `Undo` and `Redo` stay deterministic in lockstep.
+#[derive(Default, Serialize, Deserialize)]
+pub struct UndoHistory {
+    players: BTreeMap<PlayerID, PlayerHistory>,
+}
+
+impl UndoHistory {
+    pub fn can_undo(&self, player: PlayerID) -> bool {
+        self.players
+            .get(&player)
+            .is_some_and(|h| !h.undo.is_empty())
+    }
+
+    pub fn can_redo(&self, player: PlayerID) -> bool {
+        self.players
+            .get(&player)
+            .is_some_and(|h| !h.redo.is_empty())
+    }
+
+    /// Records an action done by a player, it cannot be redone anymore what was undone before
+    pub(crate) fn record(&mut self, player: PlayerID, commands: Vec<UndoCommand>, cost: Money) {
+        let h = self.players.entry(player).or_default();
+        h.redo.clear();
+        h.undo.push_back(UndoEntry { commands, cost });
+        if h.undo.len() > UNDO_HISTORY_SIZE {
+            h.undo.pop_front();
+        }
+    }
+
+    /// Reverts the last action of the acting player and refunds it
+    pub(crate) fn undo(sim: &mut Simulation) {
+        let player = sim.read::<Players>().acting();
+        let entry = sim
+            .write::<UndoHistory>()
+            .players
+            .get_mut(&player)
+            .and_then(|h| h.undo.pop_back());
+        let Some(entry) = entry.and_then(|entry| apply_entry(sim, entry, -1)) else {
+            return;
+        };
+        let mut history = sim.write::<UndoHistory>();
+        history.players.entry(player).or_default().redo.push(entry);
+    }
+
+    /// Applies again the last undone action of the acting player and charges it
+    pub(crate) fn redo(sim: &mut Simulation) {
+        let player = sim.read::<Players>().acting();
+        let entry = sim
+            .write::<UndoHistory>()
+            .players
+            .get_mut(&player)
+            .and_then(|h| h.redo.pop());
+        let Some(entry) = entry.and_then(|entry| apply_entry(sim, entry, 1)) else {
+            return;
+        };
+        let mut history = sim.write::<UndoHistory>();
+        history
+            .players
+            .entry(player)
+            .or_default()
+            .undo
+            .push_back(entry);
+    }
+
+    /// Makes the histories refer to an object built again instead of the removed one
+    fn remap(&mut self, old: ProjectKind, new: ProjectKind) {
+        for h in self.players.values_mut() {
+            for entry in h.undo.iter_mut().chain(h.redo.iter_mut()) {
+                for ucmd in &mut entry.commands {
+                    remap_command(&mut ucmd.cmd, old, new);
+                    for (restored, _) in &mut ucmd.restores {
+                        if *restored == old {
+                            *restored = new;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Applies the commands of an entry, moves its cost to the acting player, refunding it if `sign` is -1,
+/// and returns the entry reverting it.
+/// The cost is only moved if every removed object could be built again.
+/// Returns None without applying anything if the entry refers to objects that were removed or replaced since,
+/// like a road split by a newer connection.
+fn apply_entry(sim: &mut Simulation, entry: UndoEntry, sign: i64) -> Option<UndoEntry> {
+    if entry.commands.iter().any(|u| is_stale(&u.cmd, &sim.map())) {
+        log::info!("dropping an undo entry whose objects were changed since");
+        return None;
+    }
+
+    let mut inverses = Vec::with_capacity(entry.commands.len());
+    let mut complete = true;
+    for ucmd in &entry.commands {
+        let cmd = revalidate(&ucmd.cmd, &sim.map());
+        let Some(inverse) = cmd.execute(sim) else {
+            continue;
+        };
+        // the inverse removes what was built, last built first
+        let built: Vec<ProjectKind> = inverse
+            .iter()
+            .rev()
+            .filter_map(|u| match u.cmd {
+                WorldCommand::MapRemoveRoad(id) => Some(ProjectKind::Road(id)),
+                WorldCommand::MapRemoveBuilding(id) => Some(ProjectKind::Building(id)),
+                _ => None,
+            })
+            .collect();
+        complete &= built.len() >= ucmd.restores.len();
+
+        let mut history = sim.write::<UndoHistory>();
+        let mut players = sim.write::<Players>();
+        for (&(old, owner), &new) in ucmd.restores.iter().zip(&built) {
+            history.remap(old, new);
+            players.owners.restore(new, owner);
+        }
+        drop((history, players));
+        inverses.push(inverse);
+    }
+
+    let cost = if complete { entry.cost } else { Money::ZERO };
+    sim.write::<Players>()
+        .charge(&mut sim.write::<Government>(), sign * cost);
+
+    Some(UndoEntry {
+        commands: inverses.into_iter().rev().flatten().collect(),
+        cost,
+    })
+}
+
+/// Whether the command refers to objects that are not in the map anymore
+fn is_stale(cmd: &WorldCommand, map: &Map) -> bool {
+    match *cmd {
+        WorldCommand::MapRemoveRoad(id)
+        | WorldCommand::MapSetRoadRestrictions { road: id, .. }
+        | WorldCommand::MapSetOneWay { road: id, .. }
+        | WorldCommand::MapSetRoadPattern { road: id, .. } => !map.roads().contains_key(id),
+        WorldCommand::MapRemoveBuilding(id) | WorldCommand::UpdateZone { building: id, .. } => {
+            !map.buildings().contains_key(id)
+        }
+        WorldCommand::MapMakeUtilityLink { src, dst, .. } => {
+            !map.buildings().contains_key(src) || !map.buildings().contains_key(dst)
+        }
+        WorldCommand::MapRemoveUtilityLink { kind, src, dst } => {
+            map.find_utility_link(kind, src, dst).is_none()
+        }
+        WorldCommand::MapSetLotKind { ref lots, .. } => {
+            lots.iter().all(|&id| !map.lots().contains_key(id))
+        }
+        _ => false,
+    }
+}
+
+fn remap_command(cmd: &mut WorldCommand, old: ProjectKind, new: ProjectKind) {
+    let remap_proj = |proj: &mut MapProject| {
+        if proj.kind == old {
+            proj.kind = new;
+        }
+    };
+    match (cmd, new) {
+        (
+            WorldCommand::MapRemoveRoad(ref mut id)
+            | WorldCommand::MapSetRoadRestrictions {
+                road: ref mut id, ..
+            }
+            | WorldCommand::MapSetOneWay {
+                road: ref mut id, ..
+            }
+            | WorldCommand::MapSetRoadPattern {
+                road: ref mut id, ..
+            },
+            ProjectKind::Road(new_id),
+        ) if ProjectKind::Road(*id) == old => {
+            *id = new_id;
+        }
+        (
+            WorldCommand::MapRemoveBuilding(ref mut id)
+            | WorldCommand::UpdateZone {
+                building: ref mut id,
+                ..
+            },
+            ProjectKind::Building(new_id),
+        ) if ProjectKind::Building(*id) == old => {
+            *id = new_id;
+        }
+        (
+            WorldCommand::MapMakeConnection {
+                ref mut from,
+                ref mut to,
+                ..
+            }
+            | WorldCommand::MapMakeProfiledConnection {
+                ref mut from,
+                ref mut to,
+                ..
+            },
+            _,
+        ) => {
+            remap_proj(from);
+            remap_proj(to);
+        }
+        (
+            WorldCommand::MapMakeUtilityLink {
+                ref mut src,
+                ref mut dst,
+                ..
+            }
+            | WorldCommand::MapRemoveUtilityLink {
+                ref mut src,
+                ref mut dst,
+                ..
+            },
+            ProjectKind::Building(new_id),
+        ) => {
+            for id in [src, dst] {
+                if ProjectKind::Building(*id) == old {
+                    *id = new_id;
+                }
+            }
+        }
+        (WorldCommand::MapMakeMultipleConnections(ref mut projects, _), _) => {
+            projects.iter_mut().for_each(remap_proj);
+        }
+        _ => {}
+    }
+}
+
+/// The objects referred to by an inverse might have been removed since it was computed,
+/// in which case the connection is made from the ground instead.
+fn revalidate(cmd: &WorldCommand, map: &Map) -> WorldCommand {
+    let fix = |proj: &mut MapProject| {
+        if !proj.kind.check_valid(map) {
+            proj.kind = ProjectKind::Ground;
+        }
+    };
+    let mut cmd = cmd.clone();
+    match cmd {
+        WorldCommand::MapMakeConnection {
+            ref mut from,
+            ref mut to,
+            ..
+        }
+        | WorldCommand::MapMakeProfiledConnection {
+            ref mut from,
+            ref mut to,
+            ..
+        } => {
+            fix(from);
+            fix(to);
+        }
+        WorldCommand::MapMakeMultipleConnections(ref mut projects, _) => {
+            projects.iter_mut().for_each(fix);
+        }
+        _ => {}
+    }
+    cmd
+}
+
+/// Computes the commands reverting `cmd` before it is applied.
+/// Returns None if the command cannot be undone.
+/// The objects created by the command are removed by commands added once it is applied.
+pub(crate) fn inverse_before(
+    cmd: &WorldCommand,
+    map: &Map,
+    companies: &GoodsCompanyRegistry,
+    owners: &Ownership,
+) -> Option<Vec<UndoCommand>> {
+    Some(match *cmd {
+        WorldCommand::MapMakeConnection { .. }
+        | WorldCommand::MapMakeProfiledConnection { .. }
+        | WorldCommand::MapMakeMultipleConnections(..)
+        | WorldCommand::MapBuildSpecialBuilding { .. }
+        | WorldCommand::MapBuildHouse(_)
+        | WorldCommand::MapMakeUtilityLink { .. } => vec![],
+        WorldCommand::MapRemoveRoad(id) => map
+            .roads()
+            .get(id)
+            .map(|road| UndoCommand {
+                cmd: rebuild_road(map, road),
+                restores: vec![(ProjectKind::Road(id), owners.road(id))],
+            })
+            .into_iter()
+            .collect(),
+        WorldCommand::MapRemoveIntersection(id) => {
+            rebuild_intersection(map, owners, id).into_iter().collect()
+        }
+        WorldCommand::MapRemoveBuilding(id) => map
+            .buildings()
+            .get(id)
+            .map(|b| UndoCommand {
+                cmd: rebuild_building(b, companies),
+                restores: vec![(ProjectKind::Building(id), owners.building(id))],
+            })
+            .into_iter()
+            .collect(),
+        WorldCommand::UpdateZone { building, .. } => map
+            .buildings()
+            .get(building)
+            .and_then(|b| b.zone.clone())
+            .map(|zone| WorldCommand::UpdateZone { building, zone }.into())
+            .into_iter()
+            .collect(),
+        WorldCommand::MapSetRoadRestrictions { road, .. } => {
+            vec![WorldCommand::MapSetRoadRestrictions {
+                road,
+                restrictions: map.road_restrictions(road),
+            }
+            .into()]
+        }
+        WorldCommand::MapSetOneWay { road, .. } | WorldCommand::MapSetRoadPattern { road, .. } => {
+            map.roads()
+                .get(road)
+                .map(|r| {
+                    WorldCommand::MapSetRoadPattern {
+                        road,
+                        pat: r.pattern(map.lanes()),
+                    }
+                    .into()
+                })
+                .into_iter()
+                .collect()
+        }
+        WorldCommand::MapRemoveUtilityLink { kind, src, dst } => map
+            .find_utility_link(kind, src, dst)
+            .map(|id| {
+                let link = &map.utility_links()[id];
+                WorldCommand::MapMakeUtilityLink {
+                    kind,
+                    src: link.src,
+                    dst: link.dst,
+                    capacity: link.capacity,
+                }
+                .into()
+            })
+            .into_iter()
+            .collect(),
+        WorldCommand::MapSetLotKind { ref lots, .. } => {
+            let mut by_kind: Vec<(LotKind, Vec<LotID>)> = vec![];
+            for lot in lots.iter().filter_map(|&id| map.lots().get(id)) {
+                match by_kind.iter_mut().find(|(kind, _)| *kind == lot.kind) {
+                    Some((_, ids)) => ids.push(lot.id),
+                    None => by_kind.push((lot.kind, vec![lot.id])),
+                }
+            }
+            by_kind
+                .into_iter()
+                .map(|(kind, lots)| WorldCommand::MapSetLotKind { lots, kind }.into())
+                .collect()
+        }
+        _ => return None,
+    })
+}
+
+fn rebuild_road(map: &Map, road: &Road) -> WorldCommand {
+    let src = &map.intersections()[road.src];
+    let dst = &map.intersections()[road.dst];
+    WorldCommand::MapMakeConnection {
+        from: MapProject {
+            pos: src.pos,
+            kind: ProjectKind::Inter(road.src),
+        },
+        to: MapProject {
+            pos: dst.pos,
+            kind: ProjectKind::Inter(road.dst),
+        },
+        inter: road.segment.elbow(src.pos.xy()),
+        pat: road.pattern(map.lanes()),
+    }
+}
+
+fn rebuild_intersection(map: &Map, owners: &Ownership, id: IntersectionID) -> Option<UndoCommand> {
+    let inter = map.intersections().get(id)?;
+    let mut projects = vec![MapProject::ground(inter.pos)];
+    let mut links = Vec::with_capacity(inter.roads.len());
+    let mut restores = Vec::with_capacity(inter.roads.len());
+    for &r in &inter.roads {
+        let Some(road) = map.roads().get(r) else {
+            continue;
+        };
+        let Some(other) = road.other_end(id) else {
+            continue;
+        };
+        let other_pos = map.intersections()[other].pos;
+        projects.push(MapProject {
+            pos: other_pos,
+            kind: ProjectKind::Inter(other),
+        });
+        let k = projects.len() - 1;
+        let pat = road.pattern(map.lanes());
+        restores.push((ProjectKind::Road(r), owners.road(r)));
+        if road.src == id {
+            links.push((0, k, road.segment.elbow(inter.pos.xy()), pat));
+        } else {
+            links.push((k, 0, road.segment.elbow(other_pos.xy()), pat));
+        }
+    }
+    Some(UndoCommand {
+        cmd: WorldCommand::MapMakeMultipleConnections(projects, links),
+        restores,
+    })
+}
+
+fn rebuild_building(b: &Building, companies: &GoodsCompanyRegistry) -> WorldCommand {
+    let gen = match b.kind {
+        BuildingKind::House => BuildingGen::House,
+        BuildingKind::GoodsCompany(id) => companies.descriptions[id].bgen,
+        BuildingKind::RailFreightStation
+        | BuildingKind::TrainStation
+        | BuildingKind::ExternalTrading => {
+            let axis = (b.obb.corners[1] - b.obb.corners[0]).normalize();
+            BuildingGen::NoWalkway {
+                door_pos: (b.door_pos.xy() - b.obb.center()).rotated_by(Vec2::new(axis.x, -axis.y)),
+            }
+        }
+    };
+    WorldCommand::MapBuildSpecialBuilding {
+        pos: b.obb,
+        kind: b.kind,
+        gen,
+        zone: b.zone.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UndoHistory;
+    use crate::economy::Government;
+    use crate::engine_interaction::WorldCommand;
+    use crate::map::{
+        BuildingID, BuildingKind, LaneDirection, LaneKind, LanePatternBuilder, LotKind, MapProject,
+        ProjectKind, RoadID, RoadRestrictions, UtilityKind, VerticalProfile, Zone,
+    };
+    use crate::multiplayer::{PlayerID, Players};
+    use crate::souls::goods_company::GoodsCompanyRegistry;
+    use crate::tests::TestCtx;
+    use common::descriptions::BuildingGen;
+    use geom::{vec2, vec3, Polygon, Vec2, Vec3, OBB};
+
+    /// What the map looks like, without the ids that change when objects are built again
+    fn snapshot(test: &TestCtx) -> Vec<String> {
+        let map = test.g.map();
+        let round = |v: Vec3| (v.x.round() as i32, v.y.round() as i32);
+        let mut s: Vec<String> = map
+            .roads()
+            .values()
+            .map(|r| {
+                format!(
+                    "road {:?} {:?} {:?} {:?}",
+                    round(map.intersections()[r.src].pos),
+                    round(map.intersections()[r.dst].pos),
+                    r.pattern(map.lanes()),
+                    map.road_restrictions(r.id),
+                )
+            })
+            .collect();
+        s.extend(map.buildings().values().map(|b| {
+            format!(
+                "building {:?} {:?} {:?}",
+                b.kind,
+                round(b.door_pos),
+                b.zone.as_ref().map(|z| z.area.round())
+            )
+        }));
+        s.extend(
+            map.utility_links()
+                .values()
+                .map(|l| format!("link {:?} {}", l.kind, l.capacity)),
+        );
+        s.extend(
+            map.lots()
+                .values()
+                .filter(|l| l.kind != LotKind::Unassigned)
+                .map(|l| format!("lot {:?}", l.kind)),
+        );
+        s.sort();
+        s
+    }
+
+    /// Applies the command, then checks that undoing and redoing it goes back and forth
+    fn check_undo_redo(test: &mut TestCtx, cmd: WorldCommand) {
+        let before = snapshot(test);
+        test.apply(std::slice::from_ref(&cmd));
+        let after = snapshot(test);
+        assert_ne!(before, after, "{:?} did nothing", cmd);
+
+        test.apply(&[WorldCommand::Undo]);
+        assert_eq!(snapshot(test), before, "undoing {:?}", cmd);
+        test.apply(&[WorldCommand::Redo]);
+        assert_eq!(snapshot(test), after, "redoing {:?}", cmd);
+        // the history is saved with the game
+        test.tick();
+    }
+
+    fn road_at(test: &TestCtx, y: f32) -> RoadID {
+        test.g
+            .map()
+            .roads()
+            .values()
+            .find(|r| (r.points.first().y - y).abs() < 1.0 && (r.points.last().y - y).abs() < 1.0)
+            .unwrap()
+            .id
+    }
+
+    fn company(test: &TestCtx, name: &str) -> (BuildingKind, BuildingGen) {
+        let registry = test.g.read::<GoodsCompanyRegistry>();
+        let (id, descr) = registry
+            .descriptions
+            .iter()
+            .find(|(_, d)| d.name == name)
+            .unwrap();
+        (BuildingKind::GoodsCompany(id), descr.bgen)
+    }
+
+    fn building_of(test: &TestCtx, kind: BuildingKind) -> BuildingID {
+        test.g
+            .map()
+            .buildings()
+            .values()
+            .find(|b| b.kind == kind)
+            .unwrap()
+            .id
+    }
+
+    fn connection(from: Vec2, to: Vec2) -> WorldCommand {
+        WorldCommand::MapMakeConnection {
+            from: MapProject::ground(from.z0()),
+            to: MapProject::ground(to.z0()),
+            inter: None,
+            pat: LanePatternBuilder::new().build(),
+        }
+    }
+
+    #[test]
+    fn test_undo_redo_each_command() {
+        let mut test = TestCtx::new();
+        test.build_roads(&[vec3(0.0, 0.0, 0.0), vec3(400.0, 0.0, 0.0)]);
+        let house = test.build_house_near(vec2(100.0, 30.0));
+        let pat = LanePatternBuilder::new().build();
+
+        check_undo_redo(
+            &mut test,
+            connection(vec2(0.0, -300.0), vec2(400.0, -300.0)),
+        );
+        check_undo_redo(
+            &mut test,
+            WorldCommand::MapMakeProfiledConnection {
+                from: MapProject::ground(vec3(0.0, -500.0, 0.0)),
+                to: MapProject::ground(vec3(400.0, -500.0, 0.0)),
+                inter: None,
+                pat: pat.clone(),
+                profile: VerticalProfile {
+                    from_elevation: 0.0,
+                    to_elevation: 0.0,
+                    max_grade: 0.05,
+                },
+            },
+        );
+        check_undo_redo(
+            &mut test,
+            WorldCommand::MapMakeMultipleConnections(
+                vec![
+                    MapProject::ground(vec3(0.0, -700.0, 0.0)),
+                    MapProject::ground(vec3(200.0, -700.0, 0.0)),
+                    MapProject::ground(vec3(200.0, -900.0, 0.0)),
+                ],
+                vec![(0, 1, None, pat.clone()), (1, 2, None, pat.clone())],
+            ),
+        );
+
+        // two lanes one way and one the other, it is not a pattern made from a direction
+        let road = road_at(&test, -300.0);
+        let mut asymmetric = test.g.map().roads()[road].pattern(test.g.map().lanes());
+        asymmetric
+            .lanes_forward
+            .insert(0, (LaneKind::Driving, asymmetric.lanes_forward[0].1));
+        test.g.map_mut().set_road_pattern(road, &asymmetric);
+        check_undo_redo(
+            &mut test,
+            WorldCommand::MapSetOneWay {
+                road,
+                direction: Some(LaneDirection::Backward),
+            },
+        );
+        let road = road_at(&test, -300.0);
+        check_undo_redo(
+            &mut test,
+            WorldCommand::MapSetRoadRestrictions {
+                road,
+                restrictions: RoadRestrictions {
+                    speed_limit: Some(10.0),
+                    no_trucks: true,
+                    ..Default::default()
+                },
+            },
+        );
+
+        let road = road_at(&test, -500.0);
+        check_undo_redo(&mut test, WorldCommand::MapRemoveRoad(road));
+        let inter = test
+            .g
+            .map()
+            .intersections()
+            .values()
+            .find(|i| i.pos.xy().distance(vec2(200.0, -700.0)) < 1.0)
+            .unwrap()
+            .id;
+        check_undo_redo(&mut test, WorldCommand::MapRemoveIntersection(inter));
+
+        let lot = |test: &TestCtx, p: Vec2| {
+            test.g
+                .map()
+                .lots()
+                .values()
+                .min_by_key(|lot| lot.shape.center().distance2(p) as i32)
+                .unwrap()
+                .id
+        };
+        let free_lot = lot(&test, vec2(300.0, 30.0));
+        check_undo_redo(&mut test, WorldCommand::MapBuildHouse(free_lot));
+        let free_lot = lot(&test, vec2(300.0, -30.0));
+        check_undo_redo(
+            &mut test,
+            WorldCommand::MapSetLotKind {
+                lots: vec![free_lot],
+                kind: LotKind::Residential,
+            },
+        );
+
+        let (factory, gen) = company(&test, "Flour Factory");
+        check_undo_redo(
+            &mut test,
+            WorldCommand::MapBuildSpecialBuilding {
+                pos: OBB::new(vec2(200.0, 150.0), vec2(1.0, 0.0), 10.0, 10.0),
+                kind: factory,
+                gen,
+                zone: None,
+            },
+        );
+        let factory = building_of(&test, factory);
+        let link = |make: bool| {
+            if make {
+                WorldCommand::MapMakeUtilityLink {
+                    kind: UtilityKind::Water,
+                    src: factory,
+                    dst: house,
+                    capacity: 10.0,
+                }
+            } else {
+                WorldCommand::MapRemoveUtilityLink {
+                    kind: UtilityKind::Water,
+                    src: factory,
+                    dst: house,
+                }
+            }
+        };
+        check_undo_redo(&mut test, link(true));
+        check_undo_redo(&mut test, link(false));
+        check_undo_redo(&mut test, WorldCommand::MapRemoveBuilding(house));
+
+        let (farm, gen) = company(&test, "Vegetable Farm");
+        let zone = |size: f32| {
+            Zone::new(
+                Polygon::centered_rect(vec2(600.0, 200.0), size, size),
+                Vec2::X,
+            )
+        };
+        test.apply(&[WorldCommand::MapBuildSpecialBuilding {
+            pos: OBB::new(vec2(600.0, 150.0), vec2(1.0, 0.0), 20.0, 20.0),
+            kind: farm,
+            gen,
+            zone: Some(zone(60.0)),
+        }]);
+        let farm = building_of(&test, farm);
+        check_undo_redo(
+            &mut test,
+            WorldCommand::UpdateZone {
+                building: farm,
+                zone: zone(80.0),
+            },
+        );
+    }
+
+    #[test]
+    fn test_undo_gives_objects_back_to_their_owner() {
+        let mut test = TestCtx::new();
+        test.apply(&[
+            WorldCommand::ActAs(PlayerID(1)),
+            connection(vec2(0.0, 0.0), vec2(400.0, 0.0)),
+        ]);
+        let road = road_at(&test, 0.0);
+
+        test.apply(&[
+            WorldCommand::ActAs(PlayerID::HOST),
+            WorldCommand::MapRemoveRoad(road),
+            WorldCommand::Undo,
+        ]);
+        let road = road_at(&test, 0.0);
+        assert_eq!(
+            test.g.read::<Players>().owners.road(road),
+            Some(PlayerID(1))
+        );
+    }
+
+    #[test]
+    fn test_stale_entries_are_dropped_without_refund() {
+        let mut test = TestCtx::new();
+        test.apply(&[
+            WorldCommand::ActAs(PlayerID(1)),
+            connection(vec2(0.0, 0.0), vec2(400.0, 0.0)),
+        ]);
+        let road = road_at(&test, 0.0);
+        let money = test.g.read::<Players>().get(PlayerID(1)).unwrap().money;
+
+        // another player splits the road in two
+        test.apply(&[
+            WorldCommand::ActAs(PlayerID::HOST),
+            WorldCommand::MapMakeConnection {
+                from: MapProject::ground(vec3(200.0, -300.0, 0.0)),
+                to: MapProject {
+                    pos: vec3(200.0, 0.0, 0.0),
+                    kind: ProjectKind::Road(road),
+                },
+                inter: None,
+                pat: LanePatternBuilder::new().build(),
+            },
+        ]);
+        assert!(!test.g.map().roads().contains_key(road));
+        let n_roads = test.g.map().roads().len();
+
+        test.apply(&[WorldCommand::ActAs(PlayerID(1)), WorldCommand::Undo]);
+        assert_eq!(test.g.map().roads().len(), n_roads);
+        assert_eq!(
+            test.g.read::<Players>().get(PlayerID(1)).unwrap().money,
+            money
+        );
+        assert!(!test.g.read::<UndoHistory>().can_undo(PlayerID(1)));
+        assert!(!test.g.read::<UndoHistory>().can_redo(PlayerID(1)));
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut test = TestCtx::new();
+        let n_roads = test.g.map().roads().len();
+        let money = test.g.read::<Government>().money;
+
+        test.apply(&[WorldCommand::MapMakeConnection {
+            from: MapProject::ground(vec3(0.0, 0.0, 0.0)),
+            to: MapProject::ground(vec3(300.0, 100.0, 0.0)),
+            inter: Some(vec3(200.0, 0.0, 0.0).xy()),
+            pat: LanePatternBuilder::new().build(),
+        }]);
+        assert_eq!(test.g.map().roads().len(), n_roads + 1);
+        let spent = money - test.g.read::<Government>().money;
+
+        let road = test
+            .g
+            .map()
+            .roads()
+            .values()
+            .find(|r| r.points.first().x < 1000.0)
+            .unwrap()
+            .id;
+        test.apply(&[WorldCommand::MapRemoveRoad(road)]);
+        assert_eq!(test.g.map().roads().len(), n_roads);
+
+        test.apply(&[WorldCommand::Undo]);
+        assert_eq!(test.g.map().roads().len(), n_roads + 1);
+
+        test.apply(&[WorldCommand::Undo]);
+        assert_eq!(test.g.map().roads().len(), n_roads);
+        assert_eq!(test.g.read::<Government>().money, money);
+
+        test.apply(&[WorldCommand::Redo, WorldCommand::Redo]);
+        assert_eq!(test.g.map().roads().len(), n_roads);
+        assert_eq!(test.g.read::<Government>().money, money - spent);
+
+        test.apply(&[WorldCommand::Undo]);
+        assert_eq!(test.g.map().roads().len(), n_roads + 1);
+        let map = test.g.map();
+        let road = map
+            .roads()
+            .values()
+            .find(|r| r.points.first().x < 1000.0)
+            .unwrap();
+        assert!(road.points.last().xy().distance(vec2(300.0, 100.0)) < 1.0);
+        assert!(road.points.n_points() > 2);
+    }
+}
@@ -1,14 +1,18 @@
-use crate::economy::{Bought, Market, Sold, Workers};
+use crate::economy::{Bought, Market, Sold, Wallets, Workers};
 use crate::map_dynamic::{
-    DispatchID, Dispatcher, Itinerary, ItineraryFollower, ItineraryLeader, ParkingManagement,
-    Router,
+    BuildingInfos, DispatchID, Dispatcher, Itinerary, ItineraryFollower, ItineraryLeader,
+    ParkingManagement, Router, RouterV0,
 };
+use crate::multiplayer::Players;
 use crate::physics::{Collider, CollisionWorld, Speed};
-use crate::souls::desire::{BuyFood, Home, Work};
+use crate::souls::desire::{BuyFood, GenericDesire, Home, Work, WorkV0};
 use crate::souls::freight_station::FreightStation;
-use crate::souls::goods_company::GoodsCompany;
-use crate::souls::human::{HumanDecision, PersonalInfo};
+use crate::souls::goods_company::{GoodsCompany, GoodsCompanyV0};
+use crate::souls::human::{HumanDecision, HumanDecisionV0, PersonalInfo};
+use crate::souls::satisfaction::Satisfaction;
+use crate::transportation::passenger_rail::PassengerLines;
 use crate::transportation::train::{Locomotive, LocomotiveReservation, RailWagon};
+use crate::transportation::transit::TransitLines;
 use crate::transportation::{Location, Pedestrian, Vehicle, VehicleKind, VehicleState};
 use crate::utils::par_command_buffer::SimDrop;
 use crate::utils::resources::Resources;
@@ -74,9 +78,12 @@ impl SimDrop for VehicleEnt {
             res.write::<ParkingManagement>().free(resa);
         }
 
-        if matches!(self.vehicle.kind, VehicleKind::Truck) {
-            res.write::<Dispatcher>()
-                .unregister(DispatchID::SmallTruck(id))
+        match self.vehicle.kind {
+            VehicleKind::Truck => res
+                .write::<Dispatcher>()
+                .unregister(DispatchID::SmallTruck(id)),
+            VehicleKind::Bus => res.write::<TransitLines>().remove_bus(id),
+            VehicleKind::Car | VehicleKind::Bike => {}
         }
     }
 }
@@ -95,8 +102,11 @@ pub struct HumanEnt {
     pub decision: HumanDecision,
     pub home: Home,
     pub food: BuyFood,
+    /// Desires defined in data, see [`crate::souls::desire::DesireRegistry`]
+    pub desires: Vec<GenericDesire>,
     pub bought: Bought,
     pub work: Option<Work>,
+    pub satisfaction: Satisfaction,
 
     pub personal_info: Box<PersonalInfo>,
 }
@@ -108,6 +118,14 @@ impl SimDrop for HumanEnt {
         }
 
         res.write::<Market>().remove(SoulID::Human(id));
+        res.write::<Wallets>().remove(SoulID::Human(id));
+
+        let mut binfos = res.write::<BuildingInfos>();
+        binfos.remove_resident(self.home.house, SoulID::Human(id));
+        if let Location::Building(b) = self.location {
+            binfos.get_out(b, SoulID::Human(id));
+        }
+        drop(binfos);
 
         self.router
             .clear_steps(&mut res.write::<ParkingManagement>())
@@ -129,6 +147,7 @@ impl SimDrop for TrainEnt {
     fn sim_drop(self, id: TrainID, res: &mut Resources) {
         res.write::<Dispatcher>()
             .unregister(DispatchID::FreightTrain(id));
+        res.write::<PassengerLines>().remove_train(id);
     }
 }
 
@@ -153,6 +172,7 @@ pub struct FreightStationEnt {
 impl SimDrop for FreightStationEnt {
     fn sim_drop(self, id: FreightStationID, res: &mut Resources) {
         res.write::<Market>().remove(SoulID::FreightStation(id));
+        res.write::<Wallets>().remove(SoulID::FreightStation(id));
 
         let mut d = res.write::<Dispatcher>();
         for (id, _) in self.f.trains {
@@ -174,6 +194,8 @@ pub struct CompanyEnt {
 impl SimDrop for CompanyEnt {
     fn sim_drop(self, id: CompanyID, res: &mut Resources) {
         res.write::<Market>().remove(SoulID::GoodsCompany(id));
+        res.write::<Wallets>().remove(SoulID::GoodsCompany(id));
+        res.write::<Players>().owners.remove_company(id);
     }
 }
 
@@ -187,6 +209,132 @@ pub struct World {
     pub companies: HopSlotMap<CompanyID, CompanyEnt>,
 }
 
+/// The world before desires, satisfaction, bikes, loads of goods and several drivers per
+/// company, see [`crate::WORLD_KEY`]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct WorldV0 {
+    pub(crate) vehicles: HopSlotMap<VehicleID, VehicleEnt>,
+    pub(crate) humans: SavedSlots<HumanEntV0>,
+    pub(crate) trains: HopSlotMap<TrainID, TrainEnt>,
+    pub(crate) wagons: HopSlotMap<WagonID, WagonEnt>,
+    pub(crate) freight_stations: HopSlotMap<FreightStationID, FreightStationEnt>,
+    pub(crate) companies: SavedSlots<CompanyEntV0>,
+}
+
+/// Saved the same way as [`World`]
+#[derive(Serialize)]
+pub(crate) struct WorldV1 {
+    vehicles: HopSlotMap<VehicleID, VehicleEnt>,
+    humans: SavedSlots<HumanEnt>,
+    trains: HopSlotMap<TrainID, TrainEnt>,
+    wagons: HopSlotMap<WagonID, WagonEnt>,
+    freight_stations: HopSlotMap<FreightStationID, FreightStationEnt>,
+    companies: SavedSlots<CompanyEnt>,
+}
+
+impl From<WorldV0> for WorldV1 {
+    fn from(old: WorldV0) -> Self {
+        Self {
+            vehicles: old.vehicles,
+            humans: old.humans.map(|h| HumanEnt {
+                trans: h.trans,
+                speed: h.speed,
+                location: h.location,
+                pedestrian: h.pedestrian,
+                collider: h.collider,
+                router: h.router.into(),
+                it: h.it,
+                decision: h.decision.into(),
+                home: h.home,
+                food: h.food,
+                // the registered desires are added once loaded, see `add_missing_desires`
+                desires: vec![],
+                bought: h.bought,
+                work: h.work.map(Work::from),
+                satisfaction: Satisfaction::default(),
+                personal_info: h.personal_info,
+            }),
+            trains: old.trains,
+            wagons: old.wagons,
+            freight_stations: old.freight_stations,
+            companies: old.companies.map(|c| CompanyEnt {
+                trans: c.trans,
+                comp: c.comp.into(),
+                workers: c.workers,
+                sold: c.sold,
+                bought: c.bought,
+            }),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct HumanEntV0 {
+    trans: Transform,
+    speed: Speed,
+    location: Location,
+    pedestrian: Pedestrian,
+    collider: Option<Collider>,
+    router: RouterV0,
+    it: Itinerary,
+    decision: HumanDecisionV0,
+    home: Home,
+    food: BuyFood,
+    bought: Bought,
+    work: Option<WorkV0>,
+    personal_info: Box<PersonalInfo>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct CompanyEntV0 {
+    trans: Transform,
+    comp: GoodsCompanyV0,
+    workers: Workers,
+    sold: Sold,
+    bought: Bought,
+}
+
+/// The slots of a [`HopSlotMap`] as it saves them, so that the entities of an old world
+/// can be upgraded while keeping their ids
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SavedSlots<T>(Vec<SavedSlot<T>>);
+
+#[derive(Serialize, Deserialize)]
+struct SavedSlot<T> {
+    value: SavedSlotValue<T>,
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+enum SavedSlotValue<T> {
+    O(T),
+    F(FreeSlot),
+}
+
+#[derive(Serialize, Deserialize)]
+struct FreeSlot {
+    next: u32,
+    prev: u32,
+    other_end: u32,
+}
+
+impl<T> SavedSlots<T> {
+    fn map<U>(self, f: impl Fn(T) -> U) -> SavedSlots<U> {
+        SavedSlots(
+            self.0
+                .into_iter()
+                .map(|slot| SavedSlot {
+                    value: match slot.value {
+                        SavedSlotValue::O(v) => SavedSlotValue::O(f(v)),
+                        SavedSlotValue::F(free) => SavedSlotValue::F(free),
+                    },
+                    version: slot.version,
+                })
+                .collect(),
+        )
+    }
+}
+
 impl World {
     pub fn get<E: EntityID>(&self, id: E) -> Option<&E::Entity> {
         <<E as EntityID>::Entity as Entity>::storage(self).get(id)